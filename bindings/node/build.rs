@@ -0,0 +1,4 @@
+// bindings/node/build.rs
+fn main() {
+    napi_build::setup();
+}
@@ -0,0 +1,109 @@
+/* bindings/node/index.d.ts, kept in step with src/lib.rs (`napi build` regenerates it) */
+
+/** Handle returned by `index()`; pass it to the other functions */
+export declare class Index {
+  get size(): number
+  get roots(): Array<string>
+}
+
+export interface IndexOptions {
+  /** Corpus directories; the config's corpora when left out */
+  roots?: Array<string>
+  /** Path to an `infospark.toml`; `infospark.toml` in the working directory otherwise */
+  config?: string
+  /** Saved index to start from and write back to, as the CLI does */
+  indexPath?: string
+}
+
+export interface SearchOptions {
+  limit?: number
+}
+
+export interface GraphOptions {
+  /** Only this document and its strongest neighbors, as lazily loaded pages do */
+  focus?: number
+}
+
+export interface SearchResult {
+  id: number
+  title: string
+  path: string
+  score: number
+  tags: Array<string>
+  snippet: string
+  lines: Array<number>
+  modifiedTime: number
+  createdTime?: number
+  size: number
+  author?: string
+  language?: string
+  /** Name of the corpus root the document came from */
+  source: string
+}
+
+export interface UpdateReport {
+  added: Array<string>
+  updated: Array<string>
+  removed: Array<string>
+  skipped: Array<string>
+}
+
+export interface GraphNode {
+  id: number
+  label: string
+  title: string
+  group: string
+  contentPreview: string
+  jsTags: Array<string>
+  modifiedTime: number
+  createdTime?: number
+  size: number
+}
+
+export interface GraphEdge {
+  from: number
+  to: number
+  /** Number of shared tags */
+  width: number
+}
+
+export interface SearchableDocument {
+  id: number
+  title: string
+  content: string
+  tags: Array<string>
+  contentPreview: string
+  size: number
+  author?: string
+  language?: string
+  source: string
+}
+
+/** Serve mode's `/api/data`, with camelCase fields */
+export interface Graph {
+  nodes: Array<GraphNode>
+  edges: Array<GraphEdge>
+  /** Keyed by document id */
+  searchableDocuments: Record<string, SearchableDocument>
+}
+
+/**
+ * Indexes the corpus roots, starting from `indexPath` when it exists and
+ * saving back to it
+ */
+export declare function index(options?: IndexOptions): Promise<Index>
+
+/** Reindexes only the files that changed since the last call */
+export declare function update(index: Index): Promise<UpdateReport>
+
+/**
+ * Runs a query in the CLI's syntax; malformed queries reject with the
+ * parser's message
+ */
+export declare function search(index: Index, query: string, options?: SearchOptions): Promise<Array<SearchResult>>
+
+/**
+ * Graph nodes, edges and searchable documents for the whole corpus, or
+ * around one document
+ */
+export declare function graph(index: Index, options?: GraphOptions): Promise<Graph>
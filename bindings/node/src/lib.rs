@@ -0,0 +1,402 @@
+// bindings/node/src/lib.rs
+// The `infospark` Node.js addon: indexing, search and graph data for apps that
+// embed the engine. Every call runs on the libuv thread pool and returns a
+// Promise, so an Electron main process never blocks on a reindex.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use infospark::config::{Config, CorpusRoot};
+use infospark::inverted_index::{self, InvertedIndex, WebAppData};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde::Deserialize;
+
+// --- CONSTANTS ---
+const MAX_GRAPH_NEIGHBORS: usize = 200; // Same cap as serve mode's neighbor endpoint
+
+// --- HELPERS ---
+fn engine_error(e: impl std::fmt::Display) -> Error {
+    Error::new(Status::GenericFailure, e.to_string())
+}
+
+fn anyhow_error(e: anyhow::Error) -> Error {
+    engine_error(format!("{:#}", e))
+}
+
+// --- STRUCTS ---
+#[napi(object)]
+#[derive(Default)]
+pub struct IndexOptions {
+    /// Corpus directories; the config's corpora when left out
+    pub roots: Option<Vec<String>>,
+    /// Path to an `infospark.toml`; `infospark.toml` in the working directory otherwise
+    pub config: Option<String>,
+    /// Saved index to start from and write back to, as the CLI does
+    pub index_path: Option<String>,
+}
+
+#[napi(object)]
+pub struct SearchOptions {
+    pub limit: Option<u32>,
+}
+
+#[napi(object)]
+pub struct GraphOptions {
+    /// Only this document and its strongest neighbors, as lazily loaded pages do
+    pub focus: Option<u32>,
+}
+
+#[napi(object)]
+pub struct SearchResult {
+    pub id: u32,
+    pub title: String,
+    pub path: String,
+    pub score: f64,
+    pub tags: Vec<String>,
+    pub snippet: String,
+    pub lines: Vec<u32>,
+    pub modified_time: i64,
+    pub created_time: Option<i64>,
+    pub size: i64,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    /// Name of the corpus root the document came from
+    pub source: String,
+}
+
+impl From<&inverted_index::SearchResult> for SearchResult {
+    fn from(result: &inverted_index::SearchResult) -> Self {
+        let summary = result.summary();
+        SearchResult {
+            id: summary.id,
+            title: summary.title,
+            path: summary.path.display().to_string(),
+            score: summary.score,
+            tags: summary.tags,
+            snippet: summary.snippet,
+            lines: summary.lines.iter().map(|line| *line as u32).collect(),
+            modified_time: summary.modified_time as i64,
+            created_time: summary.created_time.map(|time| time as i64),
+            size: summary.size as i64,
+            author: summary.author,
+            language: summary.language,
+            source: summary.source,
+        }
+    }
+}
+
+#[napi(object)]
+pub struct UpdateReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl From<&inverted_index::IndexingReport> for UpdateReport {
+    fn from(report: &inverted_index::IndexingReport) -> Self {
+        let paths = |paths: &[PathBuf]| -> Vec<String> {
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect()
+        };
+        UpdateReport {
+            added: paths(&report.added),
+            updated: paths(&report.updated),
+            removed: paths(&report.removed),
+            skipped: paths(&report.skipped),
+        }
+    }
+}
+
+#[napi(object)]
+#[derive(Deserialize)]
+pub struct GraphNode {
+    pub id: u32,
+    pub label: String,
+    pub title: String,
+    pub group: String,
+    pub content_preview: String,
+    pub js_tags: Vec<String>,
+    pub modified_time: i64,
+    pub created_time: Option<i64>,
+    pub size: i64,
+}
+
+#[napi(object)]
+#[derive(Deserialize)]
+pub struct GraphEdge {
+    pub from: u32,
+    pub to: u32,
+    /// Number of shared tags
+    pub width: f64,
+}
+
+#[napi(object)]
+#[derive(Deserialize)]
+pub struct SearchableDocument {
+    pub id: u32,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub content_preview: String,
+    pub size: i64,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub source: String,
+}
+
+/// Serve mode's `/api/data`, with camelCase fields
+#[napi(object)]
+#[derive(Deserialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// Keyed by document id
+    pub searchable_documents: HashMap<String, SearchableDocument>,
+}
+
+// The index with the graph data derived from it, kept in step on every
+// update so graph calls don't rebuild it.
+pub struct Engine {
+    index: InvertedIndex,
+    web_data: WebAppData,
+    roots: Vec<CorpusRoot>,
+    index_path: Option<PathBuf>,
+}
+
+impl Engine {
+    fn open(options: &IndexOptions) -> anyhow::Result<Self> {
+        let config = Config::load_or_default(options.config.as_deref().map(Path::new))?;
+        let roots = match &options.roots {
+            Some(roots) if !roots.is_empty() => {
+                roots.iter().map(|root| config.corpus_root(root)).collect()
+            }
+            _ => config.corpus_roots(),
+        };
+        let index_path = options.index_path.as_ref().map(PathBuf::from);
+        let mut index = match &index_path {
+            Some(path) if path.exists() => InvertedIndex::load_from_file(path)?,
+            _ => InvertedIndex::new(),
+        };
+        index.configure_indexing(&config.indexing)?;
+        index.configure_cache(&config.cache);
+        index.configure_snippets(&config.snippets);
+        index.configure_results(&config.results);
+        index.configure_query(&config.query);
+        index.configure_boosts(&config.boosts);
+        let report = index.load_documents_from_roots(&roots)?;
+        if let Some(path) = &index_path
+            && (report.has_changes() || !path.exists())
+        {
+            index.save_changes(path, &report.changed_doc_ids)?;
+        }
+        Ok(Engine {
+            web_data: index.build_web_app_data(),
+            index,
+            roots,
+            index_path,
+        })
+    }
+
+    fn update(&mut self) -> anyhow::Result<inverted_index::IndexingReport> {
+        let report = self.index.load_documents_from_roots(&self.roots)?;
+        if report.has_changes() {
+            self.index
+                .refresh_web_app_data(&mut self.web_data, &report.changed_doc_ids);
+            if let Some(path) = &self.index_path {
+                self.index.save_changes(path, &report.changed_doc_ids)?;
+            }
+        }
+        Ok(report)
+    }
+
+    fn graph_json(&self, focus: Option<u32>) -> anyhow::Result<String> {
+        let Some(doc_id) = focus else {
+            return self.web_data.to_json();
+        };
+        if self.index.document(doc_id).is_none() {
+            anyhow::bail!("No document with id {}", doc_id);
+        }
+        let mut neighbors = self.index.graph_neighbors(doc_id);
+        neighbors.truncate(MAX_GRAPH_NEIGHBORS);
+        let edge_keys: Vec<(u32, u32)> = neighbors
+            .iter()
+            .map(|other| (doc_id.min(*other), doc_id.max(*other)))
+            .collect();
+        let mut node_ids = vec![doc_id];
+        node_ids.extend(neighbors);
+        self.web_data.subgraph_json(&node_ids, &edge_keys)
+    }
+}
+
+// --- CLASSES ---
+/// Handle returned by `index()`; pass it to the other functions
+#[napi]
+pub struct Index {
+    engine: Arc<RwLock<Engine>>,
+}
+
+#[napi]
+impl Index {
+    #[napi(getter)]
+    pub fn size(&self) -> u32 {
+        self.engine.read().unwrap().index.total_documents() as u32
+    }
+
+    #[napi(getter)]
+    pub fn roots(&self) -> Vec<String> {
+        let engine = self.engine.read().unwrap();
+        engine
+            .roots
+            .iter()
+            .map(|root| root.path.display().to_string())
+            .collect()
+    }
+}
+
+// --- TASKS ---
+pub struct IndexTask {
+    options: IndexOptions,
+}
+
+impl Task for IndexTask {
+    type Output = Engine;
+    type JsValue = Index;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Engine::open(&self.options).map_err(anyhow_error)
+    }
+
+    fn resolve(&mut self, _env: Env, engine: Self::Output) -> Result<Self::JsValue> {
+        Ok(Index {
+            engine: Arc::new(RwLock::new(engine)),
+        })
+    }
+}
+
+pub struct UpdateTask {
+    engine: Arc<RwLock<Engine>>,
+}
+
+impl Task for UpdateTask {
+    type Output = UpdateReport;
+    type JsValue = UpdateReport;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let report = self
+            .engine
+            .write()
+            .unwrap()
+            .update()
+            .map_err(anyhow_error)?;
+        Ok(UpdateReport::from(&report))
+    }
+
+    fn resolve(&mut self, _env: Env, report: Self::Output) -> Result<Self::JsValue> {
+        Ok(report)
+    }
+}
+
+pub struct SearchTask {
+    engine: Arc<RwLock<Engine>>,
+    query: String,
+    limit: Option<usize>,
+}
+
+impl Task for SearchTask {
+    type Output = Vec<SearchResult>;
+    type JsValue = Vec<SearchResult>;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let engine = self.engine.read().unwrap();
+        let results = engine
+            .index
+            .search(&self.query)
+            .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+        let limit = self.limit.unwrap_or(results.len());
+        Ok(results.iter().take(limit).map(SearchResult::from).collect())
+    }
+
+    fn resolve(&mut self, _env: Env, results: Self::Output) -> Result<Self::JsValue> {
+        Ok(results)
+    }
+}
+
+pub struct GraphTask {
+    engine: Arc<RwLock<Engine>>,
+    focus: Option<u32>,
+}
+
+impl Task for GraphTask {
+    type Output = Graph;
+    type JsValue = Graph;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let json = self
+            .engine
+            .read()
+            .unwrap()
+            .graph_json(self.focus)
+            .map_err(anyhow_error)?;
+        serde_json::from_str(&json).map_err(engine_error)
+    }
+
+    fn resolve(&mut self, _env: Env, graph: Self::Output) -> Result<Self::JsValue> {
+        Ok(graph)
+    }
+}
+
+// --- FUNCTIONS ---
+/// Indexes the corpus roots, starting from `indexPath` when it exists and
+/// saving back to it
+#[napi(ts_return_type = "Promise<Index>")]
+pub fn index(options: Option<IndexOptions>) -> AsyncTask<IndexTask> {
+    AsyncTask::new(IndexTask {
+        options: options.unwrap_or_default(),
+    })
+}
+
+/// Reindexes only the files that changed since the last call
+#[napi(ts_return_type = "Promise<UpdateReport>")]
+pub fn update(index: &Index) -> AsyncTask<UpdateTask> {
+    AsyncTask::new(UpdateTask {
+        engine: index.engine.clone(),
+    })
+}
+
+/// Runs a query in the CLI's syntax; malformed queries reject with the
+/// parser's message
+#[napi(ts_return_type = "Promise<Array<SearchResult>>")]
+pub fn search(
+    index: &Index,
+    query: String,
+    options: Option<SearchOptions>,
+) -> AsyncTask<SearchTask> {
+    AsyncTask::new(SearchTask {
+        engine: index.engine.clone(),
+        query,
+        limit: options
+            .and_then(|options| options.limit)
+            .map(|limit| limit as usize),
+    })
+}
+
+/// Graph nodes, edges and searchable documents for the whole corpus, or
+/// around one document
+#[napi(ts_return_type = "Promise<Graph>")]
+pub fn graph(index: &Index, options: Option<GraphOptions>) -> AsyncTask<GraphTask> {
+    AsyncTask::new(GraphTask {
+        engine: index.engine.clone(),
+        focus: options.and_then(|options| options.focus),
+    })
+}
+
+#[napi_derive::module_init]
+fn init() {
+    // Warnings the engine prints would otherwise carry terminal escape codes
+    // into app logs
+    colored::control::set_override(false);
+}
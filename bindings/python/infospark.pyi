@@ -0,0 +1,62 @@
+# bindings/python/infospark.pyi
+from os import PathLike
+from pathlib import Path
+from typing import Optional, Sequence, Union
+
+StrPath = Union[str, PathLike[str]]
+
+class SearchResult:
+    id: int
+    title: str
+    path: Path
+    score: float
+    tags: list[str]
+    snippet: str
+    lines: list[int]  # 1-based lines of the matches; empty for non-text formats
+    modified_time: int  # Unix seconds
+    created_time: Optional[int]
+    size: int  # Bytes
+    author: Optional[str]
+    language: Optional[str]
+    source: str  # Name of the corpus root
+
+class Document:
+    id: int
+    path: Path
+    title: str
+    content: str
+    tags: list[str]
+    num_tokens: int
+    modified_time: int
+    created_time: Optional[int]
+    size: int
+    author: Optional[str]
+    language: Optional[str]
+    source: str
+
+class UpdateReport:
+    added: list[Path]
+    updated: list[Path]
+    removed: list[Path]
+    skipped: list[Path]
+    def has_changes(self) -> bool: ...
+
+class Index:
+    @property
+    def roots(self) -> list[Path]: ...
+    @staticmethod
+    def build(
+        roots: Optional[Sequence[StrPath]] = None, config: Optional[StrPath] = None
+    ) -> Index: ...
+    @staticmethod
+    def load(
+        path: StrPath,
+        roots: Optional[Sequence[StrPath]] = None,
+        config: Optional[StrPath] = None,
+    ) -> Index: ...
+    def save(self, path: StrPath) -> None: ...
+    def update(self, verify: bool = False) -> UpdateReport: ...
+    def search(self, query: str, limit: Optional[int] = None) -> list[SearchResult]: ...
+    def document(self, id: int) -> Document: ...
+    def documents(self) -> list[Document]: ...
+    def __len__(self) -> int: ...
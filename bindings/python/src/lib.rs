@@ -0,0 +1,334 @@
+// bindings/python/src/lib.rs
+// The `infospark` Python module: builds, updates and searches an index from
+// Python, returning plain typed objects rather than the engine's own structs.
+use std::path::PathBuf;
+
+use infospark::config::{Config, CorpusRoot};
+use infospark::inverted_index::{self, IndexingReport, InvertedIndex};
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+// --- HELPERS ---
+fn runtime_error(e: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", e))
+}
+
+fn load_config(config: Option<PathBuf>) -> PyResult<Config> {
+    Config::load_or_default(config.as_deref()).map_err(runtime_error)
+}
+
+// Explicit roots still pick up the config's tag synonyms, as on the command line
+fn resolve_roots(config: &Config, roots: Option<Vec<PathBuf>>) -> Vec<CorpusRoot> {
+    match roots {
+        Some(roots) if !roots.is_empty() => roots
+            .into_iter()
+            .map(|root| config.corpus_root(root))
+            .collect(),
+        _ => config.corpus_roots(),
+    }
+}
+
+// --- CLASSES ---
+#[pyclass(frozen, get_all, name = "SearchResult")]
+struct PySearchResult {
+    id: u32,
+    title: String,
+    path: PathBuf,
+    score: f64,
+    tags: Vec<String>,
+    snippet: String,
+    lines: Vec<usize>,
+    modified_time: u64,
+    created_time: Option<u64>,
+    size: u64,
+    author: Option<String>,
+    language: Option<String>,
+    source: String,
+}
+
+#[pymethods]
+impl PySearchResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchResult(id={}, title={:?}, score={:.4})",
+            self.id, self.title, self.score
+        )
+    }
+}
+
+impl From<&inverted_index::SearchResult> for PySearchResult {
+    fn from(result: &inverted_index::SearchResult) -> Self {
+        let summary = result.summary();
+        PySearchResult {
+            id: summary.id,
+            title: summary.title,
+            path: summary.path,
+            score: summary.score,
+            tags: summary.tags,
+            snippet: summary.snippet,
+            lines: summary.lines,
+            modified_time: summary.modified_time,
+            created_time: summary.created_time,
+            size: summary.size,
+            author: summary.author,
+            language: summary.language,
+            source: summary.source,
+        }
+    }
+}
+
+#[pyclass(frozen, get_all, name = "Document")]
+struct PyDocument {
+    id: u32,
+    path: PathBuf,
+    title: String,
+    content: String,
+    tags: Vec<String>,
+    num_tokens: usize,
+    modified_time: u64,
+    created_time: Option<u64>,
+    size: u64,
+    author: Option<String>,
+    language: Option<String>,
+    source: String,
+}
+
+#[pymethods]
+impl PyDocument {
+    fn __repr__(&self) -> String {
+        format!("Document(id={}, title={:?})", self.id, self.title)
+    }
+}
+
+impl From<&inverted_index::Document> for PyDocument {
+    fn from(doc: &inverted_index::Document) -> Self {
+        PyDocument {
+            id: doc.id,
+            path: doc.path.clone(),
+            title: doc.title.clone(),
+            content: doc.content.to_string(),
+            tags: doc.tags.clone(),
+            num_tokens: doc.num_tokens,
+            modified_time: doc.modified_time,
+            created_time: doc.created_time,
+            size: doc.size,
+            author: doc.author.clone(),
+            language: doc.language.clone(),
+            source: doc.source.clone(),
+        }
+    }
+}
+
+#[pyclass(frozen, get_all, name = "UpdateReport")]
+struct PyUpdateReport {
+    added: Vec<PathBuf>,
+    updated: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    skipped: Vec<PathBuf>,
+}
+
+#[pymethods]
+impl PyUpdateReport {
+    fn has_changes(&self) -> bool {
+        !(self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "UpdateReport(added={}, updated={}, removed={}, skipped={})",
+            self.added.len(),
+            self.updated.len(),
+            self.removed.len(),
+            self.skipped.len()
+        )
+    }
+}
+
+impl From<IndexingReport> for PyUpdateReport {
+    fn from(report: IndexingReport) -> Self {
+        PyUpdateReport {
+            added: report.added,
+            updated: report.updated,
+            removed: report.removed,
+            skipped: report.skipped,
+        }
+    }
+}
+
+#[pyclass(name = "Index")]
+struct PyIndex {
+    index: InvertedIndex,
+    roots: Vec<CorpusRoot>,
+    // The file last loaded or saved, and the documents changed since
+    saved_path: Option<PathBuf>,
+    unsaved: Vec<u32>,
+}
+
+impl PyIndex {
+    fn configured(
+        mut index: InvertedIndex,
+        config: &Config,
+        roots: Vec<CorpusRoot>,
+    ) -> PyResult<Self> {
+        index
+            .configure_indexing(&config.indexing)
+            .map_err(runtime_error)?;
+        index.configure_cache(&config.cache);
+        index.configure_snippets(&config.snippets);
+        index.configure_results(&config.results);
+        index.configure_query(&config.query);
+        index.configure_boosts(&config.boosts);
+        Ok(PyIndex {
+            index,
+            roots,
+            saved_path: None,
+            unsaved: Vec::new(),
+        })
+    }
+}
+
+#[pymethods]
+impl PyIndex {
+    // Indexes `roots`, or the config's corpora when none are given
+    #[staticmethod]
+    #[pyo3(signature = (roots=None, config=None))]
+    fn build(
+        py: Python<'_>,
+        roots: Option<Vec<PathBuf>>,
+        config: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        let config = load_config(config)?;
+        let roots = resolve_roots(&config, roots);
+        let mut built = Self::configured(InvertedIndex::new(), &config, roots)?;
+        let PyIndex { index, roots, .. } = &mut built;
+        py.detach(|| index.load_documents_from_roots(roots))
+            .map_err(runtime_error)?;
+        Ok(built)
+    }
+
+    // Opens an index saved by `save` or the `infospark` binary; `roots` are
+    // what later calls to `update` rescan.
+    #[staticmethod]
+    #[pyo3(signature = (path, roots=None, config=None))]
+    fn load(
+        py: Python<'_>,
+        path: PathBuf,
+        roots: Option<Vec<PathBuf>>,
+        config: Option<PathBuf>,
+    ) -> PyResult<Self> {
+        let config = load_config(config)?;
+        let roots = resolve_roots(&config, roots);
+        let mut index = py
+            .detach(|| InvertedIndex::load_from_file(&path))
+            .map_err(runtime_error)?;
+        index.relocate(&roots);
+        let mut loaded = Self::configured(index, &config, roots)?;
+        loaded.saved_path = Some(path);
+        Ok(loaded)
+    }
+
+    // Saving back to the file the index came from only journals the documents
+    // changed since, as the binary does; other paths get a full copy.
+    fn save(&mut self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        let PyIndex {
+            index,
+            saved_path,
+            unsaved,
+            ..
+        } = self;
+        py.detach(|| {
+            if saved_path.as_ref() == Some(&path) {
+                index.save_changes(&path, unsaved)
+            } else {
+                index.save_to_file(&path)
+            }
+        })
+        .map_err(runtime_error)?;
+        *saved_path = Some(path);
+        unsaved.clear();
+        Ok(())
+    }
+
+    // Rescans the roots, reindexing only what changed; `verify` also re-reads
+    // files whose modified time is unchanged.
+    #[pyo3(signature = (verify=false))]
+    fn update(&mut self, py: Python<'_>, verify: bool) -> PyResult<PyUpdateReport> {
+        let PyIndex {
+            index,
+            roots,
+            unsaved,
+            ..
+        } = self;
+        let report = py
+            .detach(|| {
+                if verify {
+                    index.verify_documents_from_roots(roots)
+                } else {
+                    index.load_documents_from_roots(roots)
+                }
+            })
+            .map_err(runtime_error)?;
+        unsaved.extend_from_slice(&report.changed_doc_ids);
+        Ok(report.into())
+    }
+
+    // Runs a query in the same syntax as the binary; malformed queries raise
+    // ValueError with the parser's message.
+    #[pyo3(signature = (query, limit=None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<PySearchResult>> {
+        let results = py
+            .detach(|| self.index.search(query))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let limit = limit.unwrap_or(results.len());
+        Ok(results
+            .iter()
+            .take(limit)
+            .map(PySearchResult::from)
+            .collect())
+    }
+
+    fn document(&self, id: u32) -> PyResult<PyDocument> {
+        self.index
+            .document(id)
+            .map(PyDocument::from)
+            .ok_or_else(|| PyKeyError::new_err(format!("No document with id {}", id)))
+    }
+
+    fn documents(&self) -> Vec<PyDocument> {
+        let mut docs: Vec<PyDocument> = self.index.documents().map(PyDocument::from).collect();
+        docs.sort_by_key(|doc| doc.id);
+        docs
+    }
+
+    #[getter]
+    fn roots(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|root| root.path.clone()).collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.index.total_documents()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Index(documents={})", self.index.total_documents())
+    }
+}
+
+// --- MODULE ---
+#[pymodule]
+#[pyo3(name = "infospark")]
+fn infospark_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    // Warnings the engine prints would otherwise carry terminal escape codes
+    // into notebook output
+    colored::control::set_override(false);
+    m.add_class::<PyIndex>()?;
+    m.add_class::<PySearchResult>()?;
+    m.add_class::<PyDocument>()?;
+    m.add_class::<PyUpdateReport>()?;
+    Ok(())
+}
@@ -0,0 +1,351 @@
+// src/analysis.rs
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::inverted_index::InvertedIndex;
+use crate::tokenizer;
+
+// --- CONSTANTS ---
+// Words outside the dictionary used by more documents than this are taken to
+// be deliberate vocabulary (names, jargon) rather than typos
+const MAX_UNKNOWN_DOCUMENT_FREQUENCY: usize = 2;
+// How often a similar word must occur before a one-off is suspected of being
+// a misspelling of it
+const MIN_VARIANT_SOURCE_COUNT: usize = 5;
+const LONG_WORD_CHARS: usize = 8; // Words this long may be two edits off
+
+// --- STRUCTS ---
+pub struct Cooccurrence {
+    pub term: String,    // Indexed (stemmed) form
+    pub surface: String, // Most common spelling of the term near the target
+    pub count: usize,
+}
+
+pub struct ConcordanceLine {
+    pub path: PathBuf,
+    pub position: usize, // Token position within the document
+    pub left: String,
+    pub keyword: String,
+    pub right: String,
+}
+
+pub struct GrepMatch {
+    pub path: PathBuf,
+    pub offset: usize, // Byte offset of the match in the stored content
+    pub line: usize,   // 1-based line number
+    pub line_text: String,
+    pub line_match: (usize, usize), // Byte range of the match within `line_text`
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypoKind {
+    Unknown,             // Not in the dictionary, and rare in the corpus
+    RareVariant(String), // Seen once, one or two edits away from this common word
+}
+
+pub struct Typo {
+    pub word: String,
+    pub line: usize, // 1-based line of its first occurrence in the document
+    pub kind: TypoKind,
+}
+
+pub struct DocumentTypos {
+    pub path: PathBuf,
+    pub typos: Vec<Typo>,
+}
+
+// Analyzes `word` the way queries are analyzed, so it can be looked up in the
+// index.
+fn analyze_single_term(word: &str) -> Result<String> {
+    let tokens = tokenizer::tokenize(word);
+    match tokens.as_slice() {
+        [(term, _)] => Ok(term.clone()),
+        [] => Err(anyhow!("'{}' is a stop word and is not indexed", word)),
+        _ => Err(anyhow!("expected a single term, got '{}'", word)),
+    }
+}
+
+// Counts the terms appearing within `window` token positions of `word`
+// anywhere in the corpus, most frequent first.
+pub fn cooccurrences(
+    index: &InvertedIndex,
+    word: &str,
+    window: usize,
+) -> Result<Vec<Cooccurrence>> {
+    let target = analyze_single_term(word)?;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut surface_counts: HashMap<String, HashMap<String, usize>> = HashMap::new();
+
+    for (doc_id, positions) in index.postings(&target) {
+        let Some(doc) = index.document(*doc_id) else {
+            continue;
+        };
+        // Tokenizing again yields the same positions as the stored postings
+        let tokens: Vec<String> =
+            tokenizer::tokenize_in(&doc.content, InvertedIndex::analyzer(doc))
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect();
+
+        for &position in positions {
+            let start = position.saturating_sub(window);
+            let end = (position + window + 1).min(tokens.len());
+            for (neighbor_position, neighbor) in tokens.iter().enumerate().take(end).skip(start) {
+                if neighbor_position == position || *neighbor == target {
+                    continue;
+                }
+                *counts.entry(neighbor.clone()).or_default() += 1;
+                if let Some((span_start, span_end)) = index.token_span(*doc_id, neighbor_position) {
+                    let surface = doc.content[span_start..span_end].to_lowercase();
+                    *surface_counts
+                        .entry(neighbor.clone())
+                        .or_default()
+                        .entry(surface)
+                        .or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let mut cooccurrences: Vec<Cooccurrence> = counts
+        .into_iter()
+        .map(|(term, count)| {
+            let surface = surface_counts
+                .get(&term)
+                .and_then(|forms| {
+                    forms
+                        .iter()
+                        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+                        .map(|(form, _)| form.clone())
+                })
+                .unwrap_or_else(|| term.clone());
+            Cooccurrence {
+                term,
+                surface,
+                count,
+            }
+        })
+        .collect();
+    cooccurrences.sort_by(|a, b| b.count.cmp(&a.count).then(a.term.cmp(&b.term)));
+    Ok(cooccurrences)
+}
+
+// Takes up to `width` characters from `chars`, collapsing runs of whitespace
+// (including newlines) into single spaces so context fits on one line.
+fn take_context(chars: impl Iterator<Item = char>, width: usize) -> String {
+    let mut context = String::new();
+    let mut taken = 0;
+    let mut last_was_space = false;
+    for c in chars {
+        if taken == width {
+            break;
+        }
+        if c.is_whitespace() {
+            if last_was_space {
+                continue;
+            }
+            last_was_space = true;
+            context.push(' ');
+        } else {
+            last_was_space = false;
+            context.push(c);
+        }
+        taken += 1;
+    }
+    context
+}
+
+// Every occurrence of `word` with up to `width` characters of context on each
+// side, ordered by path and position.
+pub fn concordance(
+    index: &InvertedIndex,
+    word: &str,
+    width: usize,
+) -> Result<Vec<ConcordanceLine>> {
+    let target = analyze_single_term(word)?;
+    let mut lines = Vec::new();
+    for (doc_id, positions) in index.postings(&target) {
+        let Some(doc) = index.document(*doc_id) else {
+            continue;
+        };
+        for &position in positions {
+            let Some((start, end)) = index.token_span(*doc_id, position) else {
+                continue;
+            };
+            let left: String = take_context(doc.content[..start].chars().rev(), width)
+                .chars()
+                .rev()
+                .collect();
+            let right = take_context(doc.content[end..].chars(), width);
+            lines.push(ConcordanceLine {
+                path: doc.path.clone(),
+                position,
+                left: left.trim_end().to_string(),
+                keyword: doc.content[start..end].to_string(),
+                right: right.trim_start().to_string(),
+            });
+        }
+    }
+    lines.sort_by(|a, b| a.path.cmp(&b.path).then(a.position.cmp(&b.position)));
+    Ok(lines)
+}
+
+// Case-insensitive literal substring search over the stored content, without
+// tokenizing, stemming or stop words; for error messages, code and the like.
+pub fn grep(index: &InvertedIndex, needle: &str) -> Result<Vec<GrepMatch>> {
+    if needle.is_empty() {
+        return Err(anyhow!("grep needs a non-empty search string"));
+    }
+    let pattern = regex::RegexBuilder::new(&regex::escape(needle))
+        .case_insensitive(true)
+        .build()?;
+
+    let mut matches = Vec::new();
+    for doc in index.documents() {
+        let mut line = 1;
+        let mut line_start = 0;
+        let mut scanned = 0;
+        for found in pattern.find_iter(&doc.content) {
+            let before = &doc.content[scanned..found.start()];
+            line += before.matches('\n').count();
+            if let Some(last_newline) = before.rfind('\n') {
+                line_start = scanned + last_newline + 1;
+            }
+            scanned = found.start();
+            let line_end = doc.content[found.start()..]
+                .find('\n')
+                .map_or(doc.content.len(), |idx| found.start() + idx);
+            matches.push(GrepMatch {
+                path: doc.path.clone(),
+                offset: found.start(),
+                line,
+                line_text: doc.content[line_start..line_end].to_string(),
+                line_match: (
+                    found.start() - line_start,
+                    found.end().min(line_end) - line_start,
+                ),
+            });
+        }
+    }
+    matches.sort_by(|a, b| a.path.cmp(&b.path).then(a.offset.cmp(&b.offset)));
+    Ok(matches)
+}
+
+// Lowercased words, one per line, '#' starting a comment
+pub fn load_dictionary(paths: &[PathBuf]) -> Result<HashSet<String>> {
+    let mut words = HashSet::new();
+    for path in paths {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read dictionary {:?}", path))?;
+        for line in raw.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                words.insert(line.to_lowercase());
+            }
+        }
+    }
+    Ok(words)
+}
+
+// The system word list, when there is one
+pub fn default_dictionary() -> Option<PathBuf> {
+    let path = Path::new("/usr/share/dict/words");
+    path.exists().then(|| path.to_path_buf())
+}
+
+// Lowercased words of `text` with their 1-based line. Words with digits, and
+// all-caps acronyms, are left out.
+fn surface_words(text: &str, min_chars: usize) -> impl Iterator<Item = (String, usize)> + '_ {
+    text.lines()
+        .enumerate()
+        .flat_map(move |(line_index, line)| {
+            line.split(|c: char| !c.is_alphanumeric() && c != '\'')
+                .map(|word| word.trim_matches('\''))
+                .filter(move |word| {
+                    word.chars().count() >= min_chars
+                        && word.chars().all(char::is_alphabetic)
+                        && !word.chars().all(char::is_uppercase)
+                })
+                .map(move |word| (word.to_lowercase(), line_index + 1))
+        })
+}
+
+// Likely typos in every document, ordered by path. Without a dictionary only
+// one-off variants of common words are reported; with one, words it doesn't
+// know are too, unless several documents use them.
+pub fn lint_text(
+    index: &InvertedIndex,
+    dictionary: Option<&HashSet<String>>,
+    min_chars: usize,
+) -> Vec<DocumentTypos> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut document_counts: HashMap<String, usize> = HashMap::new();
+    for doc in index.documents() {
+        let mut seen = HashSet::new();
+        for (word, _) in surface_words(&doc.content, min_chars) {
+            *counts.entry(word.clone()).or_default() += 1;
+            if seen.insert(word.clone()) {
+                *document_counts.entry(word).or_default() += 1;
+            }
+        }
+    }
+
+    // Common words by length, the candidate corrections for one-offs
+    let mut common_by_length: HashMap<usize, Vec<(&str, usize)>> = HashMap::new();
+    for (word, count) in &counts {
+        if *count >= MIN_VARIANT_SOURCE_COUNT {
+            common_by_length
+                .entry(word.chars().count())
+                .or_default()
+                .push((word, *count));
+        }
+    }
+    let closest_common = |word: &str| -> Option<String> {
+        let length = word.chars().count();
+        let max_edits = if length >= LONG_WORD_CHARS { 2 } else { 1 };
+        (length.saturating_sub(max_edits)..=length + max_edits)
+            .filter_map(|length| common_by_length.get(&length))
+            .flatten()
+            .filter_map(|(candidate, count)| {
+                let edits = strsim::damerau_levenshtein(word, candidate);
+                (edits <= max_edits).then_some((edits, std::cmp::Reverse(*count), *candidate))
+            })
+            .min()
+            .map(|(_, _, candidate)| candidate.to_string())
+    };
+
+    let mut reports: Vec<DocumentTypos> = Vec::new();
+    for doc in index.documents() {
+        let mut reported = HashSet::new();
+        let mut typos = Vec::new();
+        for (word, line) in surface_words(&doc.content, min_chars) {
+            if reported.contains(&word) {
+                continue;
+            }
+            let known = dictionary.is_none_or(|dictionary| dictionary.contains(&word));
+            let variant_of = (counts[&word] == 1)
+                .then(|| closest_common(&word))
+                .flatten();
+            let kind = if let Some(common) = variant_of {
+                TypoKind::RareVariant(common)
+            } else if !known && document_counts[&word] <= MAX_UNKNOWN_DOCUMENT_FREQUENCY {
+                TypoKind::Unknown
+            } else {
+                continue;
+            };
+            reported.insert(word.clone());
+            typos.push(Typo { word, line, kind });
+        }
+        if !typos.is_empty() {
+            reports.push(DocumentTypos {
+                path: doc.path.clone(),
+                typos,
+            });
+        }
+    }
+    reports.sort_by(|a, b| a.path.cmp(&b.path));
+    reports
+}
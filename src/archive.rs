@@ -0,0 +1,150 @@
+// src/archive.rs
+// Zip and tar archives in a corpus, read in place. Each supported file inside
+// one is indexed as a document of its own, at a path naming the archive and
+// the file within it: `notes.zip!/inbox/todo.md`.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+// --- CONSTANTS ---
+// Ends the archive's component in the paths of the files inside it
+const MEMBER_MARKER: char = '!';
+
+// --- STRUCTS ---
+#[derive(Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn archive_kind(name: &str) -> Option<ArchiveKind> {
+    let name = name.to_lowercase();
+    if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::TarGz)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else {
+        None
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| archive_kind(&name.to_string_lossy()).is_some())
+}
+
+// Where the file at `inner` inside `archive` is indexed
+pub fn member_path(archive: &Path, inner: &Path) -> PathBuf {
+    let mut marked = archive.as_os_str().to_os_string();
+    marked.push(MEMBER_MARKER.to_string());
+    PathBuf::from(marked).join(inner)
+}
+
+// The corpus file a document comes from: the archive for a document inside
+// one, otherwise the document's own path
+pub fn containing_file(path: &Path) -> PathBuf {
+    let mut prefix = PathBuf::new();
+    for component in path.components() {
+        if let Component::Normal(name) = component {
+            let name = name.to_string_lossy();
+            if let Some(archive_name) = name.strip_suffix(MEMBER_MARKER)
+                && archive_kind(archive_name).is_some()
+            {
+                return prefix.join(archive_name);
+            }
+        }
+        prefix.push(component);
+    }
+    path.to_path_buf()
+}
+
+pub fn is_member(path: &Path) -> bool {
+    containing_file(path) != path
+}
+
+// Calls `visit` with the path and contents of each regular file in the
+// archive for which `wanted` holds given its path and size, skipping hidden
+// files and directories (including `__MACOSX`) and entries whose paths would
+// leave the archive. At most `max_bytes` of a file are read, and `visit` is
+// told whether that cut it short.
+pub(crate) fn for_each_file(
+    archive: &Path,
+    max_bytes: Option<u64>,
+    wanted: impl Fn(&Path, u64) -> bool,
+    mut visit: impl FnMut(PathBuf, Vec<u8>, bool) -> Result<()>,
+) -> Result<()> {
+    let kind = archive
+        .file_name()
+        .and_then(|name| archive_kind(&name.to_string_lossy()))
+        .context("Not an archive")?;
+    let file = File::open(archive).context("Failed to open archive")?;
+    let visible = |inner: &Path| {
+        inner.components().all(|component| match component {
+            Component::Normal(name) => {
+                let name = name.to_string_lossy();
+                !name.starts_with('.') && name != "__MACOSX"
+            }
+            _ => false,
+        })
+    };
+
+    match kind {
+        ArchiveKind::Zip => {
+            let mut zip = ZipArchive::new(file).context("Failed to read zip archive")?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).context("Failed to read zip entry")?;
+                let Some(inner) = entry.enclosed_name() else {
+                    continue;
+                };
+                let size = entry.size();
+                if !entry.is_file() || !visible(&inner) || !wanted(&inner, size) {
+                    continue;
+                }
+                let bytes = read_prefix(&mut entry, max_bytes)
+                    .with_context(|| format!("Failed to read {:?} from archive", inner))?;
+                visit(inner, bytes, max_bytes.is_some_and(|max| size > max))?;
+            }
+        }
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            let reader: Box<dyn Read> = match kind {
+                ArchiveKind::TarGz => Box::new(GzDecoder::new(file)),
+                _ => Box::new(file),
+            };
+            let mut tar = tar::Archive::new(reader);
+            for entry in tar.entries().context("Failed to read tar archive")? {
+                let mut entry = entry.context("Failed to read tar entry")?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let inner = entry
+                    .path()
+                    .context("Invalid path in tar archive")?
+                    .into_owned();
+                let size = entry.size();
+                if !visible(&inner) || !wanted(&inner, size) {
+                    continue;
+                }
+                let bytes = read_prefix(&mut entry, max_bytes)
+                    .with_context(|| format!("Failed to read {:?} from archive", inner))?;
+                visit(inner, bytes, max_bytes.is_some_and(|max| size > max))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_prefix(reader: &mut impl Read, max_bytes: Option<u64>) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader
+        .take(max_bytes.unwrap_or(u64::MAX))
+        .read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
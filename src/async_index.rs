@@ -0,0 +1,190 @@
+// src/async_index.rs
+// Async counterparts of the indexing and search entry points, for tokio-based
+// servers: CPU-bound work runs on the blocking thread pool and corpus files are
+// written and read back with tokio::fs, so callers never stall the runtime.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::config::{self, CorpusRoot, IndexingConfig};
+use crate::inverted_index::{Document, IndexingReport, InvertedIndex, ResultPage, SearchResult};
+use crate::query::QueryError;
+
+// --- TYPE ALIASES ---
+// An index shared between request handlers and background refreshes
+pub type SharedIndex = Arc<RwLock<InvertedIndex>>;
+
+// Runs `work` on tokio's blocking thread pool, resuming a panic in the caller
+pub async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(work).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+pub async fn search(index: &SharedIndex, query: &str) -> Result<Vec<SearchResult>, QueryError> {
+    let index = index.clone();
+    let query = query.to_string();
+    run_blocking(move || index.read().unwrap().search(&query)).await
+}
+
+// One page of the results, as `InvertedIndex::search_page`
+pub async fn search_page(
+    index: &SharedIndex,
+    query: &str,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<ResultPage, QueryError> {
+    let index = index.clone();
+    let query = query.to_string();
+    run_blocking(move || index.read().unwrap().search_page(&query, offset, limit)).await
+}
+
+// Indexes `roots` from scratch, within the `[indexing]` limits
+pub async fn build(roots: Vec<CorpusRoot>, indexing: &IndexingConfig) -> Result<InvertedIndex> {
+    let indexing = indexing.clone();
+    run_blocking(move || {
+        let mut index = InvertedIndex::new();
+        index.configure_indexing(&indexing)?;
+        index.load_documents_from_roots(&roots)?;
+        Ok(index)
+    })
+    .await
+}
+
+// Picks up changes in `roots`; searches keep being answered meanwhile (see
+// `InvertedIndex::refresh_shared`).
+pub async fn refresh(index: &SharedIndex, roots: &[CorpusRoot]) -> Result<IndexingReport> {
+    let index = index.clone();
+    let roots = roots.to_vec();
+    run_blocking(move || InvertedIndex::refresh_shared(&index, &roots)).await
+}
+
+// Imports documents received from another machine. Fails without importing
+// anything if one of them comes from a file outside `roots`.
+pub async fn import_documents(
+    index: &SharedIndex,
+    roots: &[CorpusRoot],
+    docs: Vec<Document>,
+) -> Result<IndexingReport> {
+    if let Some(doc) = docs
+        .iter()
+        .find(|doc| !config::within_roots(&InvertedIndex::source_file(doc, roots), roots))
+    {
+        return Err(anyhow!("{:?} is outside the corpus roots", doc.path));
+    }
+    let index = index.clone();
+    Ok(run_blocking(move || index.write().unwrap().import_documents(docs)).await)
+}
+
+// Writes new files into the corpus and indexes them. Fails without indexing
+// anything if one of the paths already exists; files written before the
+// failure are left in place.
+pub async fn add_files(
+    index: &SharedIndex,
+    roots: &[CorpusRoot],
+    files: Vec<(PathBuf, Vec<u8>)>,
+) -> Result<IndexingReport> {
+    run_blocking(move || write_new_files(&files)).await?;
+    refresh(index, roots).await
+}
+
+// Creates each file, failing at the first that already exists
+pub fn write_new_files(files: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+    for (path, bytes) in files {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .with_context(|| format!("Failed to create {:?}", path))?;
+        std::io::Write::write_all(&mut file, bytes)
+            .with_context(|| format!("Failed to save {:?}", path))?;
+    }
+    Ok(())
+}
+
+// Deletes files from the corpus, ignoring ones already gone, and drops their
+// documents from the index. Fails without deleting anything if one of the
+// paths is outside `roots`.
+pub async fn remove_files(
+    index: &SharedIndex,
+    roots: &[CorpusRoot],
+    paths: &[PathBuf],
+) -> Result<IndexingReport> {
+    if let Some(path) = paths.iter().find(|path| !config::within_roots(path, roots)) {
+        return Err(anyhow!("{:?} is outside the corpus roots", path));
+    }
+    for path in paths {
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(format!("Failed to delete {:?}", path)));
+            }
+        }
+    }
+    refresh(index, roots).await
+}
+
+// Loads the index file and replays its journal
+pub async fn load_from_file(index_path: &Path) -> Result<InvertedIndex> {
+    let index_path = index_path.to_path_buf();
+    run_blocking(move || InvertedIndex::load_from_file(&index_path)).await
+}
+
+pub async fn save_to_file(index: &SharedIndex, index_path: &Path) -> Result<()> {
+    let index = index.clone();
+    let index_path = index_path.to_path_buf();
+    run_blocking(move || index.read().unwrap().save_to_file(&index_path)).await
+}
+
+// Journals the documents in `changed_doc_ids` (see `InvertedIndex::save_changes`)
+pub async fn save_changes(
+    index: &SharedIndex,
+    index_path: &Path,
+    changed_doc_ids: Vec<u32>,
+) -> Result<()> {
+    let index = index.clone();
+    let index_path = index_path.to_path_buf();
+    run_blocking(move || {
+        index
+            .read()
+            .unwrap()
+            .save_changes(&index_path, &changed_doc_ids)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn files_outside_the_roots_are_not_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let root_path = dir.path().join("notes");
+        std::fs::create_dir(&root_path).unwrap();
+        let inside = root_path.join("a.md");
+        let outside = dir.path().join("outside.md");
+        std::fs::write(&inside, "# A").unwrap();
+        std::fs::write(&outside, "# B").unwrap();
+        let roots = [CorpusRoot::new(&root_path)];
+        let index: SharedIndex = Arc::new(RwLock::new(InvertedIndex::new()));
+
+        let escaping = root_path.join("../outside.md");
+        assert!(
+            remove_files(&index, &roots, &[inside.clone(), escaping])
+                .await
+                .is_err()
+        );
+        assert!(inside.exists() && outside.exists());
+
+        remove_files(&index, &roots, std::slice::from_ref(&inside))
+            .await
+            .unwrap();
+        assert!(!inside.exists());
+    }
+}
@@ -0,0 +1,310 @@
+// src/cli.rs
+
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use infospark::config::Ranking;
+
+use crate::web_app::Layout;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "infospark",
+    version,
+    about = "In-memory full-text search over your documents"
+)]
+pub struct Cli {
+    /// Path to the config file (defaults to ./infospark.toml when present)
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Corpus directory to index; repeat to index several roots (overrides $INFOSPARK_CORPUS, a
+    /// ':'-separated list, and the config)
+    #[arg(long = "corpus", value_name = "DIR", global = true)]
+    pub corpora: Vec<PathBuf>,
+
+    /// Index file to load and save (defaults to ./search_index.bin)
+    #[arg(long, value_name = "PATH", global = true, env = "INFOSPARK_INDEX")]
+    pub index: Option<PathBuf>,
+
+    /// Rebuild the index without asking when the existing file is unreadable or incompatible
+    #[arg(long, global = true)]
+    pub auto_rebuild: bool,
+
+    /// Number of queries kept in the search cache (overrides the config)
+    #[arg(long, value_name = "N", global = true)]
+    pub cache_capacity: Option<NonZeroUsize>,
+
+    /// Seconds before a cached search result is recomputed (overrides the config)
+    #[arg(long, value_name = "SECS", global = true)]
+    pub cache_ttl: Option<u64>,
+
+    /// Characters of context shown either side of a match in snippets (overrides the config)
+    #[arg(long, value_name = "CHARS", global = true)]
+    pub snippet_context: Option<NonZeroUsize>,
+
+    /// Matching lines shown in snippets of text and source files (overrides the config)
+    #[arg(long, value_name = "N", global = true)]
+    pub snippet_lines: Option<NonZeroUsize>,
+
+    /// Passages around separate matches shown in snippets of other formats (overrides the config)
+    #[arg(long, value_name = "N", global = true)]
+    pub snippet_passages: Option<NonZeroUsize>,
+
+    /// Characters shown of a result without highlighted matches (overrides the config)
+    #[arg(long, value_name = "CHARS", global = true)]
+    pub preview_chars: Option<usize>,
+
+    /// Characters of each graph node's text shown in the web app (overrides the config)
+    #[arg(long, value_name = "CHARS", global = true)]
+    pub graph_preview_chars: Option<usize>,
+
+    /// Edits a `term~` may be away from the words it matches (overrides the config)
+    #[arg(long, value_name = "N", global = true)]
+    pub fuzzy_distance: Option<usize>,
+
+    /// How matches are scored: bm25, or cosine for TF-IDF vector similarity (overrides the config)
+    #[arg(long, value_enum, value_name = "MODE", global = true)]
+    pub rank: Option<Ranking>,
+
+    /// Favor recently modified documents, halving the boost every DAYS (overrides the config)
+    #[arg(long, value_name = "DAYS", global = true)]
+    pub recency_half_life: Option<f64>,
+
+    /// Collapse results at least this alike (0 to 1) under the best ranked (overrides the config)
+    #[arg(long, value_name = "SIMILARITY", global = true)]
+    pub near_duplicates: Option<f64>,
+
+    /// Also show results scoring under the config's `min_score`
+    #[arg(long, global = true)]
+    pub all_results: bool,
+
+    /// Query a serve-mode instance (e.g. http://host:8080) instead of the local index
+    #[arg(long, value_name = "URL", global = true)]
+    pub remote: Option<String>,
+
+    /// Disable the search cache
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+
+    /// Map the index file instead of reading it in full, for fast startup on large indexes
+    /// (overrides the config)
+    #[arg(long, global = true)]
+    pub mmap: bool,
+
+    /// Stop indexing at the first file that cannot be read, instead of reporting it and
+    /// indexing the rest (overrides the config)
+    #[arg(long, global = true)]
+    pub fail_fast: bool,
+
+    /// Index each row of CSV and TSV files as a document of its own (overrides the config)
+    #[arg(long, global = true)]
+    pub rows_as_docs: bool,
+
+    /// Index each `#` and `##` section of Markdown files as a document of its own
+    /// (overrides the config)
+    #[arg(long, global = true)]
+    pub sections_as_docs: bool,
+
+    /// Print long output straight to the terminal instead of through the pager
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+
+    /// HTML template (Tera syntax) to use for the graph & search page instead of the built-in one
+    #[arg(long, value_name = "PATH", global = true)]
+    pub template: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Best match first
+    Relevance,
+    /// Most recently modified first
+    Modified,
+    /// Most recently created first; documents without a creation time come last
+    Created,
+    /// Largest file first
+    Size,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a single query and print the results
+    Search {
+        /// Query, using the same syntax as the interactive prompt; may start
+        /// with '-' to exclude, as in `-#archive notes`
+        #[arg(allow_hyphen_values = true)]
+        query: String,
+        /// Another index file to search alongside the local one; repeatable
+        #[arg(long, value_name = "INDEX_FILE")]
+        federate: Vec<PathBuf>,
+        /// Order of the results
+        #[arg(long, value_enum, default_value_t = SortOrder::Relevance)]
+        sort: SortOrder,
+        /// Also count the results by size, author and language
+        #[arg(long)]
+        facets: bool,
+        /// Show at most N results from one directory before moving on to others
+        #[arg(long, value_name = "N")]
+        max_per_dir: Option<NonZeroUsize>,
+        /// Show at most N results carrying one tag before moving on to others
+        #[arg(long, value_name = "N")]
+        max_per_tag: Option<NonZeroUsize>,
+        /// Show at most N results
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+        /// Skip the first N results, e.g. `--offset 20 --limit 20` for the second page
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        offset: usize,
+    },
+    /// List the documents most like a document, by the terms that set it apart
+    Similar {
+        /// Id of the document, as shown in search results
+        doc_id: u32,
+        /// Maximum number of documents to list
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Run the interactive prompt while indexing changes to the corpus as they happen
+    Watch,
+    /// Stay running and reindex on the schedule from the config's [[daemon.schedule]]
+    Daemon,
+    /// Serve the interactive graph & search web app over HTTP
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+        /// Watch the corpus roots and refresh the index and web data on changes
+        #[arg(long)]
+        watch: bool,
+    },
+    /// List the terms that most often appear near a term
+    Cooccur {
+        /// Term to analyze
+        term: String,
+        /// How many positions on either side count as "near"
+        #[arg(long, default_value_t = 5)]
+        window: usize,
+        /// Maximum number of terms to list
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show every occurrence of a term with its surrounding context (KWIC)
+    Concordance {
+        /// Term to look up
+        term: String,
+        /// Characters of context on each side
+        #[arg(long, default_value_t = 40)]
+        width: usize,
+    },
+    /// Case-insensitive literal substring search over the stored document text
+    Grep {
+        /// Exact text to find; no stemming or stop-word removal is applied
+        pattern: String,
+    },
+    /// Report likely typos per document: words missing from the dictionary and one-off variants of common words
+    LintText {
+        /// Word list to check against, one word per line; repeatable (defaults to /usr/share/dict/words when present)
+        #[arg(long, value_name = "PATH")]
+        dictionary: Vec<PathBuf>,
+        /// Shorter words are not checked
+        #[arg(long, default_value_t = 4)]
+        min_length: usize,
+    },
+    /// Pick a document with an fzf-style fuzzy matcher over titles, paths and tags, and print its path
+    Pick {
+        /// Text to start matching with
+        #[arg(long, short, default_value = "")]
+        query: String,
+    },
+    /// Write a standalone HTML health report of the indexed corpus
+    Report {
+        /// File to write the report to
+        #[arg(long, default_value = "infospark_report.html")]
+        output: PathBuf,
+        /// Open the report in the default browser afterwards
+        #[arg(long)]
+        open: bool,
+    },
+    /// Write the interactive graph & search page to a standalone HTML file
+    Graph {
+        /// Page layout to generate
+        #[arg(long, value_enum, default_value_t = Layout::Full)]
+        layout: Layout,
+        /// File to write the page to
+        #[arg(long, default_value = "infospark_graph.html")]
+        output: PathBuf,
+        /// Open the page in the default browser afterwards
+        #[arg(long)]
+        open: bool,
+    },
+    /// Exchange changed documents with another instance or a shared directory
+    Sync {
+        /// URL of a serve-mode instance (http://host:8080) or a shared directory
+        #[arg(value_name = "REMOTE")]
+        target: String,
+    },
+    /// Add a web page to the URL list (urls.txt) of the first corpus root, then fetch and index it
+    IndexUrl {
+        /// Page to add, an http:// or https:// URL
+        url: String,
+    },
+    /// Write a reproducible synthetic corpus of Markdown documents, for benchmarks and demos
+    GenCorpus {
+        /// Directory to write the documents to; must be empty or not exist yet
+        #[arg(long, default_value = "synthetic_corpus")]
+        output: PathBuf,
+        /// Number of documents
+        #[arg(long, default_value_t = 1000)]
+        docs: usize,
+        /// Number of distinct words, drawn with a Zipf distribution
+        #[arg(long, default_value_t = 10000)]
+        vocab: usize,
+        /// Number of distinct tags
+        #[arg(long, default_value_t = 100)]
+        tags: usize,
+        /// Average document length in words
+        #[arg(long, default_value_t = 300)]
+        words: usize,
+        /// Random seed; the same seed and options always produce the same corpus
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+    /// Manage timestamped copies of the index
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Follow the RSS and Atom feeds listed in the config's `feeds` file
+    #[command(subcommand)]
+    Feeds(FeedsCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SnapshotCommand {
+    /// Copy the current index into a new snapshot
+    Create {
+        /// Short label appended to the snapshot name
+        #[arg(long)]
+        label: Option<String>,
+        /// Also capture metadata stores such as the search history
+        #[arg(long)]
+        with_metadata: bool,
+    },
+    /// List existing snapshots, oldest first
+    List,
+    /// Replace the current index with a snapshot (the current state is snapshotted first)
+    Restore {
+        /// Snapshot name, or a unique prefix of it
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FeedsCommand {
+    /// Fetch every feed, write entries not seen before into the first corpus root and index them
+    Refresh,
+}
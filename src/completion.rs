@@ -0,0 +1,119 @@
+// src/completion.rs
+
+use rustyline::Context;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+
+use crate::inverted_index::InvertedIndex;
+use crate::query::FIELD_NAMES;
+
+// --- CONSTANTS ---
+// A short prefix matches many terms; only the most common are offered
+const MAX_TERM_CANDIDATES: usize = 20;
+
+// --- STRUCTS ---
+// Tab completion for the interactive prompt: `#tag` and `tag:` complete tag
+// names from the index, other words complete field names and indexed terms.
+pub struct QueryHelper {
+    tags: Vec<String>,
+    terms: Vec<(String, usize)>, // Sorted, with the number of documents containing each
+}
+
+impl QueryHelper {
+    pub fn new(index: &InvertedIndex) -> Self {
+        let mut tags: Vec<String> = index.tag_counts().map(|(tag, _)| tag.to_string()).collect();
+        tags.sort();
+        let mut terms: Vec<(String, usize)> = index
+            .term_documents()
+            .map(|(term, doc_ids)| (term.to_string(), doc_ids.count()))
+            .collect();
+        terms.sort();
+        QueryHelper { tags, terms }
+    }
+
+    // Terms starting with `prefix`, the most common first
+    fn term_candidates(&self, prefix: &str) -> Vec<Pair> {
+        let start = self
+            .terms
+            .partition_point(|(term, _)| term.as_str() < prefix);
+        let mut matches: Vec<&(String, usize)> = self.terms[start..]
+            .iter()
+            .take_while(|(term, _)| term.starts_with(prefix))
+            .filter(|(term, _)| term != prefix)
+            .collect();
+        matches.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        matches
+            .into_iter()
+            .take(MAX_TERM_CANDIDATES)
+            .map(|(term, _)| Pair {
+                display: term.clone(),
+                replacement: term.clone(),
+            })
+            .collect()
+    }
+
+    fn tag_candidates(&self, prefix: &str, marker: &str) -> Vec<Pair> {
+        let prefix = prefix.to_lowercase();
+        self.tags
+            .iter()
+            .filter(|tag| tag.starts_with(&prefix))
+            .map(|tag| Pair {
+                display: format!("{}{}", marker, tag),
+                replacement: format!("{}{}", marker, tag),
+            })
+            .collect()
+    }
+}
+
+impl Completer for QueryHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let mut start = line[..pos]
+            .rfind(|c: char| c.is_whitespace() || c == '(')
+            .map_or(0, |i| i + 1);
+        // `-#tag` negates; complete what follows the '-'
+        if line[start..pos].starts_with('-') {
+            start += 1;
+        }
+        let word = &line[start..pos];
+
+        let candidates = if let Some(prefix) = word.strip_prefix('#') {
+            self.tag_candidates(prefix, "#")
+        } else if let Some(prefix) = word.strip_prefix("tag:") {
+            self.tag_candidates(prefix, "tag:")
+        } else if !word.is_empty() && word.chars().all(char::is_alphanumeric) {
+            let word = word.to_lowercase();
+            let mut candidates: Vec<Pair> = FIELD_NAMES
+                .iter()
+                .filter(|field| field.starts_with(&word))
+                .map(|field| Pair {
+                    display: format!("{}:", field),
+                    replacement: format!("{}:", field),
+                })
+                .collect();
+            candidates.extend(self.term_candidates(&word));
+            candidates
+        } else {
+            Vec::new()
+        };
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for QueryHelper {
+    type Hint = String;
+}
+
+impl Highlighter for QueryHelper {}
+
+impl Validator for QueryHelper {}
+
+impl rustyline::Helper for QueryHelper {}
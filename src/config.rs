@@ -0,0 +1,809 @@
+// src/config.rs
+
+use std::collections::HashMap;
+use std::fs;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+
+// --- CONSTANTS ---
+pub const DEFAULT_CONFIG_FILE: &str = "infospark.toml";
+const DEFAULT_CORPUS_DIR: &str = "corpus";
+const DEFAULT_CACHE_CAPACITY: usize = 100;
+const DEFAULT_SNIPPET_CONTEXT_CHARS: usize = 50;
+const DEFAULT_SNIPPET_LINES: usize = 3;
+const DEFAULT_SNIPPET_PASSAGES: usize = 3;
+const DEFAULT_PREVIEW_CHARS: usize = 150;
+const DEFAULT_GRAPH_PREVIEW_CHARS: usize = 300;
+const DEFAULT_PAGE_SIZE: usize = 20;
+const DEFAULT_FUZZY_DISTANCE: usize = 2;
+const DEFAULT_BM25_K1: f64 = 1.2;
+const DEFAULT_BM25_B: f64 = 0.75;
+const DEFAULT_TITLE_BOOST: f64 = 2.0;
+const DEFAULT_TAG_BOOST: f64 = 1.5;
+const DEFAULT_MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+const DEFAULT_MAX_SYNC_BYTES: usize = 256 * 1024 * 1024;
+const DEFAULT_MAX_QUERY_CHARS: usize = 1000;
+const DEFAULT_MAX_SEARCH_RESULTS: usize = 100;
+
+// --- TYPE ALIASES ---
+pub type TagSynonyms = HashMap<String, String>; // Legacy spelling -> canonical tag
+pub type Synonyms = HashMap<String, Vec<String>>; // Query word -> words and phrases it also finds
+
+// --- STRUCTS ---
+// A single directory feeding the index, e.g.
+//
+// [[corpus]]
+// path = "notes/work"
+// name = "work"
+// include = ["*.md", "*.txt"]
+// tag_prefix = "work/"
+// max_depth = 2
+// rows_as_docs = true
+// sections_as_docs = true
+//
+// Subdirectories are indexed too, skipping hidden ones (`.git`, ...).
+// Documents record the root's name as their source, for `source:` queries.
+// With `rows_as_docs`, each row of a CSV or TSV file becomes a document of
+// its own, its cells written out as `header: value` lines. With
+// `sections_as_docs`, each `#` or `##` section of a Markdown file does, and a
+// `##` section links to the `#` section it falls under. JSON files are read
+// through the root's `json` mapping.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorpusRoot {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub name: Option<String>, // Defaults to the directory name
+    #[serde(default)]
+    pub include: Vec<String>, // Glob patterns relative to `path`; empty means every supported file
+    #[serde(default)]
+    pub tag_prefix: Option<String>, // Prepended to every tag extracted from this root
+    #[serde(default)]
+    pub max_depth: Option<usize>, // 1 indexes only files directly in `path`; None has no limit
+    #[serde(default)]
+    pub rows_as_docs: bool, // Index CSV and TSV files a document per row
+    #[serde(default)]
+    pub sections_as_docs: bool, // Index Markdown files a document per `#`/`##` section
+    #[serde(default)]
+    pub json: JsonMapping,
+    #[serde(skip)]
+    pub tag_synonyms: Arc<TagSynonyms>, // From the config's `tag_synonyms` file
+}
+
+// The fields of JSON records (a `.json` object, each object of a `.json`
+// array, or each line of a `.jsonl` file) a root's documents are made from, e.g.
+//
+// [corpus.json]
+// title = "name"
+// content = ["body", "notes"]
+// tags = "labels"
+//
+// Nested fields are reached with dots ("meta.title"). Without `content`,
+// every field is written out as a `field: value` line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JsonMapping {
+    pub title: String,
+    pub content: Vec<String>,
+    pub tags: String, // A list of strings, or one string of comma-separated tags
+}
+
+impl Default for JsonMapping {
+    fn default() -> Self {
+        JsonMapping {
+            title: "title".to_string(),
+            content: Vec::new(),
+            tags: "tags".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReindexMode {
+    Incremental, // Only files whose modified time changed
+    Full,        // Re-extract and compare every file
+}
+
+// A periodic reindex pass run by `infospark daemon`, e.g.
+//
+// [[daemon.schedule]]
+// name = "nightly-verify"
+// cron = "0 3 * * *"
+// mode = "full"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledReindex {
+    pub name: String,
+    pub cron: String, // Standard 5-field cron expression, evaluated in local time
+    pub mode: ReindexMode,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DaemonConfig {
+    #[serde(default)]
+    pub schedule: Vec<ScheduledReindex>,
+    #[serde(default)]
+    pub log_file: Option<PathBuf>,
+}
+
+// Search result cache policy, e.g.
+//
+// [cache]
+// capacity = 500
+// ttl_secs = 300
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub capacity: NonZeroUsize, // Number of cached queries
+    pub ttl_secs: Option<u64>, // Entries older than this are recomputed; None keeps them until evicted
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            capacity: NonZeroUsize::new(DEFAULT_CACHE_CAPACITY).unwrap(),
+            ttl_secs: None,
+        }
+    }
+}
+
+// How much text search results and graph nodes show, e.g.
+//
+// [snippets]
+// context_chars = 80         # either side of a match
+// max_lines = 5              # matching lines shown for line-oriented files
+// max_passages = 2           # separate matches shown for other formats
+// preview_chars = 200        # of a result without highlighted matches
+// graph_preview_chars = 500  # of a graph node's text, in the web app
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnippetConfig {
+    pub context_chars: NonZeroUsize,
+    pub max_lines: NonZeroUsize,
+    pub max_passages: NonZeroUsize,
+    pub preview_chars: usize,
+    pub graph_preview_chars: usize,
+}
+
+impl Default for SnippetConfig {
+    fn default() -> Self {
+        SnippetConfig {
+            context_chars: NonZeroUsize::new(DEFAULT_SNIPPET_CONTEXT_CHARS).unwrap(),
+            max_lines: NonZeroUsize::new(DEFAULT_SNIPPET_LINES).unwrap(),
+            max_passages: NonZeroUsize::new(DEFAULT_SNIPPET_PASSAGES).unwrap(),
+            preview_chars: DEFAULT_PREVIEW_CHARS,
+            graph_preview_chars: DEFAULT_GRAPH_PREVIEW_CHARS,
+        }
+    }
+}
+
+// Which results are listed, and how many at a time at the interactive prompt, e.g.
+//
+// [results]
+// page_size = 10   # results shown per query; `more` shows the next page
+// min_score = 0.5  # results scoring less are left out; 0 by default
+// near_duplicates = 0.8  # collapse results this alike under the best ranked; off by default
+//
+// How alike two documents are is the share of their three-word runs they
+// have in common, so 0.8 collapses a note copied with a few lines changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResultsConfig {
+    pub page_size: NonZeroUsize,
+    pub min_score: f64,
+    pub near_duplicates: Option<f64>, // Similarity from 0 to 1; None lists every result
+}
+
+impl Default for ResultsConfig {
+    fn default() -> Self {
+        ResultsConfig {
+            page_size: NonZeroUsize::new(DEFAULT_PAGE_SIZE).unwrap(),
+            min_score: 0.0,
+            near_duplicates: None,
+        }
+    }
+}
+
+// How queries are matched, e.g.
+//
+// [query]
+// fuzzy_distance = 1          # edits a `term~` may be away from the words it matches
+// synonyms = "synonyms.txt"   # lines like `car = automobile, vehicle`
+// ranking = "cosine"          # how matches are scored; "bm25" by default
+// bm25_k1 = 1.5               # how fast repeats of a term stop adding to a score
+// bm25_b = 0.3                # how much longer documents are penalized, from 0 to 1
+// title_boost = 3.0           # multiplies the score of a term the title has too
+// tag_boost = 1.0             # the same for a term one of the tags has
+// recency_half_life_days = 30 # favor recently modified documents; off by default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryConfig {
+    pub fuzzy_distance: usize,
+    pub synonyms: Option<PathBuf>,
+    pub ranking: Ranking,
+    pub bm25_k1: f64,
+    pub bm25_b: f64,
+    pub title_boost: f64,
+    pub tag_boost: f64,
+    pub recency_half_life_days: Option<f64>,
+    #[serde(skip)]
+    pub synonym_map: Arc<Synonyms>, // Read from `synonyms` on load
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        QueryConfig {
+            fuzzy_distance: DEFAULT_FUZZY_DISTANCE,
+            synonyms: None,
+            ranking: Ranking::default(),
+            bm25_k1: DEFAULT_BM25_K1,
+            bm25_b: DEFAULT_BM25_B,
+            title_boost: DEFAULT_TITLE_BOOST,
+            tag_boost: DEFAULT_TAG_BOOST,
+            recency_half_life_days: None,
+            synonym_map: Arc::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ranking {
+    /// BM25 over the query's terms, favoring shorter documents
+    #[default]
+    Bm25,
+    /// Cosine similarity of the query's and documents' TF-IDF vectors
+    Cosine,
+}
+
+// Resources indexing may use, for small machines with large corpora, e.g.
+//
+// [indexing]
+// threads = 2
+// memory_budget_mb = 256
+// max_file_mb = 100
+// oversized_files = "truncate"
+// duplicates = "link"
+// fail_fast = true
+// mmap = true
+//
+// Files are extracted in batches whose estimated size stays within the budget,
+// each committed to the index before the next is read. The budget bounds the
+// extraction in flight, not the index itself; `mmap` keeps the index on disk
+// and only reads in what searches use. Files over `max_file_mb` are skipped,
+// or for text formats with `oversized_files = "truncate"`, indexed from the
+// lines in their first `max_file_mb`; files inside archives count one by one.
+// Files with the same text as another (mirrored copies of a note) are all
+// indexed by default; `duplicates = "skip"` indexes only the copy seen first,
+// and `"link"` indexes every copy but shows them as one result and one graph
+// node, listing the other copies. A file that fails to extract (a damaged
+// PDF, say) is reported and left as it was while the rest are indexed, unless
+// `fail_fast` stops indexing there.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexingConfig {
+    pub threads: Option<NonZeroUsize>, // Extraction threads; None uses one per CPU
+    pub memory_budget_mb: Option<NonZeroU64>, // None extracts everything at once
+    pub max_file_mb: Option<NonZeroU64>, // None reads files whole however large
+    pub oversized_files: OversizedFiles,
+    pub duplicates: DuplicateFiles,
+    pub fail_fast: bool,
+    pub mmap: bool, // Map the index file rather than reading it in full on startup
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizedFiles {
+    #[default]
+    Skip, // Left out of the index, with a warning
+    Truncate, // Text formats are indexed in part; the rest are skipped
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateFiles {
+    #[default]
+    Keep, // Every copy is indexed and shown
+    Skip, // Copies of an indexed file are left out, with a warning
+    Link, // Copies are indexed, and shown as one
+}
+
+// A score multiplier for documents whose path matches `pattern`, e.g.
+//
+// [[boost]]
+// pattern = "reference/**"
+// multiplier = 2.0
+//
+// Patterns not starting with '/' or '**' may match at any directory depth.
+// The multipliers of every matching pattern are combined.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathBoost {
+    pub pattern: String,
+    pub multiplier: f64, // Above 1.0 boosts, below 1.0 demotes
+}
+
+// The command `open <doc id>` launches, e.g.
+//
+// [editor]
+// command = "code --goto {path}:{line}"
+//
+// `{path}` and `{line}` are substituted; without a command, `$VISUAL` or
+// `$EDITOR` is run as `<editor> +{line} {path}`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EditorConfig {
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+// Paging of long command output (search results, concordances, grep), e.g.
+//
+// [pager]
+// command = "less -R"
+//
+// Without a command, `$PAGER` is used, falling back to `less`. Output only
+// goes through the pager when stdout is a terminal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PagerConfig {
+    pub enabled: bool,
+    pub command: Option<String>,
+}
+
+impl Default for PagerConfig {
+    fn default() -> Self {
+        PagerConfig {
+            enabled: true,
+            command: None,
+        }
+    }
+}
+
+// Cross-origin access to the serve-mode API for separately hosted frontends
+// and browser extensions, e.g.
+//
+// [serve.cors]
+// allowed_origins = ["https://search.example.com", "chrome-extension://abcdef"]
+// allowed_methods = ["GET", "POST"]
+//
+// Without allowed origins, browsers only let same-origin pages use the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>, // "*" allows any origin
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>, // "*" allows any request header
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec!["GET".to_string()],
+            allowed_headers: vec!["content-type".to_string()],
+        }
+    }
+}
+
+// Limits protecting a serve-mode instance exposed beyond localhost, e.g.
+//
+// [serve]
+// requests_per_minute = 120
+// max_upload_bytes = 10485760
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    pub requests_per_minute: Option<u32>, // Per client IP; None disables rate limiting
+    pub max_upload_bytes: usize,          // Per `POST /documents` request
+    pub max_sync_bytes: usize,            // Per `POST /api/sync/push` request
+    pub max_body_bytes: usize,            // Per request with any other body
+    pub max_query_chars: usize,           // Longest accepted search query
+    pub max_results: usize,               // Most results one `/api/search` response holds
+    pub cors: CorsConfig,
+}
+
+impl Default for ServeConfig {
+    fn default() -> Self {
+        ServeConfig {
+            requests_per_minute: None,
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            max_sync_bytes: DEFAULT_MAX_SYNC_BYTES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_query_chars: DEFAULT_MAX_QUERY_CHARS,
+            max_results: DEFAULT_MAX_SEARCH_RESULTS,
+            cors: CorsConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default, rename = "corpus")]
+    pub corpora: Vec<CorpusRoot>,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub snippets: SnippetConfig,
+    #[serde(default)]
+    pub results: ResultsConfig,
+    #[serde(default)]
+    pub query: QueryConfig,
+    #[serde(default)]
+    pub indexing: IndexingConfig,
+    #[serde(default, rename = "boost")]
+    pub boosts: Vec<PathBoost>,
+    #[serde(default)]
+    pub editor: EditorConfig,
+    #[serde(default)]
+    pub pager: PagerConfig,
+    #[serde(default)]
+    pub serve: ServeConfig,
+    // Interactive search history; by default one file per set of corpus roots
+    // under the user's data directory
+    #[serde(default)]
+    pub history_file: Option<PathBuf>,
+    // TOML file folding legacy tag spellings into canonical ones, e.g.
+    // js = "javascript"
+    #[serde(default)]
+    pub tag_synonyms: Option<PathBuf>,
+    // TOML feed list for `feeds refresh`, with a [[feed]] table per RSS or
+    // Atom feed
+    #[serde(default)]
+    pub feeds: Option<PathBuf>,
+    #[serde(skip)]
+    tag_synonym_map: Arc<TagSynonyms>,
+}
+
+impl CorpusRoot {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        CorpusRoot {
+            path: path.into(),
+            name: None,
+            include: Vec::new(),
+            tag_prefix: None,
+            max_depth: None,
+            rows_as_docs: false,
+            sections_as_docs: false,
+            json: JsonMapping::default(),
+            tag_synonyms: Arc::default(),
+        }
+    }
+
+    // The source recorded on this root's documents
+    pub fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self
+                .path
+                .file_name()
+                .unwrap_or(self.path.as_os_str())
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    // Checks a file inside this root against the include patterns.
+    pub fn includes(&self, file_path: &Path) -> bool {
+        if self.include.is_empty() {
+            return true;
+        }
+        let relative_path = file_path.strip_prefix(&self.path).unwrap_or(file_path);
+        self.include.iter().any(|pattern| {
+            glob::Pattern::new(pattern)
+                .map(|p| p.matches_path(relative_path))
+                .unwrap_or(false)
+        })
+    }
+
+    // Lowercases an extracted tag, folds it into its canonical spelling and
+    // applies the root's prefix.
+    pub fn canonical_tag(&self, tag: &str) -> String {
+        let tag = tag.to_lowercase();
+        let tag = self.tag_synonyms.get(&tag).unwrap_or(&tag);
+        match &self.tag_prefix {
+            Some(prefix) => format!("{}{}", prefix, tag).to_lowercase(),
+            None => tag.clone(),
+        }
+    }
+
+    // Whether `path` lies inside this root once symlinks and `..` are
+    // resolved. A path that does not exist (yet) is resolved through its
+    // nearest existing ancestor; an archive member is judged by its archive.
+    pub fn contains(&self, path: &Path) -> bool {
+        let (Some(path), Ok(root)) = (
+            resolve(&archive::containing_file(path)),
+            self.path.canonicalize(),
+        ) else {
+            return false;
+        };
+        path.starts_with(root)
+    }
+}
+
+// Whether `path` lies inside one of `roots` (see `CorpusRoot::contains`)
+pub fn within_roots(path: &Path, roots: &[CorpusRoot]) -> bool {
+    roots.iter().any(|root| root.contains(path))
+}
+
+// `path` made absolute with symlinks resolved, for as much of it as exists.
+// None when the part that does not exist contains `..`, which could climb out
+// of the existing part.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return Some(
+                missing
+                    .iter()
+                    .rev()
+                    .fold(resolved, |resolved, name| resolved.join(name)),
+            );
+        }
+        missing.push(existing.file_name()?);
+        existing = existing.parent()?;
+        if existing.as_os_str().is_empty() {
+            existing = Path::new(".");
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        let mut config: Config = toml::from_str(&raw)
+            .with_context(|| format!("Failed to parse config file {:?}", path))?;
+
+        for root in &config.corpora {
+            for pattern in &root.include {
+                glob::Pattern::new(pattern).with_context(|| {
+                    format!("Invalid include pattern '{}' for {:?}", pattern, root.path)
+                })?;
+            }
+            if root.max_depth == Some(0) {
+                return Err(anyhow!(
+                    "max_depth for {:?} must be at least 1, which indexes only the files directly in it",
+                    root.path
+                ));
+            }
+        }
+        for boost in &config.boosts {
+            glob::Pattern::new(&boost.pattern)
+                .with_context(|| format!("Invalid boost pattern '{}'", boost.pattern))?;
+            if !boost.multiplier.is_finite() || boost.multiplier <= 0.0 {
+                return Err(anyhow!(
+                    "Boost multiplier for '{}' must be a positive number, got {}",
+                    boost.pattern,
+                    boost.multiplier
+                ));
+            }
+        }
+        if config.serve.requests_per_minute == Some(0) {
+            return Err(anyhow!(
+                "serve.requests_per_minute must be at least 1; leave it out to disable rate limiting"
+            ));
+        }
+        if !config.query.bm25_k1.is_finite() || config.query.bm25_k1 < 0.0 {
+            return Err(anyhow!(
+                "query.bm25_k1 must be a number of at least 0, got {}",
+                config.query.bm25_k1
+            ));
+        }
+        if !(0.0..=1.0).contains(&config.query.bm25_b) {
+            return Err(anyhow!(
+                "query.bm25_b must be between 0 and 1, got {}",
+                config.query.bm25_b
+            ));
+        }
+        for (name, boost) in [
+            ("title_boost", config.query.title_boost),
+            ("tag_boost", config.query.tag_boost),
+        ] {
+            if !boost.is_finite() || boost <= 0.0 {
+                return Err(anyhow!(
+                    "query.{} must be a positive number, got {}",
+                    name,
+                    boost
+                ));
+            }
+        }
+        if let Some(half_life) = config.query.recency_half_life_days
+            && (!half_life.is_finite() || half_life <= 0.0)
+        {
+            return Err(anyhow!(
+                "query.recency_half_life_days must be a positive number of days, got {}",
+                half_life
+            ));
+        }
+        if let Some(similarity) = config.results.near_duplicates
+            && !(similarity > 0.0 && similarity <= 1.0)
+        {
+            return Err(anyhow!(
+                "results.near_duplicates must be a similarity above 0 and at most 1, got {}",
+                similarity
+            ));
+        }
+        if let Some(synonyms_path) = &config.tag_synonyms {
+            config.tag_synonym_map = Arc::new(load_tag_synonyms(synonyms_path)?);
+        }
+        if let Some(synonyms_path) = &config.query.synonyms {
+            config.query.synonym_map = Arc::new(load_synonyms(synonyms_path)?);
+        }
+        Ok(config)
+    }
+
+    // Loads the explicitly requested config, or `infospark.toml` from the working
+    // directory when it exists, falling back to the built-in defaults.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => {
+                let default_path = Path::new(DEFAULT_CONFIG_FILE);
+                if default_path.exists() {
+                    Self::load(default_path)
+                } else {
+                    Ok(Config::default())
+                }
+            }
+        }
+    }
+
+    pub fn corpus_roots(&self) -> Vec<CorpusRoot> {
+        if self.corpora.is_empty() {
+            vec![self.corpus_root(DEFAULT_CORPUS_DIR)]
+        } else {
+            self.corpora
+                .iter()
+                .map(|root| CorpusRoot {
+                    tag_synonyms: self.tag_synonym_map.clone(),
+                    ..root.clone()
+                })
+                .collect()
+        }
+    }
+
+    // A root given outside the config (e.g. on the command line), still
+    // subject to the config's tag synonyms.
+    pub fn corpus_root(&self, path: impl Into<PathBuf>) -> CorpusRoot {
+        CorpusRoot {
+            tag_synonyms: self.tag_synonym_map.clone(),
+            ..CorpusRoot::new(path)
+        }
+    }
+}
+
+// Reads lines of `word = synonym, other synonym`, skipping blank lines and
+// `#` comments. A word listed again gets the synonyms of both lines; the
+// mapping is one way, so list each word whose synonyms it should find.
+fn load_synonyms(path: &Path) -> Result<Synonyms> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read synonyms file {:?}", path))?;
+    let mut synonyms = Synonyms::new();
+    for (number, line) in raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line.split_once('=').and_then(|(word, expansions)| {
+            let word = word.trim().to_lowercase();
+            let expansions: Vec<String> = expansions
+                .split(',')
+                .map(|expansion| expansion.trim().to_lowercase())
+                .filter(|expansion| !expansion.is_empty())
+                .collect();
+            let single_word = !word.is_empty() && !word.contains(char::is_whitespace);
+            (single_word && !expansions.is_empty()).then_some((word, expansions))
+        });
+        let Some((word, expansions)) = parsed else {
+            return Err(anyhow!(
+                "Invalid line {} in synonyms file {:?}; expected `word = synonym, ...`",
+                number + 1,
+                path
+            ));
+        };
+        let entry = synonyms.entry(word).or_default();
+        for expansion in expansions {
+            if !entry.contains(&expansion) {
+                entry.push(expansion);
+            }
+        }
+    }
+    Ok(synonyms)
+}
+
+// Reads a `legacy = "canonical"` TOML table. A leading '#' is optional on
+// both sides; chains are rejected so every tag folds in a single step.
+fn load_tag_synonyms(path: &Path) -> Result<TagSynonyms> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read tag synonyms file {:?}", path))?;
+    let entries: HashMap<String, String> = toml::from_str(&raw)
+        .with_context(|| format!("Failed to parse tag synonyms file {:?}", path))?;
+
+    let normalize = |tag: &str| tag.trim().trim_start_matches('#').to_lowercase();
+    let synonyms: TagSynonyms = entries
+        .iter()
+        .map(|(legacy, canonical)| (normalize(legacy), normalize(canonical)))
+        .collect();
+    for (legacy, canonical) in &synonyms {
+        if canonical.is_empty() {
+            return Err(anyhow!("Tag synonym '{}' maps to an empty tag", legacy));
+        }
+        if let Some(next) = synonyms.get(canonical)
+            && next != canonical
+        {
+            return Err(anyhow!(
+                "Tag synonym '{}' -> '{}' continues to '{}'; map '{}' to '{}' directly",
+                legacy,
+                canonical,
+                next,
+                legacy,
+                next
+            ));
+        }
+    }
+    Ok(synonyms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_contained_once_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let root_path = dir.path().join("notes");
+        fs::create_dir(&root_path).unwrap();
+        fs::write(root_path.join("a.md"), "# A").unwrap();
+        let roots = [CorpusRoot::new(&root_path)];
+
+        assert!(within_roots(&root_path.join("a.md"), &roots));
+        assert!(within_roots(&root_path.join("new/b.md"), &roots));
+        assert!(within_roots(&root_path.join("a.zip!/inner.md"), &roots));
+        assert!(!within_roots(&root_path.join("../outside.md"), &roots));
+        assert!(!within_roots(
+            &root_path.join("new/../../outside.md"),
+            &roots
+        ));
+        assert!(!within_roots(&dir.path().join("notes-2/a.md"), &roots));
+        assert!(!within_roots(Path::new("/etc/passwd"), &roots));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.path(), root_path.join("up")).unwrap();
+            assert!(!within_roots(&root_path.join("up/outside.md"), &roots));
+        }
+    }
+    #[test]
+    fn a_max_depth_of_zero_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("infospark.toml");
+        fs::write(
+            &config_path,
+            "[[corpus]]\npath = \"notes\"\nmax_depth = 0\n",
+        )
+        .unwrap();
+        let error = Config::load(&config_path).unwrap_err();
+        assert!(error.to_string().contains("max_depth"), "{:#}", error);
+
+        fs::write(
+            &config_path,
+            "[[corpus]]\npath = \"notes\"\nmax_depth = 1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Config::load(&config_path).unwrap().corpora[0].max_depth,
+            Some(1)
+        );
+    }
+}
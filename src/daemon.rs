@@ -0,0 +1,142 @@
+// src/daemon.rs
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::{DateTime, Local};
+use cron::Schedule;
+
+use crate::config::{CorpusRoot, DaemonConfig, ReindexMode, ScheduledReindex};
+use crate::inverted_index::{IndexingReport, InvertedIndex};
+
+struct DaemonLog {
+    file: Option<File>,
+}
+
+impl DaemonLog {
+    fn open(path: Option<&Path>) -> Result<Self> {
+        let file = match path {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open daemon log {:?}", path))?,
+            ),
+            None => None,
+        };
+        Ok(DaemonLog { file })
+    }
+
+    fn line(&mut self, message: &str) {
+        let line = format!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+        println!("{}", line);
+        if let Some(file) = &mut self.file
+            && let Err(e) = writeln!(file, "{}", line)
+        {
+            eprintln!("Failed to write daemon log: {:?}", e);
+        }
+    }
+
+    fn report(&mut self, job: &ScheduledReindex, report: &IndexingReport) {
+        self.line(&format!("{}: {}", job.name, report.summary()));
+        for path in &report.added {
+            self.line(&format!("  + {}", path.display()));
+        }
+        for path in &report.updated {
+            self.line(&format!("  ~ {}", path.display()));
+        }
+        for path in &report.removed {
+            self.line(&format!("  - {}", path.display()));
+        }
+        for warning in report.warnings() {
+            self.line(&format!("  ! {}", warning));
+        }
+        for failure in report.failures() {
+            self.line(&format!("  ! {}", failure));
+        }
+    }
+}
+
+// Cron expressions in the config use the usual 5 fields; the cron crate
+// expects a leading seconds field.
+fn parse_schedule(job: &ScheduledReindex) -> Result<Schedule> {
+    Schedule::from_str(&format!("0 {}", job.cron))
+        .map_err(|e| anyhow!("{}", e))
+        .with_context(|| format!("Invalid cron expression '{}' for '{}'", job.cron, job.name))
+}
+
+pub fn run_daemon(
+    mut index: InvertedIndex,
+    corpus_roots: &[CorpusRoot],
+    daemon_config: &DaemonConfig,
+    index_path: &Path,
+) -> Result<()> {
+    if daemon_config.schedule.is_empty() {
+        return Err(anyhow!(
+            "No reindex schedule configured; add [[daemon.schedule]] entries to the config"
+        ));
+    }
+
+    let mut jobs: Vec<(&ScheduledReindex, Schedule)> = Vec::new();
+    for job in &daemon_config.schedule {
+        jobs.push((job, parse_schedule(job)?));
+    }
+
+    let mut log = DaemonLog::open(daemon_config.log_file.as_deref())?;
+    let mut next_runs: Vec<Option<DateTime<Local>>> = jobs
+        .iter()
+        .map(|(_, schedule)| schedule.upcoming(Local).next())
+        .collect();
+
+    for ((job, _), next_run) in jobs.iter().zip(&next_runs) {
+        if let Some(next_run) = next_run {
+            log.line(&format!(
+                "Scheduled '{}' ({:?}, '{}'), next run at {}",
+                job.name,
+                job.mode,
+                job.cron,
+                next_run.format("%Y-%m-%d %H:%M")
+            ));
+        }
+    }
+
+    loop {
+        let Some((job_idx, next_run)) = next_runs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, run)| run.map(|run| (i, run)))
+            .min_by_key(|(_, run)| *run)
+        else {
+            log.line("No upcoming runs left in the schedule. Exiting.");
+            return Ok(());
+        };
+
+        let wait = (next_run - Local::now()).to_std().unwrap_or_default();
+        thread::sleep(wait);
+
+        let (job, schedule) = &jobs[job_idx];
+        let result = match job.mode {
+            ReindexMode::Incremental => index.load_documents_from_roots(corpus_roots),
+            ReindexMode::Full => index.verify_documents_from_roots(corpus_roots),
+        };
+        match result {
+            Ok(report) => {
+                log.report(job, &report);
+                if report.has_changes() {
+                    match index.save_changes(index_path, &report.changed_doc_ids) {
+                        Ok(()) => log.line(&format!("Index saved to {:?}", index_path)),
+                        Err(e) => log.line(&format!("Failed to save index: {:#}", e)),
+                    }
+                }
+            }
+            Err(e) => log.line(&format!("{}: reindex failed: {:#}", job.name, e)),
+        }
+
+        next_runs[job_idx] = schedule.after(&Local::now()).next();
+    }
+}
@@ -0,0 +1,46 @@
+// src/editor.rs
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::config::EditorConfig;
+
+// Opens `path` at `line` (1-based; `None` opens at the top) with the
+// configured command template, or `$VISUAL` / `$EDITOR` followed by
+// `+{line} {path}`.
+pub fn open_in_editor(config: &EditorConfig, path: &Path, line: Option<usize>) -> Result<()> {
+    let template = match &config.command {
+        Some(command) => command.clone(),
+        None => {
+            let editor = env::var("VISUAL")
+                .or_else(|_| env::var("EDITOR"))
+                .ok()
+                .filter(|editor| !editor.trim().is_empty())
+                .ok_or_else(|| {
+                    anyhow!("No editor configured: set $EDITOR or `command` under [editor]")
+                })?;
+            format!("{} +{{line}} {{path}}", editor)
+        }
+    };
+
+    let path = path.to_string_lossy();
+    let line = line.unwrap_or(1).to_string();
+    let mut args = template
+        .split_whitespace()
+        .map(|arg| arg.replace("{path}", &path).replace("{line}", &line));
+    let program = args
+        .next()
+        .ok_or_else(|| anyhow!("The editor command is empty"))?;
+
+    let status = Command::new(&program)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to launch editor '{}'", program))?;
+    if !status.success() {
+        return Err(anyhow!("Editor '{}' exited with {}", program, status));
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+// src/extractor.rs
+// The formats documents are extracted from. Each file extension is read by a
+// `ContentExtractor`: the built-in formats are registered from the start, and
+// programs using the library can `register` their own before indexing, for
+// new extensions or in place of a built-in extractor.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+
+use crate::inverted_index::{ExtractedMetadata, InvertedIndex, SOURCE_CODE_EXTENSIONS, read_text};
+use crate::{latex, opendocument};
+
+// --- TYPE ALIASES ---
+type ExtractFn = fn(&Path, Option<u64>) -> Result<(String, ExtractedMetadata)>;
+
+// --- STRUCTS ---
+// A document's text, and whatever metadata its format carries
+#[derive(Default)]
+pub struct ExtractedDoc {
+    pub content: String,
+    pub metadata: ExtractedMetadata,
+}
+
+impl From<(String, ExtractedMetadata)> for ExtractedDoc {
+    fn from((content, metadata): (String, ExtractedMetadata)) -> Self {
+        ExtractedDoc { content, metadata }
+    }
+}
+
+pub trait ContentExtractor: Send + Sync {
+    // Extensions of the files it reads, without the dot (`"org"`)
+    fn extensions(&self) -> &[&str];
+
+    // The file's text. With `max_bytes`, a file longer than that is to be
+    // read from its start only; extractors that cannot are never given one.
+    fn extract(&self, path: &Path, max_bytes: Option<u64>) -> Result<ExtractedDoc>;
+
+    // Whether `extract` honors `max_bytes`, so files over `max_file_mb` can
+    // be indexed in part under `oversized_files = "truncate"`
+    fn can_truncate(&self) -> bool {
+        false
+    }
+}
+
+// A built-in format, read by a plain function
+struct BuiltIn {
+    extensions: &'static [&'static str],
+    truncatable: bool,
+    extract: ExtractFn,
+}
+
+impl ContentExtractor for BuiltIn {
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+
+    fn extract(&self, path: &Path, max_bytes: Option<u64>) -> Result<ExtractedDoc> {
+        (self.extract)(path, max_bytes).map(ExtractedDoc::from)
+    }
+
+    fn can_truncate(&self) -> bool {
+        self.truncatable
+    }
+}
+
+lazy_static::lazy_static! {
+    // Extension -> the extractor registered for it last, built in or not
+    static ref EXTRACTORS: RwLock<HashMap<String, Arc<dyn ContentExtractor>>> = {
+        let mut extractors = HashMap::new();
+        for extractor in built_in() {
+            insert(&mut extractors, extractor);
+        }
+        RwLock::new(extractors)
+    };
+}
+
+// Makes `extractor` read the files with its extensions from now on, taking
+// over from the extractor registered for any of them before
+pub fn register(extractor: impl ContentExtractor + 'static) {
+    let mut extractors = EXTRACTORS.write().unwrap();
+    insert(&mut extractors, Arc::new(extractor));
+}
+
+fn insert(
+    extractors: &mut HashMap<String, Arc<dyn ContentExtractor>>,
+    extractor: Arc<dyn ContentExtractor>,
+) {
+    for extension in extractor.extensions() {
+        extractors.insert(extension.to_string(), extractor.clone());
+    }
+}
+
+// The extractor for a file, by its extension
+pub fn for_path(path: &Path) -> Option<Arc<dyn ContentExtractor>> {
+    let extension = path.extension()?.to_str()?;
+    EXTRACTORS.read().unwrap().get(extension).cloned()
+}
+
+fn built_in() -> Vec<Arc<dyn ContentExtractor>> {
+    let formats = [
+        BuiltIn {
+            extensions: &["txt"],
+            truncatable: true,
+            extract: |path, max_bytes| {
+                let text = read_text(path, max_bytes).context("Failed to read text file")?;
+                Ok((text, ExtractedMetadata::default()))
+            },
+        },
+        BuiltIn {
+            extensions: &["csv", "tsv"],
+            truncatable: true,
+            extract: |path, max_bytes| {
+                let text = read_text(path, max_bytes).context("Failed to read table file")?;
+                Ok((text, ExtractedMetadata::default()))
+            },
+        },
+        BuiltIn {
+            extensions: &SOURCE_CODE_EXTENSIONS,
+            truncatable: true,
+            extract: |path, max_bytes| {
+                let text = read_text(path, max_bytes).context("Failed to read source file")?;
+                Ok((text, ExtractedMetadata::default()))
+            },
+        },
+        // A JSON document is only readable whole; JSONL line by line
+        BuiltIn {
+            extensions: &["json"],
+            truncatable: false,
+            extract: |path, max_bytes| {
+                let text = read_text(path, max_bytes).context("Failed to read JSON file")?;
+                Ok((text, ExtractedMetadata::default()))
+            },
+        },
+        BuiltIn {
+            extensions: &["jsonl"],
+            truncatable: true,
+            extract: |path, max_bytes| {
+                let text = read_text(path, max_bytes).context("Failed to read JSON file")?;
+                Ok((text, ExtractedMetadata::default()))
+            },
+        },
+        BuiltIn {
+            extensions: &["md"],
+            truncatable: true,
+            extract: |path, max_bytes| {
+                let text = read_text(path, max_bytes).context("Failed to read markdown file")?;
+                let metadata = ExtractedMetadata {
+                    title: InvertedIndex::markdown_title(&text),
+                    ..Default::default()
+                };
+                Ok((text, metadata))
+            },
+        },
+        BuiltIn {
+            extensions: &["html"],
+            truncatable: true,
+            extract: |path, max_bytes| {
+                let html = read_text(path, max_bytes).context("Failed to read HTML file")?;
+                Ok(InvertedIndex::extract_html(&html))
+            },
+        },
+        BuiltIn {
+            extensions: &["tex"],
+            truncatable: true,
+            extract: |path, max_bytes| {
+                let source = read_text(path, max_bytes).context("Failed to read LaTeX file")?;
+                Ok(latex::extract(&source))
+            },
+        },
+        BuiltIn {
+            extensions: &["pdf"],
+            truncatable: false,
+            extract: |path, _| InvertedIndex::extract_text_from_pdf(path),
+        },
+        BuiltIn {
+            extensions: &opendocument::EXTENSIONS,
+            truncatable: false,
+            extract: |path, _| opendocument::extract(path),
+        },
+    ];
+    formats
+        .into_iter()
+        .map(|format| Arc::new(format) as Arc<dyn ContentExtractor>)
+        .collect()
+}
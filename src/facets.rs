@@ -0,0 +1,84 @@
+// src/facets.rs
+//
+// Counts of search results by document properties (source, size, author,
+// language), for slicing mixed corpora by more than their tags.
+
+use std::collections::HashMap;
+
+use crate::inverted_index::ResultSummary;
+
+// --- CONSTANTS ---
+// Labels and exclusive upper bounds of the size buckets, smallest first
+const SIZE_BUCKETS: [(&str, u64); 4] = [
+    ("< 10 KB", 10 << 10),
+    ("10 KB - 100 KB", 100 << 10),
+    ("100 KB - 1 MB", 1 << 20),
+    ("1 MB - 10 MB", 10 << 20),
+];
+const LARGEST_SIZE_BUCKET: &str = ">= 10 MB";
+const UNKNOWN_VALUE: &str = "(unknown)";
+
+// --- STRUCTS ---
+pub struct Facet {
+    pub name: &'static str,
+    pub values: Vec<(String, usize)>, // Value and number of results, empty values left out
+}
+
+fn size_bucket(size: u64) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(_, upper)| size < *upper)
+        .map_or(LARGEST_SIZE_BUCKET, |(label, _)| label)
+}
+
+// Values ordered by count, most common first; documents lacking the property
+// are counted under "(unknown)".
+fn count_values<'a>(values: impl Iterator<Item = Option<&'a str>>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value.unwrap_or(UNKNOWN_VALUE)).or_default() += 1;
+    }
+    let mut values: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(value, count)| (value.to_string(), count))
+        .collect();
+    values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    values
+}
+
+pub fn facets(results: &[&ResultSummary]) -> Vec<Facet> {
+    let mut size_counts: HashMap<&str, usize> = HashMap::new();
+    for result in results {
+        *size_counts.entry(size_bucket(result.size)).or_default() += 1;
+    }
+    // Size buckets keep their natural order rather than being ranked by count
+    let sizes = SIZE_BUCKETS
+        .iter()
+        .map(|(label, _)| *label)
+        .chain([LARGEST_SIZE_BUCKET])
+        .filter_map(|label| {
+            size_counts
+                .get(label)
+                .map(|count| (label.to_string(), *count))
+        })
+        .collect();
+
+    vec![
+        Facet {
+            name: "Source",
+            values: count_values(results.iter().map(|result| Some(result.source.as_str()))),
+        },
+        Facet {
+            name: "Size",
+            values: sizes,
+        },
+        Facet {
+            name: "Author",
+            values: count_values(results.iter().map(|result| result.author.as_deref())),
+        },
+        Facet {
+            name: "Language",
+            values: count_values(results.iter().map(|result| result.language.as_deref())),
+        },
+    ]
+}
@@ -0,0 +1,68 @@
+// src/federated.rs
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::inverted_index::{InvertedIndex, SearchResult};
+use crate::query::QueryError;
+
+// --- STRUCTS ---
+pub struct IndexSource {
+    pub label: String,
+    pub index: InvertedIndex,
+}
+
+pub struct FederatedResult {
+    pub source: String,
+    pub score: f64, // Normalized across sources, in (0, 1]
+    pub result: SearchResult,
+}
+
+impl IndexSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        let index = InvertedIndex::load_from_file(path)
+            .with_context(|| format!("Failed to open federated index {:?}", path))?;
+        Ok(IndexSource {
+            label: path.display().to_string(),
+            index,
+        })
+    }
+}
+
+// Runs `query` against every source and merges the results. BM25 scores are
+// only comparable within one collection, so each source's scores are divided
+// by its best score before merging.
+pub fn federated_search(
+    sources: &[IndexSource],
+    query: &str,
+) -> Result<Vec<FederatedResult>, QueryError> {
+    let mut merged: Vec<(usize, FederatedResult)> = Vec::new();
+    for (source_idx, source) in sources.iter().enumerate() {
+        let results = source.index.search(query)?;
+        let top_score = results
+            .iter()
+            .map(|r| r.score)
+            .fold(0.0_f64, f64::max)
+            .max(f64::EPSILON);
+        merged.extend(results.into_iter().map(|result| {
+            (
+                source_idx,
+                FederatedResult {
+                    source: source.label.clone(),
+                    score: result.score / top_score,
+                    result,
+                },
+            )
+        }));
+    }
+
+    merged.sort_by(|(a_source, a), (b_source, b)| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a_source.cmp(b_source))
+            .then(a.result.doc.id.cmp(&b.result.doc.id))
+    });
+    Ok(merged.into_iter().map(|(_, result)| result).collect())
+}
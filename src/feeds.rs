@@ -0,0 +1,336 @@
+// src/feeds.rs
+// RSS and Atom subscriptions. `feeds refresh` fetches every feed in the
+// config's feed list and writes each entry it has not seen yet into the corpus
+// as an HTML file, so posts are indexed (and kept) like any other document.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, anyhow};
+use chrono::DateTime;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+use serde::Deserialize;
+
+use infospark::config::CorpusRoot;
+use infospark::inverted_index::fnv1a;
+use infospark::tag_sidecar::{self, TagOverrides};
+
+// --- CONSTANTS ---
+// Entries are written to `<first corpus root>/feeds/<feed name>/`
+pub const FEEDS_DIR: &str = "feeds";
+const MAX_SLUG_CHARS: usize = 60;
+
+// --- STRUCTS ---
+// The feed list the config's `feeds` key points at, e.g.
+//
+// [[feed]]
+// url = "https://blog.rust-lang.org/feed.xml"
+// name = "rust-blog"   # directory the entries go to; defaults to the feed's host
+// tags = ["rust"]      # given to every entry, besides its categories
+#[derive(Debug, Deserialize)]
+struct FeedList {
+    #[serde(default, rename = "feed")]
+    feeds: Vec<Feed>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Feed {
+    url: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    id: Option<String>, // RSS guid or Atom id
+    title: Option<String>,
+    link: Option<String>,
+    author: Option<String>,
+    published: Option<String>,
+    content: Option<String>, // HTML
+    categories: Vec<String>,
+}
+
+pub struct FeedReport {
+    pub name: String,
+    pub written: Vec<PathBuf>,
+    pub error: Option<anyhow::Error>,
+}
+
+// Fetches every feed in the list at `feeds_file`, writing new entries into
+// `root`. A feed that cannot be fetched or parsed is reported without
+// stopping the others.
+pub fn refresh(feeds_file: &Path, root: &CorpusRoot) -> Result<Vec<FeedReport>> {
+    let raw = fs::read_to_string(feeds_file)
+        .with_context(|| format!("Failed to read feed list {:?}", feeds_file))?;
+    let list: FeedList = toml::from_str(&raw)
+        .with_context(|| format!("Failed to parse feed list {:?}", feeds_file))?;
+
+    let mut reports = Vec::new();
+    for feed in &list.feeds {
+        let name = match &feed.name {
+            Some(name) => name.clone(),
+            None => host_of(&feed.url),
+        };
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(anyhow!("Invalid name {:?} for feed {}", name, feed.url));
+        }
+        let dir = root.path.join(FEEDS_DIR).join(&name);
+        let mut report = FeedReport {
+            name,
+            written: Vec::new(),
+            error: None,
+        };
+        if let Err(e) = refresh_feed(feed, root, &dir, &mut report.written) {
+            report.error = Some(e.context(format!("Failed to refresh feed {}", feed.url)));
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+fn refresh_feed(
+    feed: &Feed,
+    root: &CorpusRoot,
+    dir: &Path,
+    written: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let xml = ureq::get(&feed.url)
+        .call()
+        .context("Failed to fetch feed")?
+        .body_mut()
+        .read_to_string()
+        .context("Failed to read feed")?;
+    let entries = parse_entries(&xml).context("Failed to parse feed")?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    for entry in entries {
+        let path = dir.join(file_name(&entry));
+        // Entries seen before are left as they are, edits and all
+        if path.exists() {
+            continue;
+        }
+        fs::write(&path, entry_html(&entry))
+            .with_context(|| format!("Failed to write {:?}", path))?;
+        // Kept in the entry's sidecar, as the index would show them
+        let mut overrides = TagOverrides::default();
+        for tag in feed.tags.iter().chain(&entry.categories) {
+            let tag = tag.split_whitespace().collect::<Vec<_>>().join("-");
+            let tag = root.canonical_tag(tag.trim_start_matches('#'));
+            if !tag.is_empty() && !overrides.added.contains(&tag) {
+                overrides.added.push(tag);
+            }
+        }
+        overrides.save(&path)?;
+        // Dated like the post, for `modified:` queries and sorting; the
+        // sidecar too, as the newer of the two dates the document
+        if let Some(published) = entry.published.as_deref().and_then(parse_date) {
+            let sidecar = tag_sidecar::sidecar_path(&path);
+            for file in [&path, &sidecar].into_iter().filter(|file| file.exists()) {
+                fs::File::options()
+                    .write(true)
+                    .open(file)
+                    .and_then(|file| file.set_modified(published))
+                    .with_context(|| format!("Failed to set the modified time of {:?}", file))?;
+            }
+        }
+        written.push(path);
+    }
+    Ok(())
+}
+
+fn host_of(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#', ':']).next().unwrap_or_default();
+    host.trim_start_matches("www.").to_string()
+}
+
+// `<title slug>-<hash of the entry's id>.html`, stable across refreshes
+fn file_name(entry: &Entry) -> String {
+    let key = entry
+        .id
+        .as_ref()
+        .or(entry.link.as_ref())
+        .or(entry.title.as_ref())
+        .cloned()
+        .unwrap_or_default();
+    let mut slug = String::new();
+    for c in entry.title.as_deref().unwrap_or("entry").chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.chars().count() >= MAX_SLUG_CHARS {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let slug = if slug.is_empty() { "entry" } else { slug };
+    format!("{}-{:08x}.html", slug, fnv1a(key.as_bytes()) as u32)
+}
+
+fn parse_date(date: &str) -> Option<SystemTime> {
+    let date = date.trim();
+    DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .ok()
+        .map(SystemTime::from)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// A page the HTML extractor reads the title, author and text from; the link
+// to the original post is kept in the head, out of the indexed text
+fn entry_html(entry: &Entry) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    if let Some(title) = &entry.title {
+        html.push_str(&format!("<title>{}</title>\n", escape(title)));
+    }
+    if let Some(author) = &entry.author {
+        html.push_str(&format!(
+            "<meta name=\"author\" content=\"{}\">\n",
+            escape(author)
+        ));
+    }
+    if let Some(link) = &entry.link {
+        html.push_str(&format!(
+            "<link rel=\"canonical\" href=\"{}\">\n",
+            escape(link)
+        ));
+    }
+    html.push_str("</head>\n<body>\n");
+    if let Some(title) = &entry.title {
+        html.push_str(&format!("<h1>{}</h1>\n", escape(title)));
+    }
+    html.push_str(entry.content.as_deref().unwrap_or_default());
+    html.push_str("\n</body>\n</html>\n");
+    html
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    let attribute = element.try_get_attribute(name).ok()??;
+    attribute
+        .normalized_value(XmlVersion::default())
+        .ok()
+        .map(|value| value.into_owned())
+}
+
+// The items of an RSS 2.0 or 1.0 feed, or the entries of an Atom feed
+fn parse_entries(xml: &str) -> Result<Vec<Entry>> {
+    let mut reader = Reader::from_str(xml);
+    let mut entries = Vec::new();
+    let mut entry: Option<Entry> = None;
+    // Local name of the entry's child being read, and the text read so far
+    let mut field: Option<String> = None;
+    let mut depth = 0usize; // Below the field's element, e.g. Atom's xhtml content
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                let name = element.local_name().as_ref().to_string();
+                match (&mut entry, &field) {
+                    (None, _) if name == "item" || name == "entry" => {
+                        entry = Some(Entry::default())
+                    }
+                    (Some(entry), None) => {
+                        match name.as_str() {
+                            "link" => take_atom_link(entry, &element),
+                            "category" => entry.categories.extend(attribute(&element, "term")),
+                            _ => {}
+                        }
+                        field = Some(name);
+                        text.clear();
+                    }
+                    (Some(_), Some(_)) => depth += 1,
+                    _ => {}
+                }
+            }
+            Event::Empty(element) => {
+                if let Some(entry) = &mut entry
+                    && field.is_none()
+                {
+                    match element.local_name().as_ref() {
+                        "link" => take_atom_link(entry, &element),
+                        "category" => entry.categories.extend(attribute(&element, "term")),
+                        _ => {}
+                    }
+                }
+            }
+            Event::End(element) => {
+                let name = element.local_name().as_ref().to_string();
+                match (&mut entry, field.as_deref()) {
+                    (Some(_), Some(_)) if depth > 0 => depth -= 1,
+                    (Some(entry), Some(current)) if current == name => {
+                        let value = std::mem::take(&mut text).trim().to_string();
+                        set_field(entry, current, value);
+                        field = None;
+                    }
+                    (Some(_), None) if name == "item" || name == "entry" => {
+                        entries.extend(entry.take());
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(chunk) if field.is_some() => text.push_str(&chunk.xml10_content()),
+            Event::CData(chunk) if field.is_some() => text.push_str(&chunk),
+            Event::GeneralRef(reference) if field.is_some() => {
+                if let Some(c) = reference.resolve_char_ref()? {
+                    text.push(c);
+                } else if let Some(entity) = resolve_predefined_entity(&reference) {
+                    text.push_str(entity);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+// Atom links are attributes; the first alternate (or unlabelled) one wins
+fn take_atom_link(entry: &mut Entry, element: &BytesStart) {
+    let rel = attribute(element, "rel");
+    if entry.link.is_none() && rel.as_deref().is_none_or(|rel| rel == "alternate") {
+        entry.link = attribute(element, "href");
+    }
+}
+
+fn set_field(entry: &mut Entry, name: &str, value: String) {
+    if value.is_empty() {
+        return;
+    }
+    let slot = match name {
+        "title" => &mut entry.title,
+        "link" => &mut entry.link, // RSS; Atom's are empty elements
+        "guid" | "id" => &mut entry.id,
+        // Atom's `<author><name>` is read whole
+        "author" | "creator" => &mut entry.author,
+        "pubDate" | "published" | "date" => &mut entry.published,
+        "updated" if entry.published.is_none() => &mut entry.published,
+        // Full text is preferred over summaries
+        "encoded" | "content" => {
+            entry.content = Some(value);
+            return;
+        }
+        "description" | "summary" => &mut entry.content,
+        // RSS; Atom's are in the `term` attribute
+        "category" => {
+            entry.categories.push(value);
+            return;
+        }
+        _ => return,
+    };
+    if slot.is_none() {
+        *slot = Some(value);
+    }
+}
@@ -0,0 +1,208 @@
+// src/gen_corpus.rs
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow};
+
+// --- CONSTANTS ---
+// Every syllable is two letters, so distinct word numbers always spell distinct words
+const CONSONANTS: &[u8] = b"bdfgklmnprstvz";
+const VOWELS: &[u8] = b"aiou";
+const BASE_TIME: u64 = 1_577_836_800; // 2020-01-01, the earliest generated modified time
+const TIME_SPAN_SECS: u64 = 5 * 365 * 24 * 3600;
+const MAX_TAGS_PER_DOC: usize = 4;
+const MAX_LINKS_PER_DOC: usize = 3;
+
+// --- STRUCTS ---
+pub struct CorpusSpec {
+    pub docs: usize,
+    pub vocab: usize,
+    pub tags: usize,
+    pub words_per_doc: usize,
+    pub seed: u64,
+}
+
+pub struct GeneratedCorpus {
+    pub documents: usize,
+    pub bytes: u64,
+}
+
+// SplitMix64: small, fast and identical on every platform, which is all
+// reproducibility needs.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn between(&mut self, low: usize, high: usize) -> usize {
+        low + self.below(high - low + 1)
+    }
+}
+
+// Samples ranks 0..n with probability proportional to 1 / (rank + 1), the
+// frequency curve of natural-language vocabularies.
+struct Zipf {
+    cumulative: Vec<f64>,
+}
+
+impl Zipf {
+    fn new(n: usize) -> Self {
+        let mut total = 0.0;
+        let cumulative = (1..=n)
+            .map(|rank| {
+                total += 1.0 / rank as f64;
+                total
+            })
+            .collect();
+        Zipf { cumulative }
+    }
+
+    fn sample(&self, rng: &mut Rng) -> usize {
+        let target = rng.next_f64() * self.cumulative.last().copied().unwrap_or(0.0);
+        self.cumulative
+            .partition_point(|weight| *weight < target)
+            .min(self.cumulative.len() - 1)
+    }
+}
+
+// The `n`th word of the synthetic language, at least two syllables long
+fn synthetic_word(mut n: usize) -> String {
+    let syllables = CONSONANTS.len() * VOWELS.len();
+    n += syllables;
+    let mut word = Vec::new();
+    while n > 0 {
+        let syllable = n % syllables;
+        word.push(CONSONANTS[syllable / VOWELS.len()]);
+        word.push(VOWELS[syllable % VOWELS.len()]);
+        n /= syllables;
+    }
+    String::from_utf8(word).unwrap()
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn file_name(doc: usize, width: usize) -> String {
+    format!("doc_{:0width$}.md", doc, width = width)
+}
+
+// Writes `spec.docs` Markdown documents into `output`, which must be empty or
+// missing. Each has a title, Zipf-distributed body text, a few `#tags` and
+// links to earlier documents; modified times are spread over five years from
+// 2020. The same spec always produces byte-identical files.
+pub fn generate_corpus(output: &Path, spec: &CorpusSpec) -> Result<GeneratedCorpus> {
+    if spec.docs == 0 || spec.vocab == 0 || spec.tags == 0 || spec.words_per_doc == 0 {
+        return Err(anyhow!(
+            "--docs, --vocab, --tags and --words must all be positive"
+        ));
+    }
+    if output.exists()
+        && fs::read_dir(output)
+            .with_context(|| format!("Failed to read {:?}", output))?
+            .next()
+            .is_some()
+    {
+        return Err(anyhow!(
+            "{:?} is not empty; pick a new directory for the corpus",
+            output
+        ));
+    }
+    fs::create_dir_all(output).with_context(|| format!("Failed to create {:?}", output))?;
+
+    let mut rng = Rng(spec.seed);
+    let words = Zipf::new(spec.vocab);
+    let tags = Zipf::new(spec.tags);
+    // Tags come from past the end of the vocabulary, so they never collide with body words
+    let tag_name = |rank: usize| format!("{}_{}", synthetic_word(spec.vocab + rank), rank);
+    let width = (spec.docs - 1).to_string().len();
+    let mut titles: Vec<String> = Vec::with_capacity(spec.docs);
+    let mut bytes = 0;
+
+    for doc in 0..spec.docs {
+        let title_len = rng.between(2, 5);
+        let title = (0..title_len)
+            .map(|_| capitalize(&synthetic_word(words.sample(&mut rng))))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut content = format!("# {}\n\n", title);
+        let target_words = rng.between(spec.words_per_doc / 2, spec.words_per_doc * 3 / 2);
+        let mut written = 0;
+        while written < target_words {
+            let sentences = rng.between(3, 6);
+            let mut paragraph = Vec::with_capacity(sentences);
+            for _ in 0..sentences {
+                let len = rng.between(5, 15);
+                let mut sentence: Vec<String> = (0..len)
+                    .map(|_| synthetic_word(words.sample(&mut rng)))
+                    .collect();
+                sentence[0] = capitalize(&sentence[0]);
+                paragraph.push(format!("{}.", sentence.join(" ")));
+                written += len;
+            }
+            content.push_str(&paragraph.join(" "));
+            content.push_str("\n\n");
+        }
+
+        if doc > 0 {
+            // Earlier documents only, so every link resolves
+            let links: Vec<String> = (0..rng.between(0, MAX_LINKS_PER_DOC.min(doc)))
+                .map(|_| {
+                    let target = rng.below(doc);
+                    format!("[{}]({})", titles[target], file_name(target, width))
+                })
+                .collect();
+            if !links.is_empty() {
+                content.push_str(&format!("See also: {}\n\n", links.join(", ")));
+            }
+        }
+
+        let mut doc_tags: Vec<String> = Vec::new();
+        for _ in 0..rng.between(1, MAX_TAGS_PER_DOC.min(spec.tags)) {
+            let tag = tag_name(tags.sample(&mut rng));
+            if !doc_tags.contains(&tag) {
+                doc_tags.push(tag);
+            }
+        }
+        let tag_line: Vec<String> = doc_tags.iter().map(|tag| format!("#{}", tag)).collect();
+        content.push_str(&format!("Tags: {}\n", tag_line.join(" ")));
+
+        let path: PathBuf = output.join(file_name(doc, width));
+        fs::write(&path, &content).with_context(|| format!("Failed to write {:?}", path))?;
+        let modified =
+            UNIX_EPOCH + Duration::from_secs(BASE_TIME + rng.next_u64() % TIME_SPAN_SECS);
+        File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(modified))
+            .with_context(|| format!("Failed to set the modified time of {:?}", path))?;
+        bytes += content.len() as u64;
+        titles.push(title);
+    }
+
+    Ok(GeneratedCorpus {
+        documents: spec.docs,
+        bytes,
+    })
+}
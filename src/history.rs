@@ -0,0 +1,53 @@
+// src/history.rs
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use infospark::inverted_index::fnv1a;
+
+use crate::config::{Config, CorpusRoot};
+
+// --- CONSTANTS ---
+// Where history lived before it was kept per corpus; still read once if present
+pub const LEGACY_HISTORY_FILE: &str = ".infospark_history";
+
+// The interactive history file for `corpus_roots`: the config's
+// `history_file`, or otherwise `infospark/history/<roots>-<hash>` under
+// $XDG_DATA_HOME (~/.local/share), so each corpus keeps its own queries.
+pub fn history_path(config: &Config, corpus_roots: &[CorpusRoot]) -> PathBuf {
+    if let Some(path) = &config.history_file {
+        return path.clone();
+    }
+    let Some(data_dir) = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+    else {
+        return PathBuf::from(LEGACY_HISTORY_FILE);
+    };
+
+    // Absolute paths, so the same corpus shares its history from any directory
+    let roots: Vec<PathBuf> = corpus_roots
+        .iter()
+        .map(|root| {
+            root.path
+                .canonicalize()
+                .unwrap_or_else(|_| root.path.clone())
+        })
+        .collect();
+    let key: Vec<String> = roots
+        .iter()
+        .map(|root| root.display().to_string())
+        .collect();
+    let names: Vec<String> = roots
+        .iter()
+        .filter_map(|root| root.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let file_name = format!(
+        "{}-{:016x}",
+        names.join("+"),
+        fnv1a(key.join("\n").as_bytes())
+    );
+    data_dir.join("infospark").join("history").join(file_name)
+}
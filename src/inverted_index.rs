@@ -1,51 +1,452 @@
 // src/inverted_index.rs
 
-use std::collections::HashMap;
-use std::fs;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
 use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::time::UNIX_EPOCH;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 use colored::*;
-use regex;
-use strsim;
 
 use serde::{Deserialize, Serialize};
-use serde_json;
-
-use bincode;
-use bincode::serde as bincode_serde;
+use utoipa::ToSchema;
 
 use lru::LruCache;
-use std::sync::{Arc, Mutex};
+use rayon::prelude::*;
+use std::sync::{Arc, Mutex, RwLock};
 
 use scraper::{Html, Selector};
 
-use pdf_extract::extract_text;
+use pdf_extract::{PlainTextOutput, output_doc};
+
+use ignore::WalkBuilder;
+
+use indicatif::{ProgressBar, ProgressStyle};
 
 use anyhow::{Context, Result, anyhow};
 
+use crate::archive;
+use crate::config::{
+    CacheConfig, CorpusRoot, DuplicateFiles, IndexingConfig, OversizedFiles, PathBoost,
+    QueryConfig, Ranking, ResultsConfig, SnippetConfig,
+};
+use crate::extractor;
+use crate::journal::{self, JournalEntry};
+use crate::json_records;
+use crate::near_duplicates::{self, Signature};
+use crate::query::{Query, QueryError, RangeField, TextField, parse_query};
+use crate::stored::{self, Compressed, FileBytes, Stored};
+use crate::tag_sidecar::{self, TagOverrides};
+use crate::web_pages;
+
 // --- CONSTANTS ---
-const FUZZY_THRESHOLD: usize = 2;
-const BM25_K1: f64 = 1.2;
-const BM25_B: f64 = 0.75;
+// Weight of a query word's synonyms against the word itself
+const SYNONYM_WEIGHT: f64 = 0.8;
+// Terms of a document that `similar` looks for in the others
+const SIMILAR_QUERY_TERMS: usize = 25;
+const SNIPPET_CACHE_CAPACITY: usize = 1000;
+// Peak memory while extracting and tokenizing a file, as a multiple of its size
+const EXTRACTION_MEMORY_FACTOR: u64 = 6;
+// Text files with a NUL byte this near their start are taken to be binary
+const BINARY_SNIFF_BYTES: usize = 8192;
+// The journal is compacted into the index file once it is over a quarter of its size
+const JOURNAL_COMPACTION_RATIO: u64 = 4;
+// How often the progress bar's spinner turns, so a long file does not look stuck
+const PROGRESS_TICK: Duration = Duration::from_millis(120);
+// Gitignore-style patterns of files to leave out, in any directory of a root
+const IGNORE_FILE: &str = ".infosparkignore";
+// Past this many segments, the smallest are merged until half as many remain
+const MAX_SEGMENTS: usize = 8;
+// Indexed with the code tokenizer, as line-oriented text
+pub(crate) const SOURCE_CODE_EXTENSIONS: [&str; 24] = [
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "scala", "c", "h", "cc", "cpp",
+    "hpp", "cs", "rb", "php", "swift", "lua", "sh", "hs", "ml", "zig",
+];
+const INDEX_MAGIC: &[u8; 4] = b"ISPK";
+pub const INDEX_FORMAT_VERSION: u32 = 19; // 5: document ids hashed from paths, 6: recorded roots, 7: sources, 8: segments, 9: blob area, 10: table rows, 11: Markdown sections, 12: descriptions, 13: content hashes, 14: language analyzers, 15: compressed text, 16: blob area checksum, 17: lexicon per segment, 18: word forms, 19: stop word positions
+const INDEX_HEADER_LEN: usize = 24;
 
 // --- TYPE ALIASES ---
 type TermPostings = Vec<(u32, Vec<usize>)>;
-type DocumentPartialIndex = HashMap<String, Vec<usize>>;
-type ProcessedDocumentResult = Result<(Document, DocumentPartialIndex)>;
+// Positions of a stop word: the token position it comes before in the high
+// half, and the number of stop words before it since the previous token in
+// the low half, so neighbouring stop words are one apart
+type StopPostings = Vec<(u32, Vec<u64>)>;
+type SnippetKey = (u32, Vec<String>); // Document id and its sorted highlight terms
+type Snippet = (String, Vec<usize>); // Highlighted text and the 1-based lines of the matches
+// When the matches were ranked, and how many of the best of them (all for None)
+type CachedResults = (Instant, Option<usize>, Arc<RankedMatches>);
 
 // --- STRUCTS ---
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Document {
     pub id: u32,
+    #[schema(value_type = String)]
     pub path: PathBuf,
-    pub content: String,
+    #[schema(value_type = String)]
+    pub content: Compressed<String>,
     pub title: String,
     pub tags: Vec<String>,
     pub num_tokens: usize,
     pub modified_time: u64,
+    pub created_time: Option<u64>, // Not every filesystem records a birth time
+    pub size: u64,                 // File size in bytes
+    pub author: Option<String>,    // From PDF or HTML metadata
+    pub language: Option<String>,  // Declared or detected language code, lowercased (e.g. "en-us")
+    pub source: String,            // Name of the corpus root the file was found under
+    pub row: Option<usize>,        // Line of the CSV or TSV record a row document holds
+    pub parent_doc: Option<u32>,   // Section a Markdown `##` section document falls under
+    // HTML meta description, previewed when nothing in the document matches
+    pub description: Option<String>,
+    pub content_hash: u64, // FNV-1a of the content, to tell copies of a file apart
+}
+
+// Metadata some formats carry alongside their text
+#[derive(Default)]
+pub struct ExtractedMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>, // Declared in the file, besides its #tags
+}
+
+// What the filesystem reports about a corpus file
+struct FileMetadata {
+    modified_time: u64,
+    created_time: Option<u64>,
+    size: u64,
+}
+
+impl FileMetadata {
+    // A tag sidecar counts as part of its document, so editing it alone
+    // still gets the document reindexed.
+    fn read(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let mut modified_time = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+        if let Ok(sidecar) = fs::metadata(tag_sidecar::sidecar_path(path)) {
+            modified_time =
+                modified_time.max(sidecar.modified()?.duration_since(UNIX_EPOCH)?.as_secs());
+        }
+        Ok(FileMetadata {
+            modified_time,
+            created_time: metadata
+                .created()
+                .ok()
+                .and_then(|created| created.duration_since(UNIX_EPOCH).ok())
+                .map(|created| created.as_secs()),
+            size: metadata.len(),
+        })
+    }
+}
+
+// What a pass over the corpus roots changed in the index
+#[derive(Debug, Default)]
+pub struct IndexingReport {
+    pub added: Vec<PathBuf>,
+    pub updated: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub oversized: Vec<PathBuf>, // Over `max_file_mb`, and left out
+    pub truncated: Vec<PathBuf>, // Over `max_file_mb`, and indexed in part
+    pub duplicates: Vec<(PathBuf, PathBuf)>, // Left out as a copy of the second file
+    pub not_text: Vec<PathBuf>,  // Binary or not UTF-8 despite their extension, and left out
+    pub failed: Vec<(PathBuf, anyhow::Error)>, // Could not be extracted, and left as they were
+    pub changed_doc_ids: Vec<u32>,
+}
+
+impl IndexingReport {
+    pub fn has_changes(&self) -> bool {
+        !(self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty())
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} added, {} updated, {} removed, {} skipped",
+            self.added.len(),
+            self.updated.len(),
+            self.removed.len(),
+            self.skipped.len()
+        );
+        if !self.failed.is_empty() {
+            summary.push_str(&format!(", {} failed", self.failed.len()));
+        }
+        summary
+    }
+
+    // A line per file that could not be extracted, with the reason
+    pub fn failures(&self) -> Vec<String> {
+        self.failed
+            .iter()
+            .map(|(path, e)| format!("Failed to index {:?}: {:#}", path, e))
+            .collect()
+    }
+
+    // A line per file over `max_file_mb`, left out as a copy, or not text
+    pub fn warnings(&self) -> Vec<String> {
+        let skipped = self
+            .oversized
+            .iter()
+            .map(|path| format!("Skipped {:?}: larger than max_file_mb", path));
+        let truncated = self.truncated.iter().map(|path| {
+            format!(
+                "Truncated {:?}: only its first max_file_mb are indexed",
+                path
+            )
+        });
+        let duplicates = self
+            .duplicates
+            .iter()
+            .map(|(path, original)| format!("Skipped {:?}: same text as {:?}", path, original));
+        let not_text = self
+            .not_text
+            .iter()
+            .map(|path| format!("Skipped {:?}: {}", path, NotText));
+        skipped
+            .chain(truncated)
+            .chain(duplicates)
+            .chain(not_text)
+            .collect()
+    }
+
+    fn merge(&mut self, other: IndexingReport) {
+        self.added.extend(other.added);
+        self.updated.extend(other.updated);
+        self.removed.extend(other.removed);
+        self.skipped.extend(other.skipped);
+        self.oversized.extend(other.oversized);
+        self.truncated.extend(other.truncated);
+        self.duplicates.extend(other.duplicates);
+        self.not_text.extend(other.not_text);
+        self.failed.extend(other.failed);
+        self.changed_doc_ids.extend(other.changed_doc_ids);
+    }
+}
+
+// The progress bar's message: files seen, indexed so far, and left out either
+// way (unsupported, oversized, copies or not text)
+fn progress_counts(scanned: u64, report: &IndexingReport) -> String {
+    let skipped = report.skipped.len()
+        + report.oversized.len()
+        + report.duplicates.len()
+        + report.not_text.len()
+        + report.failed.len();
+    format!(
+        "{} scanned, {} indexed, {} skipped",
+        scanned,
+        report.added.len() + report.updated.len(),
+        skipped
+    )
+}
+
+// An immutable batch of postings, from documents committed together. Removing
+// or reindexing a document only drops it from `doc_ids`; its postings stay
+// until the segment is merged.
+#[derive(Debug, Serialize, Deserialize)]
+struct Segment {
+    postings: HashMap<String, Stored<TermPostings>>,
+    lexicon: Stored<Lexicon>,
+    forms: Stored<HashMap<String, Vec<u32>>>, // Word as written -> the documents with it, for `=`
+    stop_words: HashMap<String, Stored<StopPostings>>, // Of prose in the default analyzer
+    doc_ids: HashSet<u32>,                    // Documents still live in this segment
+    deleted: usize,                           // Documents masked since the segment was built
+}
+
+// A segment's words as written (lowercased) with the terms they were indexed
+// as, for wildcards a stem cannot be matched against: `*ization` looks for
+// "organization", indexed as "organ". Sorted both forwards and by the
+// reversed words, so a leading or trailing run of letters narrows the words
+// to check to a range.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Lexicon {
+    words: Vec<(String, String, Vec<u32>)>, // Word, its term and the documents with it, sorted
+    reversed: Vec<(String, usize)>,         // Word reversed and its place in `words`, sorted
+}
+
+impl Lexicon {
+    fn new(words: HashMap<(String, String), Vec<u32>>) -> Lexicon {
+        let mut words: Vec<(String, String, Vec<u32>)> = words
+            .into_iter()
+            .map(|((word, term), doc_ids)| (word, term, doc_ids))
+            .collect();
+        words.sort_unstable();
+        let mut reversed: Vec<(String, usize)> = words
+            .iter()
+            .enumerate()
+            .map(|(idx, (word, ..))| (word.chars().rev().collect(), idx))
+            .collect();
+        reversed.sort_unstable();
+        Lexicon { words, reversed }
+    }
+
+    // The terms of the words the pattern split at its `*`s into `parts`
+    // matches, among those documents in `live` have
+    fn matching_terms<'a>(&'a self, parts: &[&str], live: &HashSet<u32>) -> Vec<&'a String> {
+        let (prefix, suffix) = (parts[0], parts[parts.len() - 1]);
+        let reversed_suffix: String = suffix.chars().rev().collect();
+        let prefix_range = (!prefix.is_empty())
+            .then(|| InvertedIndex::prefix_range(&self.words, prefix, |(word, ..)| word));
+        let suffix_range = (!suffix.is_empty()).then(|| {
+            InvertedIndex::prefix_range(&self.reversed, &reversed_suffix, |(word, _)| word)
+        });
+        let candidates: Vec<usize> = match (prefix_range, suffix_range) {
+            (Some(prefixed), Some(suffixed)) if prefixed.len() <= suffixed.len() => {
+                prefixed.collect()
+            }
+            (_, Some(suffixed)) => self.reversed[suffixed]
+                .iter()
+                .map(|(_, idx)| *idx)
+                .collect(),
+            (Some(prefixed), None) => prefixed.collect(),
+            (None, None) => (0..self.words.len()).collect(),
+        };
+        candidates
+            .into_iter()
+            .map(|idx| &self.words[idx])
+            .filter(|(word, _, doc_ids)| {
+                InvertedIndex::wildcard_matches(word, parts)
+                    && doc_ids.iter().any(|doc_id| live.contains(doc_id))
+            })
+            .map(|(_, term, _)| term)
+            .collect()
+    }
+}
+
+impl Segment {
+    fn live_postings<'a>(&'a self, term: &str) -> impl Iterator<Item = &'a (u32, Vec<usize>)> {
+        self.postings
+            .get(term)
+            .into_iter()
+            .flat_map(|postings| postings.iter())
+            .filter(|(doc_id, _)| self.doc_ids.contains(doc_id))
+    }
+
+    fn live_stop_postings<'a>(&'a self, word: &str) -> impl Iterator<Item = &'a (u32, Vec<u64>)> {
+        self.stop_words
+            .get(word)
+            .into_iter()
+            .flat_map(|postings| postings.iter())
+            .filter(|(doc_id, _)| self.doc_ids.contains(doc_id))
+    }
+
+    // One segment holding the live postings and words of `segments`
+    fn merge(segments: &[&Segment]) -> Segment {
+        let mut postings: HashMap<String, TermPostings> = HashMap::new();
+        let mut words: HashMap<(String, String), Vec<u32>> = HashMap::new();
+        let mut forms: HashMap<String, Vec<u32>> = HashMap::new();
+        let mut stop_words: HashMap<String, StopPostings> = HashMap::new();
+        let mut doc_ids = HashSet::new();
+        for segment in segments {
+            for term in segment.postings.keys() {
+                let mut live = segment.live_postings(term).cloned().peekable();
+                if live.peek().is_some() {
+                    postings.entry(term.clone()).or_default().extend(live);
+                }
+            }
+            for (word, term, word_doc_ids) in &segment.lexicon.words {
+                let mut live = word_doc_ids
+                    .iter()
+                    .filter(|doc_id| segment.doc_ids.contains(doc_id))
+                    .peekable();
+                if live.peek().is_some() {
+                    words
+                        .entry((word.clone(), term.clone()))
+                        .or_default()
+                        .extend(live);
+                }
+            }
+            for (form, form_doc_ids) in segment.forms.iter() {
+                let mut live = form_doc_ids
+                    .iter()
+                    .filter(|doc_id| segment.doc_ids.contains(doc_id))
+                    .peekable();
+                if live.peek().is_some() {
+                    forms.entry(form.clone()).or_default().extend(live);
+                }
+            }
+            for word in segment.stop_words.keys() {
+                let mut live = segment.live_stop_postings(word).cloned().peekable();
+                if live.peek().is_some() {
+                    stop_words.entry(word.clone()).or_default().extend(live);
+                }
+            }
+            doc_ids.extend(&segment.doc_ids);
+        }
+        Segment::new(postings, words, forms, stop_words, doc_ids)
+    }
+
+    fn new(
+        postings: HashMap<String, TermPostings>,
+        words: HashMap<(String, String), Vec<u32>>,
+        forms: HashMap<String, Vec<u32>>,
+        stop_words: HashMap<String, StopPostings>,
+        doc_ids: HashSet<u32>,
+    ) -> Segment {
+        Segment {
+            postings: postings
+                .into_iter()
+                .map(|(term, postings)| (term, Stored::new(postings)))
+                .collect(),
+            lexicon: Stored::new(Lexicon::new(words)),
+            forms: Stored::new(forms),
+            stop_words: stop_words
+                .into_iter()
+                .map(|(word, postings)| (word, Stored::new(postings)))
+                .collect(),
+            doc_ids,
+            deleted: 0,
+        }
+    }
+}
+
+// A document with its postings worked out, ready to be inserted
+#[derive(Debug)]
+struct TokenizedDocument {
+    doc: Document,
+    term_positions: HashMap<String, Vec<usize>>,
+    offsets: Vec<(usize, usize)>, // Byte range of each token position
+    words: HashSet<(String, String)>, // Each word (lowercased) with the term it was indexed as
+    forms: HashSet<String>,       // Each word as written (see `word_forms`)
+    stop_positions: HashMap<String, Vec<u64>>, // See `StopPostings`
+}
+
+// What `extract_batch` got from a file. The documents of a file that turns
+// out not to be text are dropped; those of one that fails to extract are kept
+// as they were, to be tried again on the next refresh.
+enum Extraction {
+    Documents(Vec<TokenizedDocument>, Vec<PathBuf>), // With the web pages that could not be fetched
+    NotText,
+    Failed(anyhow::Error),
+}
+
+// A file that needs (re-)extracting, found by `plan_scan`
+struct ExtractJob {
+    doc_id: u32,           // Of the file's document; row documents get theirs once extracted
+    indexed_ids: Vec<u32>, // The file's documents in the index, ordered by row
+    path: PathBuf,
+    metadata: FileMetadata,
+    root_idx: usize,
+    existing: Option<bool>, // Whether an indexed file was modified; None for new files
+}
+
+// The outcome of comparing the corpus roots against the index
+struct ScanPlan {
+    jobs: Vec<ExtractJob>,
+    doc_ids_to_remove: Vec<u32>,
+    report: IndexingReport, // Removed and skipped files so far
+}
+
+// A batch of documents extracted and tokenized by `extract_batch`, waiting to
+// be committed to the index
+struct PendingChanges {
+    generation: u64, // Of the index the batch was extracted against
+    doc_ids_to_remove: Vec<u32>,
+    documents: Vec<TokenizedDocument>,
+    report: IndexingReport,
 }
 
 #[derive(Debug, Clone)]
@@ -53,7 +454,89 @@ pub struct SearchResult {
     pub doc: Document,
     pub score: f64,
     pub snippet: String,
+    pub lines: Vec<usize>, // Lines of the matches in text files; empty for other formats
+    pub tags: Vec<String>,
+    pub copies: Vec<PathBuf>, // Other files with the same text, under `duplicates = "link"`
+    pub near_duplicates: Vec<u32>, // Near-identical documents collapsed under this one, best first
+}
+
+// One page of a query's results, and how many documents matched in all
+#[derive(Debug, Clone, Default)]
+pub struct ResultPage {
+    pub results: Vec<SearchResult>,
+    pub total: usize,
+    pub below_min_score: usize, // Further matches left out under `min_score`
+}
+
+// Serializable view of a search result, as exchanged with remote clients
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ResultSummary {
+    pub id: u32,
+    pub title: String,
+    #[schema(value_type = String)]
+    pub path: PathBuf,
+    pub score: f64,
     pub tags: Vec<String>,
+    pub snippet: String,
+    pub lines: Vec<usize>,
+    pub modified_time: u64,
+    pub created_time: Option<u64>,
+    pub size: u64,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub source: String,
+    pub row: Option<usize>,
+    pub parent_doc: Option<u32>,
+    #[schema(value_type = Vec<String>)]
+    pub copies: Vec<PathBuf>,
+    #[serde(default)]
+    pub near_duplicates: Vec<u32>,
+}
+
+impl SearchResult {
+    pub fn summary(&self) -> ResultSummary {
+        ResultSummary {
+            id: self.doc.id,
+            title: self.doc.title.clone(),
+            path: self.doc.path.clone(),
+            score: self.score,
+            tags: self.tags.clone(),
+            snippet: self.snippet.clone(),
+            lines: self.lines.clone(),
+            modified_time: self.doc.modified_time,
+            created_time: self.doc.created_time,
+            size: self.doc.size,
+            author: self.doc.author.clone(),
+            language: self.doc.language.clone(),
+            source: self.doc.source.clone(),
+            row: self.doc.row,
+            parent_doc: self.doc.parent_doc,
+            copies: self.copies.clone(),
+            near_duplicates: self.near_duplicates.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub capacity: usize,
+    pub ttl: Option<Duration>,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub expired: u64, // Misses caused by an entry outliving the TTL
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
 }
 
 // Structs for graph data serialization
@@ -65,6 +548,9 @@ pub struct GraphNode {
     pub group: String,
     pub content_preview: String,
     pub js_tags: Vec<String>, // Direct tags for JavaScript filtering
+    pub modified_time: u64,
+    pub created_time: Option<u64>,
+    pub size: u64,
 }
 
 #[derive(Serialize, Debug)]
@@ -81,736 +567,3531 @@ pub struct ClientSearchableDocument {
     pub content: String, // Full content for client-side search
     pub tags: Vec<String>,
     pub content_preview: String, // Keep preview for quick display
+    pub size: u64,
+    pub author: Option<String>,
+    pub language: Option<String>,
+    pub source: String,
 }
 
 // Master data structure for the full web application
 #[derive(Serialize, Debug)]
-pub struct FullWebAppData {
-    pub nodes: Vec<GraphNode>,
-    pub edges: Vec<GraphEdge>,
-    pub searchable_documents: HashMap<u32, ClientSearchableDocument>,
+pub struct FullWebAppData<'a> {
+    pub nodes: Vec<&'a GraphNode>,
+    pub edges: Vec<&'a GraphEdge>,
+    pub searchable_documents: HashMap<u32, &'a ClientSearchableDocument>,
+}
+
+// Keyed form of the web app data, so the entries of changed documents can be
+// rebuilt without regenerating everything.
+#[derive(Debug, Default)]
+pub struct WebAppData {
+    nodes: HashMap<u32, GraphNode>,
+    edges: HashMap<(u32, u32), GraphEdge>,
+    searchable_documents: HashMap<u32, ClientSearchableDocument>,
+    // Content hash of each node, to bring a copy in when its node goes
+    node_hashes: HashMap<u32, u64>,
+}
+
+impl WebAppData {
+    pub fn to_json(&self) -> Result<String> {
+        let mut nodes: Vec<&GraphNode> = self.nodes.values().collect();
+        nodes.sort_by_key(|node| node.id);
+        let mut edges: Vec<&GraphEdge> = self.edges.values().collect();
+        edges.sort_by_key(|edge| (edge.from, edge.to));
+        let full_app_data = FullWebAppData {
+            nodes,
+            edges,
+            searchable_documents: self
+                .searchable_documents
+                .iter()
+                .map(|(id, doc)| (*id, doc))
+                .collect(),
+        };
+        serde_json::to_string_pretty(&full_app_data)
+            .context("Failed to serialize full app data to JSON")
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // The nodes in `node_ids` and the edges in `edge_keys`, in the same shape
+    // as `to_json`, for web views that load the graph piece by piece.
+    pub fn subgraph_json(&self, node_ids: &[u32], edge_keys: &[(u32, u32)]) -> Result<String> {
+        let full_app_data = FullWebAppData {
+            nodes: node_ids
+                .iter()
+                .filter_map(|id| self.nodes.get(id))
+                .collect(),
+            edges: edge_keys
+                .iter()
+                .filter_map(|key| self.edges.get(key))
+                .collect(),
+            searchable_documents: node_ids
+                .iter()
+                .filter_map(|id| self.searchable_documents.get_key_value(id))
+                .map(|(id, doc)| (*id, doc))
+                .collect(),
+        };
+        serde_json::to_string(&full_app_data).context("Failed to serialize graph data to JSON")
+    }
+
+    // Node with the most edges, where lazily loaded graphs start by default
+    pub fn most_connected_node(&self) -> Option<u32> {
+        let mut degrees: HashMap<u32, usize> = HashMap::new();
+        for (from, to) in self.edges.keys() {
+            *degrees.entry(*from).or_default() += 1;
+            *degrees.entry(*to).or_default() += 1;
+        }
+        self.nodes.keys().copied().max_by_key(|id| {
+            (
+                degrees.get(id).copied().unwrap_or(0),
+                std::cmp::Reverse(*id),
+            )
+        })
+    }
+}
+
+// Terms and phrases collected while evaluating a query, used for ranking and highlighting
+#[derive(Debug, Default)]
+struct MatchContext {
+    terms: Vec<(String, f64)>, // Indexed term and its weight (fuzzy matches count half, synonyms 0.8)
+    phrases: Vec<(Vec<String>, usize)>, // Tokens and slop
+    tags: Vec<String>,
+    // Synonyms rank with the document frequency of everything their word
+    // matched, so a rare synonym never outweighs the word itself
+    blended_frequencies: HashMap<String, usize>,
+}
+
+// A scored match; better ones, with higher scores or else lower ids, are greater
+struct Hit(f64, u32);
+
+impl Ord for Hit {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(other.1.cmp(&self.1))
+    }
+}
+
+impl PartialOrd for Hit {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Hit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Hit {}
+
+// A query's matches in rank order, which is what the cache keeps: the results
+// of a page are built from them, snippets and all, only when asked for
+#[derive(Debug, Default)]
+struct RankedMatches {
+    hits: Vec<(f64, u32)>, // Score and document id
+    // When only the best were ranked, the scores of the other matches, in no
+    // order, for the total
+    rest: Vec<f64>,
+    highlight_terms: Vec<String>,
+    copies: HashMap<u64, Vec<PathBuf>>, // Content hash -> the copies its best ranked file stands for
+    near_duplicates: HashMap<u32, Vec<u32>>, // Document -> the near-identical ones it stands for
 }
 
 // Helper function for default LruCache initialization
-fn default_search_cache() -> Arc<Mutex<LruCache<String, Vec<SearchResult>>>> {
+fn default_search_cache() -> Arc<Mutex<LruCache<String, CachedResults>>> {
     let non_zero_capacity = NonZeroUsize::new(1).expect("Capacity must be non-zero");
     Arc::new(Mutex::new(LruCache::new(non_zero_capacity)))
 }
 
+fn default_snippet_cache() -> Arc<Mutex<LruCache<SnippetKey, Snippet>>> {
+    let non_zero_capacity = NonZeroUsize::new(SNIPPET_CACHE_CAPACITY).unwrap();
+    Arc::new(Mutex::new(LruCache::new(non_zero_capacity)))
+}
+
+// FNV-1a, whose output unlike std's hashers is fixed across Rust releases
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// What a document's id is derived from: its path inside the corpus root,
+// under the root's directory name so equally named files in different roots
+// don't collide. Separators are always '/'.
+fn id_key(path: &Path, root: &CorpusRoot) -> String {
+    let relative = path.strip_prefix(&root.path).unwrap_or(path);
+    let root_name = root
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    std::iter::once(root_name)
+        .chain(
+            relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// A metadata value with its whitespace collapsed, or None if it is blank
+fn clean_metadata(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|value| !value.is_empty())
+}
+
+// A file with a text format's extension holding binary data or text in an
+// encoding other than UTF-8, which is left out of the index
+#[derive(Debug)]
+pub struct NotText;
+
+impl fmt::Display for NotText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "binary data or text that is not UTF-8")
+    }
+}
+
+impl std::error::Error for NotText {}
+
+// A text file's contents, or with `max_bytes`, the whole lines in its first
+// `max_bytes` if it is longer. Fails with `NotText` for a file with a NUL
+// byte near its start (binary, or UTF-16) or with invalid UTF-8.
+pub fn read_text(path: &Path, max_bytes: Option<u64>) -> Result<String> {
+    let max_bytes = max_bytes.filter(|max| fs::metadata(path).is_ok_and(|m| m.len() > *max));
+    let mut bytes = Vec::new();
+    File::open(path)?
+        .take(max_bytes.unwrap_or(u64::MAX))
+        .read_to_end(&mut bytes)?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return Err(NotText.into());
+    }
+    if max_bytes.is_some()
+        && let Some(end) = bytes.iter().rposition(|byte| *byte == b'\n')
+    {
+        bytes.truncate(end + 1);
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        // A character cut off by `max_bytes`, in a file of one line
+        Err(e) if max_bytes.is_some() && e.utf8_error().error_len().is_none() => {
+            let valid = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid);
+            Ok(String::from_utf8(bytes).unwrap_or_default())
+        }
+        Err(_) => Err(NotText.into()),
+    }
+}
+
+// The text of a file's first bytes, without its cut-off last line (or
+// character, in a file of one line)
+fn whole_lines(mut bytes: Vec<u8>) -> String {
+    if let Some(end) = bytes.iter().rposition(|byte| *byte == b'\n') {
+        bytes.truncate(end + 1);
+    }
+    String::from_utf8(bytes).unwrap_or_else(|e| {
+        let valid = e.utf8_error().valid_up_to();
+        let mut bytes = e.into_bytes();
+        bytes.truncate(valid);
+        String::from_utf8(bytes).unwrap_or_default()
+    })
+}
+
+// `[indexing] max_file_mb`, and whether files over it are indexed in part
+#[derive(Debug, Clone, Copy)]
+struct FileSizeLimit {
+    max_bytes: u64,
+    truncate: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InvertedIndex {
-    index: HashMap<String, TermPostings>,
+    segments: Vec<Segment>,
     documents: HashMap<u32, Document>,
     tags: HashMap<String, Vec<u32>>,
+    // Byte range in the document content of each token position
+    token_offsets: HashMap<u32, Stored<Vec<(usize, usize)>>>,
+    // The corpus roots documents were last read from, so their paths can be
+    // rebased when the corpus moves, and the roots' names
+    roots: Vec<PathBuf>,
+    root_names: Vec<String>,
+    // Bumped by every change to the documents, so changes scanned against an
+    // older state can be told apart
     #[serde(skip)]
-    next_doc_id: AtomicU32,
+    generation: u64,
     pub total_docs: usize,
     pub avg_doc_length: f64,
     #[serde(skip, default = "default_search_cache")]
-    search_cache: Arc<Mutex<LruCache<String, Vec<SearchResult>>>>,
+    search_cache: Arc<Mutex<LruCache<String, CachedResults>>>,
+    // Snippets outlive the search cache entries that produced them, so
+    // different queries matching the same terms share them
+    #[serde(skip, default = "default_snippet_cache")]
+    snippet_cache: Arc<Mutex<LruCache<SnippetKey, Snippet>>>,
     cache_capacity: usize,
+    #[serde(skip)]
+    cache_policy: CacheConfig,
+    #[serde(skip)]
+    snippet_policy: SnippetConfig,
+    #[serde(skip)]
+    result_policy: ResultsConfig,
+    #[serde(skip)]
+    query_policy: QueryConfig,
+    // Compiled `[[boost]]` patterns from the config, applied while ranking
+    #[serde(skip)]
+    path_boosts: Vec<(Vec<glob::Pattern>, f64)>,
+    // `[indexing]` limits; extraction uses rayon's global pool without a
+    // configured thread count
+    #[serde(skip)]
+    extraction_pool: Option<Arc<rayon::ThreadPool>>,
+    #[serde(skip)]
+    memory_budget: Option<u64>, // Bytes
+    #[serde(skip)]
+    file_size_limit: Option<FileSizeLimit>,
+    #[serde(skip)]
+    duplicates: DuplicateFiles,
+    #[serde(skip)]
+    fail_fast: bool,
+    // Draws a progress bar on stderr while syncing with the corpus
+    #[serde(skip)]
+    show_progress: bool,
+    // Under `duplicates = "link"`, the documents with each content hash, in
+    // path order; the first of several copies stands for the rest in the graph
+    #[serde(skip)]
+    copy_groups: HashMap<u64, Vec<u32>>,
+    // Analyzers other than English that documents were tokenized with, for
+    // queries to be analyzed in too
+    #[serde(skip)]
+    analyzers: Vec<&'static str>,
+    // Length of each document's TF-IDF vector, for `ranking = "cosine"`;
+    // worked out from every posting the first time it is needed after a change
+    #[serde(skip)]
+    vector_norms: Mutex<Option<Arc<HashMap<u32, f64>>>>,
+    // MinHash signatures for `near_duplicates`, of the documents results have
+    // been compared for since the last change; None for ones without words
+    #[serde(skip)]
+    signatures: Mutex<HashMap<u32, Option<Arc<Signature>>>>,
+    // Serializes saves, which may come from several request handlers at once
+    #[serde(skip)]
+    persist_lock: Mutex<()>,
+    #[serde(skip)]
+    cache_hits: AtomicU64,
+    #[serde(skip)]
+    cache_misses: AtomicU64,
+    #[serde(skip)]
+    cache_expired: AtomicU64,
+}
+
+impl Default for InvertedIndex {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InvertedIndex {
     pub fn new() -> Self {
-        const DEFAULT_CACHE_CAPACITY: usize = 100;
-        let non_zero_capacity = NonZeroUsize::new(DEFAULT_CACHE_CAPACITY).unwrap();
+        let cache_policy = CacheConfig::default();
+        let non_zero_capacity = cache_policy.capacity;
         InvertedIndex {
-            index: HashMap::new(),
+            segments: Vec::new(),
             documents: HashMap::new(),
             tags: HashMap::new(),
-            next_doc_id: AtomicU32::new(1),
+            token_offsets: HashMap::new(),
+            roots: Vec::new(),
+            root_names: Vec::new(),
+            generation: 0,
             total_docs: 0,
             avg_doc_length: 0.0,
             search_cache: Arc::new(Mutex::new(LruCache::new(non_zero_capacity))),
-            cache_capacity: DEFAULT_CACHE_CAPACITY,
+            snippet_cache: default_snippet_cache(),
+            cache_capacity: non_zero_capacity.get(),
+            cache_policy,
+            snippet_policy: SnippetConfig::default(),
+            result_policy: ResultsConfig::default(),
+            query_policy: QueryConfig::default(),
+            path_boosts: Vec::new(),
+            extraction_pool: None,
+            memory_budget: None,
+            file_size_limit: None,
+            duplicates: DuplicateFiles::Keep,
+            fail_fast: false,
+            show_progress: false,
+            copy_groups: HashMap::new(),
+            analyzers: Vec::new(),
+            vector_norms: Mutex::new(None),
+            signatures: Mutex::new(HashMap::new()),
+            persist_lock: Mutex::new(()),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            cache_expired: AtomicU64::new(0),
         }
     }
 
     // Persistence Methods
+    // Serialized layout: INDEX_MAGIC, INDEX_FORMAT_VERSION (u32 LE), length of
+    // the blob area (u64 LE), FNV-1a of the blob area (u64 LE), the blob area
+    // holding the stored values, then the bincode payload (see `stored`).
     pub fn from_serialized_data(serialized_data: &[u8]) -> Result<Self> {
-        let (mut index, _bytes_read): (InvertedIndex, usize) =
-            bincode_serde::decode_from_slice(serialized_data, bincode::config::standard())
-                .context("Failed to decode index data from slice")?;
+        Self::decode(Arc::new(serialized_data.to_vec()), false)
+    }
+
+    // With `lazy`, document text, postings and token offsets stay in `bytes`
+    // until first used
+    fn decode(bytes: FileBytes, lazy: bool) -> Result<Self> {
+        let serialized_data = (*bytes).as_ref();
+        let (blob_len, blob_hash) = match serialized_data.strip_prefix(INDEX_MAGIC.as_slice()) {
+            Some(rest) if rest.len() >= 4 => {
+                let version = u32::from_le_bytes(rest[..4].try_into().unwrap());
+                if version != INDEX_FORMAT_VERSION {
+                    return Err(anyhow!(
+                        "Index format version {} is {} than the supported version {}",
+                        version,
+                        if version > INDEX_FORMAT_VERSION {
+                            "newer"
+                        } else {
+                            "older"
+                        },
+                        INDEX_FORMAT_VERSION
+                    ));
+                }
+                let field = |range: std::ops::Range<usize>| {
+                    rest.get(range)
+                        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                        .context("Index file is truncated")
+                };
+                (field(4..12)? as usize, field(12..20)?)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Index file has no format header (written by an older infospark?)"
+                ));
+            }
+        };
+        let payload_start = INDEX_HEADER_LEN
+            .checked_add(blob_len)
+            .filter(|&start| start <= serialized_data.len())
+            .context("Index file is truncated")?;
+        let payload = payload_start..serialized_data.len();
+        // Stored values are decoded as they are used, so damage to them is
+        // caught here rather than in the middle of a search
+        if fnv1a(&serialized_data[INDEX_HEADER_LEN..payload_start]) != blob_hash {
+            return Err(anyhow!(
+                "Index file is corrupt: its stored values fail the checksum"
+            ));
+        }
+
+        let mut index: InvertedIndex = stored::decode_with_blobs(
+            bytes.clone(),
+            INDEX_HEADER_LEN..payload_start,
+            payload,
+            lazy,
+        )
+        .context("Failed to decode index data from slice")?;
 
-        let max_id = index.documents.keys().max().copied().unwrap_or(0);
-        index.next_doc_id = AtomicU32::new(max_id + 1);
         let non_zero_capacity =
             NonZeroUsize::new(index.cache_capacity).context("Cache capacity cannot be zero")?;
         index.search_cache = Arc::new(Mutex::new(LruCache::new(non_zero_capacity)));
+        index.update_analyzers();
 
         Ok(index)
     }
 
     pub fn to_serialized_data(&self) -> Result<Vec<u8>> {
-        let encoded_data = bincode_serde::encode_to_vec(self, bincode::config::standard())
-            .context("Failed to encode index data to vector")?;
+        let (blobs, payload) =
+            stored::encode_with_blobs(self).context("Failed to encode index data to vector")?;
+        let mut encoded_data = Vec::with_capacity(INDEX_HEADER_LEN + blobs.len() + payload.len());
+        encoded_data.extend_from_slice(INDEX_MAGIC);
+        encoded_data.extend_from_slice(&INDEX_FORMAT_VERSION.to_le_bytes());
+        encoded_data.extend_from_slice(&(blobs.len() as u64).to_le_bytes());
+        encoded_data.extend_from_slice(&fnv1a(&blobs).to_le_bytes());
+        encoded_data.extend(blobs);
+        encoded_data.extend(payload);
         Ok(encoded_data)
     }
 
-    #[allow(dead_code)]
-    pub fn add_document(&mut self, doc: Document) {
-        let doc_id = doc.id;
+    // Reads the index file and replays the journal of changes saved since
+    pub fn load_from_file(index_path: &Path) -> Result<Self> {
+        let encoded_data = fs::read(index_path).context("Failed to read existing index file")?;
+        let mut index = Self::decode(Arc::new(encoded_data), false)
+            .context("Failed to deserialize existing index")?;
+        index.replay_journal(&journal::journal_path(index_path))?;
+        Ok(index)
+    }
 
-        let current_doc = Document {
-            id: doc_id,
-            path: doc.path,
-            content: doc.content,
-            title: doc.title,
-            tags: doc.tags.clone(),
-            num_tokens: doc.num_tokens,
-            modified_time: doc.modified_time,
-        };
+    // Like `load_from_file`, but maps the index file instead of reading it, so
+    // only the term dictionary and document metadata are decoded up front and
+    // the rest is paged in as searches use it. Saves replace the file rather
+    // than writing into it, so the mapping stays valid.
+    pub fn map_from_file(index_path: &Path) -> Result<Self> {
+        let file = File::open(index_path).context("Failed to open existing index file")?;
+        // Safety: infospark never modifies an index file in place
+        let map = unsafe { memmap2::Mmap::map(&file) }.context("Failed to map index file")?;
+        let mut index =
+            Self::decode(Arc::new(map), true).context("Failed to deserialize existing index")?;
+        index.replay_journal(&journal::journal_path(index_path))?;
+        Ok(index)
+    }
 
-        let tokens_with_positions = crate::tokenizer::tokenize(&current_doc.content);
-        let mut doc_token_positions: HashMap<String, Vec<usize>> = HashMap::new();
-        for (token, pos) in tokens_with_positions {
-            doc_token_positions
-                .entry(token)
-                .or_insert_with(Vec::new)
-                .push(pos);
+    fn replay_journal(&mut self, journal_path: &Path) -> Result<()> {
+        let Some(contents) = journal::read(journal_path)? else {
+            return Ok(());
+        };
+        if contents.torn {
+            eprintln!(
+                "{} {:?} ends in an incomplete record, probably from a crash; dropping it",
+                "Warning:".yellow(),
+                journal_path
+            );
+            journal::truncate(journal_path, contents.valid_len)?;
         }
-
-        for (token, positions) in doc_token_positions {
-            self.index
-                .entry(token)
-                .or_insert_with(Vec::new)
-                .push((doc_id, positions));
+        // Every document the journal mentions is dropped, then the last version
+        // of each upserted one goes into a single new segment
+        let mut doc_ids_to_remove = Vec::new();
+        let mut upserts: HashMap<u32, Document> = HashMap::new();
+        for entry in contents.entries {
+            match entry {
+                JournalEntry::Upsert(doc) => {
+                    doc_ids_to_remove.push(doc.id);
+                    upserts.insert(doc.id, *doc);
+                }
+                JournalEntry::Remove(doc_id) => {
+                    doc_ids_to_remove.push(doc_id);
+                    upserts.remove(&doc_id);
+                }
+            }
         }
+        self.remove_documents(&doc_ids_to_remove);
+        self.insert_tokenized(upserts.into_values().collect());
+        self.update_collection_stats();
+        self.merge_segments();
+        Ok(())
+    }
+
+    // Writes the whole index, replacing the index file atomically, and drops
+    // the journal it supersedes.
+    pub fn save_to_file(&self, index_path: &Path) -> Result<()> {
+        let _guard = self.persist_lock.lock().unwrap();
+        self.write_snapshot(index_path)
+    }
+
+    fn write_snapshot(&self, index_path: &Path) -> Result<()> {
+        let encoded_data = self
+            .to_serialized_data()
+            .context("Failed to serialize index for saving")?;
+        let mut temp_path = index_path.as_os_str().to_os_string();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, encoded_data).context("Failed to write index to file")?;
+        fs::rename(&temp_path, index_path).context("Failed to write index to file")?;
+        journal::remove(&journal::journal_path(index_path))
+    }
 
-        for tag in &current_doc.tags {
-            self.tags
-                .entry(tag.clone())
-                .or_insert_with(Vec::new)
-                .push(doc_id);
+    // Persists the documents of an `IndexingReport` by appending them to the
+    // journal next to the index file, instead of rewriting the whole file.
+    // Once the journal outgrows a fraction of the index file, it is compacted
+    // into a full save.
+    pub fn save_changes(&self, index_path: &Path, changed_doc_ids: &[u32]) -> Result<()> {
+        let _guard = self.persist_lock.lock().unwrap();
+        let Ok(index_metadata) = fs::metadata(index_path) else {
+            return self.write_snapshot(index_path);
+        };
+        let entries: Vec<JournalEntry> = changed_doc_ids
+            .iter()
+            .map(|doc_id| match self.documents.get(doc_id) {
+                Some(doc) => JournalEntry::Upsert(Box::new(doc.clone())),
+                None => JournalEntry::Remove(*doc_id),
+            })
+            .collect();
+        let journal_path = journal::journal_path(index_path);
+        journal::append(&journal_path, &entries)?;
+        let journal_len = fs::metadata(&journal_path).map(|m| m.len()).unwrap_or(0);
+        if journal_len.saturating_mul(JOURNAL_COMPACTION_RATIO) > index_metadata.len() {
+            self.write_snapshot(index_path)?;
         }
+        Ok(())
+    }
 
-        self.documents.insert(doc_id, current_doc);
-        self.clear_cache();
+    #[allow(dead_code)]
+    pub fn add_document(&mut self, doc: Document) {
+        self.insert_documents(vec![Self::tokenize_document(doc)]);
     }
 
-    fn remove_document(&mut self, doc_id: u32) {
-        if let Some(doc_to_remove) = self.documents.remove(&doc_id) {
-            let tokens = crate::tokenizer::tokenize(&doc_to_remove.content);
-            for (token, _) in tokens {
-                if let Some(postings) = self.index.get_mut(&token) {
-                    postings.retain(|&(id, _)| id != doc_id);
-                    if postings.is_empty() {
-                        self.index.remove(&token);
-                    }
-                }
-            }
+    fn insert_tokenized(&mut self, docs: Vec<Document>) {
+        let documents = docs.into_par_iter().map(Self::tokenize_document).collect();
+        self.insert_documents(documents);
+    }
+
+    // The analyzer prose in the document's language is tokenized with
+    pub fn analyzer(doc: &Document) -> &'static str {
+        crate::tokenizer::analyzer_for(doc.language.as_deref())
+    }
 
-            for tag in &doc_to_remove.tags {
-                if let Some(doc_ids) = self.tags.get_mut(tag) {
-                    doc_ids.retain(|&id| id != doc_id);
-                    if doc_ids.is_empty() {
-                        self.tags.remove(tag);
+    // Tokenizing needs no access to the index, so scans do it before the
+    // index is locked for writing.
+    fn tokenize_document(doc: Document) -> TokenizedDocument {
+        let mut term_positions: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut offsets = Vec::new();
+        let mut words = HashSet::new();
+        let mut stop_positions: HashMap<String, Vec<u64>> = HashMap::new();
+        if Self::is_source_code(&doc.path) {
+            // Code tokens are the words themselves
+            for (token, pos, span) in crate::tokenizer::tokenize_code_with_offsets(&doc.content) {
+                words.insert((token.clone(), token.clone()));
+                term_positions.entry(token).or_default().push(pos);
+                offsets.push(span);
+            }
+        } else {
+            let analyzer = Self::analyzer(&doc);
+            let mut stops_since_token = 0u64;
+            for (word, token, span) in
+                crate::tokenizer::words_with_offsets_in(&doc.content, analyzer)
+            {
+                // Stop words take no token position
+                match token {
+                    Some(token) => {
+                        term_positions
+                            .entry(token.clone())
+                            .or_default()
+                            .push(offsets.len());
+                        offsets.push(span);
+                        words.insert((word, token));
+                        stops_since_token = 0;
+                    }
+                    None if analyzer == crate::tokenizer::DEFAULT_ANALYZER => {
+                        let position = (offsets.len() as u64) << 32 | stops_since_token;
+                        stop_positions.entry(word).or_default().push(position);
+                        stops_since_token = (stops_since_token + 1).min(u32::MAX as u64);
                     }
+                    None => {}
                 }
             }
-            self.clear_cache();
+        }
+        let forms = Self::word_forms(&doc.content).map(str::to_string).collect();
+        TokenizedDocument {
+            doc,
+            term_positions,
+            offsets,
+            words,
+            forms,
+            stop_positions,
         }
     }
 
-    fn clear_cache(&self) {
-        let mut cache = self.search_cache.lock().unwrap();
-        cache.clear();
-    }
-
-    pub fn search(&self, query: &str) -> Vec<SearchResult> {
-        if query.is_empty() {
-            return Vec::new();
+    // Indexes a batch of documents, none of them in the index yet, into a new
+    // segment
+    fn insert_documents(&mut self, documents: Vec<TokenizedDocument>) {
+        if documents.is_empty() {
+            return;
         }
+        let mut postings: HashMap<String, TermPostings> = HashMap::new();
+        let mut words: HashMap<(String, String), Vec<u32>> = HashMap::new();
+        let mut forms: HashMap<String, Vec<u32>> = HashMap::new();
+        let mut stop_words: HashMap<String, StopPostings> = HashMap::new();
+        let mut doc_ids = HashSet::new();
+        for tokenized in documents {
+            let TokenizedDocument {
+                doc,
+                term_positions,
+                offsets,
+                words: doc_words,
+                forms: doc_forms,
+                stop_positions,
+            } = tokenized;
+            let doc_id = doc.id;
+            self.token_offsets.insert(doc_id, Stored::new(offsets));
 
-        {
-            let mut cache = self.search_cache.lock().unwrap();
-            if let Some(results) = cache.get(query) {
-                return results.clone();
+            for (token, positions) in term_positions {
+                postings.entry(token).or_default().push((doc_id, positions));
             }
-        }
-
-        let results = if query.starts_with('#') {
-            let tag_name = query[1..].trim().to_lowercase();
-            if tag_name.is_empty() {
-                return Vec::new();
+            for word in doc_words {
+                words.entry(word).or_default().push(doc_id);
+            }
+            for form in doc_forms {
+                forms.entry(form).or_default().push(doc_id);
+            }
+            for (word, positions) in stop_positions {
+                stop_words
+                    .entry(word)
+                    .or_default()
+                    .push((doc_id, positions));
             }
 
-            let mut tag_results: Vec<SearchResult> = Vec::new();
-            if let Some(doc_ids) = self.tags.get(&tag_name) {
-                for &doc_id in doc_ids {
-                    if let Some(doc) = self.documents.get(&doc_id) {
-                        let snippet = "...".to_string();
-                        tag_results.push(SearchResult {
-                            doc: doc.clone(),
-                            score: 1.0,
-                            snippet: snippet,
-                            tags: doc.tags.clone(),
-                        });
-                    }
-                }
+            for tag in &doc.tags {
+                self.tags.entry(tag.clone()).or_default().push(doc_id);
             }
-            tag_results.sort_by(|a, b| {
-                b.score
-                    .partial_cmp(&a.score)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            });
-            tag_results
-        } else if query.starts_with('"') && query.ends_with('"') && query.len() > 1 {
-            let phrase_content = &query[1..query.len() - 1];
-            self.perform_phrase_search_and_rank(phrase_content, query)
-        } else {
-            let mut processed_query_terms: Vec<(String, bool)> = Vec::new();
-
-            for raw_word in query.to_lowercase().split_whitespace() {
-                let clean_word =
-                    raw_word.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '*');
-
-                if clean_word.ends_with('*') && clean_word.len() > 1 {
-                    let prefix = &clean_word[0..clean_word.len() - 1];
-                    let stemmed_prefix_tokens = crate::tokenizer::tokenize(prefix);
-
-                    let mut found_wildcard_matches = false;
-                    for (stemmed_prefix_part, _) in stemmed_prefix_tokens {
-                        for indexed_term in self.index.keys() {
-                            if indexed_term.starts_with(&stemmed_prefix_part) {
-                                processed_query_terms.push((indexed_term.clone(), true));
-                                found_wildcard_matches = true;
+
+            doc_ids.insert(doc_id);
+            self.documents.insert(doc_id, doc);
+        }
+        self.segments
+            .push(Segment::new(postings, words, forms, stop_words, doc_ids));
+        self.generation += 1;
+        self.clear_cache();
+    }
+
+    fn remove_document(&mut self, doc_id: u32) {
+        self.remove_documents(&[doc_id]);
+    }
+
+    // Removes documents by masking them in their segments
+    fn remove_documents(&mut self, doc_ids: &[u32]) {
+        let mut removed_ids: HashSet<u32> = HashSet::new();
+        let mut affected_tags: HashSet<String> = HashSet::new();
+        for doc_id in doc_ids {
+            if let Some(doc_to_remove) = self.documents.remove(doc_id) {
+                self.token_offsets.remove(doc_id);
+                affected_tags.extend(doc_to_remove.tags);
+                removed_ids.insert(*doc_id);
+                for segment in &mut self.segments {
+                    if segment.doc_ids.remove(doc_id) {
+                        segment.deleted += 1;
+                        break;
+                    }
+                }
+            }
+        }
+        if removed_ids.is_empty() {
+            return;
+        }
+
+        for tag in &affected_tags {
+            if let Some(tag_doc_ids) = self.tags.get_mut(tag) {
+                tag_doc_ids.retain(|id| !removed_ids.contains(id));
+                if tag_doc_ids.is_empty() {
+                    self.tags.remove(tag);
+                }
+            }
+        }
+        self.generation += 1;
+        self.clear_cache();
+    }
+
+    // The segments worth merging: each one holding more deleted documents
+    // than live ones and, past MAX_SEGMENTS, the smallest ones
+    fn merge_candidates(&self) -> Vec<usize> {
+        let mut by_size: Vec<usize> = (0..self.segments.len()).collect();
+        by_size.sort_by_key(|&i| self.segments[i].doc_ids.len());
+        let excess = if self.segments.len() > MAX_SEGMENTS {
+            self.segments.len() - MAX_SEGMENTS / 2 + 1
+        } else {
+            0
+        };
+        let mut candidates = by_size[..excess].to_vec();
+        candidates.extend(by_size[excess..].iter().filter(|&&i| {
+            let segment = &self.segments[i];
+            segment.deleted > segment.doc_ids.len()
+        }));
+        candidates
+    }
+
+    fn replace_segments(&mut self, merged_indices: &[usize], merged: Segment) {
+        let mut i = 0;
+        self.segments.retain(|_| {
+            i += 1;
+            !merged_indices.contains(&(i - 1))
+        });
+        if !merged.doc_ids.is_empty() {
+            self.segments.push(merged);
+        }
+        // Lookups by position into the old segments are no longer valid
+        self.generation += 1;
+    }
+
+    fn merge_segments(&mut self) {
+        let candidates = self.merge_candidates();
+        if candidates.is_empty() {
+            return;
+        }
+        let segments: Vec<&Segment> = candidates.iter().map(|&i| &self.segments[i]).collect();
+        let merged = Segment::merge(&segments);
+        self.replace_segments(&candidates, merged);
+    }
+
+    // Merges the segments of a shared index in the background of searches:
+    // the merged segment is built under the read lock and swapped in under the
+    // write lock, unless the index changed meanwhile (the next refresh then
+    // tries again).
+    pub fn merge_segments_shared(index: &RwLock<Self>) {
+        let (candidates, merged, generation) = {
+            let index = index.read().unwrap();
+            let candidates = index.merge_candidates();
+            if candidates.is_empty() {
+                return;
+            }
+            let segments: Vec<&Segment> = candidates.iter().map(|&i| &index.segments[i]).collect();
+            (candidates, Segment::merge(&segments), index.generation)
+        };
+        let mut index = index.write().unwrap();
+        if index.generation == generation {
+            index.replace_segments(&candidates, merged);
+        }
+    }
+
+    fn clear_cache(&self) {
+        let mut cache = self.search_cache.lock().unwrap();
+        cache.clear();
+        self.snippet_cache.lock().unwrap().clear();
+    }
+
+    // Cached results were built with the old lengths, so they go
+    pub fn configure_snippets(&mut self, snippet_config: &SnippetConfig) {
+        self.snippet_policy = snippet_config.clone();
+        self.clear_cache();
+    }
+
+    // The cache keeps every match's score, so a new cutoff applies to cached
+    // queries too; collapsed near-duplicates are left out of it, though
+    pub fn configure_results(&mut self, results_config: &ResultsConfig) {
+        let collapse_changed = self.result_policy.near_duplicates != results_config.near_duplicates;
+        self.result_policy = results_config.clone();
+        if collapse_changed {
+            self.clear_cache();
+        }
+    }
+
+    pub fn configure_query(&mut self, query_config: &QueryConfig) {
+        self.query_policy = query_config.clone();
+        self.clear_cache();
+    }
+
+    pub fn configure_cache(&mut self, cache_config: &CacheConfig) {
+        self.cache_capacity = cache_config.capacity.get();
+        self.cache_policy = cache_config.clone();
+        self.search_cache
+            .lock()
+            .unwrap()
+            .resize(cache_config.capacity);
+        if !cache_config.enabled {
+            self.clear_cache();
+        }
+    }
+
+    pub fn configure_indexing(&mut self, indexing: &IndexingConfig) -> Result<()> {
+        self.extraction_pool = match indexing.threads {
+            Some(threads) => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads.get())
+                    .build()
+                    .context("Failed to start the indexing threads")?,
+            )),
+            None => None,
+        };
+        self.memory_budget = indexing
+            .memory_budget_mb
+            .map(|mb| mb.get().saturating_mul(1024 * 1024));
+        self.file_size_limit = indexing.max_file_mb.map(|mb| FileSizeLimit {
+            max_bytes: mb.get().saturating_mul(1024 * 1024),
+            truncate: indexing.oversized_files == OversizedFiles::Truncate,
+        });
+        self.duplicates = indexing.duplicates;
+        self.fail_fast = indexing.fail_fast;
+        self.update_copy_groups();
+        Ok(())
+    }
+
+    pub fn configure_boosts(&mut self, boosts: &[PathBoost]) {
+        self.path_boosts = boosts
+            .iter()
+            .map(|boost| {
+                let mut patterns = vec![boost.pattern.clone()];
+                // Unanchored patterns may start at any directory
+                if !boost.pattern.starts_with('/') && !boost.pattern.starts_with("**") {
+                    patterns.push(format!("**/{}", boost.pattern));
+                }
+                let patterns = patterns
+                    .iter()
+                    .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+                    .collect();
+                (patterns, boost.multiplier)
+            })
+            .collect();
+        self.clear_cache();
+    }
+
+    // Product of the multipliers of the boosts whose pattern matches `path`
+    fn path_boost(&self, path: &Path) -> f64 {
+        self.path_boosts
+            .iter()
+            .filter(|(patterns, _)| patterns.iter().any(|pattern| pattern.matches_path(path)))
+            .map(|(_, multiplier)| multiplier)
+            .product()
+    }
+
+    // With `recency_half_life_days`, from 2 for a document modified now down
+    // towards 1, halving the difference every half-life
+    fn recency_boost(&self, modified_time: u64, now: u64) -> f64 {
+        let Some(half_life_days) = self.query_policy.recency_half_life_days else {
+            return 1.0;
+        };
+        let age_days = now.saturating_sub(modified_time) as f64 / 86_400.0;
+        1.0 + 0.5f64.powf(age_days / half_life_days)
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            enabled: self.cache_policy.enabled,
+            capacity: self.cache_capacity,
+            ttl: self.cache_policy.ttl_secs.map(Duration::from_secs),
+            entries: self.search_cache.lock().unwrap().len(),
+            hits: self.cache_hits.load(Ordering::Relaxed),
+            misses: self.cache_misses.load(Ordering::Relaxed),
+            expired: self.cache_expired.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn search(&self, query: &str) -> Result<Vec<SearchResult>, QueryError> {
+        Ok(self.search_page(query, 0, None)?.results)
+    }
+
+    // The results ranked `offset` onwards, at most `limit` of them. Only
+    // those are built, and with a `limit` only the best `offset + limit`
+    // matches are ranked in full, so asking for the first page of a broad
+    // query is cheap. Rankings are cached by query, and the next pages of one
+    // come from its cached ranking as long as it ranked that far.
+    pub fn search_page(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<ResultPage, QueryError> {
+        let ranked = self.ranked_matches(query, Self::page_end(offset, limit))?;
+        Ok(self.page(&ranked, offset, limit))
+    }
+
+    // Like `search_page`, for a query built or parsed beforehand rather than
+    // given as text
+    pub fn search_parsed(&self, query: &Query, offset: usize, limit: Option<usize>) -> ResultPage {
+        let ranked = self.ranked_query(&query.to_string(), query, Self::page_end(offset, limit));
+        self.page(&ranked, offset, limit)
+    }
+
+    fn page_end(offset: usize, limit: Option<usize>) -> Option<usize> {
+        limit.map(|limit| offset.saturating_add(limit))
+    }
+
+    // Matches past the ranked ones count towards the total uncollapsed by
+    // `near_duplicates`, since they were never compared
+    fn page(&self, ranked: &RankedMatches, offset: usize, limit: Option<usize>) -> ResultPage {
+        // Hits are best first, so the ones under the cutoff are a tail
+        let min_score = self.result_policy.min_score;
+        let ranked_total = ranked
+            .hits
+            .partition_point(|&(score, _)| score >= min_score);
+        let rest_total = ranked
+            .rest
+            .iter()
+            .filter(|&&score| score >= min_score)
+            .count();
+        let total = ranked_total + rest_total;
+        let end = offset
+            .saturating_add(limit.unwrap_or(usize::MAX))
+            .min(ranked_total);
+        let results = self.build_results(ranked, &ranked.hits[offset.min(end)..end]);
+        ResultPage {
+            results,
+            total,
+            below_min_score: ranked.hits.len() + ranked.rest.len() - total,
+        }
+    }
+
+    // The `limit` documents most like `doc_id`, ranked as a query for its
+    // terms with the highest TF-IDF weights, or None for an unknown id. The
+    // document itself and identical copies of it are left out.
+    pub fn similar(&self, doc_id: u32, limit: usize) -> Option<Vec<SearchResult>> {
+        let doc = self.documents.get(&doc_id)?;
+        let tokens = if Self::is_source_code(&doc.path) {
+            crate::tokenizer::tokenize_code(&doc.content)
+        } else {
+            crate::tokenizer::tokenize_in(&doc.content, Self::analyzer(doc))
+        };
+        let mut term_counts: HashMap<String, usize> = HashMap::new();
+        for (token, _) in tokens {
+            *term_counts.entry(token).or_default() += 1;
+        }
+        let mut weighted_terms: Vec<(String, f64)> = term_counts
+            .into_iter()
+            .filter_map(|(term, count)| {
+                // Terms no other document has find nothing, and ones every
+                // document has tell nothing apart
+                let num_docs_with_term = self.document_frequency(&term);
+                let idf = (self.total_docs as f64 / num_docs_with_term as f64).ln();
+                (num_docs_with_term > 1 && idf > 0.0).then_some((term, count as f64 * idf))
+            })
+            .collect();
+        weighted_terms.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        weighted_terms.truncate(SIMILAR_QUERY_TERMS);
+
+        // Weighted against the top term, so the most telling ones count most
+        let top_weight = weighted_terms.first().map_or(1.0, |(_, weight)| *weight);
+        let mut match_context = MatchContext::default();
+        let mut doc_ids = HashSet::new();
+        for (term, weight) in weighted_terms {
+            doc_ids.extend(self.docs_with_term(&term));
+            match_context.terms.push((term, weight / top_weight));
+        }
+        doc_ids.retain(|other_id| {
+            self.documents
+                .get(other_id)
+                .is_some_and(|other| other.id != doc.id && other.content_hash != doc.content_hash)
+        });
+        let ranked = self.rank_matches(doc_ids, &match_context, Some(limit));
+        Some(self.build_results(&ranked, &ranked.hits))
+    }
+
+    // Results for `hits` from `ranked`, with their snippets
+    fn build_results(&self, ranked: &RankedMatches, hits: &[(f64, u32)]) -> Vec<SearchResult> {
+        hits.iter()
+            .filter_map(|&(score, doc_id)| {
+                self.documents.get(&doc_id).map(|doc| {
+                    let (snippet, lines) = self.cached_snippet(doc, &ranked.highlight_terms);
+                    let copies = if Self::is_whole_text(doc) {
+                        ranked
+                            .copies
+                            .get(&doc.content_hash)
+                            .cloned()
+                            .unwrap_or_default()
+                    } else {
+                        Vec::new()
+                    };
+                    SearchResult {
+                        doc: doc.clone(),
+                        score,
+                        snippet,
+                        lines,
+                        tags: doc.tags.clone(),
+                        copies,
+                        near_duplicates: ranked
+                            .near_duplicates
+                            .get(&doc_id)
+                            .cloned()
+                            .unwrap_or_default(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    // The matches of `query`, the best `limit` of them ranked in full
+    fn ranked_matches(
+        &self,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<Arc<RankedMatches>, QueryError> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Ok(Arc::default());
+        }
+        if let Some(ranked) = self.cached_ranking(query, limit) {
+            return Ok(ranked);
+        }
+        let parsed_query = parse_query(query)?;
+        Ok(self.rank_and_cache(query, &parsed_query, limit))
+    }
+
+    // Ranks `parsed_query`, cached under its text `query`
+    fn ranked_query(
+        &self,
+        query: &str,
+        parsed_query: &Query,
+        limit: Option<usize>,
+    ) -> Arc<RankedMatches> {
+        self.cached_ranking(query, limit)
+            .unwrap_or_else(|| self.rank_and_cache(query, parsed_query, limit))
+    }
+
+    // A cached ranking of `query` covers `limit` when it ranked at least that
+    // many of the best matches, or every match there was
+    fn cached_ranking(&self, query: &str, limit: Option<usize>) -> Option<Arc<RankedMatches>> {
+        if self.cache_policy.enabled {
+            let mut cache = self.search_cache.lock().unwrap();
+            let ttl = self.cache_policy.ttl_secs.map(Duration::from_secs);
+            match cache.get(query) {
+                Some((cached_at, _, _)) if ttl.is_some_and(|ttl| cached_at.elapsed() >= ttl) => {
+                    cache.pop(query);
+                    self.cache_expired.fetch_add(1, Ordering::Relaxed);
+                }
+                Some((_, ranked_end, ranked))
+                    if ranked.rest.is_empty()
+                        || ranked_end.is_some_and(|ranked_end| {
+                            limit.is_some_and(|limit| limit <= ranked_end)
+                        }) =>
+                {
+                    self.cache_hits.fetch_add(1, Ordering::Relaxed);
+                    return Some(ranked.clone());
+                }
+                _ => {}
+            }
+            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        }
+        None
+    }
+
+    // Ranks twice the matches asked for, so paging on with `more` is served
+    // from the cache for a page or so, and once past them twice again
+    fn rank_and_cache(
+        &self,
+        query: &str,
+        parsed_query: &Query,
+        limit: Option<usize>,
+    ) -> Arc<RankedMatches> {
+        let limit = limit.map(|limit| limit.saturating_mul(2));
+        let mut match_context = MatchContext::default();
+        let ranked = Arc::new(
+            match self.evaluate(parsed_query, false, &mut match_context) {
+                Some(doc_ids) => self.rank_matches(doc_ids, &match_context, limit),
+                None => RankedMatches::default(),
+            },
+        );
+
+        if self.cache_policy.enabled {
+            let mut cache = self.search_cache.lock().unwrap();
+            cache.put(query.to_string(), (Instant::now(), limit, ranked.clone()));
+        }
+        ranked
+    }
+
+    // Returns the documents matching `query`, or None when the query places no
+    // constraint (e.g. it consists only of stop words). Terms and phrases from
+    // non-negated parts are recorded in `match_context` for ranking.
+    fn evaluate(
+        &self,
+        query: &Query,
+        negated: bool,
+        match_context: &mut MatchContext,
+    ) -> Option<HashSet<u32>> {
+        match query {
+            Query::Term(word) => {
+                let tokens: Vec<String> = crate::tokenizer::tokenize(word)
+                    .into_iter()
+                    .map(|(token, _)| token)
+                    .collect();
+                let code_match = self.code_matches(word, &tokens);
+                let language_matches = self.language_matches(word, &tokens);
+                let mut matching: Option<HashSet<u32>> = None;
+                for token in tokens {
+                    let term_docs = self.docs_with_term(&token);
+                    if !term_docs.is_empty() && !negated {
+                        match_context.terms.push((token.clone(), 1.0));
+                    }
+                    matching = Some(match matching {
+                        Some(docs) => docs.intersection(&term_docs).copied().collect(),
+                        None => term_docs,
+                    });
+                }
+                // Stop words still place no constraint, unless written as an
+                // identifier (`is_empty`)
+                if let Some((code_tokens, code_docs)) = code_match
+                    && (matching.is_some() || code_tokens.len() > 1)
+                {
+                    matching.get_or_insert_default().extend(code_docs);
+                    if !negated {
+                        Self::record_match_tokens(match_context, code_tokens);
+                    }
+                }
+                if let Some(matching) = &mut matching {
+                    for (language_tokens, language_docs) in language_matches {
+                        matching.extend(language_docs);
+                        if !negated {
+                            Self::record_match_tokens(match_context, language_tokens);
+                        }
+                    }
+                }
+                // Synonyms widen the match, ranking a little below the word
+                let synonym_matches = self.synonym_matches(word);
+                let mut synonym_tokens = Vec::new();
+                for (tokens, synonym_docs) in synonym_matches {
+                    matching.get_or_insert_default().extend(synonym_docs);
+                    synonym_tokens.extend(tokens);
+                }
+                if !negated && let Some(matching) = &matching {
+                    for token in synonym_tokens {
+                        if !match_context.terms.iter().any(|(term, _)| *term == token) {
+                            match_context.terms.push((token.clone(), SYNONYM_WEIGHT));
+                            match_context
+                                .blended_frequencies
+                                .insert(token, matching.len());
+                        }
+                    }
+                }
+                matching
+            }
+            Query::Fuzzy { term, distance } => {
+                let max_distance = distance.unwrap_or(self.query_policy.fuzzy_distance);
+                let mut matching: Option<HashSet<u32>> = None;
+                for (token, _) in crate::tokenizer::tokenize(term) {
+                    let close_terms = self.find_fuzzy_matches(&token, max_distance);
+                    let mut token_docs = HashSet::new();
+                    for (close_term, distance, doc_ids) in close_terms {
+                        token_docs.extend(doc_ids);
+                        if !negated {
+                            let weight = if distance == 0 { 1.0 } else { 0.5 };
+                            match_context.terms.push((close_term, weight));
+                        }
+                    }
+                    matching = Some(match matching {
+                        Some(docs) => docs.intersection(&token_docs).copied().collect(),
+                        None => token_docs,
+                    });
+                }
+                matching
+            }
+            Query::Prefix(prefix) => {
+                let stemmed_prefix_tokens = crate::tokenizer::tokenize(prefix);
+                if stemmed_prefix_tokens.is_empty() {
+                    return None;
+                }
+                let mut matching = HashSet::new();
+                for (stemmed_prefix_part, _) in stemmed_prefix_tokens {
+                    let terms = self.matching_terms(|indexed_term| {
+                        indexed_term.starts_with(&stemmed_prefix_part)
+                    });
+                    for (indexed_term, doc_ids) in terms {
+                        matching.extend(doc_ids);
+                        if !negated {
+                            match_context.terms.push((indexed_term.to_string(), 1.0));
+                        }
+                    }
+                }
+                Some(matching)
+            }
+            Query::Wildcard(pattern) => {
+                let mut matching = HashSet::new();
+                for indexed_term in self.wildcard_terms(pattern) {
+                    matching.extend(self.docs_with_term(&indexed_term));
+                    if !negated {
+                        match_context.terms.push((indexed_term, 1.0));
+                    }
+                }
+                Some(matching)
+            }
+            Query::Exact(text) => {
+                let matching = self.exact_matches(text);
+                // Ranked and highlighted as the terms it is indexed as, unless
+                // it is a stop word, which only filters
+                let tokens = crate::tokenizer::tokenize(text);
+                if !negated && !tokens.is_empty() {
+                    let mut terms: Vec<String> = tokens
+                        .into_iter()
+                        .chain(crate::tokenizer::tokenize_code(text).into_iter().take(1))
+                        .map(|(token, _)| token)
+                        .collect();
+                    terms.sort_unstable();
+                    terms.dedup();
+                    for term in terms {
+                        match_context.terms.push((term, 1.0));
+                    }
+                }
+                Some(matching)
+            }
+            Query::Phrase { text, slop } => {
+                let phrase_tokens: Vec<String> = crate::tokenizer::tokenize(text)
+                    .into_iter()
+                    .map(|(token, _)| token)
+                    .collect();
+                // Stop words take no token position, so an exact phrase with
+                // them is checked against the text of the documents it finds
+                // without them. One of stop words alone is looked up by their
+                // own positions.
+                let phrase_words =
+                    crate::tokenizer::words_in(text, crate::tokenizer::DEFAULT_ANALYZER);
+                let verbatim = *slop == 0 && phrase_words.iter().any(|(_, token)| token.is_none());
+                if phrase_tokens.is_empty() && !verbatim {
+                    return None;
+                }
+                let mut matching: HashSet<u32> = match phrase_tokens.first() {
+                    Some(first_token) => self.docs_with_term(first_token),
+                    None => self.stop_word_phrase_matches(&phrase_words),
+                };
+                for token in phrase_tokens.iter().skip(1) {
+                    let token_docs = self.docs_with_term(token);
+                    matching.retain(|doc_id| token_docs.contains(doc_id));
+                }
+                if phrase_tokens.len() > 1 {
+                    matching.retain(|doc_id| {
+                        self.phrase_match_count(*doc_id, &phrase_tokens, *slop) > 0
+                    });
+                }
+                if verbatim && !phrase_tokens.is_empty() {
+                    // Source files keep their stop words, and match below as code
+                    matching = matching
+                        .into_par_iter()
+                        .filter(|doc_id| {
+                            self.documents.get(doc_id).is_some_and(|doc| {
+                                !Self::is_source_code(&doc.path)
+                                    && Self::contains_words(doc, &phrase_words)
+                            })
+                        })
+                        .collect();
+                }
+                let code_match = self.code_matches(text, &phrase_tokens);
+                for (language_tokens, language_docs) in self.language_matches(text, &phrase_tokens)
+                {
+                    matching.extend(language_docs);
+                    if !negated {
+                        Self::record_match_tokens(match_context, language_tokens);
+                    }
+                }
+                if !negated {
+                    for token in &phrase_tokens {
+                        match_context.terms.push((token.clone(), 1.0));
+                    }
+                    if !phrase_tokens.is_empty() {
+                        match_context.phrases.push((phrase_tokens, *slop));
+                    }
+                }
+                if let Some((code_tokens, code_docs)) = code_match {
+                    matching.extend(code_docs);
+                    if !negated {
+                        Self::record_match_tokens(match_context, code_tokens);
+                    }
+                }
+                Some(matching)
+            }
+            Query::Tag(tag_name) => {
+                if !negated {
+                    match_context.tags.push(tag_name.clone());
+                }
+                Some(
+                    self.tags
+                        .get(tag_name)
+                        .map(|doc_ids| doc_ids.iter().copied().collect())
+                        .unwrap_or_default(),
+                )
+            }
+            Query::Field {
+                field,
+                value,
+                phrase,
+            } => {
+                let value_lower = value.to_lowercase();
+                let value_tokens: Vec<String> = crate::tokenizer::tokenize(value)
+                    .into_iter()
+                    .map(|(token, _)| token)
+                    .collect();
+                Some(
+                    self.documents
+                        .values()
+                        .filter(|doc| match field {
+                            TextField::Title => {
+                                let title_tokens: Vec<String> =
+                                    crate::tokenizer::tokenize(&doc.title)
+                                        .into_iter()
+                                        .map(|(token, _)| token)
+                                        .collect();
+                                if value_tokens.is_empty() {
+                                    false
+                                } else if *phrase {
+                                    title_tokens
+                                        .windows(value_tokens.len())
+                                        .any(|window| window == value_tokens.as_slice())
+                                } else {
+                                    value_tokens.iter().all(|t| title_tokens.contains(t))
+                                }
                             }
+                            TextField::Filename => doc.path.file_name().is_some_and(|name| {
+                                name.to_string_lossy().to_lowercase().contains(&value_lower)
+                            }),
+                            TextField::Author => doc
+                                .author
+                                .as_ref()
+                                .is_some_and(|author| author.to_lowercase().contains(&value_lower)),
+                            // `lang:en` also matches regional variants such as "en-us"
+                            TextField::Language => doc.language.as_ref().is_some_and(|language| {
+                                language == &value_lower
+                                    || language.starts_with(&format!("{}-", value_lower))
+                            }),
+                            TextField::Path => doc
+                                .path
+                                .to_string_lossy()
+                                .to_lowercase()
+                                .contains(&value_lower),
+                            TextField::Type => doc.path.extension().is_some_and(|ext| {
+                                ext.to_string_lossy().to_lowercase() == value_lower
+                            }),
+                            TextField::Source => doc.source.to_lowercase() == value_lower,
+                        })
+                        .map(|doc| doc.id)
+                        .collect(),
+                )
+            }
+            Query::Range {
+                field,
+                lower,
+                upper,
+            } => Some(
+                self.documents
+                    .values()
+                    .filter(|doc| {
+                        let value = match field {
+                            RangeField::Modified => doc.modified_time,
+                            // Documents without a known creation time never match
+                            RangeField::Created => match doc.created_time {
+                                Some(created_time) => created_time,
+                                None => return false,
+                            },
+                            RangeField::Size => doc.size,
+                            RangeField::Tokens => doc.num_tokens as u64,
+                        };
+                        lower.is_none_or(|lower| value >= lower)
+                            && upper.is_none_or(|upper| value <= upper)
+                    })
+                    .map(|doc| doc.id)
+                    .collect(),
+            ),
+            Query::And(operands) => {
+                let mut matching: Option<HashSet<u32>> = None;
+                for operand in operands {
+                    if let Some(operand_docs) = self.evaluate(operand, negated, match_context) {
+                        matching = Some(match matching {
+                            Some(docs) => docs.intersection(&operand_docs).copied().collect(),
+                            None => operand_docs,
+                        });
+                    }
+                }
+                matching
+            }
+            Query::Or(operands) => {
+                let mut matching: Option<HashSet<u32>> = None;
+                for operand in operands {
+                    if let Some(operand_docs) = self.evaluate(operand, negated, match_context) {
+                        matching
+                            .get_or_insert_with(HashSet::new)
+                            .extend(operand_docs);
+                    }
+                }
+                matching
+            }
+            Query::Not(operand) => {
+                let excluded = self.evaluate(operand, !negated, match_context)?;
+                Some(
+                    self.documents
+                        .keys()
+                        .filter(|doc_id| !excluded.contains(doc_id))
+                        .copied()
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    // Documents containing `text` as written, looked up by its words as
+    // written. Only text with other characters around or between its words
+    // is checked against the text of the documents with all of them, and
+    // text without any word against every document.
+    fn exact_matches(&self, text: &str) -> HashSet<u32> {
+        let forms: Vec<&str> = Self::word_forms(text).collect();
+        if forms == [text] {
+            return self.docs_with_form(text);
+        }
+        let candidates = match forms.split_first() {
+            Some((first, others)) => {
+                let mut docs = self.docs_with_form(first);
+                for form in others {
+                    let form_docs = self.docs_with_form(form);
+                    docs.retain(|doc_id| form_docs.contains(doc_id));
+                }
+                docs
+            }
+            None => self.documents.keys().copied().collect(),
+        };
+        candidates
+            .into_par_iter()
+            .filter(|doc_id| {
+                self.documents
+                    .get(doc_id)
+                    .is_some_and(|doc| Self::contains_exact(&doc.content, text))
+            })
+            .collect()
+    }
+
+    fn docs_with_form(&self, form: &str) -> HashSet<u32> {
+        self.segments
+            .iter()
+            .flat_map(|segment| {
+                segment
+                    .forms
+                    .get(form)
+                    .into_iter()
+                    .flatten()
+                    .filter(|doc_id| segment.doc_ids.contains(doc_id))
+            })
+            .copied()
+            .collect()
+    }
+
+    // The words of `text` as written: its longest runs of letters, digits and
+    // underscores, which is what `contains_exact` takes a word to be
+    fn word_forms(text: &str) -> impl Iterator<Item = &str> {
+        text.split(|c: char| !Self::is_word_char(c))
+            .filter(|form| !form.is_empty())
+    }
+
+    fn is_word_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
+    // Whether `text` occurs in `content` exactly, not as part of a longer word
+    fn contains_exact(content: &str, text: &str) -> bool {
+        let is_word_char = Self::is_word_char;
+        let starts_word = text.starts_with(is_word_char);
+        let ends_word = text.ends_with(is_word_char);
+        content.match_indices(text).any(|(start, _)| {
+            let end = start + text.len();
+            (!starts_word || !content[..start].ends_with(is_word_char))
+                && (!ends_word || !content[end..].starts_with(is_word_char))
+        })
+    }
+
+    // Documents of prose with the stop words of `phrase_words` one after
+    // another. Documents in another language than the default have their
+    // text checked, as their stop words are not the same.
+    fn stop_word_phrase_matches(&self, phrase_words: &[(String, Option<String>)]) -> HashSet<u32> {
+        let words: Vec<&str> = phrase_words.iter().map(|(word, _)| word.as_str()).collect();
+        let mut matching: HashSet<u32> = self
+            .segments
+            .par_iter()
+            .flat_map_iter(|segment| {
+                let others: Vec<HashMap<u32, &Vec<u64>>> = words[1..]
+                    .iter()
+                    .map(|word| {
+                        segment
+                            .live_stop_postings(word)
+                            .map(|(doc_id, positions)| (*doc_id, positions))
+                            .collect()
+                    })
+                    .collect();
+                segment
+                    .live_stop_postings(words[0])
+                    .filter(move |(doc_id, first_positions)| {
+                        first_positions.iter().any(|&start| {
+                            others.iter().enumerate().all(|(i, word_postings)| {
+                                word_postings.get(doc_id).is_some_and(|positions| {
+                                    positions.binary_search(&(start + i as u64 + 1)).is_ok()
+                                })
+                            })
+                        })
+                    })
+                    .map(|(doc_id, _)| *doc_id)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        matching.par_extend(self.documents.par_iter().filter_map(|(doc_id, doc)| {
+            (Self::analyzer(doc) != crate::tokenizer::DEFAULT_ANALYZER
+                && !Self::is_source_code(&doc.path)
+                && Self::contains_words(doc, phrase_words))
+            .then_some(*doc_id)
+        }));
+        matching
+    }
+
+    // Whether the words of a phrase occur one after another in the document's
+    // text: its tokens as stemmed, its stop words as written
+    fn contains_words(doc: &Document, phrase_words: &[(String, Option<String>)]) -> bool {
+        let words = crate::tokenizer::words_in(&doc.content, Self::analyzer(doc));
+        words.windows(phrase_words.len()).any(|window| {
+            window
+                .iter()
+                .zip(phrase_words)
+                .all(
+                    |((word, token), (phrase_word, phrase_token))| match phrase_token {
+                        Some(phrase_token) => token.as_ref() == Some(phrase_token),
+                        None => word == phrase_word,
+                    },
+                )
+        })
+    }
+
+    // Documents where `text` occurs split up as source code, whose tokens are
+    // not stemmed, so `parseQuery` or `is_empty` are looked up as written too.
+    // None if that gives the same tokens as the prose analysis, or no matches.
+    fn code_matches(
+        &self,
+        text: &str,
+        prose_tokens: &[String],
+    ) -> Option<(Vec<String>, HashSet<u32>)> {
+        let tokens: Vec<String> = crate::tokenizer::tokenize_code(text)
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        if tokens.is_empty() || tokens == prose_tokens {
+            return None;
+        }
+        let mut matching = self.docs_with_term(&tokens[0]);
+        for token in &tokens[1..] {
+            let token_docs = self.docs_with_term(token);
+            matching.retain(|doc_id| token_docs.contains(doc_id));
+        }
+        if tokens.len() > 1 {
+            matching.retain(|doc_id| self.phrase_match_count(*doc_id, &tokens, 0) > 0);
+        }
+        (!matching.is_empty()).then_some((tokens, matching))
+    }
+
+    // Documents with a synonym of `word` from the synonyms file, and the
+    // synonym's tokens; a synonym of several words must occur as a phrase
+    fn synonym_matches(&self, word: &str) -> Vec<(Vec<String>, HashSet<u32>)> {
+        let Some(synonyms) = self.query_policy.synonym_map.get(&word.to_lowercase()) else {
+            return Vec::new();
+        };
+        synonyms
+            .iter()
+            .filter_map(|synonym| {
+                let tokens: Vec<String> = crate::tokenizer::tokenize(synonym)
+                    .into_iter()
+                    .map(|(token, _)| token)
+                    .collect();
+                let mut matching = self.docs_with_term(tokens.first()?);
+                for token in &tokens[1..] {
+                    let token_docs = self.docs_with_term(token);
+                    matching.retain(|doc_id| token_docs.contains(doc_id));
+                }
+                if tokens.len() > 1 {
+                    matching.retain(|doc_id| self.phrase_match_count(*doc_id, &tokens, 0) > 0);
+                }
+                (!matching.is_empty()).then_some((tokens, matching))
+            })
+            .collect()
+    }
+
+    // Another analysis of a query word or phrase that matched, as code or in
+    // another language: its tokens rank and highlight like the phrase they form
+    fn record_match_tokens(match_context: &mut MatchContext, tokens: Vec<String>) {
+        for token in &tokens {
+            if !match_context.terms.iter().any(|(term, _)| term == token) {
+                match_context.terms.push((token.clone(), 1.0));
+            }
+        }
+        if tokens.len() > 1 {
+            match_context.phrases.push((tokens, 0));
+        }
+    }
+
+    // Documents in another language the index holds where `text` occurs as
+    // analyzed in that language, so `Häuser` also finds German text stemmed
+    // to "haus". Languages giving the same tokens as English, or no matches,
+    // are left out.
+    fn language_matches(
+        &self,
+        text: &str,
+        prose_tokens: &[String],
+    ) -> Vec<(Vec<String>, HashSet<u32>)> {
+        let mut matches = Vec::new();
+        for &analyzer in &self.analyzers {
+            let tokens: Vec<String> = crate::tokenizer::tokenize_in(text, analyzer)
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect();
+            if tokens.is_empty() || tokens == prose_tokens {
+                continue;
+            }
+            let mut matching = self.docs_with_term(&tokens[0]);
+            for token in &tokens[1..] {
+                let token_docs = self.docs_with_term(token);
+                matching.retain(|doc_id| token_docs.contains(doc_id));
+            }
+            matching.retain(|doc_id| {
+                self.documents
+                    .get(doc_id)
+                    .is_some_and(|doc| Self::analyzer(doc) == analyzer)
+            });
+            if tokens.len() > 1 {
+                matching.retain(|doc_id| self.phrase_match_count(*doc_id, &tokens, 0) > 0);
+            }
+            if !matching.is_empty() {
+                matches.push((tokens, matching));
+            }
+        }
+        matches
+    }
+
+    // Segments are searched in parallel
+    fn docs_with_term(&self, term: &str) -> HashSet<u32> {
+        self.segments
+            .par_iter()
+            .flat_map_iter(|segment| segment.live_postings(term).map(|(doc_id, _)| *doc_id))
+            .collect()
+    }
+
+    fn document_frequency(&self, term: &str) -> usize {
+        self.segments
+            .par_iter()
+            .map(|segment| segment.live_postings(term).count())
+            .sum()
+    }
+
+    // The terms of a document's title, and of its tags
+    fn field_terms(doc: &Document) -> (HashSet<String>, HashSet<String>) {
+        let analyzer = Self::analyzer(doc);
+        let title_terms = crate::tokenizer::tokenize_in(&doc.title, analyzer)
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        let tag_terms = doc
+            .tags
+            .iter()
+            .flat_map(|tag| crate::tokenizer::tokenize_in(tag, analyzer))
+            .map(|(token, _)| token)
+            .collect();
+        (title_terms, tag_terms)
+    }
+
+    // A term's weight in a TF-IDF vector, from its count in the document (or
+    // query) and the number of documents it occurs in
+    fn tf_idf(&self, tf: usize, num_docs_with_term: f64) -> f64 {
+        if tf == 0 || num_docs_with_term == 0.0 {
+            return 0.0;
+        }
+        let idf = (1.0 + self.total_docs as f64 / num_docs_with_term).ln();
+        (1.0 + (tf as f64).ln()) * idf
+    }
+
+    fn vector_norms(&self) -> Arc<HashMap<u32, f64>> {
+        let mut vector_norms = self.vector_norms.lock().unwrap();
+        if let Some(norms) = &*vector_norms {
+            return norms.clone();
+        }
+        let document_frequencies: HashMap<&str, usize> = self
+            .matching_terms(|_| true)
+            .into_iter()
+            .map(|(term, doc_ids)| (term, doc_ids.len()))
+            .collect();
+        let mut squared_norms: HashMap<u32, f64> = HashMap::new();
+        for segment in &self.segments {
+            for term in segment.postings.keys() {
+                let Some(&num_docs_with_term) = document_frequencies.get(term.as_str()) else {
+                    continue;
+                };
+                for (doc_id, positions) in segment.live_postings(term) {
+                    let weight = self.tf_idf(positions.len(), num_docs_with_term as f64);
+                    *squared_norms.entry(*doc_id).or_default() += weight * weight;
+                }
+            }
+        }
+        let norms: Arc<HashMap<u32, f64>> = Arc::new(
+            squared_norms
+                .into_iter()
+                .map(|(doc_id, squared)| (doc_id, squared.sqrt()))
+                .collect(),
+        );
+        *vector_norms = Some(norms.clone());
+        norms
+    }
+
+    // Terms of the words `pattern` matches, each `*` in it standing for any
+    // run of characters. Only the words sharing its first or last letters
+    // are checked; a pattern with `*` at both ends checks every word.
+    fn wildcard_terms(&self, pattern: &str) -> Vec<String> {
+        let parts: Vec<&str> = pattern.split('*').collect();
+        let mut terms: Vec<String> = self
+            .segments
+            .par_iter()
+            .flat_map_iter(|segment| {
+                segment
+                    .lexicon
+                    .matching_terms(&parts, &segment.doc_ids)
+                    .into_iter()
+                    .cloned()
+            })
+            .collect();
+        terms.sort_unstable();
+        terms.dedup();
+        terms
+    }
+
+    // The range of `sorted` whose keys start with `prefix`
+    fn prefix_range<T>(
+        sorted: &[T],
+        prefix: &str,
+        key: impl Fn(&T) -> &String,
+    ) -> std::ops::Range<usize> {
+        let start = sorted.partition_point(|entry| key(entry).as_str() < prefix);
+        let len = sorted[start..].partition_point(|entry| key(entry).starts_with(prefix));
+        start..start + len
+    }
+
+    // Whether `word` is the pattern split at its `*`s into `parts`
+    fn wildcard_matches(word: &str, parts: &[&str]) -> bool {
+        let (prefix, suffix) = (parts[0], parts[parts.len() - 1]);
+        if word.len() < prefix.len() + suffix.len()
+            || !word.starts_with(prefix)
+            || !word.ends_with(suffix)
+        {
+            return false;
+        }
+        let mut middle = &word[prefix.len()..word.len() - suffix.len()];
+        for part in &parts[1..parts.len() - 1] {
+            match middle.find(part) {
+                Some(idx) => middle = &middle[idx + part.len()..],
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Each indexed term satisfying `predicate`, with the documents containing it
+    fn matching_terms(
+        &self,
+        predicate: impl Fn(&str) -> bool + Sync,
+    ) -> HashMap<&str, HashSet<u32>> {
+        self.segments
+            .par_iter()
+            .map(|segment| {
+                let mut found: HashMap<&str, HashSet<u32>> = HashMap::new();
+                for term in segment.postings.keys() {
+                    if !predicate(term) {
+                        continue;
+                    }
+                    let mut live = segment.live_postings(term).peekable();
+                    if live.peek().is_some() {
+                        found
+                            .entry(term.as_str())
+                            .or_default()
+                            .extend(live.map(|(doc_id, _)| *doc_id));
+                    }
+                }
+                found
+            })
+            .reduce(HashMap::new, |mut found, more| {
+                for (term, doc_ids) in more {
+                    found.entry(term).or_default().extend(doc_ids);
+                }
+                found
+            })
+    }
+
+    fn term_positions(&self, term: &str, doc_id: u32) -> Option<&Vec<usize>> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|segment| segment.doc_ids.contains(&doc_id))?;
+        segment
+            .postings
+            .get(term)?
+            .iter()
+            .find(|(id, _)| *id == doc_id)
+            .map(|(_, positions)| positions)
+    }
+
+    // Number of places where `phrase_tokens` occur in order, with at most
+    // `slop` other positions between them in all (consecutively for 0)
+    fn phrase_match_count(&self, doc_id: u32, phrase_tokens: &[String], slop: usize) -> usize {
+        let Some(first_token_positions) = self.term_positions(&phrase_tokens[0], doc_id) else {
+            return 0;
+        };
+        let mut other_token_positions: Vec<&Vec<usize>> = Vec::new();
+        for token in &phrase_tokens[1..] {
+            match self.term_positions(token, doc_id) {
+                Some(positions) => other_token_positions.push(positions),
+                None => return 0,
+            }
+        }
+
+        first_token_positions
+            .iter()
+            .filter(|&&start_pos| {
+                // Taking each token's first position after the previous one
+                // leaves the most slop for the rest
+                let mut previous = start_pos;
+                let mut spare = slop;
+                other_token_positions.iter().all(|positions| {
+                    let next = positions.partition_point(|&pos| pos <= previous);
+                    match positions.get(next) {
+                        Some(&pos) if pos - previous - 1 <= spare => {
+                            spare -= pos - previous - 1;
+                            previous = pos;
+                            true
                         }
+                        _ => false,
+                    }
+                })
+            })
+            .count()
+    }
+
+    // Indexed terms at most `max_distance` edits from `query_token`, closest
+    // first, with their distances and documents. Swapping two neighbouring
+    // letters counts as one edit, so `recieve~1` finds "receive".
+    fn find_fuzzy_matches(
+        &self,
+        query_token: &str,
+        max_distance: usize,
+    ) -> Vec<(String, usize, HashSet<u32>)> {
+        let mut fuzzy_matches: Vec<(String, usize, HashSet<u32>)> = self
+            .matching_terms(|indexed_term| {
+                strsim::damerau_levenshtein(query_token, indexed_term) <= max_distance
+            })
+            .into_iter()
+            .map(|(indexed_term, doc_ids)| {
+                let distance = strsim::damerau_levenshtein(query_token, indexed_term);
+                (indexed_term.to_string(), distance, doc_ids)
+            })
+            .collect();
+        fuzzy_matches.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        fuzzy_matches
+    }
+
+    // BM25 over the matched terms, plus one point per exact phrase occurrence.
+    // Queries made only of filters (tags, fields, ranges) score every match 1.0,
+    // or one point per tag of the query it carries, so `#rust OR #go` puts
+    // documents with both first.
+    // Path boosts from the config then scale the score. With a `limit`, only
+    // that many of the best matches are ranked; the others are only counted.
+    fn rank_matches(
+        &self,
+        doc_ids: HashSet<u32>,
+        match_context: &MatchContext,
+        limit: Option<usize>,
+    ) -> RankedMatches {
+        let mut ranked_results: Vec<(f64, u32)> = Vec::new();
+        let document_frequencies: Vec<f64> = match_context
+            .terms
+            .iter()
+            .map(
+                |(term, _)| match match_context.blended_frequencies.get(term) {
+                    Some(&frequency) => frequency as f64,
+                    None => self.document_frequency(term) as f64,
+                },
+            )
+            .collect();
+
+        // Under cosine ranking, the query vector's weights and length
+        let (vector_norms, query_weights, query_norm) =
+            if self.query_policy.ranking == Ranking::Cosine {
+                let query_weights: Vec<f64> = match_context
+                    .terms
+                    .iter()
+                    .zip(&document_frequencies)
+                    .map(|((_, weight), &num_docs_with_term)| {
+                        weight * self.tf_idf(1, num_docs_with_term)
+                    })
+                    .collect();
+                let query_norm = query_weights.iter().map(|w| w * w).sum::<f64>().sqrt();
+                (Some(self.vector_norms()), query_weights, query_norm)
+            } else {
+                (None, Vec::new(), 0.0)
+            };
+
+        let (k1, b) = (self.query_policy.bm25_k1, self.query_policy.bm25_b);
+        let (title_boost, tag_boost) = (self.query_policy.title_boost, self.query_policy.tag_boost);
+        let now = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        for doc_id in doc_ids {
+            let doc = self.documents.get(&doc_id);
+            let doc_len = doc.map_or(0.0, |d| d.num_tokens as f64);
+            // Terms the title or a tag has too count more
+            let (title_terms, tag_terms) = match doc {
+                Some(doc) if !match_context.terms.is_empty() => Self::field_terms(doc),
+                _ => Default::default(),
+            };
+            let field_boost = |term: &str| {
+                let mut boost = 1.0;
+                if title_terms.contains(term) {
+                    boost *= title_boost;
+                }
+                if tag_terms.contains(term) {
+                    boost *= tag_boost;
+                }
+                boost
+            };
+
+            let mut score = 0.0;
+            // Phrases only filter under cosine ranking, whose scores stay
+            // between 0 and 1
+            if let Some(vector_norms) = &vector_norms {
+                let doc_norm = vector_norms.get(&doc_id).copied().unwrap_or(0.0);
+                for (((term, _), &num_docs_with_term), query_weight) in match_context
+                    .terms
+                    .iter()
+                    .zip(&document_frequencies)
+                    .zip(&query_weights)
+                {
+                    let tf = self.term_positions(term, doc_id).map_or(0, |v| v.len());
+                    score += self.tf_idf(tf, num_docs_with_term) * query_weight * field_boost(term);
+                }
+                if doc_norm > 0.0 && query_norm > 0.0 {
+                    score /= doc_norm * query_norm;
+                }
+            } else {
+                for ((term, weight), &num_docs_with_term) in
+                    match_context.terms.iter().zip(&document_frequencies)
+                {
+                    let tf = self.term_positions(term, doc_id).map_or(0, |v| v.len()) as f64;
+                    if tf == 0.0 {
+                        continue;
                     }
-                    if !found_wildcard_matches {
-                        if query.split_whitespace().count() == 1 && processed_query_terms.is_empty()
-                        {
-                            return Vec::new();
-                        }
+
+                    let idf = ((self.total_docs as f64 - num_docs_with_term + 0.5)
+                        / (num_docs_with_term + 0.5)
+                        + 1.0)
+                        .log10();
+
+                    let term_freq_comp = (tf * (k1 + 1.0))
+                        / (tf + k1 * (1.0 - b + b * (doc_len / self.avg_doc_length.max(1.0))));
+
+                    score += idf * term_freq_comp * weight * field_boost(term);
+                }
+
+                for (phrase_tokens, slop) in &match_context.phrases {
+                    if phrase_tokens.len() > 1 {
+                        score += self.phrase_match_count(doc_id, phrase_tokens, *slop) as f64;
                     }
-                } else {
-                    let normal_tokens = crate::tokenizer::tokenize(clean_word);
-                    for (token, _) in normal_tokens {
-                        if !token.is_empty() {
-                            processed_query_terms.push((token, false));
+                }
+            }
+
+            if match_context.terms.is_empty() && match_context.phrases.is_empty() {
+                let tags_carried = doc.map_or(0, |doc| {
+                    let mut tags: Vec<&String> = match_context
+                        .tags
+                        .iter()
+                        .filter(|tag| doc.tags.contains(tag))
+                        .collect();
+                    tags.sort_unstable();
+                    tags.dedup();
+                    tags.len()
+                });
+                score = tags_carried.max(1) as f64;
+            }
+            if let Some(doc) = doc {
+                score *= self.path_boost(&doc.path) * self.recency_boost(doc.modified_time, now);
+            }
+            ranked_results.push((score, doc_id));
+        }
+
+        let mut terms_for_snippet_highlighting: Vec<String> = match_context
+            .terms
+            .iter()
+            .map(|(term, _)| term.clone())
+            .collect();
+        terms_for_snippet_highlighting.sort_unstable();
+        terms_for_snippet_highlighting.dedup();
+
+        // Under `duplicates = "link"`, the best ranked copy of a file stands
+        // for the others that matched, and under `near_duplicates` the best
+        // ranked of near-identical documents. Matches are kept or left out in
+        // rank order.
+        let mut copies: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        let mut near_duplicates: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut groups = self
+            .result_policy
+            .near_duplicates
+            .map(near_duplicates::Groups::new);
+        let mut keep = |doc_id: u32, compare_near_duplicates: bool| {
+            if self.duplicates == DuplicateFiles::Link {
+                let Some(doc) = self.documents.get(&doc_id) else {
+                    return false;
+                };
+                let has_copies = self
+                    .copy_groups
+                    .get(&doc.content_hash)
+                    .is_some_and(|doc_ids| doc_ids.len() > 1);
+                if has_copies && Self::is_whole_text(doc) {
+                    match copies.get_mut(&doc.content_hash) {
+                        Some(paths) => {
+                            paths.push(doc.path.clone());
+                            return false;
+                        }
+                        None => {
+                            copies.insert(doc.content_hash, Vec::new());
                         }
                     }
                 }
             }
-
-            if processed_query_terms.is_empty() {
-                return Vec::new();
+            if compare_near_duplicates
+                && let Some(groups) = &mut groups
+                && let Some(signature) = self.signature(doc_id)
+                && let Some(head) = groups.insert(doc_id, signature)
+            {
+                near_duplicates.entry(head).or_default().push(doc_id);
+                return false;
             }
+            true
+        };
 
-            self.perform_keyword_search_and_rank(&processed_query_terms, query)
+        let (ranked_results, rest) = match limit {
+            // Only the best are wanted, so they come off a heap until there
+            // are enough rather than every match being sorted
+            Some(limit) => {
+                let mut heap: BinaryHeap<Hit> = ranked_results
+                    .into_iter()
+                    .map(|(score, doc_id)| Hit(score, doc_id))
+                    .collect();
+                let mut hits = Vec::with_capacity(limit.min(heap.len()));
+                while hits.len() < limit
+                    && let Some(Hit(score, doc_id)) = heap.pop()
+                {
+                    if keep(doc_id, true) {
+                        hits.push((score, doc_id));
+                    }
+                }
+                // The rest are only counted. Copies among them still go with
+                // their best ranked file, so they are taken in rank order, but
+                // they are not compared for near-duplicates.
+                let mut rest = heap.into_vec();
+                if self.duplicates == DuplicateFiles::Link {
+                    rest.sort_unstable_by(|a, b| b.cmp(a));
+                }
+                let rest = rest
+                    .into_iter()
+                    .filter(|&Hit(_, doc_id)| keep(doc_id, false))
+                    .map(|Hit(score, _)| score)
+                    .collect();
+                (hits, rest)
+            }
+            None => {
+                ranked_results.sort_by_key(|&(score, doc_id)| Reverse(Hit(score, doc_id)));
+                ranked_results.retain(|&(_, doc_id)| keep(doc_id, true));
+                (ranked_results, Vec::new())
+            }
         };
 
-        {
-            let mut cache = self.search_cache.lock().unwrap();
-            cache.put(query.to_string(), results.clone());
+        RankedMatches {
+            hits: ranked_results,
+            rest,
+            highlight_terms: terms_for_snippet_highlighting,
+            copies,
+            near_duplicates,
         }
+    }
 
-        results
+    // Whole files only, like copies: rows and sections of one file often
+    // repeat each other
+    fn signature(&self, doc_id: u32) -> Option<Arc<Signature>> {
+        if let Some(signature) = self.signatures.lock().unwrap().get(&doc_id) {
+            return signature.clone();
+        }
+        let signature = self
+            .documents
+            .get(&doc_id)
+            .filter(|doc| Self::is_whole_text(doc))
+            .and_then(|doc| {
+                let tokens = if Self::is_source_code(&doc.path) {
+                    crate::tokenizer::tokenize_code(&doc.content)
+                } else {
+                    crate::tokenizer::tokenize_in(&doc.content, Self::analyzer(doc))
+                };
+                let words: Vec<String> = tokens.into_iter().map(|(token, _)| token).collect();
+                Signature::of(&words).map(Arc::new)
+            });
+        self.signatures
+            .lock()
+            .unwrap()
+            .insert(doc_id, signature.clone());
+        signature
     }
 
-    fn find_fuzzy_matches(&self, query_token: &str) -> Vec<(String, usize)> {
-        let mut fuzzy_matches = Vec::new();
-        for (indexed_term, _) in &self.index {
-            let distance = strsim::levenshtein(query_token, indexed_term);
-            if distance <= FUZZY_THRESHOLD {
-                fuzzy_matches.push((indexed_term.clone(), distance));
-            }
+    fn cached_snippet(&self, doc: &Document, terms_for_snippet_highlighting: &[String]) -> Snippet {
+        if !self.cache_policy.enabled {
+            return self.build_snippet(doc, terms_for_snippet_highlighting);
         }
-        fuzzy_matches.sort_by_key(|(_, distance)| *distance);
-        fuzzy_matches
+        let key = (doc.id, terms_for_snippet_highlighting.to_vec());
+        if let Some(snippet) = self.snippet_cache.lock().unwrap().get(&key) {
+            return snippet.clone();
+        }
+        let snippet = self.build_snippet(doc, terms_for_snippet_highlighting);
+        self.snippet_cache.lock().unwrap().put(key, snippet.clone());
+        snippet
     }
 
-    fn perform_keyword_search_and_rank(
-        &self,
-        processed_query_terms: &[(String, bool)],
-        _original_query: &str,
-    ) -> Vec<SearchResult> {
-        let mut candidate_docs: HashMap<u32, HashMap<String, Vec<usize>>> = HashMap::new();
-        let mut fuzzy_matched_terms: HashMap<String, String> = HashMap::new();
-
-        for (token, is_wildcard_origin) in processed_query_terms {
-            if let Some(doc_entries) = self.index.get(token) {
-                for (doc_id, positions) in doc_entries {
-                    candidate_docs
-                        .entry(*doc_id)
-                        .or_insert_with(HashMap::new)
-                        .insert(token.clone(), positions.clone());
-                }
+    // Highlights every match inside the snippet, using the stored token
+    // positions and offsets rather than searching the text for the (stemmed)
+    // terms. Text files get the first matching lines with their numbers;
+    // other formats windows around the first few matches far enough apart,
+    // and table rows the line of their record.
+    fn build_snippet(&self, doc: &Document, terms_for_snippet_highlighting: &[String]) -> Snippet {
+        let offsets = self.token_offsets.get(&doc.id);
+        let mut match_spans: Vec<(usize, usize)> = terms_for_snippet_highlighting
+            .iter()
+            .filter_map(|term| self.term_positions(term, doc.id))
+            .flatten()
+            .filter_map(|pos| offsets.and_then(|offsets| offsets.get(*pos)).copied())
+            .collect();
+        match_spans.sort_unstable();
+        match_spans.dedup();
+
+        let row_lines: Vec<usize> = doc.row.into_iter().collect();
+        if match_spans.is_empty() {
+            let preview = doc.description.as_deref().unwrap_or(&doc.content);
+            let preview_end = preview
+                .char_indices()
+                .nth(self.snippet_policy.preview_chars)
+                .map_or(preview.len(), |(idx, _)| idx);
+            let ellipsis = if preview_end < preview.len() {
+                "..."
             } else {
-                if !is_wildcard_origin {
-                    let matches = self.find_fuzzy_matches(token);
-                    if let Some((closest_match, distance)) = matches.into_iter().next() {
-                        if let Some(doc_entries) = self.index.get(&closest_match) {
-                            for (doc_id, positions) in doc_entries {
-                                candidate_docs
-                                    .entry(*doc_id)
-                                    .or_insert_with(HashMap::new)
-                                    .insert(closest_match.clone(), positions.clone());
-                            }
-                            fuzzy_matched_terms.insert(token.clone(), closest_match.clone());
-                            println!(
-                                "Note: Fuzzy matched '{}' to '{}' (distance: {})",
-                                token.yellow(),
-                                closest_match.yellow(),
-                                distance
-                            );
-                        } else {
-                        }
-                    } else {
-                        if processed_query_terms.len() == 1 {
-                            return Vec::new();
-                        }
-                    }
-                } else {
-                }
-            }
+                ""
+            };
+            return (
+                format!("{}{}", &preview[..preview_end], ellipsis),
+                row_lines,
+            );
+        }
+        if doc.row.is_none() && Self::is_line_oriented(&doc.path) {
+            return self.line_snippet(&doc.content, &match_spans, 1);
+        }
+        // Markdown sections too, numbered as lines of their file
+        if let Some(first_line) = doc.row.filter(|_| Self::is_markdown(&doc.path)) {
+            return self.line_snippet(&doc.content, &match_spans, first_line);
         }
 
-        let mut intersection_results: HashMap<u32, HashMap<String, Vec<usize>>> = HashMap::new();
-        for (doc_id, term_map) in candidate_docs {
-            let mut all_terms_present = true;
-            for (q_token_original, is_wildcard_origin) in processed_query_terms {
-                let actual_term = if *is_wildcard_origin {
-                    q_token_original
-                } else {
-                    fuzzy_matched_terms
-                        .get(q_token_original)
-                        .unwrap_or(q_token_original)
-                };
+        let passages: Vec<String> = self
+            .passage_windows(&doc.content, &match_spans)
+            .into_iter()
+            .map(|(byte_start, byte_end)| {
+                Self::highlight(&doc.content, byte_start, byte_end, &match_spans)
+            })
+            .collect();
+        (format!("...{}...", passages.join(" ... ")), row_lines)
+    }
 
-                if !term_map.contains_key(actual_term) {
-                    all_terms_present = false;
-                    break;
-                }
+    // Byte ranges around up to `[snippets] max_passages` matches, each match
+    // past the end of the window before; windows that overlap are merged
+    fn passage_windows(&self, text: &str, match_spans: &[(usize, usize)]) -> Vec<(usize, usize)> {
+        let max_passages = self.snippet_policy.max_passages.get();
+        let mut windows: Vec<(usize, usize)> = Vec::new();
+        let mut passages = 0;
+        for &(start, end) in match_spans {
+            if windows
+                .last()
+                .is_some_and(|&(_, window_end)| start < window_end)
+            {
+                continue;
+            }
+            if passages == max_passages {
+                break;
             }
-            if all_terms_present {
-                intersection_results.insert(doc_id, term_map);
+            passages += 1;
+            let (window_start, window_end) = self.context_window(text, start, end);
+            match windows.last_mut() {
+                Some(last) if window_start <= last.1 => last.1 = window_end,
+                _ => windows.push((window_start, window_end)),
             }
         }
+        windows
+    }
 
-        let mut ranked_results: Vec<(f64, u32)> = Vec::new();
+    pub fn is_line_oriented(path: &Path) -> bool {
+        matches!(
+            path.extension().and_then(|s| s.to_str()),
+            Some("txt") | Some("md") | Some("csv") | Some("tsv")
+        ) || Self::is_source_code(path)
+    }
 
-        for (doc_id, term_frequencies_and_pos) in intersection_results {
-            let mut score = 0.0;
-            let doc_len = self
-                .documents
-                .get(&doc_id)
-                .map_or(0.0, |d| d.num_tokens as f64);
+    fn is_source_code(path: &Path) -> bool {
+        path.extension()
+            .and_then(|s| s.to_str())
+            .is_some_and(|ext| SOURCE_CODE_EXTENSIONS.contains(&ext))
+    }
 
-            for (q_token_original, is_wildcard_origin) in processed_query_terms {
-                let actual_term = if *is_wildcard_origin {
-                    q_token_original
-                } else {
-                    fuzzy_matched_terms
-                        .get(q_token_original)
-                        .unwrap_or(q_token_original)
-                };
+    fn is_table(path: &Path) -> bool {
+        matches!(
+            path.extension().and_then(|s| s.to_str()),
+            Some("csv") | Some("tsv")
+        )
+    }
+
+    // Text formats still readable from their first lines; PDFs, OpenDocument
+    // files and JSON documents are not
+    fn can_truncate(path: &Path) -> bool {
+        extractor::for_path(path).is_some_and(|extractor| extractor.can_truncate())
+    }
 
-                let tf = term_frequencies_and_pos
-                    .get(actual_term)
-                    .map_or(0, |v| v.len()) as f64;
+    // Whether a file of `size` bytes is over the size limit: None if not (or
+    // if it is an archive, whose files count one by one), otherwise whether it
+    // is indexed in part
+    fn oversized(&self, path: &Path, size: u64) -> Option<bool> {
+        let limit = self.file_size_limit?;
+        (size > limit.max_bytes && !archive::is_archive(path))
+            .then(|| limit.truncate && Self::can_truncate(path))
+    }
 
-                if tf == 0.0 {
-                    continue;
-                }
+    fn is_markdown(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "md")
+    }
+
+    fn is_json(path: &Path) -> bool {
+        path.extension()
+            .and_then(|s| s.to_str())
+            .is_some_and(|ext| json_records::EXTENSIONS.contains(&ext))
+    }
+
+    // Byte range reaching `[snippets] context_chars` characters either side
+    // of a match
+    fn context_window(&self, text: &str, match_start: usize, match_end: usize) -> (usize, usize) {
+        let context_chars = self.snippet_policy.context_chars.get();
+        let byte_start = text[..match_start]
+            .char_indices()
+            .rev()
+            .nth(context_chars - 1)
+            .map_or(0, |(idx, _)| idx);
+        let byte_end = text[match_end..]
+            .char_indices()
+            .nth(context_chars)
+            .map_or(text.len(), |(idx, _)| match_end + idx);
+        (byte_start, byte_end)
+    }
+
+    // `text[byte_start..byte_end]` with the spans lying fully inside it highlighted
+    fn highlight(
+        text: &str,
+        byte_start: usize,
+        byte_end: usize,
+        spans: &[(usize, usize)],
+    ) -> String {
+        let mut highlighted = String::new();
+        let mut cursor = byte_start;
+        for &(start, end) in spans {
+            if start < cursor || end > byte_end {
+                continue;
+            }
+            highlighted.push_str(&text[cursor..start]);
+            highlighted.push_str(&text[start..end].red().bold().to_string());
+            cursor = end;
+        }
+        highlighted.push_str(&text[cursor..byte_end]);
+        highlighted
+    }
+
+    // Up to `[snippets] max_lines` matching lines as `<line> | <text>`, long
+    // lines clipped around their first match, plus the numbers of all matching
+    // lines. Lines are numbered from `first_line`, where the text starts in its
+    // file.
+    fn line_snippet(
+        &self,
+        content: &str,
+        match_spans: &[(usize, usize)],
+        first_line: usize,
+    ) -> Snippet {
+        let max_lines = self.snippet_policy.max_lines.get();
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset);
 
-                let num_docs_with_term = self.index.get(actual_term).map_or(0, |v| v.len()) as f64;
+        let mut lines: Vec<usize> = match_spans
+            .iter()
+            .map(|(start, _)| line_of(*start))
+            .collect();
+        lines.dedup();
+
+        let shown_lines = &lines[..lines.len().min(max_lines)];
+        let file_line = |line: usize| line + first_line - 1;
+        let number_width = shown_lines
+            .last()
+            .map_or(1, |&line| file_line(line).to_string().len());
+        let mut rows = Vec::new();
+        for &line in shown_lines {
+            let line_start = line_starts[line - 1];
+            let line_end = line_starts.get(line).map_or(content.len(), |next| next - 1);
+            let line_end = if content[line_start..line_end].ends_with('\r') {
+                line_end - 1
+            } else {
+                line_end
+            };
+            let (first_start, first_end) = match_spans
+                .iter()
+                .copied()
+                .find(|(start, _)| line_of(*start) == line)
+                .unwrap_or((line_start, line_start));
+            let (window_start, window_end) = self.context_window(content, first_start, first_end);
+            let clip_start = window_start.max(line_start);
+            let clip_end = window_end.min(line_end);
+            let mut text = Self::highlight(content, clip_start, clip_end, match_spans);
+            if clip_start > line_start {
+                text.insert_str(0, "...");
+            }
+            if clip_end < line_end {
+                text.push_str("...");
+            }
+            rows.push(format!(
+                "{:>width$} | {}",
+                file_line(line),
+                text,
+                width = number_width
+            ));
+        }
+        if lines.len() > max_lines {
+            rows.push(format!(
+                "{:>width$} | ({} more)",
+                "",
+                lines.len() - max_lines,
+                width = number_width
+            ));
+        }
+        (rows.join("\n"), lines.into_iter().map(file_line).collect())
+    }
 
-                let idf = ((self.total_docs as f64 - num_docs_with_term + 0.5)
-                    / (num_docs_with_term + 0.5)
-                    + 1.0)
-                    .log10();
+    // Helper function to extract text and the document information metadata
+    // from a PDF file
+    pub(crate) fn extract_text_from_pdf(path: &Path) -> Result<(String, ExtractedMetadata)> {
+        let mut pdf = pdf_extract::Document::load(path).context("Failed to load PDF")?;
+        if pdf.is_encrypted() {
+            pdf.decrypt("").context("Failed to decrypt PDF")?;
+        }
+        let mut text = String::new();
+        output_doc(&pdf, &mut PlainTextOutput::new(&mut text))
+            .context("Failed to extract text from PDF")?;
+        let text_entry = |dict: &[u8], key: &[u8]| {
+            pdf.trailer
+                .get_deref(dict, &pdf)
+                .and_then(|dict| dict.as_dict())
+                .and_then(|dict| dict.get_deref(key, &pdf))
+                .and_then(pdf_extract::decode_text_string)
+                .ok()
+        };
+        let metadata = ExtractedMetadata {
+            title: text_entry(b"Info", b"Title"),
+            author: text_entry(b"Info", b"Author"),
+            language: text_entry(b"Root", b"Lang"),
+            ..Default::default()
+        };
+        Ok((text, metadata))
+    }
 
-                let term_freq_comp = (tf * (BM25_K1 + 1.0))
-                    / (tf
-                        + BM25_K1
-                            * (1.0 - BM25_B + BM25_B * (doc_len / self.avg_doc_length.max(1.0))));
+    // First level-one heading, ATX (`# Title`) or setext (`Title` over `===`),
+    // outside fenced code blocks
+    pub(crate) fn markdown_title(content: &str) -> Option<String> {
+        let mut in_fence = false;
+        let mut previous_line: Option<&str> = None;
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+                previous_line = None;
+                continue;
+            }
+            if in_fence {
+                continue;
+            }
+            if let Some(heading) = trimmed.strip_prefix("# ") {
+                return Some(heading.trim_end_matches('#').to_string());
+            }
+            if trimmed.starts_with('=')
+                && trimmed.chars().all(|c| c == '=')
+                && let Some(heading) = previous_line
+            {
+                return Some(heading.to_string());
+            }
+            previous_line = (!trimmed.is_empty() && !trimmed.starts_with('#')).then_some(trimmed);
+        }
+        None
+    }
 
-                let mut term_score = idf * term_freq_comp;
+    // Level of an ATX heading (`# Title`, `## Title`) that starts a section
+    fn section_level(line: &str) -> Option<usize> {
+        let trimmed = line.trim_start_matches(' ');
+        if line.len() - trimmed.len() > 3 {
+            return None; // Indented code
+        }
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        let rest = &trimmed[level..];
+        ((1..=2).contains(&level) && (rest.is_empty() || rest.starts_with(char::is_whitespace)))
+            .then_some(level)
+    }
 
-                if !is_wildcard_origin && fuzzy_matched_terms.contains_key(q_token_original) {
-                    term_score *= 0.5;
+    // Splits Markdown text at its `#` and `##` headings outside fenced code
+    // blocks, into the 1-based line each part starts at, its heading, and its
+    // text (heading included). Text before the first heading is a part
+    // without a heading, left out if blank.
+    fn markdown_sections(content: &str) -> Vec<(usize, Option<String>, String)> {
+        let mut sections: Vec<(usize, Option<String>, String)> = Vec::new();
+        let mut current = (1, None, String::new());
+        let mut in_fence = false;
+        for (number, line) in content.split_inclusive('\n').enumerate() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_fence = !in_fence;
+            } else if !in_fence && Self::section_level(line).is_some() {
+                let heading = trimmed.trim_start_matches('#').trim_end_matches('#').trim();
+                let previous = std::mem::replace(
+                    &mut current,
+                    (number + 1, Some(heading.to_string()), String::new()),
+                );
+                if previous.1.is_some() || !previous.2.trim().is_empty() {
+                    sections.push(previous);
                 }
+            }
+            current.2.push_str(line);
+        }
+        if current.1.is_some() || !current.2.trim().is_empty() || sections.is_empty() {
+            sections.push(current);
+        }
+        sections
+    }
 
-                score += term_score;
+    // Points each `##` section document at the `#` section before it in the
+    // same file, once the documents have their final ids
+    fn link_sections<'a>(docs: impl IntoIterator<Item = &'a mut Document>) {
+        let mut sections: Vec<&mut Document> = docs
+            .into_iter()
+            .filter(|doc| Self::is_markdown(&doc.path) && doc.row.is_some())
+            .collect();
+        sections.sort_by(|a, b| (&a.path, a.row).cmp(&(&b.path, b.row)));
+        let mut parent: Option<(PathBuf, u32)> = None;
+        for doc in sections {
+            let level = doc.content.lines().next().and_then(Self::section_level);
+            if level == Some(1) || parent.as_ref().is_some_and(|(path, _)| *path != doc.path) {
+                parent = None;
+            }
+            doc.parent_doc = match level {
+                Some(2) => parent.as_ref().map(|(_, id)| *id),
+                _ => None,
+            };
+            if level == Some(1) {
+                parent = Some((doc.path.clone(), doc.id));
             }
-            ranked_results.push((score, doc_id));
         }
+    }
+
+    // Archives, and files with a registered extractor
+    pub fn is_supported_file(path: &Path) -> bool {
+        archive::is_archive(path) || extractor::for_path(path).is_some()
+    }
 
-        ranked_results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    // The document text, plus whatever metadata the format carries, from the
+    // extractor registered for the file's extension. Text formats are read up
+    // to `max_bytes`.
+    fn extract_content(path: &Path, max_bytes: Option<u64>) -> Result<(String, ExtractedMetadata)> {
+        let extractor = extractor::for_path(path)
+            .ok_or_else(|| anyhow!("Unsupported file type for indexing: {:?}", path))?;
+        let max_bytes = max_bytes.filter(|_| extractor.can_truncate());
+        let extracted = extractor.extract(path, max_bytes)?;
+        Ok((extracted.content, extracted.metadata))
+    }
+
+    // The body text of an HTML page, with its title, author, language and
+    // meta description
+    pub(crate) fn extract_html(html_content: &str) -> (String, ExtractedMetadata) {
+        let html = Html::parse_document(html_content);
+        let select = |selector: &str| html.select(&Selector::parse(selector).unwrap()).next();
+        let element_text = |selector: &str| select(selector).map(|e| e.text().collect::<String>());
+        let attribute = |selector: &str, name: &str| {
+            select(selector).and_then(|e| e.value().attr(name).map(str::to_string))
+        };
+        let metadata = ExtractedMetadata {
+            title: element_text("title"),
+            author: attribute("meta[name=author]", "content"),
+            language: attribute("html[lang]", "lang"),
+            description: attribute("meta[name=description]", "content")
+                .or_else(|| attribute("meta[property='og:description']", "content")),
+            ..Default::default()
+        };
+        (element_text("body").unwrap_or_default(), metadata)
+    }
 
-        let terms_for_snippet_highlighting: Vec<String> = processed_query_terms
+    // The records of a CSV or TSV file after its header row, each as its line
+    // in the file and its cells as `header: value` lines. Empty cells are left
+    // out; cells beyond the header are labelled by their column number.
+    fn table_rows(path: &Path, max_bytes: Option<u64>) -> Result<Vec<(usize, String)>> {
+        let delimiter = if path.extension().is_some_and(|ext| ext == "tsv") {
+            b'\t'
+        } else {
+            b','
+        };
+        let text = read_text(path, max_bytes).context("Failed to read table file")?;
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let headers: Vec<String> = reader
+            .headers()
+            .context("Failed to read table header")?
             .iter()
-            .filter_map(|(token, is_wildcard_origin)| {
-                if *is_wildcard_origin {
-                    Some(token.clone())
-                } else {
-                    fuzzy_matched_terms
-                        .get(token)
-                        .cloned()
-                        .or(Some(token.clone()))
-                }
-            })
+            .map(|header| header.trim().to_string())
             .collect();
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record.context("Failed to parse table row")?;
+            let line = record
+                .position()
+                .map_or(0, |position| position.line() as usize);
+            let mut content = String::new();
+            for (column, value) in record.iter().enumerate() {
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                match headers.get(column).filter(|header| !header.is_empty()) {
+                    Some(header) => content.push_str(header),
+                    None => content.push_str(&format!("column {}", column + 1)),
+                }
+                content.push_str(": ");
+                content.push_str(value);
+                content.push('\n');
+            }
+            if !content.is_empty() {
+                rows.push((line, content));
+            }
+        }
+        Ok(rows)
+    }
 
-        ranked_results
-            .into_iter()
-            .filter_map(|(score, doc_id)| {
-                self.documents.get(&doc_id).cloned().map(|doc| {
-                    let content_lower = doc.content.to_lowercase();
-
-                    let mut first_match_idx = None;
-                    for highlight_term in &terms_for_snippet_highlighting {
-                        if let Some(idx) = content_lower.find(highlight_term) {
-                            first_match_idx = Some(idx);
-                            break;
+    // The documents a file is indexed as: usually one, with id `doc_id`, but
+    // one per record of a JSON array or JSONL file, under a `rows_as_docs`
+    // root one per row of a table, and under a `sections_as_docs` root one per
+    // `#` or `##` section of a Markdown file. Files without records are indexed
+    // whole. Row documents are titled after the file and the row's number
+    // unless mapped otherwise, sections after the file and their heading, and
+    // both given their ids by `extract_batch`. A URL list's pages that cannot
+    // be fetched are returned alongside, to be reported and left out.
+    fn build_documents(
+        doc_id: u32,
+        path: &Path,
+        metadata: &FileMetadata,
+        root: &CorpusRoot,
+        tag_regex: &regex::Regex,
+        limit: Option<FileSizeLimit>,
+    ) -> Result<(Vec<Document>, Vec<PathBuf>)> {
+        let overrides = TagOverrides::load(path)?;
+        if archive::is_archive(path) {
+            return Self::archive_documents(path, metadata, root, tag_regex, overrides, limit)
+                .map(|docs| (docs, Vec::new()));
+        }
+        if web_pages::is_manifest(path, root) {
+            return Self::web_page_documents(path, metadata, root, tag_regex, overrides);
+        }
+        let max_bytes = limit
+            .filter(|limit| limit.truncate)
+            .map(|limit| limit.max_bytes);
+        Self::file_documents(
+            doc_id, path, metadata, root, tag_regex, &overrides, max_bytes,
+        )
+        .map(|docs| (docs, Vec::new()))
+    }
+
+    // The documents of each supported file in an archive, at its member path
+    // and with the archive's times. Tags in the archive's sidecar apply to all
+    // of them; its title would name every member the same, so it is left out.
+    // Members over the size limit are skipped or truncated like files.
+    fn archive_documents(
+        path: &Path,
+        metadata: &FileMetadata,
+        root: &CorpusRoot,
+        tag_regex: &regex::Regex,
+        overrides: TagOverrides,
+        limit: Option<FileSizeLimit>,
+    ) -> Result<Vec<Document>> {
+        let overrides = TagOverrides {
+            title: None,
+            ..overrides
+        };
+        // Members are extracted one at a time, as the extractors read files
+        let dir =
+            tempfile::tempdir().context("Failed to create a directory for archive members")?;
+        let wanted = |inner: &Path, size: u64| {
+            Self::is_supported_file(inner)
+                && !archive::is_archive(inner)
+                && limit.is_none_or(|limit| {
+                    size <= limit.max_bytes || (limit.truncate && Self::can_truncate(inner))
+                })
+        };
+        let max_bytes = limit.map(|limit| limit.max_bytes);
+        let mut docs = Vec::new();
+        archive::for_each_file(path, max_bytes, wanted, |inner, bytes, truncated| {
+            let member = archive::member_path(path, &inner);
+            let extracted = dir.path().join(inner.file_name().unwrap_or_default());
+            let bytes = if truncated {
+                whole_lines(bytes).into_bytes()
+            } else {
+                bytes
+            };
+            fs::write(&extracted, &bytes)
+                .with_context(|| format!("Failed to extract {:?}", member))?;
+            let member_metadata = FileMetadata {
+                size: bytes.len() as u64,
+                ..*metadata
+            };
+            let member_docs = match Self::file_documents(
+                0,
+                &extracted,
+                &member_metadata,
+                root,
+                tag_regex,
+                &overrides,
+                None,
+            ) {
+                Ok(member_docs) => member_docs,
+                // Skipped like members of unsupported types
+                Err(e) if e.is::<NotText>() => Vec::new(),
+                Err(e) => return Err(e.context(format!("Failed to index {:?}", member))),
+            };
+            docs.extend(member_docs.into_iter().map(|doc| Document {
+                path: member.clone(),
+                ..doc
+            }));
+            fs::remove_file(&extracted).ok();
+            Ok(())
+        })?;
+        docs.sort_by(|a, b| (&a.path, a.row).cmp(&(&b.path, b.row)));
+        Ok(docs)
+    }
+
+    // The pages listed in a root's URL list, fetched and extracted as HTML,
+    // with their URLs as paths and the list's times. Like an archive's, the
+    // list's sidecar tags apply to every page. Pages that fail to fetch are
+    // returned apart, and the rest indexed.
+    fn web_page_documents(
+        path: &Path,
+        metadata: &FileMetadata,
+        root: &CorpusRoot,
+        tag_regex: &regex::Regex,
+        overrides: TagOverrides,
+    ) -> Result<(Vec<Document>, Vec<PathBuf>)> {
+        let overrides = TagOverrides {
+            title: None,
+            ..overrides
+        };
+        let mut docs = Vec::new();
+        let mut unfetched = Vec::new();
+        for url in web_pages::urls(path)? {
+            let Ok(html) = web_pages::fetch(&url) else {
+                unfetched.push(PathBuf::from(url));
+                continue;
+            };
+            let (content, mut extracted) = Self::extract_html(&html);
+            extracted.title = clean_metadata(extracted.title).or_else(|| Some(url.clone()));
+            let page_metadata = FileMetadata {
+                size: html.len() as u64,
+                ..*metadata
+            };
+            docs.push(Self::new_document(
+                0,
+                Path::new(&url),
+                content,
+                extracted,
+                &page_metadata,
+                root,
+                tag_regex,
+                &overrides,
+            ));
+        }
+        Ok((docs, unfetched))
+    }
+
+    fn file_documents(
+        doc_id: u32,
+        path: &Path,
+        metadata: &FileMetadata,
+        root: &CorpusRoot,
+        tag_regex: &regex::Regex,
+        overrides: &TagOverrides,
+        max_bytes: Option<u64>,
+    ) -> Result<Vec<Document>> {
+        let file_stem = || {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        };
+        let mut records = if Self::is_json(path) {
+            let text = read_text(path, max_bytes).context("Failed to read JSON file")?;
+            json_records::records(path, &text, &root.json)?
+        } else {
+            Vec::new()
+        };
+        let single_object = matches!(records.as_slice(), [record] if record.line.is_none());
+        if !single_object && !records.is_empty() {
+            let title = overrides.title.clone().unwrap_or_else(file_stem);
+            return Ok(records
+                .into_iter()
+                .enumerate()
+                .map(|(number, record)| {
+                    let mut extracted = record.metadata;
+                    extracted.title = clean_metadata(extracted.title)
+                        .or_else(|| Some(format!("{} record {}", title, number + 1)));
+                    Document {
+                        row: Some(record.line.unwrap_or(number + 1)),
+                        ..Self::new_document(
+                            0,
+                            path,
+                            record.content,
+                            extracted,
+                            metadata,
+                            root,
+                            tag_regex,
+                            overrides,
+                        )
+                    }
+                })
+                .collect());
+        }
+        if root.rows_as_docs && Self::is_table(path) {
+            let rows = Self::table_rows(path, max_bytes)?;
+            if !rows.is_empty() {
+                let title = overrides.title.clone().unwrap_or_else(file_stem);
+                return Ok(rows
+                    .into_iter()
+                    .enumerate()
+                    .map(|(number, (line, content))| Document {
+                        title: format!("{} row {}", title, number + 1),
+                        row: Some(line),
+                        ..Self::new_document(
+                            0,
+                            path,
+                            content,
+                            ExtractedMetadata::default(),
+                            metadata,
+                            root,
+                            tag_regex,
+                            overrides,
+                        )
+                    })
+                    .collect());
+            }
+        }
+        if root.sections_as_docs && Self::is_markdown(path) {
+            let text = read_text(path, max_bytes).context("Failed to read markdown file")?;
+            let title = overrides
+                .title
+                .clone()
+                .or_else(|| clean_metadata(Self::markdown_title(&text)))
+                .unwrap_or_else(file_stem);
+            return Ok(Self::markdown_sections(&text)
+                .into_iter()
+                .map(|(line, heading, content)| {
+                    let section_title = match heading {
+                        Some(heading) if !heading.is_empty() && heading != title => {
+                            format!("{} › {}", title, heading)
                         }
+                        _ => title.clone(),
+                    };
+                    Document {
+                        title: section_title,
+                        row: Some(line),
+                        ..Self::new_document(
+                            0,
+                            path,
+                            content,
+                            ExtractedMetadata::default(),
+                            metadata,
+                            root,
+                            tag_regex,
+                            overrides,
+                        )
                     }
+                })
+                .collect());
+        }
 
-                    let snippet = if let Some(start_char_idx) = first_match_idx {
-                        let context_start = start_char_idx.saturating_sub(50);
-                        let context_end =
-                            (start_char_idx + terms_for_snippet_highlighting[0].len() + 50)
-                                .min(content_lower.len());
+        let (content, mut extracted) = match records.pop() {
+            Some(record) if single_object => (record.content, record.metadata),
+            _ => Self::extract_content(path, max_bytes)?,
+        };
+        // Falls back to the file stem when the document has no usable title
+        extracted.title = overrides
+            .title
+            .clone()
+            .or_else(|| clean_metadata(extracted.title))
+            .or_else(|| Some(file_stem()));
+        Ok(vec![Self::new_document(
+            doc_id, path, content, extracted, metadata, root, tag_regex, overrides,
+        )])
+    }
 
-                        let mut byte_start = 0;
-                        for (i, (byte_idx, _)) in doc.content.char_indices().enumerate() {
-                            if i == context_start {
-                                byte_start = byte_idx;
-                                break;
-                            }
-                        }
-                        let mut byte_end = doc.content.len();
-                        for (i, (byte_idx, _)) in doc.content.char_indices().enumerate() {
-                            if i == context_end {
-                                byte_end = byte_idx;
-                                break;
-                            }
-                        }
+    #[allow(clippy::too_many_arguments)]
+    fn new_document(
+        doc_id: u32,
+        path: &Path,
+        content: String,
+        extracted: ExtractedMetadata,
+        metadata: &FileMetadata,
+        root: &CorpusRoot,
+        tag_regex: &regex::Regex,
+        overrides: &TagOverrides,
+    ) -> Document {
+        let mut extracted_tags: Vec<String> = Vec::new();
+        for tag in &extracted.tags {
+            let tag = root.canonical_tag(tag);
+            if !extracted_tags.contains(&tag) {
+                extracted_tags.push(tag);
+            }
+        }
+        // `#include` and the like are not tags
+        let inline_tags = if Self::is_source_code(path) {
+            None
+        } else {
+            Some(tag_regex.captures_iter(&content))
+        };
+        for cap in inline_tags.into_iter().flatten() {
+            if let Some(m) = cap.get(1) {
+                let tag = root.canonical_tag(m.as_str());
+                if !extracted_tags.contains(&tag) {
+                    extracted_tags.push(tag);
+                }
+            }
+        }
+        overrides.apply(&mut extracted_tags);
+        // Prose without a declared language is analyzed in the one detected
+        let language = clean_metadata(extracted.language)
+            .map(|language| language.to_lowercase())
+            .or_else(|| {
+                (!Self::is_source_code(path))
+                    .then(|| crate::tokenizer::detect_language(&content))
+                    .flatten()
+            });
+        let num_doc_tokens = if Self::is_source_code(path) {
+            crate::tokenizer::tokenize_code(&content).len()
+        } else {
+            let analyzer = crate::tokenizer::analyzer_for(language.as_deref());
+            crate::tokenizer::tokenize_in(&content, analyzer).len()
+        };
+
+        Document {
+            id: doc_id,
+            path: path.to_path_buf(),
+            content_hash: fnv1a(content.as_bytes()),
+            content: Stored::new(content),
+            title: extracted.title.unwrap_or_default(),
+            tags: extracted_tags,
+            num_tokens: num_doc_tokens,
+            modified_time: metadata.modified_time,
+            created_time: metadata.created_time,
+            size: metadata.size,
+            author: clean_metadata(extracted.author),
+            language,
+            source: root.name(),
+            row: None,
+            parent_doc: None,
+            description: clean_metadata(extracted.description),
+        }
+    }
 
-                        let snippet_text = &doc.content[byte_start..byte_end];
-                        let mut highlighted_snippet = snippet_text.to_string();
+    // A bar with the files scanned, extracted, indexed and skipped, drawn on
+    // stderr (when it is a terminal) by the next syncs with the corpus
+    pub fn show_progress(&mut self, show: bool) {
+        self.show_progress = show;
+    }
 
-                        for term_to_highlight in &terms_for_snippet_highlighting {
-                            let re_str = format!(r"(?i)\b{}\b", regex::escape(term_to_highlight));
-                            let re = regex::Regex::new(&re_str).unwrap();
+    pub fn load_documents_from_roots(&mut self, roots: &[CorpusRoot]) -> Result<IndexingReport> {
+        self.sync_roots(roots, false)
+    }
 
-                            highlighted_snippet = re
-                                .replace_all(&highlighted_snippet, |caps: &regex::Captures| {
-                                    caps[0].red().bold().to_string()
-                                })
-                                .to_string();
-                        }
-                        format!("...{}...", highlighted_snippet)
-                    } else {
-                        format!("{}...", &doc.content[..doc.content.len().min(150)])
-                    };
+    // Like `load_documents_from_roots`, but re-extracts documents whose modified
+    // time is unchanged too, catching edits from tools that preserve mtimes.
+    pub fn verify_documents_from_roots(&mut self, roots: &[CorpusRoot]) -> Result<IndexingReport> {
+        self.sync_roots(roots, true)
+    }
 
-                    SearchResult {
-                        doc: doc.clone(),
-                        score,
-                        snippet,
-                        tags: doc.tags.clone(),
+    fn sync_roots(&mut self, roots: &[CorpusRoot], verify_content: bool) -> Result<IndexingReport> {
+        self.relocate(roots);
+        let progress = if self.show_progress {
+            ProgressBar::new_spinner()
+        } else {
+            ProgressBar::hidden()
+        };
+        progress.set_style(
+            ProgressStyle::with_template("{spinner} Scanning: {pos} files")
+                .expect("valid progress template"),
+        );
+        progress.enable_steady_tick(PROGRESS_TICK);
+        let plan = self.plan_scan(roots, verify_content, &progress)?;
+        let scanned = progress.position();
+        let mut report = plan.report;
+        progress.set_style(
+            ProgressStyle::with_template(
+                "{spinner} Extracting [{bar:30}] {pos}/{len} files, {msg} (ETA {eta})",
+            )
+            .expect("valid progress template")
+            .progress_chars("=> "),
+        );
+        progress.set_length(plan.jobs.len() as u64);
+        progress.set_position(0);
+        progress.set_message(progress_counts(scanned, &report));
+        let mut removals = Some(plan.doc_ids_to_remove);
+        for batch in self.extraction_batches(plan.jobs) {
+            let changes =
+                self.extract_batch(roots, batch, removals.take().unwrap_or_default(), &progress)?;
+            let batch_report = self
+                .commit_changes(changes)
+                .expect("the index cannot change between scan and commit");
+            report.merge(batch_report);
+            progress.set_message(progress_counts(scanned, &report));
+            self.merge_segments();
+        }
+        if let Some(doc_ids) = removals {
+            let changes = self.extract_batch(roots, Vec::new(), doc_ids, &progress)?;
+            self.commit_changes(changes)
+                .expect("the index cannot change between scan and commit");
+            self.merge_segments();
+        }
+        progress.finish_and_clear();
+        Ok(report)
+    }
+
+    // Brings a shared index up to date with `roots` while searches go on:
+    // files are scanned and extracted under the read lock, and the write lock
+    // is only taken to swap each batch of changed documents in. If another
+    // writer got there in between, the rest is redone under the write lock.
+    pub fn refresh_shared(index: &RwLock<Self>, roots: &[CorpusRoot]) -> Result<IndexingReport> {
+        let moved = index.read().unwrap().roots_moved(roots);
+        if moved {
+            index.write().unwrap().relocate(roots);
+        }
+        let (plan, batches, mut generation) = {
+            let index = index.read().unwrap();
+            let mut plan = index.plan_scan(roots, false, &ProgressBar::hidden())?;
+            let batches = index.extraction_batches(std::mem::take(&mut plan.jobs));
+            (plan, batches, index.generation)
+        };
+        if batches.is_empty() && plan.doc_ids_to_remove.is_empty() {
+            return Ok(plan.report);
+        }
+        // The scan's removals and skips only count once its first batch is in
+        let mut scan_report = Some(plan.report);
+        let mut report = IndexingReport::default();
+        let mut removals = Some(plan.doc_ids_to_remove);
+        let batch_count = batches.len().max(1);
+        let mut batches = batches.into_iter();
+        for _ in 0..batch_count {
+            let batch = batches.next().unwrap_or_default();
+            let mut changes = index.read().unwrap().extract_batch(
+                roots,
+                batch,
+                removals.take().unwrap_or_default(),
+                &ProgressBar::hidden(),
+            )?;
+            // Our own earlier batches moved the generation on, which is fine
+            changes.generation = generation;
+            let mut index = index.write().unwrap();
+            match index.commit_changes(changes) {
+                Some(batch_report) => {
+                    if let Some(scan_report) = scan_report.take() {
+                        report.merge(scan_report);
                     }
-                })
-            })
-            .collect()
+                    report.merge(batch_report);
+                    generation = index.generation;
+                }
+                None => {
+                    let mut rest = index.load_documents_from_roots(roots)?;
+                    if scan_report.is_none() {
+                        rest.skipped.clear();
+                        rest.oversized.clear();
+                        rest.truncated.clear();
+                    }
+                    report.merge(rest);
+                    return Ok(report);
+                }
+            }
+        }
+        Self::merge_segments_shared(index);
+        Ok(report)
+    }
+
+    fn roots_moved(&self, roots: &[CorpusRoot]) -> bool {
+        !self.roots.iter().eq(roots.iter().map(|root| &root.path))
+            || !self
+                .root_names
+                .iter()
+                .cloned()
+                .eq(roots.iter().map(CorpusRoot::name))
+    }
+
+    // Rebases documents under the recorded roots onto `roots` after the corpus
+    // was moved or synced elsewhere, so unchanged files are not re-extracted.
+    // A recorded root maps to the new root with the same directory name, or
+    // failing that the one in the same position. Sources follow renamed roots.
+    pub fn relocate(&mut self, roots: &[CorpusRoot]) {
+        if !self.roots_moved(roots) {
+            return;
+        }
+        let mut taken = vec![false; roots.len()];
+        let mut moves: Vec<(PathBuf, PathBuf)> = Vec::new();
+        for (position, old_root) in self.roots.iter().enumerate() {
+            let by_name = roots.iter().enumerate().position(|(i, root)| {
+                !taken[i]
+                    && old_root.file_name().is_some()
+                    && root.path.file_name() == old_root.file_name()
+            });
+            let Some(i) =
+                by_name.or((position < roots.len() && !taken[position]).then_some(position))
+            else {
+                continue;
+            };
+            taken[i] = true;
+            moves.push((old_root.clone(), roots[i].path.clone()));
+        }
+        // Nested roots: the deepest recorded root containing a document wins
+        moves.sort_by_key(|(old_root, _)| Reverse(old_root.components().count()));
+
+        let mut relocated = false;
+        for doc in self.documents.values_mut() {
+            let rebased = moves.iter().find_map(|(old_root, new_root)| {
+                doc.path
+                    .strip_prefix(old_root)
+                    .ok()
+                    .map(|relative| new_root.join(relative))
+            });
+            if let Some(path) = rebased
+                && path != doc.path
+            {
+                doc.path = path;
+                relocated = true;
+            }
+            let source = roots
+                .iter()
+                .filter(|root| doc.path.starts_with(&root.path))
+                .max_by_key(|root| root.path.components().count())
+                .map(CorpusRoot::name);
+            if let Some(source) = source
+                && source != doc.source
+            {
+                doc.source = source;
+                relocated = true;
+            }
+        }
+        self.roots = roots.iter().map(|root| root.path.clone()).collect();
+        self.root_names = roots.iter().map(CorpusRoot::name).collect();
+        if relocated {
+            self.generation += 1;
+            self.clear_cache();
+        }
+    }
+
+    // The id of a new document, from a hash of `key` so it comes out the same
+    // on every rebuild and machine. Ids held by other documents, except ones
+    // about to be removed, or `claimed` earlier in the same pass are skipped by
+    // rehashing with a counter.
+    fn stable_doc_id(&self, key: &str, removed: &[u32], claimed: &mut HashSet<u32>) -> u32 {
+        let mut attempt = 0u32;
+        loop {
+            let hash = if attempt == 0 {
+                fnv1a(key.as_bytes())
+            } else {
+                fnv1a(format!("{}#{}", key, attempt).as_bytes())
+            };
+            let id = (hash ^ (hash >> 32)) as u32;
+            let taken = self.documents.contains_key(&id) && !removed.contains(&id);
+            if id != 0 && !taken && claimed.insert(id) {
+                return id;
+            }
+            attempt += 1;
+        }
     }
 
-    fn perform_phrase_search_and_rank(
+    // Compares `roots` against the index: which indexed files are gone, and
+    // which files need (re-)extracting. `progress` counts the files seen.
+    fn plan_scan(
         &self,
-        phrase_query_text: &str,
-        _original_query: &str,
-    ) -> Vec<SearchResult> {
-        let query_tokens_with_pos = crate::tokenizer::tokenize(phrase_query_text);
-
-        if query_tokens_with_pos.is_empty() {
-            return Vec::new();
+        roots: &[CorpusRoot],
+        verify_content: bool,
+        progress: &ProgressBar,
+    ) -> Result<ScanPlan> {
+        let mut report = IndexingReport::default();
+        for root in roots {
+            if !root.path.is_dir() {
+                return Err(anyhow!("Corpus root {:?} is not a directory", root.path));
+            }
         }
 
-        let query_stemmed_tokens: Vec<String> = query_tokens_with_pos
-            .iter()
-            .map(|(s, _)| s.clone())
-            .collect();
-
-        let mut common_docs_data: HashMap<u32, HashMap<String, Vec<usize>>> = HashMap::new();
-
-        for (token_idx, token) in query_stemmed_tokens.iter().enumerate() {
-            if let Some(doc_entries) = self.index.get(token) {
-                if token_idx == 0 {
-                    for (doc_id, positions) in doc_entries {
-                        common_docs_data
-                            .entry(*doc_id)
-                            .or_insert_with(HashMap::new)
-                            .insert(token.clone(), positions.clone());
-                    }
-                } else {
-                    let current_matches_for_token: HashMap<u32, Vec<usize>> = doc_entries
-                        .iter()
-                        .map(|(id, pos)| (*id, pos.clone()))
-                        .collect();
+        // Path -> (file metadata, index of the root it was found under)
+        let mut files_in_corpus: HashMap<PathBuf, (FileMetadata, usize)> = HashMap::new();
+        let mut document_paths_in_index: HashMap<PathBuf, Vec<u32>> = HashMap::new();
 
-                    common_docs_data
-                        .retain(|doc_id, _| current_matches_for_token.contains_key(doc_id));
+        for (doc_id, doc) in &self.documents {
+            document_paths_in_index
+                .entry(Self::source_file(doc, roots))
+                .or_default()
+                .push(*doc_id);
+        }
 
-                    for (doc_id, positions) in current_matches_for_token {
-                        if let Some(doc_token_map) = common_docs_data.get_mut(&doc_id) {
-                            doc_token_map.insert(token.clone(), positions);
-                        }
-                    }
+        for (root_idx, root) in roots.iter().enumerate() {
+            // Hidden files and directories are skipped, as are those the
+            // root's ignore files match; Git's own ignore files are not read
+            let entries = WalkBuilder::new(&root.path)
+                .max_depth(root.max_depth)
+                .standard_filters(false)
+                .hidden(true)
+                .add_custom_ignore_filename(IGNORE_FILE)
+                .build();
+            for entry in entries {
+                let file_path = entry?.into_path();
+                if !file_path.is_file() {
+                    continue;
                 }
-            } else {
-                return Vec::new();
+                progress.inc(1);
+                if tag_sidecar::is_sidecar(&file_path) {
+                    continue;
+                }
+                if !Self::is_supported_file(&file_path) {
+                    report.skipped.push(file_path);
+                    continue;
+                }
+                if !root.includes(&file_path) || files_in_corpus.contains_key(&file_path) {
+                    continue;
+                }
+                let metadata = FileMetadata::read(&file_path)?;
+                if self.oversized(&file_path, metadata.size) == Some(false) {
+                    report.oversized.push(file_path);
+                    continue;
+                }
+                files_in_corpus.insert(file_path, (metadata, root_idx));
             }
         }
 
-        let mut phrase_matching_docs: HashMap<u32, f64> = HashMap::new();
-
-        for (doc_id, doc_tokens_pos_map) in common_docs_data {
-            if let Some(first_token_positions) = doc_tokens_pos_map.get(&query_stemmed_tokens[0]) {
-                for &start_pos in first_token_positions {
-                    let mut is_phrase_match = true;
-                    for i in 1..query_stemmed_tokens.len() {
-                        let current_query_token = &query_stemmed_tokens[i];
-                        let expected_pos = start_pos + (i as usize);
+        let mut doc_ids_to_remove: Vec<u32> = Vec::new();
+        let mut current_doc_ids_in_corpus = HashMap::new();
+        for (indexed_path, indexed_doc_ids) in document_paths_in_index {
+            if !files_in_corpus.contains_key(&indexed_path) {
+                doc_ids_to_remove.extend(&indexed_doc_ids);
+                report.removed.push(indexed_path);
+                report.changed_doc_ids.extend(indexed_doc_ids);
+            } else {
+                current_doc_ids_in_corpus.insert(indexed_path, indexed_doc_ids);
+            }
+        }
 
-                        if let Some(doc_token_positions) =
-                            doc_tokens_pos_map.get(current_query_token)
+        let mut jobs = Vec::new();
+        // Sorted, so that hash collisions are resolved the same way everywhere
+        let mut files_in_corpus: Vec<(PathBuf, (FileMetadata, usize))> =
+            files_in_corpus.into_iter().collect();
+        files_in_corpus.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut claimed_ids = HashSet::new();
+        for (path, (metadata, root_idx)) in files_in_corpus {
+            let root = &roots[root_idx];
+            let job = match current_doc_ids_in_corpus.remove(&path) {
+                Some(mut indexed_ids) => {
+                    let existing_docs: Vec<&Document> = indexed_ids
+                        .iter()
+                        .filter_map(|doc_id| self.documents.get(doc_id))
+                        .collect();
+                    let modified = existing_docs
+                        .iter()
+                        .any(|doc| doc.modified_time != metadata.modified_time);
+                    // A root switched to or from `rows_as_docs` or
+                    // `sections_as_docs` has its tables or Markdown files
+                    // compared again, as a table without rows stays whole
+                    let split_toggled = existing_docs.iter().any(|doc| {
+                        (Self::is_table(&doc.path) && doc.row.is_some() != root.rows_as_docs)
+                            || (Self::is_markdown(&doc.path)
+                                && doc.row.is_some() != root.sections_as_docs)
+                    });
+                    if !modified && !verify_content && !split_toggled {
+                        continue;
+                    }
+                    indexed_ids.sort_by_key(|doc_id| {
+                        self.documents
+                            .get(doc_id)
+                            .map(|doc| (doc.path.clone(), doc.row))
+                    });
+                    let doc_id = match indexed_ids.as_slice() {
+                        [doc_id]
+                            if existing_docs[0].row.is_none() && existing_docs[0].path == path =>
                         {
-                            if !doc_token_positions.contains(&expected_pos) {
-                                is_phrase_match = false;
-                                break;
-                            }
-                        } else {
-                            is_phrase_match = false;
-                            break;
+                            *doc_id
                         }
-                    }
-
-                    if is_phrase_match {
-                        *phrase_matching_docs.entry(doc_id).or_insert(0.0) += 1.0;
+                        _ => {
+                            self.stable_doc_id(&id_key(&path, root), &indexed_ids, &mut claimed_ids)
+                        }
+                    };
+                    ExtractJob {
+                        doc_id,
+                        indexed_ids,
+                        existing: Some(modified),
+                        path,
+                        metadata,
+                        root_idx,
                     }
                 }
+                None => ExtractJob {
+                    doc_id: self.stable_doc_id(
+                        &id_key(&path, root),
+                        &doc_ids_to_remove,
+                        &mut claimed_ids,
+                    ),
+                    indexed_ids: Vec::new(),
+                    existing: None,
+                    path,
+                    metadata,
+                    root_idx,
+                },
+            };
+            if self.oversized(&job.path, job.metadata.size).is_some() {
+                report.truncated.push(job.path.clone());
             }
+            jobs.push(job);
         }
 
-        let mut ranked_results: Vec<(f64, u32)> = phrase_matching_docs
-            .into_iter()
-            .map(|(doc_id, score)| (score, doc_id))
-            .collect();
-        ranked_results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(ScanPlan {
+            jobs,
+            doc_ids_to_remove,
+            report,
+        })
+    }
 
-        let terms_to_highlight_phrase: Vec<String> = query_stemmed_tokens.clone();
+    // Splits `jobs` so that each batch's estimated memory while extracted
+    // stays within the configured budget. Batches hold at least one file.
+    fn extraction_batches(&self, jobs: Vec<ExtractJob>) -> Vec<Vec<ExtractJob>> {
+        let Some(budget) = self.memory_budget else {
+            return if jobs.is_empty() {
+                Vec::new()
+            } else {
+                vec![jobs]
+            };
+        };
+        let mut batches: Vec<Vec<ExtractJob>> = Vec::new();
+        let mut batch_bytes = 0;
+        for job in jobs {
+            let job_bytes = job.metadata.size.saturating_mul(EXTRACTION_MEMORY_FACTOR);
+            if batches.is_empty() || batch_bytes + job_bytes > budget {
+                batches.push(Vec::new());
+                batch_bytes = 0;
+            }
+            batch_bytes += job_bytes;
+            batches.last_mut().unwrap().push(job);
+        }
+        batches
+    }
 
-        ranked_results
-            .into_iter()
-            .filter_map(|(score, doc_id)| {
-                self.documents.get(&doc_id).cloned().map(|doc| {
-                    let content_lower = doc.content.to_lowercase();
-                    let snippet_highlight_target = phrase_query_text.to_lowercase();
-
-                    let snippet = if let Some(first_match_idx) =
-                        content_lower.find(&snippet_highlight_target)
-                    {
-                        let context_start = first_match_idx.saturating_sub(50);
-                        let context_end = (first_match_idx + snippet_highlight_target.len() + 50)
-                            .min(content_lower.len());
-
-                        let mut byte_start = 0;
-                        for (i, (byte_idx, _)) in doc.content.char_indices().enumerate() {
-                            if i == context_start {
-                                byte_start = byte_idx;
-                                break;
-                            }
-                        }
-                        let mut byte_end = doc.content.len();
-                        for (i, (byte_idx, _)) in doc.content.char_indices().enumerate() {
-                            if i == context_end {
-                                byte_end = byte_idx;
-                                break;
-                            }
+    // Extracts and tokenizes a batch of files on the indexing thread pool,
+    // without modifying the index. `progress` counts the files extracted.
+    fn extract_batch(
+        &self,
+        roots: &[CorpusRoot],
+        jobs: Vec<ExtractJob>,
+        doc_ids_to_remove: Vec<u32>,
+        progress: &ProgressBar,
+    ) -> Result<PendingChanges> {
+        let tag_regex = regex::Regex::new(r"#(\w+)").unwrap();
+        let extract = || {
+            jobs.into_par_iter()
+                .map(|job| {
+                    let docs = Self::build_documents(
+                        job.doc_id,
+                        &job.path,
+                        &job.metadata,
+                        &roots[job.root_idx],
+                        &tag_regex,
+                        self.file_size_limit,
+                    );
+                    progress.inc(1);
+                    let (docs, unfetched) = match docs {
+                        Ok(built) => built,
+                        Err(e) if e.is::<NotText>() => return Ok(Some((job, Extraction::NotText))),
+                        Err(e) if !self.fail_fast => {
+                            return Ok(Some((job, Extraction::Failed(e))));
                         }
-
-                        let snippet_text = &doc.content[byte_start..byte_end];
-                        let mut highlighted_snippet = snippet_text.to_string();
-
-                        for term_to_highlight in &terms_to_highlight_phrase {
-                            let re_str = format!(r"(?i)\b{}\b", regex::escape(term_to_highlight));
-                            let re = regex::Regex::new(&re_str).unwrap();
-
-                            highlighted_snippet = re
-                                .replace_all(&highlighted_snippet, |caps: &regex::Captures| {
-                                    caps[0].red().bold().to_string()
+                        Err(e) => return Err(e.context(format!("Failed to index {:?}", job.path))),
+                    };
+                    // Unmodified files re-extracted for verification only
+                    // count as updated if their text, tags or rows changed
+                    let changed = match job.existing {
+                        Some(false) => {
+                            docs.len() != job.indexed_ids.len()
+                                || docs.iter().zip(&job.indexed_ids).any(|(doc, doc_id)| {
+                                    self.documents.get(doc_id).is_none_or(|existing_doc| {
+                                        doc.content != existing_doc.content
+                                            || doc.path != existing_doc.path
+                                            || doc.tags != existing_doc.tags
+                                            || doc.row != existing_doc.row
+                                    })
                                 })
-                                .to_string();
                         }
-                        format!("...{}...", highlighted_snippet)
+                        _ => true,
+                    };
+                    let tokenized: Vec<TokenizedDocument> = if changed {
+                        docs.into_iter().map(Self::tokenize_document).collect()
                     } else {
-                        format!("{}...", &doc.content[..doc.content.len().min(150)])
+                        Vec::new()
                     };
-
-                    SearchResult {
-                        doc: doc.clone(),
-                        score,
-                        snippet,
-                        tags: doc.tags.clone(),
-                    }
+                    Ok(changed.then_some((job, Extraction::Documents(tokenized, unfetched))))
                 })
-            })
-            .collect()
-    }
-
-    // Helper function to extract text from a PDF file
-    fn extract_text_from_pdf(path: &Path) -> Result<String> {
-        let text = extract_text(path).context("Failed to extract text from PDF")?;
-        Ok(text)
-    }
-
-    pub fn load_documents_from_directory(&mut self, path: &Path) -> Result<()> {
-        if !path.is_dir() {
-            return Err(anyhow!("Provided path is not a directory"));
+                .collect::<Result<Vec<_>>>()
+        };
+        let extracted: Vec<(ExtractJob, Extraction)> = match &self.extraction_pool {
+            Some(pool) => pool.install(extract)?,
+            None => extract()?,
         }
+        .into_iter()
+        .flatten()
+        .collect();
 
-        let tag_regex = regex::Regex::new(r"#(\w+)").unwrap();
-
-        let mut files_in_corpus: HashMap<PathBuf, u64> = HashMap::new();
-        let mut document_paths_in_index: HashMap<PathBuf, u32> = HashMap::new();
-
-        for (doc_id, doc) in &self.documents {
-            document_paths_in_index.insert(doc.path.clone(), *doc_id);
+        let mut report = IndexingReport::default();
+        let mut doc_ids_to_remove = doc_ids_to_remove;
+        for (job, extraction) in &extracted {
+            if matches!(extraction, Extraction::Failed(_)) {
+                continue;
+            }
+            doc_ids_to_remove.extend(&job.indexed_ids);
+            report.changed_doc_ids.extend(&job.indexed_ids);
         }
-
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let file_path = entry.path();
-            if file_path.is_file() {
-                let extension = file_path.extension().and_then(|s| s.to_str());
-                match extension {
-                    Some("txt") | Some("md") | Some("html") | Some("pdf") => {
-                        let metadata = fs::metadata(&file_path)?;
-                        let modified_time_secs =
-                            metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
-                        files_in_corpus.insert(file_path, modified_time_secs);
+        // Row and archive member ids, from the member's id key and the row's
+        // line, avoid the batch's other documents
+        let mut claimed: HashSet<u32> = extracted.iter().map(|(job, _)| job.doc_id).collect();
+        // Under `duplicates = "skip"`, whole files with the same text as one
+        // that stays indexed, or one earlier in the batch, are left out
+        let mut originals: HashMap<u64, PathBuf> = HashMap::new();
+        if self.duplicates == DuplicateFiles::Skip {
+            let removed: HashSet<u32> = doc_ids_to_remove.iter().copied().collect();
+            for doc in self.documents.values() {
+                if Self::is_whole_text(doc) && !removed.contains(&doc.id) {
+                    originals
+                        .entry(doc.content_hash)
+                        .or_insert_with(|| doc.path.clone());
+                }
+            }
+        }
+        let mut documents = Vec::new();
+        for (job, extraction) in extracted {
+            let mut tokenized = match extraction {
+                Extraction::Documents(tokenized, unfetched) => {
+                    report.skipped.extend(unfetched);
+                    tokenized
+                }
+                Extraction::NotText => {
+                    report.not_text.push(job.path);
+                    continue;
+                }
+                Extraction::Failed(e) => {
+                    report.failed.push((job.path, e));
+                    continue;
+                }
+            };
+            let extracted_any = !tokenized.is_empty();
+            if self.duplicates == DuplicateFiles::Skip {
+                tokenized.retain(|tokenized| {
+                    let doc = &tokenized.doc;
+                    if !Self::is_whole_text(doc) {
+                        return true;
                     }
-                    _ => {
-                        println!("Skipping unsupported file type: {:?}", file_path);
+                    match originals.get(&doc.content_hash) {
+                        Some(original) => {
+                            report.duplicates.push((doc.path.clone(), original.clone()));
+                            false
+                        }
+                        None => {
+                            originals.insert(doc.content_hash, doc.path.clone());
+                            true
+                        }
                     }
+                });
+                if extracted_any && tokenized.is_empty() {
+                    continue;
                 }
             }
-        }
-
-        let mut docs_to_add_or_update_details: Vec<Document> = Vec::new();
-        let mut doc_ids_to_remove: Vec<u32> = Vec::new();
-
-        let mut current_doc_ids_in_corpus = HashMap::new();
-        for (indexed_path, indexed_doc_id) in &document_paths_in_index {
-            if !files_in_corpus.contains_key(indexed_path) {
-                doc_ids_to_remove.push(*indexed_doc_id);
+            if job.existing.is_some() {
+                report.updated.push(job.path.clone());
             } else {
-                current_doc_ids_in_corpus.insert(indexed_path.clone(), *indexed_doc_id);
+                report.added.push(job.path.clone());
             }
-        }
-
-        for (file_path_owned, current_modified_time) in files_in_corpus {
-            if let Some(existing_doc_id) = current_doc_ids_in_corpus.get(&file_path_owned) {
-                if let Some(existing_doc) = self.documents.get(existing_doc_id) {
-                    if existing_doc.modified_time != current_modified_time {
-                        println!("Updating modified document: {:?}", file_path_owned);
-                        doc_ids_to_remove.push(*existing_doc_id);
-
-                        let content = match file_path_owned.extension().and_then(|ext| ext.to_str())
-                        {
-                            Some("txt") | Some("md") => fs::read_to_string(&file_path_owned)
-                                .context("Failed to read text/markdown file")?,
-                            Some("html") => {
-                                let html_content = fs::read_to_string(&file_path_owned)
-                                    .context("Failed to read HTML file")?;
-                                Html::parse_document(&html_content)
-                                    .select(&Selector::parse("body").unwrap())
-                                    .next()
-                                    .map(|element| element.text().collect::<String>())
-                                    .unwrap_or_else(|| "".to_string())
-                            }
-                            Some("pdf") => Self::extract_text_from_pdf(&file_path_owned)?,
-                            _ => Err(anyhow!(
-                                "Unsupported file type for indexing: {:?}",
-                                file_path_owned
-                            ))?,
-                        };
-                        let extracted_tags = tag_regex
-                            .captures_iter(&content)
-                            .filter_map(|cap| cap.get(1).map(|m| m.as_str().to_lowercase()))
-                            .collect();
-                        let num_doc_tokens = crate::tokenizer::tokenize(&content).len();
-
-                        docs_to_add_or_update_details.push(Document {
-                            id: *existing_doc_id,
-                            path: file_path_owned.clone(),
-                            content,
-                            title: file_path_owned
-                                .file_stem()
-                                .unwrap_or_default()
-                                .to_string_lossy()
-                                .to_string(),
-                            tags: extracted_tags,
-                            num_tokens: num_doc_tokens,
-                            modified_time: current_modified_time,
-                        });
+            for mut tokenized in tokenized {
+                let doc = &tokenized.doc;
+                if doc.row.is_some() || doc.path != job.path {
+                    let mut key = id_key(&doc.path, &roots[job.root_idx]);
+                    if let Some(row) = doc.row {
+                        key.push_str(&format!("#{}", row));
                     }
+                    tokenized.doc.id = self.stable_doc_id(&key, &doc_ids_to_remove, &mut claimed);
                 }
-            } else {
-                println!("Adding new document: {:?}", file_path_owned);
-                let content = match file_path_owned.extension().and_then(|ext| ext.to_str()) {
-                    Some("txt") | Some("md") => fs::read_to_string(&file_path_owned)
-                        .context("Failed to read text/markdown file")?,
-                    Some("html") => {
-                        let html_content = fs::read_to_string(&file_path_owned)
-                            .context("Failed to read HTML file")?;
-                        Html::parse_document(&html_content)
-                            .select(&Selector::parse("body").unwrap())
-                            .next()
-                            .map(|element| element.text().collect::<String>())
-                            .unwrap_or_else(|| "".to_string())
-                    }
-                    Some("pdf") => Self::extract_text_from_pdf(&file_path_owned)?,
-                    _ => Err(anyhow!(
-                        "Unsupported file type for indexing: {:?}",
-                        file_path_owned
-                    ))?,
-                };
-                let extracted_tags = tag_regex
-                    .captures_iter(&content)
-                    .filter_map(|cap| cap.get(1).map(|m| m.as_str().to_lowercase()))
-                    .collect();
-                let num_doc_tokens = crate::tokenizer::tokenize(&content).len();
-
-                let new_doc_id = self.next_doc_id.fetch_add(1, Ordering::SeqCst);
-                docs_to_add_or_update_details.push(Document {
-                    id: new_doc_id,
-                    path: file_path_owned.clone(),
-                    content,
-                    title: file_path_owned
-                        .file_stem()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .to_string(),
-                    tags: extracted_tags,
-                    num_tokens: num_doc_tokens,
-                    modified_time: current_modified_time,
-                });
+                report.changed_doc_ids.push(tokenized.doc.id);
+                documents.push(tokenized);
             }
         }
+        report.changed_doc_ids.sort_unstable();
+        report.changed_doc_ids.dedup();
+        Ok(PendingChanges {
+            generation: self.generation,
+            doc_ids_to_remove,
+            documents,
+            report,
+        })
+    }
 
-        for doc_id in doc_ids_to_remove {
-            self.remove_document(doc_id);
+    // Applies changes from `extract_batch`, or returns `None` without applying
+    // them if the documents were modified since the scan.
+    fn commit_changes(&mut self, mut changes: PendingChanges) -> Option<IndexingReport> {
+        if changes.generation != self.generation {
+            return None;
+        }
+        self.remove_documents(&changes.doc_ids_to_remove);
+        // Ids planned by the scan may have gone to the rows of an earlier batch
+        let mut claimed: HashSet<u32> = changes.documents.iter().map(|t| t.doc.id).collect();
+        for tokenized in &mut changes.documents {
+            let doc = &mut tokenized.doc;
+            if self.documents.contains_key(&doc.id) {
+                let key = format!("{}#{:?}", doc.path.to_string_lossy(), doc.row);
+                let id = self.stable_doc_id(&key, &[], &mut claimed);
+                changes
+                    .report
+                    .changed_doc_ids
+                    .retain(|doc_id| *doc_id != doc.id);
+                changes.report.changed_doc_ids.push(id);
+                doc.id = id;
+            }
         }
+        Self::link_sections(
+            changes
+                .documents
+                .iter_mut()
+                .map(|tokenized| &mut tokenized.doc),
+        );
+        self.insert_documents(changes.documents);
+
+        self.update_collection_stats();
+        Some(changes.report)
+    }
+
+    // Whole files with text, which `duplicates` compares by content hash; rows,
+    // records and sections often repeat, and empty files are not copies
+    fn is_whole_text(doc: &Document) -> bool {
+        doc.row.is_none() && doc.num_tokens > 0
+    }
 
-        for doc_details in docs_to_add_or_update_details {
-            self.add_document(doc_details);
+    fn update_copy_groups(&mut self) {
+        self.copy_groups.clear();
+        if self.duplicates != DuplicateFiles::Link {
+            return;
         }
+        let mut docs: Vec<&Document> = self
+            .documents
+            .values()
+            .filter(|doc| Self::is_whole_text(doc))
+            .collect();
+        docs.sort_by(|a, b| (&a.path, a.id).cmp(&(&b.path, b.id)));
+        for doc in docs {
+            self.copy_groups
+                .entry(doc.content_hash)
+                .or_default()
+                .push(doc.id);
+        }
+    }
+
+    // Under `duplicates = "link"`, whether another copy of the document
+    // stands for it in the graph
+    fn is_hidden_copy(&self, doc: &Document) -> bool {
+        Self::is_whole_text(doc)
+            && self
+                .copy_groups
+                .get(&doc.content_hash)
+                .is_some_and(|doc_ids| doc_ids[0] != doc.id)
+    }
+
+    fn update_analyzers(&mut self) {
+        let mut analyzers: Vec<&'static str> = self
+            .documents
+            .values()
+            .filter(|doc| !Self::is_source_code(&doc.path))
+            .map(Self::analyzer)
+            .filter(|analyzer| *analyzer != crate::tokenizer::DEFAULT_ANALYZER)
+            .collect();
+        analyzers.sort_unstable();
+        analyzers.dedup();
+        self.analyzers = analyzers;
+    }
 
+    fn update_collection_stats(&mut self) {
+        self.update_copy_groups();
+        self.update_analyzers();
+        *self.vector_norms.get_mut().unwrap() = None;
+        self.signatures.get_mut().unwrap().clear();
         self.total_docs = self.documents.len();
         let mut total_tokens: usize = 0;
         for doc in self.documents.values() {
@@ -824,7 +4105,139 @@ impl InvertedIndex {
         }
 
         self.clear_cache();
-        Ok(())
+    }
+
+    // Adds already-extracted documents (e.g. received from another machine),
+    // replacing the documents of every file they come from. Documents keep
+    // the id of the local document for the same file and row, or else their
+    // incoming id, which was derived from the same relative path, unless a
+    // different local document holds it.
+    pub fn import_documents(&mut self, docs: Vec<Document>) -> IndexingReport {
+        let mut report = IndexingReport::default();
+        let mut ids_by_path: HashMap<&Path, Vec<&Document>> = HashMap::new();
+        for doc in self.documents.values() {
+            ids_by_path.entry(&doc.path).or_default().push(doc);
+        }
+
+        let mut doc_ids_to_remove = Vec::new();
+        let mut imported_paths: HashSet<PathBuf> = HashSet::new();
+        for doc in &docs {
+            if !imported_paths.insert(doc.path.clone()) {
+                continue;
+            }
+            match ids_by_path.get(doc.path.as_path()) {
+                Some(existing_docs) => {
+                    doc_ids_to_remove.extend(existing_docs.iter().map(|existing| existing.id));
+                    report.updated.push(doc.path.clone());
+                }
+                None => report.added.push(doc.path.clone()),
+            }
+        }
+        let existing_ids: HashMap<(&Path, Option<usize>), u32> = ids_by_path
+            .values()
+            .flatten()
+            .map(|existing| ((existing.path.as_path(), existing.row), existing.id))
+            .collect();
+        report.changed_doc_ids.extend(&doc_ids_to_remove);
+
+        let mut claimed = HashSet::new();
+        let mut imported: HashMap<u32, Document> = HashMap::new();
+        for mut doc in docs {
+            match existing_ids.get(&(doc.path.as_path(), doc.row)) {
+                Some(&existing_id) if claimed.insert(existing_id) => doc.id = existing_id,
+                _ => {
+                    let taken = self.documents.contains_key(&doc.id)
+                        && !doc_ids_to_remove.contains(&doc.id);
+                    if doc.id == 0 || taken || !claimed.insert(doc.id) {
+                        let key = format!("{}#{:?}", doc.path.to_string_lossy(), doc.row);
+                        doc.id = self.stable_doc_id(&key, &doc_ids_to_remove, &mut claimed);
+                    }
+                }
+            }
+            report.changed_doc_ids.push(doc.id);
+            imported.insert(doc.id, doc);
+        }
+        Self::link_sections(imported.values_mut());
+
+        self.remove_documents(&doc_ids_to_remove);
+        self.insert_tokenized(imported.into_values().collect());
+        self.update_collection_stats();
+        self.merge_segments();
+        report.changed_doc_ids.sort_unstable();
+        report.changed_doc_ids.dedup();
+        report
+    }
+
+    // Changes the tags or title of a document in place, e.g. after they were
+    // edited in its sidecar. Returns `None` if there is no such document.
+    pub fn edit_document(
+        &mut self,
+        doc_id: u32,
+        edit: impl FnOnce(&mut Document),
+    ) -> Option<IndexingReport> {
+        let mut doc = self.documents.get(&doc_id)?.clone();
+        edit(&mut doc);
+        self.remove_document(doc_id);
+        let mut report = IndexingReport::default();
+        report.updated.push(doc.path.clone());
+        report.changed_doc_ids.push(doc_id);
+        self.add_document(doc);
+        self.update_collection_stats();
+        self.merge_segments();
+        Some(report)
+    }
+
+    pub fn documents(&self) -> impl Iterator<Item = &Document> {
+        self.documents.values()
+    }
+
+    // Each indexed term with the ids of the documents containing it
+    pub fn term_documents(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = u32>)> {
+        self.matching_terms(|_| true)
+            .into_iter()
+            .map(|(term, doc_ids)| (term, doc_ids.into_iter()))
+    }
+
+    // (document id, positions) for every document containing `term`
+    pub fn postings(&self, term: &str) -> Vec<&(u32, Vec<usize>)> {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.live_postings(term))
+            .collect()
+    }
+
+    pub fn document(&self, doc_id: u32) -> Option<&Document> {
+        self.documents.get(&doc_id)
+    }
+
+    // Byte range of the token at `position` in the document's content
+    pub fn token_span(&self, doc_id: u32, position: usize) -> Option<(usize, usize)> {
+        self.token_offsets
+            .get(&doc_id)
+            .and_then(|offsets| offsets.get(position))
+            .copied()
+    }
+
+    // The corpus file a document was extracted from: the archive for a file
+    // inside one, its root's URL list for a web page, otherwise its own path
+    pub fn source_file(doc: &Document, roots: &[CorpusRoot]) -> PathBuf {
+        if web_pages::is_url(&doc.path)
+            && let Some(root) = roots.iter().find(|root| root.name() == doc.source)
+        {
+            return web_pages::manifest_path(&root.path);
+        }
+        archive::containing_file(&doc.path)
+    }
+
+    // The documents of a file: one, or its rows or archive members in order
+    pub fn documents_at_path(&self, path: &Path) -> Vec<&Document> {
+        let mut docs: Vec<&Document> = self
+            .documents
+            .values()
+            .filter(|doc| doc.path == path || archive::containing_file(&doc.path) == path)
+            .collect();
+        docs.sort_by(|a, b| (&a.path, a.row).cmp(&(&b.path, b.row)));
+        docs
     }
 
     pub fn total_documents(&self) -> usize {
@@ -832,15 +4245,105 @@ impl InvertedIndex {
     }
 
     pub fn generate_network_graph_data(&self) -> Result<String> {
-        let mut nodes: Vec<GraphNode> = Vec::new();
-        let mut edges: Vec<GraphEdge> = Vec::new();
-        let mut searchable_documents: HashMap<u32, ClientSearchableDocument> = HashMap::new();
-        let mut processed_edges: std::collections::HashSet<(u32, u32)> =
-            std::collections::HashSet::new();
+        self.build_web_app_data().to_json()
+    }
 
-        for doc in self.documents.values() {
-            let mut content_preview = doc.content.chars().take(300).collect::<String>();
-            if doc.content.len() > 300 {
+    pub fn build_web_app_data(&self) -> WebAppData {
+        let mut web_data = WebAppData::default();
+        let doc_ids: Vec<u32> = self.documents.keys().copied().collect();
+        self.refresh_web_app_data(&mut web_data, &doc_ids);
+        web_data
+    }
+
+    // Rebuilds the node, searchable entry and edges of each given document,
+    // dropping them for documents no longer in the index.
+    // Count shared tags through the tag map instead of comparing against every document
+    fn shared_tag_counts(&self, doc: &Document) -> HashMap<u32, usize> {
+        let mut shared_tags_count: HashMap<u32, usize> = HashMap::new();
+        for tag in &doc.tags {
+            for other_id in self.tags.get(tag).into_iter().flatten() {
+                if *other_id != doc.id {
+                    *shared_tags_count.entry(*other_id).or_insert(0) += 1;
+                }
+            }
+        }
+        shared_tags_count
+    }
+
+    // Documents linked to `doc_id` in the graph, most shared tags first
+    pub fn graph_neighbors(&self, doc_id: u32) -> Vec<u32> {
+        let Some(doc) = self.documents.get(&doc_id) else {
+            return Vec::new();
+        };
+        let mut neighbors: Vec<(u32, usize)> = self
+            .shared_tag_counts(doc)
+            .into_iter()
+            .filter(|(other_id, _)| {
+                self.documents
+                    .get(other_id)
+                    .is_some_and(|other| !self.is_hidden_copy(other))
+            })
+            .collect();
+        neighbors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        neighbors.into_iter().map(|(id, _)| id).collect()
+    }
+
+    // Every tag with the number of documents carrying it
+    pub fn tag_counts(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.tags
+            .iter()
+            .map(|(tag, doc_ids)| (tag.as_str(), doc_ids.len()))
+    }
+
+    pub fn documents_with_tag(&self, tag: &str) -> Vec<u32> {
+        let mut doc_ids = self.tags.get(tag).cloned().unwrap_or_default();
+        doc_ids.sort_unstable();
+        doc_ids
+    }
+
+    pub fn refresh_web_app_data(&self, web_data: &mut WebAppData, changed_doc_ids: &[u32]) {
+        // Under `duplicates = "link"`, a copy of a changed or removed document
+        // may take over its node, or give its own up
+        let mut doc_ids = changed_doc_ids.to_vec();
+        if !self.copy_groups.is_empty() {
+            for doc_id in changed_doc_ids {
+                let hashes = web_data
+                    .node_hashes
+                    .get(doc_id)
+                    .copied()
+                    .into_iter()
+                    .chain(self.documents.get(doc_id).map(|doc| doc.content_hash));
+                for hash in hashes {
+                    doc_ids.extend(self.copy_groups.get(&hash).into_iter().flatten());
+                }
+            }
+            doc_ids.sort_unstable();
+            doc_ids.dedup();
+        }
+        let changed_doc_ids = doc_ids.as_slice();
+
+        for doc_id in changed_doc_ids {
+            web_data.nodes.remove(doc_id);
+            web_data.searchable_documents.remove(doc_id);
+            web_data.node_hashes.remove(doc_id);
+            web_data
+                .edges
+                .retain(|(from, to), _| from != doc_id && to != doc_id);
+        }
+
+        for doc_id in changed_doc_ids {
+            let Some(doc) = self.documents.get(doc_id) else {
+                continue;
+            };
+            if self.is_hidden_copy(doc) {
+                continue;
+            }
+            web_data.node_hashes.insert(doc.id, doc.content_hash);
+
+            let preview = doc.description.as_deref().unwrap_or(&doc.content);
+            let preview_chars = self.snippet_policy.graph_preview_chars;
+            let mut content_preview = preview.chars().take(preview_chars).collect::<String>();
+            if preview.chars().nth(preview_chars).is_some() {
                 content_preview.push_str("...");
             }
 
@@ -850,65 +4353,330 @@ impl InvertedIndex {
                 .and_then(|os_str| os_str.to_str())
                 .unwrap_or("unknown")
                 .to_string();
-            nodes.push(GraphNode {
-                id: doc.id,
-                label: doc.title.clone(),
-                title: format!("{} (Tags: {})", doc.title, doc.tags.join(", ")),
-                group: file_extension,
-                content_preview: content_preview.clone(), // Clone for graph node
-                js_tags: doc.tags.clone(),
-            });
+            web_data.nodes.insert(
+                doc.id,
+                GraphNode {
+                    id: doc.id,
+                    label: doc.title.clone(),
+                    title: format!("{} (Tags: {})", doc.title, doc.tags.join(", ")),
+                    group: file_extension,
+                    content_preview: content_preview.clone(), // Clone for graph node
+                    js_tags: doc.tags.clone(),
+                    modified_time: doc.modified_time,
+                    created_time: doc.created_time,
+                    size: doc.size,
+                },
+            );
 
-            // Populate searchable_documents map
-            searchable_documents.insert(
+            web_data.searchable_documents.insert(
                 doc.id,
                 ClientSearchableDocument {
                     id: doc.id,
                     title: doc.title.clone(),
-                    content: doc.content.clone(),
+                    content: doc.content.to_string(),
                     tags: doc.tags.clone(),
                     content_preview,
+                    size: doc.size,
+                    author: doc.author.clone(),
+                    language: doc.language.clone(),
+                    source: doc.source.clone(),
                 },
             );
 
-            for other_doc in self.documents.values() {
-                if doc.id == other_doc.id {
+            for (other_id, count) in self.shared_tag_counts(doc) {
+                if self
+                    .documents
+                    .get(&other_id)
+                    .is_some_and(|other| self.is_hidden_copy(other))
+                {
                     continue;
                 }
+                let (node1, node2) = if doc.id < other_id {
+                    (doc.id, other_id)
+                } else {
+                    (other_id, doc.id)
+                };
+                web_data.edges.insert(
+                    (node1, node2),
+                    GraphEdge {
+                        from: node1,
+                        to: node2,
+                        width: count as f64,
+                    },
+                );
+            }
+        }
+    }
+}
 
-                let mut shared_tags_count = 0;
-                for tag in &doc.tags {
-                    if other_doc.tags.contains(tag) {
-                        shared_tags_count += 1;
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                if shared_tags_count > 0 {
-                    let (node1, node2) = if doc.id < other_doc.id {
-                        (doc.id, other_doc.id)
-                    } else {
-                        (other_doc.id, doc.id)
-                    };
+    fn document(n: usize, content: String) -> Document {
+        Document {
+            id: n as u32 + 1,
+            path: PathBuf::from(format!("notes/{}.md", n)),
+            content_hash: fnv1a(content.as_bytes()),
+            num_tokens: content.split_whitespace().count(),
+            content: Stored::new(content),
+            title: String::new(),
+            tags: Vec::new(),
+            modified_time: 0,
+            created_time: None,
+            size: 0,
+            author: None,
+            language: None,
+            source: "notes".to_string(),
+            row: None,
+            parent_doc: None,
+            description: None,
+        }
+    }
 
-                    if processed_edges.insert((node1, node2)) {
-                        edges.push(GraphEdge {
-                            from: node1,
-                            to: node2,
-                            width: shared_tags_count as f64,
-                        });
-                    }
+    // Documents mentioning rust a varying number of times, with exact and
+    // near-identical copies among them. Searches are not cached, so that a
+    // page is ranked on its own rather than cut from an earlier ranking.
+    fn corpus_index(duplicates: DuplicateFiles) -> InvertedIndex {
+        let mut index = InvertedIndex::new();
+        index
+            .configure_indexing(&IndexingConfig {
+                duplicates,
+                ..IndexingConfig::default()
+            })
+            .unwrap();
+        index.configure_cache(&CacheConfig {
+            enabled: false,
+            ..CacheConfig::default()
+        });
+        let content = |n: usize| {
+            let mentions = "rust ".repeat(n % 7 + 1);
+            let words: Vec<String> = (0..30).map(|i| format!("w{}x{}", n % 20, i)).collect();
+            format!("{} {} number {}", mentions, words.join(" "), n)
+        };
+        // The last ten are copies of the first ten
+        let docs = (0..60)
+            .map(|n| document(n, content(if n < 50 { n } else { n - 50 })))
+            .collect();
+        index.import_documents(docs);
+        index
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<u32> {
+        results.iter().map(|result| result.doc.id).collect()
+    }
+
+    fn replaced(doc: &Document, content: &str) -> Document {
+        Document {
+            content: Stored::new(content.to_string()),
+            num_tokens: content.split_whitespace().count(),
+            ..doc.clone()
+        }
+    }
+
+    #[test]
+    fn wildcards_follow_the_words_of_live_documents() {
+        let mut index = InvertedIndex::new();
+        let first = document(0, "the organization of things".to_string());
+        let second = document(1, "realization happens".to_string());
+        index.import_documents(vec![first.clone(), second]);
+        assert_eq!(index.wildcard_terms("*ization"), vec!["organ", "realiz"]);
+
+        // "organs" is indexed as "organ" too, but no longer matches *ization
+        index.import_documents(vec![replaced(&first, "organs of things")]);
+        assert_eq!(index.wildcard_terms("*ization"), vec!["realiz"]);
+        assert_eq!(index.wildcard_terms("org*"), vec!["organ"]);
+
+        let segments: Vec<&Segment> = index.segments.iter().collect();
+        let merged = Segment::merge(&segments);
+        index.replace_segments(&(0..index.segments.len()).collect::<Vec<_>>(), merged);
+        let index =
+            InvertedIndex::from_serialized_data(&index.to_serialized_data().unwrap()).unwrap();
+        assert_eq!(index.segments.len(), 1);
+        assert_eq!(index.wildcard_terms("*ization"), vec!["realiz"]);
+        assert_eq!(index.wildcard_terms("org*s"), vec!["organ"]);
+    }
+
+    #[test]
+    fn exact_words_are_looked_up_as_written() {
+        let mut index = InvertedIndex::new();
+        let docs = vec![
+            document(0, "IT asked for C++ notes".to_string()),
+            document(1, "it is done, not C".to_string()),
+            document(2, "ITEMS and NaN".to_string()),
+        ];
+        index.import_documents(docs);
+        let matching = |index: &InvertedIndex, query: &str| {
+            let mut ids = ids(&index.search(query).unwrap());
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(matching(&index, "=IT"), vec![1]);
+        assert_eq!(matching(&index, "=it"), vec![2]);
+        assert_eq!(matching(&index, "=C"), vec![1, 2]);
+        assert_eq!(matching(&index, "=C++"), vec![1]);
+        assert_eq!(matching(&index, "=done,"), vec![2]);
+
+        let index =
+            InvertedIndex::from_serialized_data(&index.to_serialized_data().unwrap()).unwrap();
+        assert_eq!(matching(&index, "=NaN"), vec![3]);
+        assert_eq!(matching(&index, "=IT"), vec![1]);
+    }
+
+    #[test]
+    fn phrases_of_stop_words_are_matched_by_position() {
+        let mut index = InvertedIndex::new();
+        let first = document(0, "most of the time".to_string());
+        let mut code = document(2, "// one of the helpers".to_string());
+        code.path = PathBuf::from("notes/helpers.rs");
+        let docs = vec![
+            first.clone(),
+            document(1, "the day of reckoning, and the rest of it".to_string()),
+            code,
+        ];
+        index.import_documents(docs);
+        let matching = |index: &InvertedIndex, query: &str| {
+            let mut ids = ids(&index.search(query).unwrap());
+            ids.sort_unstable();
+            ids
+        };
+        // Source files match as code
+        assert_eq!(matching(&index, "\"of the\""), vec![1, 3]);
+        assert_eq!(matching(&index, "\"the of\""), Vec::<u32>::new());
+        assert_eq!(matching(&index, "\"of it\""), vec![2]);
+
+        index.import_documents(vec![replaced(&first, "most of all")]);
+        let segments: Vec<&Segment> = index.segments.iter().collect();
+        let merged = Segment::merge(&segments);
+        index.replace_segments(&(0..index.segments.len()).collect::<Vec<_>>(), merged);
+        let index =
+            InvertedIndex::from_serialized_data(&index.to_serialized_data().unwrap()).unwrap();
+        assert_eq!(matching(&index, "\"of the\""), vec![3]);
+        assert_eq!(matching(&index, "\"of all\""), vec![1]);
+    }
+
+    #[test]
+    fn damaged_index_files_are_rejected() {
+        let mut index = InvertedIndex::new();
+        index.import_documents(vec![document(0, "stored text".to_string())]);
+        let data = index.to_serialized_data().unwrap();
+        let blob_len = u64::from_le_bytes(data[8..16].try_into().unwrap()) as usize;
+        assert!(blob_len > 0);
+        assert!(InvertedIndex::from_serialized_data(&data).is_ok());
+
+        let mut corrupt = data.clone();
+        corrupt[INDEX_HEADER_LEN + blob_len / 2] ^= 1;
+        let error = InvertedIndex::from_serialized_data(&corrupt).unwrap_err();
+        assert!(error.to_string().contains("corrupt"), "{}", error);
+
+        let truncated = &data[..INDEX_HEADER_LEN + blob_len - 1];
+        let error = InvertedIndex::from_serialized_data(truncated).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{}", error);
+
+        let mut newer = data;
+        newer[4..8].copy_from_slice(&(INDEX_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(InvertedIndex::from_serialized_data(&newer).is_err());
+    }
+
+    #[test]
+    fn web_pages_that_fail_to_fetch_are_skipped() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let served = format!("http://{}/page", server.local_addr().unwrap());
+        // Nothing listens on a port just given back
+        let unreachable = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/page", listener.local_addr().unwrap())
+        };
+        std::thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let body = "<html><title>Served</title><body>fetched words</body></html>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            web_pages::manifest_path(dir.path()),
+            format!("{}\n{}\n", unreachable, served),
+        )
+        .unwrap();
+        let mut index = InvertedIndex::new();
+        let report = index
+            .load_documents_from_roots(&[CorpusRoot::new(dir.path())])
+            .unwrap();
+        assert_eq!(report.skipped, vec![PathBuf::from(&unreachable)]);
+        assert!(report.failed.is_empty());
+        let results = index.search("fetched").unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc.path, PathBuf::from(&served));
+    }
+
+    #[test]
+    fn top_k_ranking_matches_the_full_sort() {
+        for duplicates in [DuplicateFiles::Keep, DuplicateFiles::Link] {
+            let index = corpus_index(duplicates);
+            let full = index.search_page("rust", 0, None).unwrap();
+            assert!(full.results.len() > 20);
+            let linked = full.results.iter().any(|result| !result.copies.is_empty());
+            assert_eq!(linked, duplicates == DuplicateFiles::Link);
+            for (offset, limit) in [(0, 1), (0, 10), (5, 5), (40, 30)] {
+                let page = index.search_page("rust", offset, Some(limit)).unwrap();
+                let end = (offset + limit).min(full.results.len());
+                assert_eq!(ids(&page.results), ids(&full.results[offset..end]));
+                assert_eq!(page.total, full.total);
+                for (ranked, fully_ranked) in page.results.iter().zip(&full.results[offset..end]) {
+                    assert_eq!(ranked.score, fully_ranked.score);
+                    assert_eq!(ranked.copies, fully_ranked.copies);
                 }
             }
         }
+    }
 
-        let full_app_data = FullWebAppData {
-            nodes,
-            edges,
-            searchable_documents,
-        };
-        let json_string = serde_json::to_string_pretty(&full_app_data)
-            .context("Failed to serialize full app data to JSON")?;
+    #[test]
+    fn top_k_ranking_collapses_near_duplicates_like_the_full_sort() {
+        let mut index = corpus_index(DuplicateFiles::Keep);
+        index.configure_results(&ResultsConfig {
+            near_duplicates: Some(0.5),
+            ..ResultsConfig::default()
+        });
+        let full = index.search_page("rust", 0, None).unwrap();
+        assert!(
+            full.results
+                .iter()
+                .any(|result| !result.near_duplicates.is_empty())
+        );
+        let page = index.search_page("rust", 0, Some(10)).unwrap();
+        assert_eq!(ids(&page.results), ids(&full.results[..10]));
+    }
+
+    #[test]
+    fn more_results_come_from_the_cached_ranking() {
+        let mut index = corpus_index(DuplicateFiles::Keep);
+        index.configure_cache(&CacheConfig::default());
+        let full: Vec<u32> = ids(&index.search_page("rust", 0, None).unwrap().results);
+        index.clear_cache();
+
+        let first = index.search_page("rust", 0, Some(10)).unwrap();
+        let more = index.search_page("rust", 10, Some(10)).unwrap();
+        let stats = index.cache_stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert_eq!(ids(&first.results), full[..10]);
+        assert_eq!(ids(&more.results), full[10..20]);
 
-        Ok(json_string)
+        // Past what was ranked, the query is ranked again, further ahead
+        let later = index.search_page("rust", 20, Some(10)).unwrap();
+        assert_eq!(ids(&later.results), full[20..30]);
+        index.search_page("rust", 30, Some(10)).unwrap();
+        let stats = index.cache_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 3));
     }
 }
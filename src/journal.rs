@@ -0,0 +1,139 @@
+// src/journal.rs
+// Append-only log of document changes made since the index file was last
+// written in full. Each record is an upsert or removal of one document, so
+// replaying a record twice is harmless.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::inverted_index::Document;
+
+// --- CONSTANTS ---
+const JOURNAL_MAGIC: &[u8; 4] = b"ISPJ";
+const JOURNAL_VERSION: u32 = 6;
+const HEADER_LEN: usize = 8;
+
+// --- STRUCTS ---
+#[derive(Debug, Serialize, Deserialize)]
+pub enum JournalEntry {
+    Upsert(Box<Document>),
+    Remove(u32),
+}
+
+// Entries read back from a journal. `valid_len` is where the last complete
+// record ends; anything after it was torn by a crash mid-append.
+pub struct JournalContents {
+    pub entries: Vec<JournalEntry>,
+    pub valid_len: u64,
+    pub torn: bool,
+}
+
+// `search_index.bin` journals to `search_index.bin.journal`
+pub fn journal_path(index_path: &Path) -> PathBuf {
+    let mut file_name = index_path.as_os_str().to_os_string();
+    file_name.push(".journal");
+    PathBuf::from(file_name)
+}
+
+// Layout: JOURNAL_MAGIC, JOURNAL_VERSION (u32 LE), then records of a length
+// (u32 LE) and a bincode-encoded entry.
+pub fn append(path: &Path, entries: &[JournalEntry]) -> Result<()> {
+    let mut buffer = Vec::new();
+    // An empty file is what's left of a journal torn inside its header
+    let is_new = fs::metadata(path).map_or(true, |metadata| metadata.len() == 0);
+    if is_new {
+        buffer.extend_from_slice(JOURNAL_MAGIC);
+        buffer.extend_from_slice(&JOURNAL_VERSION.to_le_bytes());
+    }
+    for entry in entries {
+        let record = bincode::serde::encode_to_vec(entry, bincode::config::standard())
+            .context("Failed to encode journal entry")?;
+        buffer.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&record);
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open journal {:?}", path))?;
+    file.write_all(&buffer)
+        .and_then(|()| file.sync_data())
+        .with_context(|| format!("Failed to append to journal {:?}", path))
+}
+
+// The journal's entries, or `None` if there is no journal
+pub fn read(path: &Path) -> Result<Option<JournalContents>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(anyhow::Error::new(e).context(format!("Failed to read journal {:?}", path)));
+        }
+    };
+    if data.len() < HEADER_LEN {
+        return Ok(Some(JournalContents {
+            entries: Vec::new(),
+            valid_len: 0,
+            torn: true,
+        }));
+    }
+    let version = data
+        .strip_prefix(JOURNAL_MAGIC.as_slice())
+        .and_then(|rest| rest.get(..4))
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+    if version != Some(JOURNAL_VERSION) {
+        return Err(anyhow!(
+            "{:?} is not a journal this version of infospark can replay",
+            path
+        ));
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = HEADER_LEN;
+    while offset < data.len() {
+        let Some(len_bytes) = data.get(offset..offset + 4) else {
+            break;
+        };
+        let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+        let Some(record) = data.get(offset + 4..offset + 4 + len) else {
+            break;
+        };
+        let Ok((entry, _)) = bincode::serde::decode_from_slice::<JournalEntry, _>(
+            record,
+            bincode::config::standard(),
+        ) else {
+            break;
+        };
+        entries.push(entry);
+        offset += 4 + len;
+    }
+    Ok(Some(JournalContents {
+        entries,
+        valid_len: offset as u64,
+        torn: offset < data.len(),
+    }))
+}
+
+// Drops a torn tail, so later appends are not hidden behind it
+pub fn truncate(path: &Path, len: u64) -> Result<()> {
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_len(len))
+        .with_context(|| format!("Failed to repair journal {:?}", path))
+}
+
+pub fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => {
+            Err(anyhow::Error::new(e).context(format!("Failed to remove journal {:?}", path)))
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// src/json_records.rs
+// Documents from JSON exported by other tools: a `.json` file holding one
+// object or an array of them, or a `.jsonl` file with a value per line. The
+// corpus root's `JsonMapping` picks the fields making up each record's title,
+// text and tags.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::config::JsonMapping;
+use crate::inverted_index::ExtractedMetadata;
+
+// --- CONSTANTS ---
+pub const EXTENSIONS: [&str; 2] = ["json", "jsonl"];
+
+// --- STRUCTS ---
+pub(crate) struct Record {
+    pub(crate) line: Option<usize>, // None when the file holds a single object
+    pub(crate) content: String,
+    pub(crate) metadata: ExtractedMetadata, // Mapped title and tags
+}
+
+// The records in `text`, read from the file at `path`
+pub(crate) fn records(path: &Path, text: &str, mapping: &JsonMapping) -> Result<Vec<Record>> {
+    let record = |line, value: &Value| Record {
+        line,
+        content: content(value, mapping),
+        metadata: ExtractedMetadata {
+            title: field(value, &mapping.title).map(text_of),
+            tags: field(value, &mapping.tags).map(tags_of).unwrap_or_default(),
+            ..Default::default()
+        },
+    };
+
+    if path.extension().is_some_and(|ext| ext == "jsonl") {
+        let mut records = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value: Value = serde_json::from_str(line)
+                .with_context(|| format!("Failed to parse JSON on line {}", idx + 1))?;
+            records.push(record(Some(idx + 1), &value));
+        }
+        return Ok(records);
+    }
+    let value: Value = serde_json::from_str(text).context("Failed to parse JSON file")?;
+    Ok(match &value {
+        Value::Array(elements) => {
+            let lines = element_lines(text);
+            elements
+                .iter()
+                .enumerate()
+                .map(|(idx, element)| record(lines.get(idx).copied(), element))
+                .collect()
+        }
+        _ => vec![record(None, &value)],
+    })
+}
+
+// The value at a dotted path of object keys
+fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return None;
+    }
+    path.split('.')
+        .try_fold(value, |value, key| value.get(key))
+        .filter(|value| !value.is_null())
+}
+
+fn content(value: &Value, mapping: &JsonMapping) -> String {
+    if mapping.content.is_empty() {
+        let mut lines = String::new();
+        write_fields("", value, &mut lines);
+        return lines;
+    }
+    mapping
+        .content
+        .iter()
+        .filter_map(|path| field(value, path))
+        .map(text_of)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// A line of text per string, number and boolean in `value`, one per element
+// of arrays and `field: value` lines for objects
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(elements) => elements
+            .iter()
+            .map(text_of)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Object(_) => {
+            let mut lines = String::new();
+            write_fields("", value, &mut lines);
+            lines.trim_end().to_string()
+        }
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+// `prefix.key: value` lines for the scalars under `value`
+fn write_fields(prefix: &str, value: &Value, lines: &mut String) {
+    match value {
+        Value::Object(entries) => {
+            for (key, value) in entries {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                write_fields(&name, value, lines);
+            }
+        }
+        Value::Array(elements) => {
+            for element in elements {
+                write_fields(prefix, element, lines);
+            }
+        }
+        Value::Null => {}
+        Value::String(text) if text.trim().is_empty() => {}
+        scalar => {
+            if !prefix.is_empty() {
+                lines.push_str(prefix);
+                lines.push_str(": ");
+            }
+            match scalar {
+                Value::String(text) => lines.push_str(text.trim()),
+                other => lines.push_str(&other.to_string()),
+            }
+            lines.push('\n');
+        }
+    }
+}
+
+fn tags_of(value: &Value) -> Vec<String> {
+    let list: Vec<&str> = match value {
+        Value::String(text) => text.split(',').collect(),
+        Value::Array(elements) => elements.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    list.into_iter()
+        .map(|tag| tag.trim().trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// The 1-based line each element of the top-level array in `text` starts on
+fn element_lines(text: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut line = 1;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut expecting_element = false;
+    for c in text.chars() {
+        if c == '\n' {
+            line += 1;
+        }
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        if depth == 1 && expecting_element && c != ']' {
+            lines.push(line);
+            expecting_element = false;
+        }
+        match c {
+            '[' | '{' => {
+                depth += 1;
+                expecting_element = depth == 1;
+            }
+            ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 1 => expecting_element = true,
+            '"' => in_string = true,
+            _ => {}
+        }
+    }
+    lines
+}
@@ -0,0 +1,345 @@
+// src/latex.rs
+// Readable text from LaTeX sources: section titles and body text, without
+// the commands, math, comments and environment markup around them. The
+// title and authors come from `\title` and `\author`.
+
+use crate::inverted_index::ExtractedMetadata;
+
+// --- CONSTANTS ---
+const SECTIONING_COMMANDS: [&str; 7] = [
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+// Commands whose braced arguments are text
+const TEXT_COMMANDS: [&str; 17] = [
+    "textbf",
+    "textit",
+    "textsl",
+    "textsc",
+    "textrm",
+    "textsf",
+    "texttt",
+    "textup",
+    "textmd",
+    "emph",
+    "underline",
+    "mbox",
+    "caption",
+    "footnote",
+    "textnormal",
+    "enquote",
+    "uppercase",
+];
+// Left out along with their contents
+const MATH_ENVIRONMENTS: [&str; 11] = [
+    "equation",
+    "align",
+    "alignat",
+    "gather",
+    "multline",
+    "eqnarray",
+    "displaymath",
+    "math",
+    "flalign",
+    "tikzpicture",
+    "thebibliography",
+];
+// Kept as is
+const VERBATIM_ENVIRONMENTS: [&str; 4] = ["verbatim", "lstlisting", "minted", "comment"];
+
+// --- STRUCTS ---
+struct Scanner {
+    chars: Vec<char>,
+    pos: usize,
+    title: Option<String>,
+    authors: Option<String>,
+}
+
+pub(crate) fn extract(source: &str) -> (String, ExtractedMetadata) {
+    let mut scanner = Scanner {
+        chars: source.chars().collect(),
+        pos: 0,
+        title: None,
+        authors: None,
+    };
+    let mut body = String::new();
+    scanner.text_until(None, &mut body);
+
+    let mut text = String::new();
+    if let Some(title) = &scanner.title {
+        text.push_str(title);
+        text.push_str("\n\n");
+    }
+    text.push_str(&tidy(&body));
+    let metadata = ExtractedMetadata {
+        title: scanner.title,
+        author: scanner.authors,
+        ..Default::default()
+    };
+    (text, metadata)
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    // Writes text to `out` until the unmatched `end` (consumed) or the end
+    fn text_until(&mut self, end: Option<char>, out: &mut String) {
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                _ if Some(c) == end => return,
+                '%' => self.skip_comment(),
+                '\\' => self.command(out),
+                '{' => self.text_until(Some('}'), out),
+                '$' => self.skip_inline_math(),
+                '~' => out.push(' '),
+                '&' => out.push(' '),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == '\n' {
+                return;
+            }
+        }
+    }
+
+    fn skip_inline_math(&mut self) {
+        let display = self.peek() == Some('$');
+        if display {
+            self.pos += 1;
+        }
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => self.pos += 1,
+                '$' if !display => return,
+                '$' if self.peek() == Some('$') => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Skips past `closing` (e.g. `\]`), ignoring everything before it
+    fn skip_past(&mut self, closing: &str) {
+        let closing: Vec<char> = closing.chars().collect();
+        while self.pos < self.chars.len() {
+            if self.chars[self.pos..].starts_with(&closing) {
+                self.pos += closing.len();
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+
+    // The raw text up to `\end{name}`, which is consumed
+    fn raw_until_end(&mut self, name: &str) -> String {
+        let closing: Vec<char> = format!("\\end{{{}}}", name).chars().collect();
+        let start = self.pos;
+        while self.pos < self.chars.len() {
+            if self.chars[self.pos..].starts_with(&closing) {
+                let raw = self.chars[start..self.pos].iter().collect();
+                self.pos += closing.len();
+                return raw;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..].iter().collect()
+    }
+
+    fn command_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+            name.push(c);
+            self.pos += 1;
+        }
+        if self.peek() == Some('*') {
+            self.pos += 1;
+        }
+        name
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.pos += 1;
+        }
+    }
+
+    // Skips `[...]` arguments, returning whether there were any
+    fn skip_optional_args(&mut self) -> bool {
+        let mut skipped = false;
+        loop {
+            let start = self.pos;
+            self.skip_spaces();
+            if self.peek() != Some('[') {
+                self.pos = start;
+                return skipped;
+            }
+            let mut depth = 0usize;
+            while let Some(c) = self.peek() {
+                self.pos += 1;
+                match c {
+                    '[' | '{' => depth += 1,
+                    ']' | '}' => {
+                        depth = depth.saturating_sub(1);
+                        if depth == 0 && c == ']' {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            skipped = true;
+        }
+    }
+
+    // The text of a `{...}` argument, or None if none follows
+    fn braced_text(&mut self) -> Option<String> {
+        let start = self.pos;
+        self.skip_spaces();
+        if self.peek() != Some('{') {
+            self.pos = start;
+            return None;
+        }
+        self.pos += 1;
+        let mut text = String::new();
+        self.text_until(Some('}'), &mut text);
+        Some(text)
+    }
+
+    // Skips the arguments of a command whose arguments are not text
+    fn skip_args(&mut self) {
+        loop {
+            let optional = self.skip_optional_args();
+            if self.braced_text().is_none() && !optional {
+                return;
+            }
+        }
+    }
+
+    fn command(&mut self, out: &mut String) {
+        let Some(c) = self.peek() else {
+            return;
+        };
+        if !c.is_ascii_alphabetic() {
+            self.pos += 1;
+            match c {
+                '\\' => {
+                    self.skip_optional_args(); // `\\[2pt]`
+                    out.push('\n');
+                }
+                '[' => self.skip_past("\\]"),
+                '(' => self.skip_past("\\)"),
+                '&' | '%' | '$' | '#' | '_' | '{' | '}' => out.push(c),
+                ',' | ';' | ' ' | '!' | ':' => out.push(' '),
+                _ => {} // Accents and the like; the letter after them stays
+            }
+            return;
+        }
+
+        let name = self.command_name();
+        let name = name.as_str();
+        match name {
+            "begin" => {
+                let environment = self.braced_text().unwrap_or_default();
+                let base = environment.trim_end_matches('*');
+                if MATH_ENVIRONMENTS.contains(&base) {
+                    self.raw_until_end(&environment);
+                } else if VERBATIM_ENVIRONMENTS.contains(&base) {
+                    self.skip_args();
+                    out.push('\n');
+                    out.push_str(&self.raw_until_end(&environment));
+                    out.push('\n');
+                } else {
+                    // Column specs, widths and the like
+                    self.skip_args();
+                    out.push('\n');
+                }
+            }
+            "end" => {
+                self.braced_text();
+                out.push('\n');
+            }
+            "title" => {
+                self.skip_optional_args();
+                self.title = self.braced_text().map(|title| collapse(&title));
+            }
+            "author" => {
+                self.skip_optional_args();
+                self.authors = self.braced_text().map(|authors| {
+                    authors
+                        .split('\u{1}')
+                        .map(collapse)
+                        .filter(|author| !author.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                });
+            }
+            // Separates the names inside `\author`
+            "and" => out.push('\u{1}'),
+            "par" | "newline" | "linebreak" | "item" | "maketitle" => {
+                self.skip_optional_args();
+                out.push('\n');
+            }
+            "href" => {
+                self.braced_text();
+                if let Some(text) = self.braced_text() {
+                    out.push_str(&text);
+                }
+            }
+            "ldots" | "dots" => out.push_str("..."),
+            _ if SECTIONING_COMMANDS.contains(&name) => {
+                self.skip_optional_args();
+                if let Some(heading) = self.braced_text() {
+                    out.push_str("\n\n");
+                    out.push_str(&collapse(&heading));
+                    out.push_str("\n\n");
+                }
+            }
+            _ if TEXT_COMMANDS.contains(&name) => {
+                self.skip_optional_args();
+                while let Some(text) = self.braced_text() {
+                    out.push_str(&text);
+                }
+            }
+            _ => self.skip_args(),
+        }
+    }
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Paragraphs from the extracted body: lines with collapsed spaces, at most
+// one blank line in a row
+fn tidy(body: &str) -> String {
+    let mut text = String::new();
+    let mut blank_lines = 0;
+    for line in body.replace('\u{1}', " ").lines().map(collapse) {
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !text.is_empty() {
+            text.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        text.push_str(&line);
+        blank_lines = 0;
+    }
+    text
+}
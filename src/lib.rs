@@ -0,0 +1,18 @@
+// src/lib.rs
+// The search engine proper, shared by the `infospark` binary and the language
+// bindings under `bindings/`.
+pub mod archive;
+pub mod async_index;
+pub mod config;
+pub mod extractor;
+pub mod inverted_index;
+pub mod journal;
+mod json_records;
+mod latex;
+mod near_duplicates;
+mod opendocument;
+pub mod query;
+pub mod stored;
+pub mod tag_sidecar;
+pub mod tokenizer;
+pub mod web_pages;
@@ -1,65 +1,314 @@
 // src/main.rs
-mod inverted_index;
-mod tokenizer;
-
-use inverted_index::{InvertedIndex, SearchResult};
+mod analysis;
+mod cli;
+mod completion;
+mod daemon;
+mod editor;
+mod facets;
+mod federated;
+mod feeds;
+mod gen_corpus;
+mod history;
+mod pager;
+mod picker;
+mod rate_limit;
+mod remote;
+mod report;
+mod saved_queries;
+mod serve;
+mod snapshot;
+mod sync;
+mod watcher;
+mod web_app;
+
+use infospark::{
+    archive, async_index, config, inverted_index, journal, query, tag_sidecar, tokenizer, web_pages,
+};
+
+use analysis::TypoKind;
+use async_index::SharedIndex;
+use cli::{Cli, Command, FeedsCommand, SnapshotCommand, SortOrder};
+use completion::QueryHelper;
+use config::{Config, CorpusRoot, IndexingConfig};
+use federated::IndexSource;
+use gen_corpus::CorpusSpec;
+use inverted_index::{CacheStats, IndexingReport, InvertedIndex, ResultSummary, SearchResult};
+use saved_queries::SavedQueries;
+use serve::ServeOptions;
+use snapshot::SnapshotTargets;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::path::Path;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use watcher::{CorpusWatcher, DEFAULT_DEBOUNCE};
+use web_app::{Layout, PageTemplate};
+
+use chrono::{Local, TimeZone};
+use clap::Parser;
 
-use rustyline::DefaultEditor;
+use rustyline::Editor;
 use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 use colored::*;
 
 const INDEX_FILE: &str = "search_index.bin";
-const HISTORY_FILE: &str = ".infospark_history";
+const CORPUS_ENV_VAR: &str = "INFOSPARK_CORPUS";
 const GRAPH_HTML_FILE: &str = "infospark_graph.html";
 
 fn main() -> Result<()> {
-    let mut index = InvertedIndex::new();
-    let index_path = Path::new(INDEX_FILE);
+    let cli = Cli::parse();
+    let mut config = Config::load_or_default(cli.config.as_deref())?;
+    if let Some(capacity) = cli.cache_capacity {
+        config.cache.capacity = capacity;
+    }
+    if let Some(ttl_secs) = cli.cache_ttl {
+        config.cache.ttl_secs = Some(ttl_secs);
+    }
+    if let Some(context_chars) = cli.snippet_context {
+        config.snippets.context_chars = context_chars;
+    }
+    if let Some(max_lines) = cli.snippet_lines {
+        config.snippets.max_lines = max_lines;
+    }
+    if let Some(max_passages) = cli.snippet_passages {
+        config.snippets.max_passages = max_passages;
+    }
+    if let Some(preview_chars) = cli.preview_chars {
+        config.snippets.preview_chars = preview_chars;
+    }
+    if let Some(graph_preview_chars) = cli.graph_preview_chars {
+        config.snippets.graph_preview_chars = graph_preview_chars;
+    }
+    if let Some(fuzzy_distance) = cli.fuzzy_distance {
+        config.query.fuzzy_distance = fuzzy_distance;
+    }
+    if let Some(ranking) = cli.rank {
+        config.query.ranking = ranking;
+    }
+    if let Some(half_life) = cli.recency_half_life {
+        if !half_life.is_finite() || half_life <= 0.0 {
+            return Err(anyhow!(
+                "--recency-half-life must be a positive number of days, got {}",
+                half_life
+            ));
+        }
+        config.query.recency_half_life_days = Some(half_life);
+    }
+    if let Some(similarity) = cli.near_duplicates {
+        if !(similarity > 0.0 && similarity <= 1.0) {
+            return Err(anyhow!(
+                "--near-duplicates must be a similarity above 0 and at most 1, got {}",
+                similarity
+            ));
+        }
+        config.results.near_duplicates = Some(similarity);
+    }
+    if cli.all_results {
+        config.results.min_score = 0.0;
+    }
+    if cli.no_cache {
+        config.cache.enabled = false;
+    }
+    if cli.no_pager {
+        config.pager.enabled = false;
+    }
+    if cli.mmap {
+        config.indexing.mmap = true;
+    }
+    if cli.fail_fast {
+        config.indexing.fail_fast = true;
+    }
+    let corpora: Vec<PathBuf> = if cli.corpora.is_empty() {
+        env::var_os(CORPUS_ENV_VAR)
+            .map(|paths| env::split_paths(&paths).collect())
+            .unwrap_or_default()
+    } else {
+        cli.corpora.clone()
+    };
+    let mut corpus_roots: Vec<CorpusRoot> = if corpora.is_empty() {
+        config.corpus_roots()
+    } else {
+        corpora
+            .iter()
+            .map(|path| config.corpus_root(path))
+            .collect()
+    };
+    for root in &mut corpus_roots {
+        root.rows_as_docs |= cli.rows_as_docs;
+        root.sections_as_docs |= cli.sections_as_docs;
+    }
+    let index_path = cli
+        .index
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(INDEX_FILE));
+
+    if let Some(remote_url) = &cli.remote {
+        return match cli.command {
+            Some(Command::Search {
+                query,
+                federate,
+                sort,
+                facets,
+                max_per_dir,
+                max_per_tag,
+                limit,
+                offset,
+            }) if federate.is_empty() => {
+                // In rank order, only the page shown needs fetching
+                let in_rank_order = sort == SortOrder::Relevance
+                    && max_per_dir.is_none()
+                    && max_per_tag.is_none()
+                    && !facets;
+                if in_rank_order {
+                    let (results, total) = remote::search(remote_url, &query, offset, limit)?;
+                    if total == 0 {
+                        println!("No results found for '{}'", query);
+                    }
+                    let _pager = pager::start(&config.pager);
+                    for result in &results {
+                        print_result(result, None);
+                    }
+                    print_page_note(offset, limit, total);
+                    return Ok(());
+                }
+                let (mut results, _) = remote::search(remote_url, &query, 0, None)?;
+                if results.is_empty() {
+                    println!("No results found for '{}'", query);
+                }
+                sort_results(&mut results, sort, |result| result);
+                let results = diversify(results, max_per_dir, max_per_tag, |result| result);
+                let total = results.len();
+                let _pager = pager::start(&config.pager);
+                for result in page(&results, offset, limit) {
+                    print_result(result, None);
+                }
+                print_page_note(offset, limit, total);
+                if facets {
+                    print_facets(&results.iter().collect::<Vec<_>>());
+                }
+                Ok(())
+            }
+            Some(Command::Search { .. }) => {
+                Err(anyhow!("--federate cannot be combined with --remote"))
+            }
+            _ => Err(anyhow!(
+                "--remote is only supported with the search command"
+            )),
+        };
+    }
 
-    let mut rl = DefaultEditor::new().context("Failed to create readline editor")?;
+    match cli.command {
+        Some(command) => run_command(
+            command,
+            &config,
+            &corpus_roots,
+            &index_path,
+            cli.auto_rebuild,
+            cli.template.as_deref(),
+        ),
+        None => run_interactive(
+            &config,
+            &corpus_roots,
+            &index_path,
+            cli.auto_rebuild,
+            cli.template.as_deref(),
+            false,
+        ),
+    }
+}
 
-    if rl.load_history(HISTORY_FILE).is_err() {
+// The interactive prompt. With `watch`, changes to the corpus roots are
+// indexed and saved in the background while it runs.
+fn run_interactive(
+    config: &Config,
+    corpus_roots: &[CorpusRoot],
+    index_path: &Path,
+    auto_rebuild: bool,
+    template: Option<&Path>,
+    watch: bool,
+) -> Result<()> {
+    let mut rl: Editor<QueryHelper, FileHistory> =
+        Editor::new().context("Failed to create readline editor")?;
+
+    let history_file = history::history_path(config, corpus_roots);
+    if rl.load_history(&history_file).is_err()
+        && rl.load_history(history::LEGACY_HISTORY_FILE).is_err()
+    {
         println!("No previous search history found.");
     }
 
-    if index_path.exists() {
-        println!("Loading existing index from '{}'...", INDEX_FILE);
-        let encoded_data = fs::read(index_path).context("Failed to read existing index file")?;
-
-        index = InvertedIndex::from_serialized_data(&encoded_data)
-            .context("Failed to deserialize existing index")?;
-
-        println!(
-            "Index loaded. Total documents indexed: {}\n",
-            index.total_documents()
-        );
+    let mut index = if index_path.exists() {
+        println!("Loading existing index from '{}'...", index_path.display());
+        match load_index(index_path, &config.indexing) {
+            Ok(mut index) => {
+                index.configure_indexing(&config.indexing)?;
+                index.relocate(corpus_roots);
+                println!(
+                    "Index loaded. Total documents indexed: {}\n",
+                    index.total_documents()
+                );
+                index
+            }
+            Err(e) => {
+                eprintln!("{} {:#}", "Existing index cannot be used:".yellow(), e);
+                if !auto_rebuild && !confirm_rebuild(&mut rl)? {
+                    return Err(e.context(format!(
+                        "Delete '{}' or run with --auto-rebuild to reindex",
+                        index_path.display()
+                    )));
+                }
+                println!("Rebuilding index from the corpus...\n");
+                build_and_save_index(corpus_roots, &config.indexing, index_path)?
+            }
+        }
     } else {
-        let corpus_path = Path::new("corpus");
-        println!(
-            "No existing index found. Loading documents from: {:?}\n",
-            corpus_path
-        );
-        index
-            .load_documents_from_directory(corpus_path)
-            .context("Failed to load documents from directory")?;
-        println!(
-            "\nIndexing complete. Total documents indexed: {}\n",
-            index.total_documents()
-        );
-
-        println!("Saving index to '{}'...", INDEX_FILE);
-        let encoded_data = index
-            .to_serialized_data()
-            .context("Failed to serialize index for saving")?;
-        fs::write(index_path, encoded_data).context("Failed to write index to file")?;
-        println!("Index saved.\n");
+        println!("No existing index found.");
+        build_and_save_index(corpus_roots, &config.indexing, index_path)?
+    };
+    configure_index(&mut index, config);
+    rl.set_helper(Some(QueryHelper::new(&index)));
+    let index: SharedIndex = Arc::new(RwLock::new(index));
+    // Reports from background refreshes, printed before the next prompt
+    let notices: Arc<Mutex<Vec<String>>> = Arc::default();
+    if watch {
+        let watcher = CorpusWatcher::new(corpus_roots)?;
+        let (watch_index, watch_notices) = (index.clone(), notices.clone());
+        let (roots, watch_index_path) = (corpus_roots.to_vec(), index_path.to_path_buf());
+        thread::spawn(move || {
+            reindex_on_changes(
+                &watch_index,
+                &watcher,
+                &roots,
+                &watch_index_path,
+                &watch_notices,
+            )
+        });
+        println!("Watching corpus roots for changes...\n");
     }
+    // Results of the latest query shown so far, so `open` can jump to their
+    // first match and `more` can carry on after them
+    let mut last_results: Vec<SearchResult> = Vec::new();
+    let mut last_query: Option<(String, usize)> = None; // Query and its number of results
+    let page_size = config.results.page_size.get();
+    let saved_queries_file = saved_queries::saved_queries_path(index_path);
+    let mut saved_queries = SavedQueries::load(&saved_queries_file)?;
 
     loop {
+        let pending = std::mem::take(&mut *notices.lock().unwrap());
+        if !pending.is_empty() {
+            for notice in &pending {
+                println!("{}", notice.dimmed());
+            }
+            println!();
+            // Completion picks up the tags and terms of newly indexed documents
+            rl.set_helper(Some(QueryHelper::new(&index.read().unwrap())));
+        }
         let readline =
             rl.readline("Enter search query (or 'graph' to open web app, 'exit' to quit): ");
 
@@ -74,708 +323,102 @@ fn main() -> Result<()> {
                 rl.add_history_entry(line.as_str())
                     .context("Failed to add query to history")?;
 
+                // `run <name>` stands for the query saved as `name`; like
+                // `open`, any other `run ...` is an ordinary query
+                let saved_query = query
+                    .strip_prefix("run ")
+                    .and_then(|name| saved_queries.get(name.trim()))
+                    .map(str::to_string);
+                let (query, saved_limit) = match &saved_query {
+                    Some(saved_query) => {
+                        println!("{}", format!("Running '{}'", saved_query).dimmed());
+                        match saved_queries::split_limit(saved_query) {
+                            Ok(split) => split,
+                            Err(e) => {
+                                println!("{}\n", format!("{:#}", e).red());
+                                continue;
+                            }
+                        }
+                    }
+                    None => (query, None),
+                };
+
                 if query.eq_ignore_ascii_case("exit") {
                     break;
+                } else if let Some(cache_command) = query.strip_prefix(":cache") {
+                    match cache_command.trim() {
+                        "stats" => print_cache_stats(&index.read().unwrap().cache_stats()),
+                        _ => println!("Usage: :cache stats\n"),
+                    }
+                } else if let Some(args) = query.strip_prefix("save ") {
+                    let Some((name, saved_query)) = saved_queries::parse_save_args(args) else {
+                        println!("Usage: save <name> <query>\n");
+                        continue;
+                    };
+                    match saved_queries
+                        .insert(name, saved_query)
+                        .and_then(|()| saved_queries.save(&saved_queries_file))
+                    {
+                        Ok(()) => println!(
+                            "Saved '{}' as {}; type 'run {}' to run it\n",
+                            saved_query, name, name
+                        ),
+                        Err(e) => println!("{}\n", format!("{:#}", e).red()),
+                    }
+                } else if let Some(name) = query.strip_prefix("unsave ") {
+                    let name = name.trim();
+                    if !saved_queries.remove(name) {
+                        println!("No saved query named '{}'\n", name);
+                        continue;
+                    }
+                    match saved_queries.save(&saved_queries_file) {
+                        Ok(()) => println!("Removed the saved query {}\n", name),
+                        Err(e) => println!("{}\n", format!("{:#}", e).red()),
+                    }
+                } else if query.eq_ignore_ascii_case("saved") {
+                    print_saved_queries(&saved_queries);
+                } else if let Some(doc_id) = query
+                    .strip_prefix("open ")
+                    .and_then(|doc_id| doc_id.trim().parse::<u32>().ok())
+                {
+                    // `open <doc id>`; any other `open ...` is an ordinary query
+                    open_document(&index.read().unwrap(), &last_results, doc_id, config);
+                } else if let Some(doc_id) = query
+                    .strip_prefix("similar ")
+                    .and_then(|doc_id| doc_id.trim().parse::<u32>().ok())
+                {
+                    // Like `open`, any other `similar ...` is an ordinary query
+                    let index = index.read().unwrap();
+                    let Some(results) = index.similar(doc_id, page_size) else {
+                        println!("{}\n", format!("No document with id {}", doc_id).red());
+                        continue;
+                    };
+                    let _pager = pager::start(&config.pager);
+                    print_similar(&index, doc_id, &results);
+                    println!();
+                    // `open` goes to the shown documents; `more` has no query to continue
+                    last_results = results;
+                    last_query = None;
+                } else if let Some(doc_id) = query
+                    .strip_prefix("expand ")
+                    .and_then(|doc_id| doc_id.trim().parse::<u32>().ok())
+                {
+                    // Like `open`, any other `expand ...` is an ordinary query
+                    match last_results.iter().find(|result| result.doc.id == doc_id) {
+                        Some(result) => print_near_duplicates(&index.read().unwrap(), result),
+                        None => println!("Document {} is not among the results shown\n", doc_id),
+                    }
                 } else if query.eq_ignore_ascii_case("graph") {
                     println!("Generating interactive web app data...");
-                    match index.generate_network_graph_data() {
-                        Ok(json_data) => {
-                            let escaped_json_data = json_data
-                                .replace("\\", "\\\\")
-                                .replace("\"", "\\\"")
-                                .replace("\n", "\\n")
-                                .replace("\r", "\\r")
-                                .replace("\t", "\\t")
-                                .replace("`", "\\`");
-
-                            let html_content = format!(
-                                r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Infospark Interactive Graph & Search</title>
-    <script type="text/javascript" src="https://unpkg.com/vis-network@9.1.2/dist/vis-network.min.js"></script>
-    <link href="https://unpkg.com/vis-network@9.1.2/dist/vis-network.min.css" rel="stylesheet" type="text/css" />
-    <style type="text/css">
-        @import url('https://fonts.googleapis.com/css2?family=Inter:wght@400;700&display=swap');
-        body {{
-            font-family: 'Inter', sans-serif;
-            margin: 0;
-            padding: 0;
-            overflow: hidden; /* Prevent scrollbars */
-            background-color: #f0f2f5;
-        }}
-        #app-container {{ /* Main app container */
-            display: flex; 
-            height: 100vh;
-            width: 100vw;
-        }}
-        #sidebar {{
-            width: 300px;
-            background-color: #fff;
-            box-shadow: 2px 0 5px rgba(0,0,0,0.1);
-            display: flex;
-            flex-direction: column;
-            padding: 15px;
-            overflow-y: auto; 
-            z-index: 101; 
-            transition: width 0.3s ease-in-out, padding 0.3s ease-in-out;
-            flex-shrink: 0;
-        }}
-        #sidebar.collapsed {{
-            width: 0;
-            padding: 0;
-            overflow: hidden;
-        }}
-        #main-content {{
-            flex-grow: 1; 
-            position: relative;
-            transition: margin-left 0.3s ease-in-out;
-        }}
-        #main-content.expanded-margin {{
-        }}
-        #mynetwork {{
-            width: 100%;
-            height: 100%;
-            border: 1px solid lightgray;
-            background-color: #f9f9f9;
-        }}
-        #search-container {{
-            margin-bottom: 20px;
-            padding-bottom: 15px;
-            border-bottom: 1px solid #eee;
-        }}
-        #search-input {{
-            width: calc(100% - 20px);
-            padding: 10px;
-            margin-bottom: 10px;
-            border: 1px solid #ddd;
-            border-radius: 5px;
-            font-size: 1em;
-        }}
-        .search-button {{
-            padding: 8px 12px;
-            background-color: #007bff;
-            color: white;
-            border: none;
-            border-radius: 5px;
-            cursor: pointer;
-            font-size: 0.9em;
-            margin-right: 5px;
-            transition: background-color 0.2s ease;
-        }}
-        .search-button:hover {{
-            background-color: #0056b3;
-        }}
-        #reset-search-button {{
-            background-color: #6c757d;
-        }}
-        #reset-search-button:hover {{
-            background-color: #5a6268;
-        }}
-        #search-results {{
-            flex-grow: 1;
-            overflow-y: auto;
-            border-top: 1px solid #eee;
-            padding-top: 15px;
-        }}
-        .search-result-item {{
-            background-color: #f8f9fa;
-            border: 1px solid #e9ecef;
-            border-radius: 5px;
-            padding: 10px;
-            margin-bottom: 10px;
-            cursor: pointer;
-            transition: background-color 0.2s ease;
-        }}
-        .search-result-item:hover {{
-            background-color: #e2e6ea;
-        }}
-        .search-result-item h4 {{
-            margin-top: 0;
-            margin-bottom: 5px;
-            color: #333;
-        }}
-        .search-result-item p {{
-            font-size: 0.9em;
-            color: #666;
-            margin-bottom: 5px;
-        }}
-        .search-result-item .tags {{
-            font-size: 0.8em;
-            color: #00796b;
-        }}
-        .search-result-item .tags span {{
-            background-color: #e0f7fa;
-            padding: 2px 6px;
-            border-radius: 3px;
-            margin-right: 3px;
-            display: inline-block;
-            margin-bottom: 3px;
-        }}
-
-        /* Graph filter controls */
-        #graph-filter-controls {{
-            position: absolute;
-            top: 10px;
-            right: 10px;
-            background: rgba(255, 255, 255, 0.9);
-            padding: 10px 15px;
-            border-radius: 8px;
-            box-shadow: 0 2px 10px rgba(0,0,0,0.1);
-            display: flex;
-            gap: 10px;
-            align-items: center;
-            z-index: 100;
-        }}
-        #graph-filter-input {{
-            padding: 8px;
-            border: 1px solid #ccc;
-            border-radius: 5px;
-            font-size: 0.9em;
-            width: 180px;
-        }}
-        .graph-filter-button {{
-            padding: 8px 12px;
-            background-color: #4CAF50;
-            color: white;
-            border: none;
-            border-radius: 5px;
-            cursor: pointer;
-            font-size: 0.9em;
-            transition: background-color 0.2s ease;
-        }}
-        .graph-filter-button:hover {{
-            background-color: #45a049;
-        }}
-        #reset-graph-filter-button {{
-            background-color: #008CBA;
-        }}
-        #reset-graph-filter-button:hover {{
-            background-color: #007bb5;
-        }}
-
-        .vis-tooltip {{
-            background-color: #333;
-            color: white;
-            padding: 8px 12px;
-            border-radius: 5px;
-            font-size: 14px;
-            box-shadow: 0 2px 10px rgba(0,0,0,0.2);
-            max-width: 300px;
-            word-wrap: break-word;
-        }}
-        .modal-overlay {{
-            position: fixed;
-            top: 0;
-            left: 0;
-            width: 100%;
-            height: 100%;
-            background: rgba(0, 0, 0, 0.6);
-            display: flex;
-            justify-content: center;
-            align-items: center;
-            z-index: 1000;
-            visibility: hidden;
-            opacity: 0;
-            transition: visibility 0s, opacity 0.3s ease;
-        }}
-        .modal-overlay.visible {{
-            visibility: visible;
-            opacity: 1;
-        }}
-        .modal-content {{
-            background: white;
-            padding: 30px;
-            border-radius: 10px;
-            box-shadow: 0 5px 20px rgba(0, 0, 0, 0.3);
-            width: 80%;
-            max-width: 600px;
-            max-height: 80vh;
-            overflow-y: auto;
-            position: relative;
-        }}
-        .modal-header {{
-            display: flex;
-            justify-content: space-between;
-            align-items: center;
-            border-bottom: 1px solid #eee;
-            padding-bottom: 15px;
-            margin-bottom: 15px;
-        }}
-        .modal-header h3 {{
-            margin: 0;
-            color: #333;
-            font-size: 1.5em;
-        }}
-        .modal-close-button {{
-            background: #f44336;
-            color: white;
-            border: none;
-            border-radius: 50%;
-            width: 30px;
-            height: 30px;
-            font-size: 1.2em;
-            cursor: pointer;
-            display: flex;
-            justify-content: center;
-            align-items: center;
-            transition: background-color 0.2s ease;
-        }}
-        .modal-close-button:hover {{
-            background-color: #d32f2f;
-        }}
-        .modal-body p {{
-            font-size: 0.95em;
-            line-height: 1.6;
-            color: #555;
-            white-space: pre-wrap;
-        }}
-        .modal-tags {{
-            margin-top: 10px;
-            font-size: 0.85em;
-            color: #666;
-        }}
-        .modal-tags span {{
-            background-color: #e0f7fa;
-            color: #00796b;
-            padding: 3px 8px;
-            border-radius: 5px;
-            margin-right: 5px;
-            display: inline-block;
-            margin-bottom: 5px;
-        }}
-        #sidebar-toggle {{
-            position: absolute;
-            top: 15px;
-            left: 310px;
-            z-index: 102;
-            background-color: #007bff;
-            color: white;
-            border: none;
-            border-radius: 5px;
-            padding: 8px 12px;
-            cursor: pointer;
-            font-size: 1.2em;
-            transition: left 0.3s ease-in-out, background-color 0.2s ease;
-        }}
-        #sidebar-toggle.collapsed-position {{
-            left: 10px;
-        }}
-        #sidebar-toggle:hover {{
-            background-color: #0056b3;
-        }}
-    </style>
-</head>
-<body>
-    <div id="app-container">
-        <div id="sidebar">
-            <div id="search-container">
-                <h3>Document Search</h3>
-                <input type="text" id="search-input-text" placeholder="Search documents...">
-                <button id="perform-search-button" class="search-button">Search</button>
-                <button id="clear-search-button" class="search-button">Clear Results</button>
-            </div>
-            <div id="search-results">
-                <p style="color: #777;">Type a query and click 'Search' or hit Enter.</p>
-            </div>
-        </div>
-        <div id="main-content">
-            <div id="mynetwork"></div>
-            <div id="graph-filter-controls">
-                <input type="text" id="graph-filter-input" placeholder="Filter graph by tag or keyword...">
-                <button id="graph-filter-tag-button" class="graph-filter-button">Filter by Tag</button>
-                <button id="graph-filter-keyword-button" class="graph-filter-button">Filter by Keyword</button>
-                <button id="reset-graph-filter-button" class="graph-filter-button">Reset Graph</button>
-            </div>
-        </div>
-    </div>
-
-    <!-- Sidebar Toggle Button -->
-    <button id="sidebar-toggle">&lt;</button> 
-
-    <!-- Document Preview Modal -->
-    <div id="documentModal" class="modal-overlay">
-        <div class="modal-content">
-            <div class="modal-header">
-                <h3 id="modalTitle"></h3>
-                <button id="modalCloseButton" class="modal-close-button">&times;</button>
-            </div>
-            <div class="modal-body">
-                <p id="modalContent"></p>
-                <div id="modalTags" class="modal-tags"></div>
-            </div>
-        </div>
-    </div>
-
-    <script type="text/javascript">
-        console.log("Vis object after script load:", typeof vis !== 'undefined' ? vis : "vis not defined yet.");
-
-        const fullAppDataJson = `{}`;
-
-        let originalNodes = new vis.DataSet([]);
-        let originalEdges = new vis.DataSet([]);
-        let searchableDocuments = {{}};
-        let network;
-
-        try {{
-            const parsedData = JSON.parse(fullAppDataJson);
-            console.log("Parsed Full App Data from Rust:", parsedData);
-            originalNodes = new vis.DataSet(parsedData.nodes);
-            originalEdges = new vis.DataSet(parsedData.edges);
-            searchableDocuments = parsedData.searchable_documents;
-        }} catch (e) {{
-            console.error("Error parsing full app data:", e);
-            console.error("Data was likely malformed. Please check backend generation or content of fullAppDataJson."); 
-            document.body.innerHTML = '<div style="text-align: center; padding-top: 50px; color: #777;">Error loading application data. Check browser console for details.</div>';
-        }}
-
-        const container = document.getElementById('mynetwork');
-        const data = {{ nodes: originalNodes, edges: originalEdges }};
-        const options = {{
-            nodes: {{
-                shape: 'dot',
-                size: 16,
-                font: {{
-                    size: 12,
-                    color: '#333'
-                }},
-                borderWidth: 2,
-                shadow:true
-            }},
-            edges: {{
-                width: 1,
-                shadow:true,
-                color: {{
-                    color: '#848484',
-                    highlight: '#848484',
-                    hover: '#848484',
-                    inherit: 'from',
-                    opacity: 0.5
-                }}
-            }},
-            groups: {{
-                txt: {{ color: {{ background: '#ADD8E6', border: '#4682B4' }} }},
-                md: {{ color: {{ background: '#90EE90', border: '#3CB371' }} }},
-                html: {{ color: {{ background: '#FFDAB9', border: '#FF8C00' }} }},
-                pdf: {{ color: {{ background: '#FFB6C1', border: '#DC143C' }} }},
-                unknown: {{ color: {{ background: '#D3D3D3', border: '#696969' }} }}
-            }},
-            physics: {{
-                enabled: true,
-                barnesHut: {{
-                    gravitationalConstant: -2000,
-                    centralGravity: 0.3,
-                    springLength: 95,
-                    springConstant: 0.04,
-                    damping: 0.09,
-                    avoidOverlap: 0
-                }},
-                solver: 'barnesHut',
-                stabilization: {{
-                    iterations: 2500
-                }}
-            }},
-            interaction: {{
-                hover: true,
-                navigationButtons: true,
-                keyboard: true
-            }}
-        }};
-
-        // Initialize network only if nodes are properly initialized
-        if (originalNodes.length > 0) {{
-            network = new vis.Network(container, data, options);
-
-            network.on("doubleClick", function (params) {{
-                if (params.nodes.length > 0) {{
-                    const nodeId = params.nodes[0];
-                    const node = originalNodes.get(nodeId);
-
-                    const modal = document.getElementById('documentModal');
-                    const modalTitle = document.getElementById('modalTitle');
-                    const modalContent = document.getElementById('modalContent');
-                    const modalTags = document.getElementById('modalTags');
-
-                    modalTitle.textContent = node.label; 
-                    modalContent.textContent = node.content_preview;
-
-                    modalTags.innerHTML = ''; 
-                    if (node.js_tags && node.js_tags.length > 0) {{
-                        node.js_tags.forEach(tag => {{
-                            const tagSpan = document.createElement('span');
-                            tagSpan.textContent = `#${{tag}}`;
-                            modalTags.appendChild(tagSpan);
-                        }});
-                    }}
-
-                    modal.classList.add('visible');
-                }}
-            }});
-        }} else {{
-            console.warn("No nodes to display. Graph will be empty.");
-            document.getElementById('mynetwork').innerHTML = '<div style="text-align: center; padding-top: 50px; color: #777;">No graph data to display. Please ensure your corpus has documents and/or tags.</div>';
-        }}
-
-        document.getElementById('modalCloseButton').addEventListener('click', function() {{
-            document.getElementById('documentModal').classList.remove('visible');
-        }});
-
-        document.getElementById('documentModal').addEventListener('click', function(event) {{
-            if (event.target === this) {{ 
-                this.classList.remove('visible');
-            }}
-        }});
-
-
-        // ----- Client-Side Search Logic -----
-        const searchInputText = document.getElementById('search-input-text');
-        const performSearchButton = document.getElementById('perform-search-button');
-        const clearSearchButton = document.getElementById('clear-search-button');
-        const searchResultsDiv = document.getElementById('search-results');
-
-        // Simple tokenizer for client-side search (JS version)
-        function tokenize(text) {{
-            return text.toLowerCase().match(/\b\w+\b/g) || [];
-        }}
-
-        function displaySearchResults(results) {{
-            searchResultsDiv.innerHTML = '';
-            if (results.length === 0) {{
-                searchResultsDiv.innerHTML = '<p style="color: #777;">No documents found matching your search.</p>';
-                return;
-            }}
-
-            results.forEach(doc => {{
-                const item = document.createElement('div');
-                item.className = 'search-result-item';
-                item.onclick = () => {{
-                    network.selectNodes([doc.id]);
-                    network.focus(doc.id, {{scale: 1.5, animation: {{duration: 500, easingFunction: "easeOutCubic"}} }});
-                    const node = originalNodes.get(doc.id);
-                    if (node) {{
-                        document.getElementById('modalTitle').textContent = node.label; 
-                        document.getElementById('modalContent').textContent = node.content_preview; 
-                        const modalTags = document.getElementById('modalTags');
-                        modalTags.innerHTML = ''; 
-                        if (node.js_tags && node.js_tags.length > 0) {{
-                            node.js_tags.forEach(tag => {{
-                                const tagSpan = document.createElement('span');
-                                tagSpan.textContent = `#${{tag}}`;
-                                modalTags.appendChild(tagSpan);
-                            }});
-                        }}
-                        document.getElementById('documentModal').classList.add('visible');
-                    }}
-                }};
-
-                const titleElem = document.createElement('h4');
-                titleElem.textContent = doc.title;
-                item.appendChild(titleElem);
-
-                const previewElem = document.createElement('p');
-                previewElem.textContent = doc.content_preview;
-                item.appendChild(previewElem);
-
-                if (doc.tags && doc.tags.length > 0) {{
-                    const tagsElem = document.createElement('div');
-                    tagsElem.className = 'tags';
-                    doc.tags.forEach(tag => {{
-                        const tagSpan = document.createElement('span');
-                        tagSpan.textContent = `#${{tag}}`;
-                        tagsElem.appendChild(tagSpan);
-                    }});
-                    item.appendChild(tagsElem);
-                }}
-                searchResultsDiv.appendChild(item);
-            }});
-        }}
-
-        function performClientSideSearch() {{
-            const query = searchInputText.value.toLowerCase().trim();
-            const results = [];
-            const queryTokens = tokenize(query);
-
-            if (query === "") {{
-                displaySearchResults([]);
-                filterGraphByNodeIds([]);
-                return;
-            }}
-
-            let filteredNodeIds = new Set();
-
-            for (const docId in searchableDocuments) {{
-                const doc = searchableDocuments[docId];
-                let isMatch = false;
-
-                // Tag Search (starts with #)
-                if (query.startsWith('#')) {{
-                    const tagQuery = query.substring(1);
-                    if (doc.tags && doc.tags.some(tag => tag.includes(tagQuery))) {{
-                        isMatch = true;
-                    }}
-                }} 
-                // Keyword/General Search
-                else {{
-                    const docContentTokens = tokenize(doc.content);
-                    const docTitleTokens = tokenize(doc.title);
-
-                    for (const qToken of queryTokens) {{
-                        // Basic keyword match in content or title
-                        if (docContentTokens.includes(qToken) || docTitleTokens.includes(qToken)) {{
-                            isMatch = true;
-                            break;
-                        }}
-                        // Simple wildcard match (ends with *)
-                        if (qToken.endsWith('*') && qToken.length > 1) {{
-                            const prefix = qToken.slice(0, -1);
-                            if (docContentTokens.some(dToken => dToken.startsWith(prefix)) || 
-                                docTitleTokens.some(dToken => dToken.startsWith(prefix))) {{
-                                isMatch = true;
-                                break;
-                            }}
-                        }}
-                        // Fuzzy search (very basic, just check if query is substring)
-                        if (doc.content.toLowerCase().includes(query) || doc.title.toLowerCase().includes(query)) {{
-                            isMatch = true;
-                            break;
-                        }}
-                    }}
-                }}
-
-                if (isMatch) {{
-                    results.push(doc);
-                    filteredNodeIds.add(doc.id);
-                }}
-            }}
-            displaySearchResults(results);
-            filterGraphByNodeIds(Array.from(filteredNodeIds)); 
-        }}
-
-        function clearClientSideSearch() {{
-            searchInputText.value = '';
-            displaySearchResults([]);
-            filterGraphByNodeIds([]);
-        }}
-
-        performSearchButton.addEventListener('click', performClientSideSearch);
-        clearSearchButton.addEventListener('click', clearClientSideSearch);
-        searchInputText.addEventListener('keypress', (e) => {{
-            if (e.key === 'Enter') {{
-                performClientSideSearch();
-            }}
-        }});
-
-        // ----- Graph Filtering Controls -----
-        const graphFilterInput = document.getElementById('graph-filter-input');
-        const graphFilterTagButton = document.getElementById('graph-filter-tag-button');
-        const graphFilterKeywordButton = document.getElementById('graph-filter-keyword-button');
-        const resetGraphFilterButton = document.getElementById('reset-graph-filter-button');
-
-        function filterGraphByNodeIds(nodeIdsToShow) {{
-            if (network) {{
-                if (nodeIdsToShow.length === 0) {{
-                    // If no IDs to show, display all original nodes/edges
-                    network.setData({{
-                        nodes: originalNodes,
-                        edges: originalEdges
-                    }});
-                }} else {{
-                    // Filter nodes: only include those in nodeIdsToShow
-                    const filteredNodes = originalNodes.get({{
-                        filter: function (node) {{
-                            return nodeIdsToShow.includes(node.id);
-                        }}
-                    }});
-
-                    // Filter edges: only include edges where BOTH connected nodes are visible
-                    const visibleNodeIdsSet = new Set(nodeIdsToShow);
-                    const filteredEdges = originalEdges.get({{
-                        filter: function (edge) {{
-                            return visibleNodeIdsSet.has(edge.from) && visibleNodeIdsSet.has(edge.to);
-                        }}
-                    }});
-
-                    network.setData({{
-                        nodes: new vis.DataSet(filteredNodes),
-                        edges: new vis.DataSet(filteredEdges)
-                    }});
-                }}
-                network.fit();
-            }}
-        }}
-
-        // Combined graph filter logic
-        function applyGraphFilter(filterType) {{
-            const query = graphFilterInput.value.toLowerCase().trim();
-            let nodesMatchingFilter = new Set();
-
-            if (!query) {{
-                filterGraphByNodeIds([]);
-                return;
-            }}
-
-            originalNodes.forEach(node => {{
-                let isMatch = false;
-                if (filterType === 'tag') {{
-                    if (node.js_tags && node.js_tags.some(tag => tag.includes(query))) {{
-                        isMatch = true;
-                    }}
-                }} else if (filterType === 'keyword') {{
-                    if (node.label.toLowerCase().includes(query) || node.content_preview.toLowerCase().includes(query)) {{
-                        isMatch = true;
-                    }}
-                }}
-                if (isMatch) {{
-                    nodesMatchingFilter.add(node.id);
-                }}
-            }});
-            filterGraphByNodeIds(Array.from(nodesMatchingFilter));
-        }}
-
-        function resetGraphFilter() {{
-            graphFilterInput.value = '';
-            filterGraphByNodeIds([]);
-        }}
-
-        graphFilterTagButton.addEventListener('click', () => applyGraphFilter('tag'));
-        graphFilterKeywordButton.addEventListener('click', () => applyGraphFilter('keyword'));
-        resetGraphFilterButton.addEventListener('click', resetGraphFilter);
-
-        graphFilterInput.addEventListener('keypress', (e) => {{
-            if (e.key === 'Enter') {{
-                applyGraphFilter('keyword');
-            }}
-        }});
-
-        // Sidebar Toggle Logic
-        const sidebar = document.getElementById('sidebar');
-        const mainContent = document.getElementById('main-content');
-        const sidebarToggle = document.getElementById('sidebar-toggle');
-
-        sidebarToggle.addEventListener('click', () => {{
-            sidebar.classList.toggle('collapsed');
-            sidebarToggle.classList.toggle('collapsed-position');
-            // Update button text/icon
-            if (sidebar.classList.contains('collapsed')) {{
-                sidebarToggle.textContent = '>';
-            }} else {{
-                sidebarToggle.textContent = '<';
-            }}
-            // Force Vis.js to redraw and adjust layout
-            if (network) {{
-                network.redraw();
-                network.fit(); 
-            }}
-        }});
-
-    </script>
-</body>
-</html>"#,
-                                escaped_json_data
-                            );
-
+                    let page = index
+                        .read()
+                        .unwrap()
+                        .generate_network_graph_data()
+                        .and_then(|json_data| {
+                            PageTemplate::load(template)?.render(&json_data, false, Layout::Full)
+                        });
+                    match page {
+                        Ok(html_content) => {
                             fs::write(GRAPH_HTML_FILE, html_content)
                                 .context("Failed to write graph HTML file")?;
 
@@ -794,31 +437,71 @@ fn main() -> Result<()> {
                             eprintln!("Error generating web app data: {:?}", e);
                         }
                     }
+                } else if query.eq_ignore_ascii_case("more") {
+                    let Some((last_query, total)) = &mut last_query else {
+                        println!("No query to show more results of\n");
+                        continue;
+                    };
+                    let offset = last_results.len();
+                    let result_page =
+                        match index
+                            .read()
+                            .unwrap()
+                            .search_page(last_query, offset, Some(page_size))
+                        {
+                            Ok(result_page) => result_page,
+                            Err(e) => {
+                                println!("{}\n", e.to_string().red());
+                                continue;
+                            }
+                        };
+                    *total = result_page.total;
+                    if result_page.results.is_empty() {
+                        println!("No more results for '{}'\n", last_query);
+                        continue;
+                    }
+                    let _pager = pager::start(&config.pager);
+                    println!(
+                        "Results {}-{} of {} for '{}':",
+                        offset + 1,
+                        offset + result_page.results.len(),
+                        total,
+                        last_query
+                    );
+                    for result in &result_page.results {
+                        print_result(&result.summary(), None);
+                    }
+                    last_results.extend(result_page.results);
+                    if last_results.len() == *total {
+                        print_min_score_note(result_page.below_min_score);
+                    }
+                    print_more_hint(last_results.len(), *total);
                 } else {
-                    let results: Vec<SearchResult> = index.search(query);
+                    let limit = saved_limit.unwrap_or(page_size);
+                    let result_page = match index.read().unwrap().search_page(query, 0, Some(limit))
+                    {
+                        Ok(result_page) => result_page,
+                        Err(e) => {
+                            println!("{}\n", e.to_string().red());
+                            continue;
+                        }
+                    };
 
-                    if results.is_empty() {
+                    if result_page.results.is_empty() {
                         println!("No results found for '{}'", query);
                     } else {
+                        let _pager = pager::start(&config.pager);
                         println!("Results for '{}':", query);
-                        for result in results {
-                            println!(
-                                "  - Doc ID: {}, Title: {:?}, Score: {:.4}",
-                                result.doc.id, result.doc.title, result.score
-                            );
-                            if !result.tags.is_empty() {
-                                let formatted_tags: Vec<String> = result
-                                    .tags
-                                    .iter()
-                                    .map(|tag| format!("#{}", tag).blue().to_string())
-                                    .collect();
-                                println!("    - Tags: {}", formatted_tags.join(", "));
-                            }
-                            println!("    - Path: {:?}", result.doc.path);
-                            println!("    - Snippet: {}\n", result.snippet);
+                        for result in &result_page.results {
+                            print_result(&result.summary(), None);
                         }
                     }
-                    println!("");
+                    last_results = result_page.results;
+                    last_query = Some((query.to_string(), result_page.total));
+                    if last_results.len() == result_page.total {
+                        print_min_score_note(result_page.below_min_score);
+                    }
+                    print_more_hint(last_results.len(), result_page.total);
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -836,8 +519,966 @@ fn main() -> Result<()> {
         }
     }
 
-    rl.save_history(HISTORY_FILE)
+    if let Some(history_dir) = history_file.parent() {
+        fs::create_dir_all(history_dir).context("Failed to create history directory")?;
+    }
+    rl.save_history(&history_file)
         .context("Failed to save history file")?;
 
     Ok(())
 }
+
+fn print_saved_queries(saved_queries: &SavedQueries) {
+    let mut saved = saved_queries.iter().peekable();
+    if saved.peek().is_none() {
+        println!("No saved queries; save one with 'save <name> <query>'\n");
+        return;
+    }
+    println!("Saved queries:");
+    for (name, query) in saved {
+        println!("  {}  {}", name.green(), query);
+    }
+    println!();
+}
+
+fn print_similar(index: &InvertedIndex, doc_id: u32, results: &[SearchResult]) {
+    let title = index.document(doc_id).map_or("", |doc| doc.title.as_str());
+    if results.is_empty() {
+        println!("No documents similar to '{}'", title);
+        return;
+    }
+    println!("Documents similar to '{}':", title);
+    for result in results {
+        print_result(&result.summary(), None);
+    }
+}
+
+// The documents collapsed under `result` as near-identical to it
+fn print_near_duplicates(index: &InvertedIndex, result: &SearchResult) {
+    if result.near_duplicates.is_empty() {
+        println!(
+            "No similar documents were collapsed under '{}'\n",
+            result.doc.title
+        );
+        return;
+    }
+    println!("Documents similar to '{}':", result.doc.title);
+    for doc in result
+        .near_duplicates
+        .iter()
+        .filter_map(|doc_id| index.document(*doc_id))
+    {
+        println!("  - Doc ID: {}, Title: {:?}", doc.id, doc.title);
+        println!("    - Source: {}, Path: {:?}", doc.source, doc.path);
+    }
+    println!();
+}
+
+// After a page at the prompt; ends the query's output with a blank line
+fn print_more_hint(shown: usize, total: usize) {
+    if shown < total {
+        let hint = format!(
+            "Showing {} of {} results; type 'more' for the next ones",
+            shown, total
+        );
+        println!("{}", hint.dimmed());
+    }
+    println!();
+}
+
+// After the last results, says how many more `min_score` left out
+fn print_min_score_note(below_min_score: usize) {
+    if below_min_score > 0 {
+        let note = format!(
+            "{} result(s) scoring under min_score left out; --all-results shows them",
+            below_min_score
+        );
+        println!("{}", note.dimmed());
+    }
+}
+
+// The results `search --offset --limit` asks for
+fn page<T>(results: &[T], offset: usize, limit: Option<usize>) -> &[T] {
+    let start = offset.min(results.len());
+    let end = limit.map_or(results.len(), |limit| {
+        start.saturating_add(limit).min(results.len())
+    });
+    &results[start..end]
+}
+
+// Says which results a partial page showed
+fn print_page_note(offset: usize, limit: Option<usize>, total: usize) {
+    let end = limit.map_or(total, |limit| offset.saturating_add(limit).min(total));
+    if total == 0 || (offset == 0 && end == total) {
+        return;
+    }
+    let note = if offset >= total {
+        format!("No results past the first {}", total)
+    } else {
+        format!("Showing results {}-{} of {}", offset + 1, end, total)
+    };
+    println!("{}", note.dimmed());
+}
+
+// Opens a document in the editor at its first match from the latest query, or
+// at the top when it was not among the results. Formats without line numbers
+// go to the system's default application instead.
+fn open_document(
+    index: &InvertedIndex,
+    last_results: &[SearchResult],
+    doc_id: u32,
+    config: &Config,
+) {
+    let Some(doc) = index.document(doc_id) else {
+        println!("{}\n", format!("No document with id {}", doc_id).red());
+        return;
+    };
+    let line = last_results
+        .iter()
+        .find(|result| result.doc.id == doc_id)
+        .and_then(|result| result.lines.first().copied())
+        .or(doc.row);
+
+    // Files inside an archive open as the archive itself
+    let opened = if archive::is_member(&doc.path) {
+        let file = archive::containing_file(&doc.path);
+        open::that(&file).with_context(|| format!("Failed to open {:?}", file))
+    } else if !web_pages::is_url(&doc.path)
+        && (line.is_some() || InvertedIndex::is_line_oriented(&doc.path))
+    {
+        editor::open_in_editor(&config.editor, &doc.path, line)
+    } else {
+        open::that(&doc.path).with_context(|| format!("Failed to open {:?}", doc.path))
+    };
+    match opened {
+        Ok(()) => println!(
+            "Opened '{}'{}\n",
+            doc.path.display().to_string().blue(),
+            line.map(|line| format!(" at line {}", line))
+                .unwrap_or_default()
+        ),
+        Err(e) => eprintln!("{:#}\n", e),
+    }
+}
+
+// Refreshes the index once to catch up with changes made while nothing was
+// watching, then again for each debounced batch of file events, journaling
+// what changed. Reports are queued on `notices` rather than printed, so they
+// never land in the middle of the line being typed.
+fn reindex_on_changes(
+    index: &SharedIndex,
+    watcher: &CorpusWatcher,
+    corpus_roots: &[CorpusRoot],
+    index_path: &Path,
+    notices: &Mutex<Vec<String>>,
+) {
+    let mut events = None;
+    loop {
+        let notice = |message: String| notices.lock().unwrap().push(message);
+        match InvertedIndex::refresh_shared(index, corpus_roots) {
+            Ok(report) if report.has_changes() => {
+                let message = match events {
+                    Some(events) => {
+                        format!("Refreshed {} file event(s): {}", events, report.summary())
+                    }
+                    None => format!("Caught up with the corpus: {}", report.summary()),
+                };
+                notice(message);
+                for warning in report.warnings() {
+                    notice(warning);
+                }
+                for failure in report.failures() {
+                    notice(failure);
+                }
+                if let Err(e) = index
+                    .read()
+                    .unwrap()
+                    .save_changes(index_path, &report.changed_doc_ids)
+                {
+                    notice(format!("Failed to save index: {:#}", e));
+                }
+            }
+            Ok(report) => {
+                for failure in report.failures() {
+                    notice(failure);
+                }
+            }
+            Err(e) => notice(format!(
+                "Failed to refresh index after file changes: {:#}",
+                e
+            )),
+        }
+        let Some(paths) = watcher.next_batch(DEFAULT_DEBOUNCE) else {
+            break;
+        };
+        events = Some(paths.len());
+    }
+}
+
+fn run_command(
+    command: Command,
+    config: &Config,
+    corpus_roots: &[CorpusRoot],
+    index_path: &Path,
+    auto_rebuild: bool,
+    template: Option<&Path>,
+) -> Result<()> {
+    match command {
+        Command::Search {
+            query,
+            federate,
+            sort,
+            facets,
+            max_per_dir,
+            max_per_tag,
+            limit,
+            offset,
+        } => {
+            let mut index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            configure_index(&mut index, config);
+            // In rank order, only the page shown needs building
+            let in_rank_order = sort == SortOrder::Relevance
+                && max_per_dir.is_none()
+                && max_per_tag.is_none()
+                && !facets;
+            if federate.is_empty() && in_rank_order {
+                let result_page = index.search_page(&query, offset, limit)?;
+                if result_page.total == 0 {
+                    println!("No results found for '{}'", query);
+                }
+                let _pager = pager::start(&config.pager);
+                for result in &result_page.results {
+                    print_result(&result.summary(), None);
+                }
+                print_page_note(offset, limit, result_page.total);
+                print_min_score_note(result_page.below_min_score);
+                return Ok(());
+            }
+            if federate.is_empty() {
+                let result_page = index.search_page(&query, 0, None)?;
+                let mut results: Vec<ResultSummary> = result_page
+                    .results
+                    .iter()
+                    .map(|result| result.summary())
+                    .collect();
+                if results.is_empty() {
+                    println!("No results found for '{}'", query);
+                }
+                sort_results(&mut results, sort, |result| result);
+                let results = diversify(results, max_per_dir, max_per_tag, |result| result);
+                let total = results.len();
+                let _pager = pager::start(&config.pager);
+                for result in page(&results, offset, limit) {
+                    print_result(result, None);
+                }
+                print_page_note(offset, limit, total);
+                print_min_score_note(result_page.below_min_score);
+                if facets {
+                    print_facets(&results.iter().collect::<Vec<_>>());
+                }
+                return Ok(());
+            }
+
+            let mut sources = vec![IndexSource {
+                label: "local".to_string(),
+                index,
+            }];
+            for path in &federate {
+                let mut source = IndexSource::open(path)?;
+                configure_index(&mut source.index, config);
+                sources.push(source);
+            }
+            let results = federated::federated_search(&sources, &query)?;
+            if results.is_empty() {
+                println!(
+                    "No results found for '{}' in {} indexes",
+                    query,
+                    sources.len()
+                );
+            }
+            let mut summaries: Vec<(&str, ResultSummary)> = results
+                .iter()
+                .map(|federated_result| {
+                    let summary = ResultSummary {
+                        score: federated_result.score,
+                        ..federated_result.result.summary()
+                    };
+                    (federated_result.source.as_str(), summary)
+                })
+                .collect();
+            sort_results(&mut summaries, sort, |(_, summary)| summary);
+            let summaries = diversify(summaries, max_per_dir, max_per_tag, |(_, summary)| summary);
+            let _pager = pager::start(&config.pager);
+            for (source, summary) in page(&summaries, offset, limit) {
+                print_result(summary, Some(source));
+            }
+            print_page_note(offset, limit, summaries.len());
+            if facets {
+                print_facets(
+                    &summaries
+                        .iter()
+                        .map(|(_, summary)| summary)
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+        Command::Watch => {
+            run_interactive(
+                config,
+                corpus_roots,
+                index_path,
+                auto_rebuild,
+                template,
+                true,
+            )?;
+        }
+        Command::Daemon => {
+            let index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            daemon::run_daemon(index, corpus_roots, &config.daemon, index_path)?;
+        }
+        Command::Serve { addr, watch } => {
+            let mut index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            configure_index(&mut index, config);
+            serve::run_server(
+                index,
+                ServeOptions {
+                    addr,
+                    watch,
+                    corpus_roots: corpus_roots.to_vec(),
+                    index_path: index_path.to_path_buf(),
+                    template: PageTemplate::load(template)?,
+                    limits: config.serve.clone(),
+                },
+            )?;
+        }
+        Command::Similar { doc_id, limit } => {
+            let mut index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            configure_index(&mut index, config);
+            let results = index
+                .similar(doc_id, limit)
+                .ok_or_else(|| anyhow!("No document with id {}", doc_id))?;
+            let _pager = pager::start(&config.pager);
+            print_similar(&index, doc_id, &results);
+        }
+        Command::Cooccur {
+            term,
+            window,
+            limit,
+        } => {
+            let index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            let cooccurrences = analysis::cooccurrences(&index, &term, window)?;
+            let _pager = pager::start(&config.pager);
+            if cooccurrences.is_empty() {
+                println!("'{}' does not appear near any other term", term);
+            } else {
+                println!("Terms within {} positions of '{}':", window, term);
+            }
+            for cooccurrence in cooccurrences.iter().take(limit) {
+                println!(
+                    "  {:>6}  {} {}",
+                    cooccurrence.count,
+                    cooccurrence.surface.green(),
+                    format!("({})", cooccurrence.term).dimmed()
+                );
+            }
+        }
+        Command::Concordance { term, width } => {
+            let index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            let lines = analysis::concordance(&index, &term, width)?;
+            let _pager = pager::start(&config.pager);
+            if lines.is_empty() {
+                println!("No occurrences of '{}'", term);
+            }
+            for line in &lines {
+                println!(
+                    "{:>width$} {} {:<width$}  {}",
+                    line.left,
+                    line.keyword.red().bold(),
+                    line.right,
+                    format!("{}:{}", line.path.display(), line.position).dimmed(),
+                    width = width
+                );
+            }
+        }
+        Command::Grep { pattern } => {
+            let index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            let matches = analysis::grep(&index, &pattern)?;
+            let _pager = pager::start(&config.pager);
+            if matches.is_empty() {
+                println!("No matches for '{}'", pattern);
+            }
+            for found in &matches {
+                let (start, end) = found.line_match;
+                println!(
+                    "{}:{}:{}: {}{}{}",
+                    found.path.display().to_string().blue(),
+                    found.line,
+                    found.offset,
+                    &found.line_text[..start],
+                    found.line_text[start..end].red().bold(),
+                    &found.line_text[end..]
+                );
+            }
+        }
+        Command::LintText {
+            dictionary,
+            min_length,
+        } => {
+            let index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            let dictionary_paths = if dictionary.is_empty() {
+                analysis::default_dictionary().into_iter().collect()
+            } else {
+                dictionary
+            };
+            let words = if dictionary_paths.is_empty() {
+                println!(
+                    "{}",
+                    "No dictionary found; only checking for one-off variants of common words (pass --dictionary)."
+                        .yellow()
+                );
+                None
+            } else {
+                Some(analysis::load_dictionary(&dictionary_paths)?)
+            };
+            let reports = analysis::lint_text(&index, words.as_ref(), min_length);
+            let _pager = pager::start(&config.pager);
+            if reports.is_empty() {
+                println!("No likely typos found");
+            }
+            for report in &reports {
+                println!("{}", report.path.display().to_string().blue());
+                for typo in &report.typos {
+                    let note = match &typo.kind {
+                        TypoKind::RareVariant(common) => {
+                            format!("seen once; did you mean {}?", common.green())
+                        }
+                        TypoKind::Unknown => "not in the dictionary".to_string(),
+                    };
+                    println!("  {:>5}: {}  {}", typo.line, typo.word.red(), note.dimmed());
+                }
+            }
+            let total: usize = reports.iter().map(|report| report.typos.len()).sum();
+            if total > 0 {
+                println!("\n{} likely typos in {} documents", total, reports.len());
+            }
+        }
+        Command::Pick { query } => {
+            let index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            match picker::pick(&index, &query)? {
+                Some(path) => println!("{}", path.display()),
+                // Like fzf, so scripts can tell a cancelled pick from a failure
+                None => std::process::exit(130),
+            }
+        }
+        Command::Report { output, open } => {
+            let index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            fs::write(&output, report::render_report(&index))
+                .with_context(|| format!("Failed to write report to {:?}", output))?;
+            println!(
+                "Wrote corpus report to {}",
+                output.display().to_string().blue()
+            );
+            if open && let Err(e) = open::that(&output) {
+                eprintln!("Failed to automatically open {:?}: {:?}", output, e);
+            }
+        }
+        Command::Graph {
+            layout,
+            output,
+            open,
+        } => {
+            let mut index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            configure_index(&mut index, config);
+            let json_data = index.generate_network_graph_data()?;
+            let html_content = PageTemplate::load(template)?.render(&json_data, false, layout)?;
+            fs::write(&output, html_content)
+                .with_context(|| format!("Failed to write graph page to {:?}", output))?;
+            println!(
+                "Wrote graph page to {}",
+                output.display().to_string().blue()
+            );
+            if open && let Err(e) = open::that(&output) {
+                eprintln!("Failed to automatically open {:?}: {:?}", output, e);
+            }
+        }
+        Command::Sync { target } => {
+            let mut index = open_index_noninteractive(
+                corpus_roots,
+                &config.indexing,
+                index_path,
+                auto_rebuild,
+            )?;
+            let report = sync::sync(&mut index, corpus_roots, &target)?;
+            for path in &report.pulled.added {
+                println!("  + {}", path.display());
+            }
+            for path in &report.pulled.updated {
+                println!("  ~ {}", path.display());
+            }
+            for path in &report.pushed {
+                println!("  > {}", path.display());
+            }
+            println!(
+                "Pulled {} added, {} updated; pushed {} document(s) to {}",
+                report.pulled.added.len(),
+                report.pulled.updated.len(),
+                report.pushed.len(),
+                target
+            );
+            if report.pulled.has_changes() {
+                index.save_changes(index_path, &report.pulled.changed_doc_ids)?;
+            }
+        }
+        Command::IndexUrl { url } => {
+            let root = corpus_roots
+                .first()
+                .context("No corpus root to keep the URL list in")?;
+            let manifest = web_pages::manifest_path(&root.path);
+            if !web_pages::add_url(&manifest, &url)? {
+                println!("{} is already listed in {:?}", url, manifest);
+            }
+            refresh_index_file(corpus_roots, &config.indexing, index_path, auto_rebuild)?;
+        }
+        Command::GenCorpus {
+            output,
+            docs,
+            vocab,
+            tags,
+            words,
+            seed,
+        } => {
+            let spec = CorpusSpec {
+                docs,
+                vocab,
+                tags,
+                words_per_doc: words,
+                seed,
+            };
+            let corpus = gen_corpus::generate_corpus(&output, &spec)?;
+            println!(
+                "Wrote {} documents ({:.1} MB) to {}",
+                corpus.documents,
+                corpus.bytes as f64 / 1_000_000.0,
+                output.display().to_string().blue()
+            );
+            println!("Index it with: infospark --corpus {}", output.display());
+        }
+        Command::Feeds(FeedsCommand::Refresh) => {
+            let feeds_file = config.feeds.as_deref().context(
+                "No feed list; set `feeds` in infospark.toml to a file of [[feed]] tables",
+            )?;
+            let root = corpus_roots
+                .first()
+                .context("No corpus root to write feed entries to")?;
+            for report in feeds::refresh(feeds_file, root)? {
+                match report.error {
+                    Some(e) => eprintln!("{} {:#}", "Skipped:".yellow(), e),
+                    None => println!("{}: {} new entries", report.name, report.written.len()),
+                }
+            }
+            refresh_index_file(corpus_roots, &config.indexing, index_path, auto_rebuild)?;
+        }
+        Command::Snapshot(snapshot_command) => {
+            let targets = SnapshotTargets {
+                index_file: index_path.to_path_buf(),
+                journal_file: journal::journal_path(index_path),
+                metadata_files: vec![
+                    history::history_path(config, corpus_roots),
+                    saved_queries::saved_queries_path(index_path),
+                ],
+            };
+            match snapshot_command {
+                SnapshotCommand::Create {
+                    label,
+                    with_metadata,
+                } => {
+                    let info =
+                        snapshot::create_snapshot(&targets, label.as_deref(), with_metadata)?;
+                    println!(
+                        "Created snapshot '{}' at {:?}",
+                        info.name.green(),
+                        info.path
+                    );
+                }
+                SnapshotCommand::List => {
+                    snapshot::print_snapshots(&snapshot::list_snapshots(&targets)?);
+                }
+                SnapshotCommand::Restore { name } => {
+                    if let Some(safety) = snapshot::restore_snapshot(&targets, &name)? {
+                        println!("Saved the previous state as snapshot '{}'", safety.name);
+                    }
+                    println!("Restored snapshot '{}'", name.green());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn load_index(index_path: &Path, indexing: &IndexingConfig) -> Result<InvertedIndex> {
+    if indexing.mmap {
+        InvertedIndex::map_from_file(index_path)
+    } else {
+        InvertedIndex::load_from_file(index_path)
+    }
+}
+
+// Applies the search settings from the config file to a loaded index
+fn configure_index(index: &mut InvertedIndex, config: &Config) {
+    index.configure_cache(&config.cache);
+    index.configure_snippets(&config.snippets);
+    index.configure_results(&config.results);
+    index.configure_query(&config.query);
+    index.configure_boosts(&config.boosts);
+}
+
+// Loads the index for commands that cannot prompt, rebuilding it only when
+// asked to with --auto-rebuild.
+fn open_index_noninteractive(
+    corpus_roots: &[CorpusRoot],
+    indexing: &IndexingConfig,
+    index_path: &Path,
+    auto_rebuild: bool,
+) -> Result<InvertedIndex> {
+    if !index_path.exists() {
+        return build_and_save_index(corpus_roots, indexing, index_path);
+    }
+    match load_index(index_path, indexing) {
+        Ok(mut index) => {
+            // Limits later refreshes, in daemon and serve mode
+            index.configure_indexing(indexing)?;
+            index.relocate(corpus_roots);
+            Ok(index)
+        }
+        Err(e) if auto_rebuild => {
+            eprintln!("{} {:#}", "Existing index cannot be used:".yellow(), e);
+            build_and_save_index(corpus_roots, indexing, index_path)
+        }
+        Err(e) => Err(e.context("Run with --auto-rebuild to reindex automatically")),
+    }
+}
+
+// Catches the index file up with files a command just wrote to the corpus
+fn refresh_index_file(
+    corpus_roots: &[CorpusRoot],
+    indexing: &IndexingConfig,
+    index_path: &Path,
+    auto_rebuild: bool,
+) -> Result<()> {
+    let mut index = open_index_noninteractive(corpus_roots, indexing, index_path, auto_rebuild)?;
+    index.show_progress(true);
+    let report = index
+        .load_documents_from_roots(corpus_roots)
+        .context("Failed to refresh the index")?;
+    for warning in report.warnings() {
+        println!("{}", warning.yellow());
+    }
+    println!("Refreshed the index: {}", report.summary());
+    print_failures(&report);
+    if report.has_changes() {
+        index.save_changes(index_path, &report.changed_doc_ids)?;
+    }
+    Ok(())
+}
+
+fn build_and_save_index(
+    corpus_roots: &[CorpusRoot],
+    indexing: &IndexingConfig,
+    index_path: &Path,
+) -> Result<InvertedIndex> {
+    let mut index = InvertedIndex::new();
+    index.configure_indexing(indexing)?;
+    let corpus_paths: Vec<&Path> = corpus_roots.iter().map(|r| r.path.as_path()).collect();
+    println!("Loading documents from: {:?}\n", corpus_paths);
+    index.show_progress(true);
+    let report = index
+        .load_documents_from_roots(corpus_roots)
+        .context("Failed to load documents from corpus roots")?;
+    // Later syncs may run behind a prompt or in the background
+    index.show_progress(false);
+    for path in &report.skipped {
+        if web_pages::is_url(path) {
+            println!(
+                "Skipping web page that could not be fetched: {}",
+                path.display()
+            );
+        } else {
+            println!("Skipping unsupported file type: {:?}", path);
+        }
+    }
+    for warning in report.warnings() {
+        println!("{}", warning.yellow());
+    }
+    for path in &report.added {
+        println!("Adding new document: {:?}", path);
+    }
+    println!(
+        "\nIndexing complete. Total documents indexed: {}\n",
+        index.total_documents()
+    );
+    if print_failures(&report) {
+        println!();
+    }
+
+    println!("Saving index to '{}'...", index_path.display());
+    index.save_to_file(index_path)?;
+    println!("Index saved.\n");
+    Ok(index)
+}
+
+// Lists the files a sync could not read, after its summary. Returns whether
+// there were any.
+fn print_failures(report: &IndexingReport) -> bool {
+    if report.failed.is_empty() {
+        return false;
+    }
+    println!(
+        "{}",
+        format!("{} file(s) could not be indexed:", report.failed.len()).red()
+    );
+    for (path, e) in &report.failed {
+        println!("  {} {:#}", format!("{}:", path.display()).red(), e);
+    }
+    println!("They are tried again on the next refresh; --fail-fast stops at the first one.");
+    true
+}
+
+// Reorders ranked results for `--sort`; ties keep their ranked order.
+fn sort_results<T>(results: &mut [T], order: SortOrder, summary: impl Fn(&T) -> &ResultSummary) {
+    match order {
+        SortOrder::Relevance => {}
+        SortOrder::Modified => results.sort_by_key(|r| Reverse(summary(r).modified_time)),
+        SortOrder::Created => results.sort_by_key(|r| Reverse(summary(r).created_time)),
+        SortOrder::Size => results.sort_by_key(|r| Reverse(summary(r).size)),
+    }
+}
+
+// Reorders `results` so no directory or tag contributes more than its cap
+// before every other result has had a turn. Results over a cap keep their
+// relative order and follow the rest, rather than being dropped.
+fn diversify<T>(
+    results: Vec<T>,
+    max_per_dir: Option<NonZeroUsize>,
+    max_per_tag: Option<NonZeroUsize>,
+    summary: impl Fn(&T) -> &ResultSummary,
+) -> Vec<T> {
+    if max_per_dir.is_none() && max_per_tag.is_none() {
+        return results;
+    }
+    let mut per_dir: HashMap<PathBuf, usize> = HashMap::new();
+    let mut per_tag: HashMap<String, usize> = HashMap::new();
+    let mut kept = Vec::with_capacity(results.len());
+    let mut deferred = Vec::new();
+    for result in results {
+        let summary = summary(&result);
+        let dir = summary.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let dir_full =
+            max_per_dir.is_some_and(|max| per_dir.get(&dir).copied().unwrap_or(0) >= max.get());
+        let tag_full = max_per_tag.is_some_and(|max| {
+            summary
+                .tags
+                .iter()
+                .any(|tag| per_tag.get(tag).copied().unwrap_or(0) >= max.get())
+        });
+        if dir_full || tag_full {
+            deferred.push(result);
+            continue;
+        }
+        for tag in &summary.tags {
+            *per_tag.entry(tag.clone()).or_insert(0) += 1;
+        }
+        *per_dir.entry(dir).or_insert(0) += 1;
+        kept.push(result);
+    }
+    kept.extend(deferred);
+    kept
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn print_result(result: &ResultSummary, source: Option<&str>) {
+    let source_label = match source {
+        Some(source) => format!("[{}] ", source).cyan().to_string(),
+        None => String::new(),
+    };
+    println!(
+        "  - {}Doc ID: {}, Title: {:?}, Score: {:.4}",
+        source_label, result.id, result.title, result.score
+    );
+    if !result.tags.is_empty() {
+        let formatted_tags: Vec<String> = result
+            .tags
+            .iter()
+            .map(|tag| format!("#{}", tag).blue().to_string())
+            .collect();
+        println!("    - Tags: {}", formatted_tags.join(", "));
+    }
+    println!("    - Source: {}, Path: {:?}", result.source, result.path);
+    if !result.copies.is_empty() {
+        let copies: Vec<String> = result
+            .copies
+            .iter()
+            .map(|path| format!("{:?}", path))
+            .collect();
+        println!("    - Copies: {}", copies.join(", "));
+    }
+    if !result.near_duplicates.is_empty() {
+        let doc_ids: Vec<String> = result
+            .near_duplicates
+            .iter()
+            .map(|doc_id| doc_id.to_string())
+            .collect();
+        let marker = format!(
+            "{} similar document(s) collapsed: Doc ID {}",
+            doc_ids.len(),
+            doc_ids.join(", ")
+        );
+        println!("    - {}", marker.dimmed());
+    }
+    let created = match result.created_time {
+        Some(created_time) => format_timestamp(created_time),
+        None => "unknown".to_string(),
+    };
+    println!(
+        "    - Modified: {}, Created: {}, Size: {}",
+        format_timestamp(result.modified_time),
+        created,
+        format_size(result.size)
+    );
+    if result.lines.is_empty() {
+        println!("    - Snippet: {}\n", result.snippet);
+    } else {
+        let lines: Vec<String> = result.lines.iter().map(|line| line.to_string()).collect();
+        println!("    - Lines: {}", lines.join(", "));
+        println!("    - Snippet:");
+        for row in result.snippet.lines() {
+            println!("      {}", row);
+        }
+        println!();
+    }
+}
+
+fn print_facets(results: &[&ResultSummary]) {
+    if results.is_empty() {
+        return;
+    }
+    println!("{}", "Facets:".bold());
+    for facet in facets::facets(results) {
+        let values: Vec<String> = facet
+            .values
+            .iter()
+            .map(|(value, count)| format!("{} ({})", value, count))
+            .collect();
+        println!("  {}: {}", facet.name, values.join(", "));
+    }
+    println!();
+}
+
+fn print_cache_stats(stats: &CacheStats) {
+    let ttl = match stats.ttl {
+        Some(ttl) => format!("{}s", ttl.as_secs()),
+        None => "none".to_string(),
+    };
+    println!(
+        "Search cache: {} ({}/{} entries, TTL {})",
+        if stats.enabled {
+            "enabled".green()
+        } else {
+            "disabled".yellow()
+        },
+        stats.entries,
+        stats.capacity,
+        ttl
+    );
+    println!(
+        "  Hits: {}, misses: {} ({} expired), hit rate: {:.1}%\n",
+        stats.hits,
+        stats.misses,
+        stats.expired,
+        stats.hit_rate() * 100.0
+    );
+}
+
+fn confirm_rebuild(rl: &mut Editor<QueryHelper, FileHistory>) -> Result<bool> {
+    match rl.readline("Rebuild the index from the corpus now? [y/N] ") {
+        Ok(answer) => Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")),
+        Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => Ok(false),
+        Err(err) => Err(anyhow::Error::new(err).context("Error during readline operation")),
+    }
+}
@@ -0,0 +1,104 @@
+// src/near_duplicates.rs
+// Near-identical documents (a note copied and lightly edited) told apart from
+// merely related ones by the overlap of their runs of words, estimated with
+// MinHash: each document is reduced to the smallest hash of its word runs
+// under a number of hash functions, and the share of minimums two documents
+// have in common approximates their Jaccard similarity.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::inverted_index::fnv1a;
+
+// --- CONSTANTS ---
+const SHINGLE_WORDS: usize = 3; // Words in each run compared
+const SIGNATURE_HASHES: usize = 64;
+// Documents sharing all the minimums of any band are compared in full; with
+// 16 bands of 4, documents 50% alike are compared more often than not
+const BAND_ROWS: usize = 4;
+
+// --- STRUCTS ---
+#[derive(Debug)]
+pub(crate) struct Signature([u64; SIGNATURE_HASHES]);
+
+impl Signature {
+    // None for a document without words
+    pub(crate) fn of(words: &[String]) -> Option<Signature> {
+        if words.is_empty() {
+            return None;
+        }
+        let mut minimums = [u64::MAX; SIGNATURE_HASHES];
+        for shingle in words.windows(SHINGLE_WORDS.min(words.len())) {
+            let hash = fnv1a(shingle.join(" ").as_bytes());
+            for (seed, minimum) in minimums.iter_mut().enumerate() {
+                *minimum = (*minimum).min(mix(hash, seed as u64));
+            }
+        }
+        Some(Signature(minimums))
+    }
+
+    // Estimated share of word runs the two documents have in common
+    pub(crate) fn similarity(&self, other: &Signature) -> f64 {
+        let shared = self.0.iter().zip(&other.0).filter(|(a, b)| a == b).count();
+        shared as f64 / SIGNATURE_HASHES as f64
+    }
+
+    fn bands(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0
+            .chunks(BAND_ROWS)
+            .enumerate()
+            .map(|(band, rows)| rows.iter().fold(band as u64, |hash, row| mix(hash, *row)))
+    }
+}
+
+// One of the hash functions, from a shingle's hash and the function's number
+// (splitmix64's finalizer)
+fn mix(hash: u64, seed: u64) -> u64 {
+    let mut z = hash ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1330_11eb);
+    z ^ (z >> 31)
+}
+
+// Groups documents fed to it best first under the first one each is at least
+// `threshold` alike with
+pub(crate) struct Groups {
+    threshold: f64,
+    heads: Vec<(u32, Arc<Signature>)>,
+    buckets: HashMap<u64, Vec<usize>>, // Band hash -> places of the heads in `heads` with it
+}
+
+impl Groups {
+    pub(crate) fn new(threshold: f64) -> Self {
+        Groups {
+            threshold,
+            heads: Vec::new(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    // The document `doc_id` goes under, or None when it heads a group of its own
+    pub(crate) fn insert(&mut self, doc_id: u32, signature: Arc<Signature>) -> Option<u32> {
+        let bands: Vec<u64> = signature.bands().collect();
+        let mut candidates: Vec<usize> = bands
+            .iter()
+            .filter_map(|band| self.buckets.get(band))
+            .flatten()
+            .copied()
+            .collect();
+        // Earlier heads ranked higher
+        candidates.sort_unstable();
+        candidates.dedup();
+        if let Some(head) = candidates
+            .into_iter()
+            .find(|&head| self.heads[head].1.similarity(&signature) >= self.threshold)
+        {
+            return Some(self.heads[head].0);
+        }
+        for band in bands {
+            self.buckets.entry(band).or_default().push(self.heads.len());
+        }
+        self.heads.push((doc_id, signature));
+        None
+    }
+}
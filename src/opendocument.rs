@@ -0,0 +1,165 @@
+// src/opendocument.rs
+// Text and properties of OpenDocument files (LibreOffice's .odt, .ods and
+// .odp), which are zip archives holding the document body in content.xml and
+// its title, author and language in meta.xml.
+
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+use crate::inverted_index::ExtractedMetadata;
+
+// --- CONSTANTS ---
+pub const EXTENSIONS: [&str; 3] = ["odt", "ods", "odp"];
+
+pub(crate) fn extract(path: &Path) -> Result<(String, ExtractedMetadata)> {
+    let file = File::open(path).context("Failed to open OpenDocument file")?;
+    let mut archive = ZipArchive::new(file).context("Failed to read OpenDocument archive")?;
+    let content =
+        read_entry(&mut archive, "content.xml")?.context("OpenDocument file has no content.xml")?;
+    let text = body_text(&content).context("Failed to parse OpenDocument content.xml")?;
+    let metadata = match read_entry(&mut archive, "meta.xml")? {
+        Some(meta) => properties(&meta).context("Failed to parse OpenDocument meta.xml")?,
+        None => ExtractedMetadata::default(),
+    };
+    Ok((text, metadata))
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {} in archive", name)),
+    };
+    let mut xml = String::new();
+    entry
+        .read_to_string(&mut xml)
+        .with_context(|| format!("Failed to read {} from archive", name))?;
+    Ok(Some(xml))
+}
+
+// Raw value of an attribute; the ones read here never hold entities
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    let attribute = element.try_get_attribute(name).ok()??;
+    Some(attribute.value.into_owned())
+}
+
+// The text of office:body, a line per paragraph or heading and, in
+// spreadsheets and tables, a line per row with tab-separated cells. Deleted
+// text kept for change tracking is left out.
+fn body_text(xml: &str) -> Result<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut text = String::new();
+    let mut in_body = false;
+    let mut tracked_changes_depth = 0usize;
+    let mut cell_depth = 0usize;
+    let mut row_starts: Vec<usize> = Vec::new();
+    loop {
+        let collecting = in_body && tracked_changes_depth == 0;
+        match reader.read_event()? {
+            Event::Start(element) => match element.name().as_ref() {
+                "office:body" => in_body = true,
+                "text:tracked-changes" => tracked_changes_depth += 1,
+                "table:table-cell" | "table:covered-table-cell" => cell_depth += 1,
+                "table:table-row" => row_starts.push(text.len()),
+                _ => {}
+            },
+            Event::End(element) => match element.name().as_ref() {
+                "office:body" => in_body = false,
+                "text:tracked-changes" => {
+                    tracked_changes_depth = tracked_changes_depth.saturating_sub(1)
+                }
+                "text:p" | "text:h" if collecting => {
+                    text.push(if cell_depth > 0 { ' ' } else { '\n' });
+                }
+                "table:table-cell" | "table:covered-table-cell" => {
+                    cell_depth = cell_depth.saturating_sub(1);
+                    if collecting {
+                        text.truncate(text.trim_end_matches(' ').len());
+                        text.push('\t');
+                    }
+                }
+                "table:table-row" => {
+                    let row_start = row_starts.pop().unwrap_or(0);
+                    text.truncate(text.trim_end_matches(['\t', ' ']).len().max(row_start));
+                    if collecting && text.len() > row_start {
+                        text.push('\n');
+                    }
+                }
+                _ => {}
+            },
+            Event::Empty(element) if collecting => match element.name().as_ref() {
+                "text:s" => {
+                    let count = attribute(&element, "text:c")
+                        .and_then(|count| count.parse().ok())
+                        .unwrap_or(1);
+                    text.extend(std::iter::repeat_n(' ', count));
+                }
+                "text:tab" => text.push('\t'),
+                "text:line-break" => text.push('\n'),
+                "text:p" | "text:h" if cell_depth == 0 => text.push('\n'),
+                "table:table-cell" | "table:covered-table-cell" => text.push('\t'),
+                _ => {}
+            },
+            Event::Text(chunk) if collecting => text.push_str(&chunk.xml10_content()),
+            Event::CData(chunk) if collecting => text.push_str(&chunk),
+            Event::GeneralRef(reference) if collecting => {
+                if let Some(c) = reference.resolve_char_ref()? {
+                    text.push(c);
+                } else if let Some(entity) = resolve_predefined_entity(&reference) {
+                    text.push_str(entity);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+// Title, author (the initial creator, falling back to the last editor) and
+// language from meta.xml
+fn properties(xml: &str) -> Result<ExtractedMetadata> {
+    let mut reader = Reader::from_str(xml);
+    let mut metadata = ExtractedMetadata::default();
+    let mut last_editor = None;
+    let mut current: Option<String> = None;
+    let mut value = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                current = Some(element.name().as_ref().to_string());
+                value.clear();
+            }
+            Event::Text(chunk) if current.is_some() => value.push_str(&chunk.xml10_content()),
+            Event::GeneralRef(reference) if current.is_some() => {
+                if let Some(c) = reference.resolve_char_ref()? {
+                    value.push(c);
+                } else if let Some(entity) = resolve_predefined_entity(&reference) {
+                    value.push_str(entity);
+                }
+            }
+            Event::End(_) => {
+                let field = match current.take().as_deref() {
+                    Some("dc:title") => &mut metadata.title,
+                    Some("meta:initial-creator") => &mut metadata.author,
+                    Some("dc:creator") => &mut last_editor,
+                    Some("dc:language") => &mut metadata.language,
+                    _ => continue,
+                };
+                *field = Some(std::mem::take(&mut value));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    metadata.author = metadata.author.or(last_editor);
+    Ok(metadata)
+}
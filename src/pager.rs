@@ -0,0 +1,129 @@
+// src/pager.rs
+// Pages long output the way git does: while a `Pager` is alive, stdout points
+// at a pipe whose contents are forwarded to `$PAGER`, so the printing code
+// stays plain `println!`. Output after the user quits the pager is discarded
+// rather than failing, which keeps the interactive prompt alive.
+
+#[cfg(unix)]
+use std::env;
+#[cfg(unix)]
+use std::io::{self, IsTerminal, Write};
+#[cfg(unix)]
+use std::process::{Child, Command, Stdio};
+
+use crate::config::PagerConfig;
+
+// --- CONSTANTS ---
+#[cfg(unix)]
+const DEFAULT_PAGER: &str = "less";
+// Quit when the output fits on one screen, keep colors and leave the output on
+// the terminal afterwards, as git sets them
+#[cfg(unix)]
+const DEFAULT_LESS_OPTIONS: &str = "FRX";
+
+// --- STRUCTS ---
+#[cfg(unix)]
+pub struct Pager {
+    child: Child,
+    saved_stdout: std::os::fd::OwnedFd,
+    forwarder: Option<std::thread::JoinHandle<()>>,
+}
+
+#[cfg(not(unix))]
+pub struct Pager;
+
+// --- HELPERS ---
+#[cfg(unix)]
+fn pager_command(config: &PagerConfig) -> Option<String> {
+    config
+        .command
+        .clone()
+        .or_else(|| env::var("PAGER").ok())
+        .or_else(|| Some(DEFAULT_PAGER.to_string()))
+        .filter(|command| !command.trim().is_empty() && command.trim() != "cat")
+}
+
+#[cfg(unix)]
+fn spawn(command: &str) -> io::Result<Child> {
+    let mut args = command.split_whitespace();
+    let program = args.next().unwrap_or(DEFAULT_PAGER);
+    let mut pager = Command::new(program);
+    pager.args(args).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        pager.env("LESS", DEFAULT_LESS_OPTIONS);
+    }
+    pager.spawn()
+}
+
+// Starts paging stdout, unless paging is disabled, stdout is not a terminal or
+// the pager cannot be launched (then output goes straight to the terminal).
+#[cfg(unix)]
+pub fn start(config: &PagerConfig) -> Option<Pager> {
+    use std::os::fd::{AsFd, AsRawFd};
+
+    if !config.enabled || !io::stdout().is_terminal() {
+        return None;
+    }
+    let command = pager_command(config)?;
+    let (mut reader, writer) = io::pipe().ok()?;
+    let saved_stdout = io::stdout().as_fd().try_clone_to_owned().ok()?;
+    let mut child = match spawn(&command) {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Failed to launch pager '{}': {}", command, e);
+            return None;
+        }
+    };
+    let mut pager_stdin = child.stdin.take()?;
+
+    // Decided while stdout is still the terminal; colored checks it only once
+    colored::control::set_override(colored::control::SHOULD_COLORIZE.should_colorize());
+    io::stdout().flush().ok()?;
+    // SAFETY: both descriptors are open for the duration of the call
+    if unsafe { libc::dup2(writer.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        return None;
+    }
+    drop(writer);
+
+    let forwarder = std::thread::spawn(move || {
+        let mut buffer = [0; 8192];
+        let mut pager_open = true;
+        while let Ok(n) = io::Read::read(&mut reader, &mut buffer) {
+            if n == 0 {
+                break;
+            }
+            if pager_open && pager_stdin.write_all(&buffer[..n]).is_err() {
+                // The user quit the pager; drain the rest so printing never blocks
+                pager_open = false;
+            }
+        }
+    });
+    Some(Pager {
+        child,
+        saved_stdout,
+        forwarder: Some(forwarder),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn start(_config: &PagerConfig) -> Option<Pager> {
+    None
+}
+
+// Restoring stdout closes the pipe, so the pager sees the end of the output;
+// dropping waits until the user quits it.
+#[cfg(unix)]
+impl Drop for Pager {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        let _ = io::stdout().flush();
+        // SAFETY: `saved_stdout` is an open descriptor owned by this pager
+        unsafe { libc::dup2(self.saved_stdout.as_raw_fd(), libc::STDOUT_FILENO) };
+        if let Some(forwarder) = self.forwarder.take() {
+            let _ = forwarder.join();
+        }
+        let _ = self.child.wait();
+        colored::control::unset_override();
+    }
+}
@@ -0,0 +1,300 @@
+// src/picker.rs
+// `infospark pick`: an fzf-like full-screen matcher over titles, paths and
+// tags. The screen is drawn on stderr, so stdout carries only the chosen path
+// and `$(infospark pick)` works in shell pipelines and editor bindings.
+
+use std::io::{self, Stderr, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Print, PrintStyledContent, Stylize};
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
+    enable_raw_mode,
+};
+use crossterm::{execute, queue};
+
+use crate::inverted_index::InvertedIndex;
+
+// --- CONSTANTS ---
+const PROMPT: &str = "> ";
+const HEADER_ROWS: u16 = 2; // The prompt and the match count
+const MATCH_SCORE: i64 = 16;
+const CONSECUTIVE_BONUS: i64 = 8;
+const WORD_START_BONUS: i64 = 12;
+
+// --- STRUCTS ---
+struct Candidate {
+    path: PathBuf,
+    text: Vec<char>, // Title, path and tags, as matched and shown
+}
+
+struct Match {
+    candidate: usize,
+    score: i64,
+    positions: Vec<usize>, // Matched character indices, for highlighting
+}
+
+// Restores the terminal however the picker exits
+struct RawScreen {
+    stderr: Stderr,
+}
+
+impl RawScreen {
+    fn enter() -> Result<Self> {
+        enable_raw_mode()?;
+        let mut stderr = io::stderr();
+        execute!(stderr, EnterAlternateScreen)?;
+        Ok(RawScreen { stderr })
+    }
+}
+
+impl Drop for RawScreen {
+    fn drop(&mut self) {
+        let _ = execute!(self.stderr, LeaveAlternateScreen, Show);
+        let _ = disable_raw_mode();
+    }
+}
+
+// --- HELPERS ---
+fn is_word_start(text: &[char], i: usize) -> bool {
+    i == 0 || !text[i - 1].is_alphanumeric()
+}
+
+// Scores `term` as a case-insensitive subsequence of `text`, trying each
+// place its first character occurs and keeping the best-scoring match.
+// Consecutive runs and matches at word starts score higher; gaps lower.
+fn fuzzy_match(term: &[char], text: &[char]) -> Option<(i64, Vec<usize>)> {
+    let first = *term.first()?;
+    let mut best: Option<(i64, Vec<usize>)> = None;
+    for start in (0..text.len()).filter(|&i| text[i].to_lowercase().eq(first.to_lowercase())) {
+        let mut positions = Vec::with_capacity(term.len());
+        let mut i = start;
+        for c in term {
+            while i < text.len() && !text[i].to_lowercase().eq(c.to_lowercase()) {
+                i += 1;
+            }
+            if i == text.len() {
+                break;
+            }
+            positions.push(i);
+            i += 1;
+        }
+        if positions.len() < term.len() {
+            // A later start cannot match if this one ran out of text
+            break;
+        }
+        let mut score = 0;
+        for (n, &position) in positions.iter().enumerate() {
+            score += MATCH_SCORE;
+            if is_word_start(text, position) {
+                score += WORD_START_BONUS;
+            }
+            if n > 0 {
+                let gap = (position - positions[n - 1] - 1) as i64;
+                score += if gap == 0 { CONSECUTIVE_BONUS } else { -gap };
+            }
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, _)| score > *best_score)
+        {
+            best = Some((score, positions));
+        }
+    }
+    best
+}
+
+// Candidates matching every space-separated term of `query`, best first
+fn rank(candidates: &[Candidate], query: &str) -> Vec<Match> {
+    let terms: Vec<Vec<char>> = query
+        .split_whitespace()
+        .map(|term| term.chars().collect())
+        .collect();
+    let mut matches: Vec<Match> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, candidate)| {
+            let mut score = 0;
+            let mut positions = Vec::new();
+            for term in &terms {
+                let (term_score, term_positions) = fuzzy_match(term, &candidate.text)?;
+                score += term_score;
+                positions.extend(term_positions);
+            }
+            Some(Match {
+                candidate: i,
+                score,
+                positions,
+            })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(
+                candidates[a.candidate]
+                    .text
+                    .len()
+                    .cmp(&candidates[b.candidate].text.len()),
+            )
+            .then(
+                candidates[a.candidate]
+                    .path
+                    .cmp(&candidates[b.candidate].path),
+            )
+    });
+    matches
+}
+
+fn draw(
+    stderr: &mut Stderr,
+    candidates: &[Candidate],
+    matches: &[Match],
+    query: &str,
+    selected: usize,
+    scroll: usize,
+) -> Result<()> {
+    let (width, height) = terminal::size()?;
+    let width = width as usize;
+    queue!(stderr, Hide, MoveTo(0, 0), Clear(ClearType::All))?;
+    queue!(stderr, Print(PROMPT), Print(query))?;
+    queue!(
+        stderr,
+        MoveTo(0, 1),
+        PrintStyledContent(format!("  {}/{}", matches.len(), candidates.len()).dark_grey())
+    )?;
+
+    let rows = height.saturating_sub(HEADER_ROWS) as usize;
+    for (row, found) in matches.iter().skip(scroll).take(rows).enumerate() {
+        let is_selected = scroll + row == selected;
+        queue!(stderr, MoveTo(0, HEADER_ROWS + row as u16))?;
+        queue!(
+            stderr,
+            PrintStyledContent(if is_selected {
+                "> ".red().bold()
+            } else {
+                "  ".reset()
+            })
+        )?;
+        // Runs of matched and unmatched characters, each printed in one go
+        let text = &candidates[found.candidate].text;
+        let visible = text.len().min(width.saturating_sub(2));
+        let mut run_start = 0;
+        while run_start < visible {
+            let matched = found.positions.contains(&run_start);
+            let mut run_end = run_start + 1;
+            while run_end < visible && found.positions.contains(&run_end) == matched {
+                run_end += 1;
+            }
+            let run: String = text[run_start..run_end].iter().collect();
+            let styled = if matched {
+                run.green().bold()
+            } else if is_selected {
+                run.bold()
+            } else {
+                run.reset()
+            };
+            queue!(stderr, PrintStyledContent(styled))?;
+            run_start = run_end;
+        }
+    }
+    let cursor_column = (PROMPT.len() + query.chars().count()).min(width.saturating_sub(1));
+    queue!(stderr, MoveTo(cursor_column as u16, 0), Show)?;
+    stderr.flush()?;
+    Ok(())
+}
+
+// Runs the picker until a document is chosen (its path is returned) or the
+// user gives up with Esc or Ctrl-C (`None`).
+pub fn pick(index: &InvertedIndex, initial_query: &str) -> Result<Option<PathBuf>> {
+    let mut candidates: Vec<Candidate> = index
+        .documents()
+        .map(|doc| {
+            let mut text = format!("{}  {}", doc.title, doc.path.display());
+            for tag in &doc.tags {
+                text.push_str(&format!(" #{}", tag));
+            }
+            Candidate {
+                path: doc.path.clone(),
+                text: text.chars().collect(),
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut screen = RawScreen::enter()?;
+    let mut query = initial_query.to_string();
+    let mut matches = rank(&candidates, &query);
+    let mut selected = 0;
+    let mut scroll = 0;
+
+    loop {
+        let rows = (terminal::size()?.1.saturating_sub(HEADER_ROWS) as usize).max(1);
+        if selected < scroll {
+            scroll = selected;
+        } else if selected >= scroll + rows {
+            scroll = selected + 1 - rows;
+        }
+        draw(
+            &mut screen.stderr,
+            &candidates,
+            &matches,
+            &query,
+            selected,
+            scroll,
+        )?;
+
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        else {
+            continue;
+        };
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        let last = matches.len().saturating_sub(1);
+        let mut edited = false;
+        match code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c' | 'g') if ctrl => return Ok(None),
+            KeyCode::Enter => {
+                return Ok(matches
+                    .get(selected)
+                    .map(|found| candidates[found.candidate].path.clone()));
+            }
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Char('p' | 'k') if ctrl => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected = (selected + 1).min(last),
+            KeyCode::Char('n' | 'j') if ctrl => selected = (selected + 1).min(last),
+            KeyCode::PageUp => selected = selected.saturating_sub(rows),
+            KeyCode::PageDown => selected = (selected + rows).min(last),
+            KeyCode::Char('u') if ctrl => {
+                query.clear();
+                edited = true;
+            }
+            KeyCode::Char('w') if ctrl => {
+                let kept = query.trim_end().rfind(' ').map_or(0, |i| i + 1);
+                query.truncate(kept);
+                edited = true;
+            }
+            KeyCode::Backspace => {
+                query.pop();
+                edited = true;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                query.push(c);
+                edited = true;
+            }
+            _ => {}
+        }
+        if edited {
+            matches = rank(&candidates, &query);
+            selected = 0;
+        }
+    }
+}
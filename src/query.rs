@@ -0,0 +1,803 @@
+// src/query.rs
+//
+// Query language:
+//
+//   query      := or_expr
+//   or_expr    := and_expr (("OR" | "||") and_expr)*
+//   and_expr   := unary ([("AND" | "&&")] unary)*   adjacent terms are ANDed
+//   unary      := ("NOT" | "!" | "-") unary | atom
+//   atom       := '(' or_expr ')' | phrase | tag | exact | field | word
+//   phrase     := '"' text '"' ['~' digits]   `~N` lets the words be up to N positions apart
+//   tag        := '#' name
+//   exact      := '=' text                      matched as written, case and all
+//   field      := ("title" | "filename" | "author" | "lang" | "path" | "tag" | "type"
+//                  | "source") ':' (phrase | word)
+//               | ("modified" | "created" | "size" | "tokens") ':' '[' bound "TO" bound ']'
+//               | ("modified" | "created" | "size" | "tokens") ':' ('<' | '<=' | '>' | '>=') bound
+//   word       := text, optionally ending in '*' for a prefix wildcard, with '*'
+//                 anywhere for a wildcard pattern ('*ization', 'inter*tion'), or
+//                 ending in '~' [digits] to also match words that many edits away
+//
+// Dates in `modified` and `created` ranges are YYYY-MM-DD (local time) or unix seconds;
+// `*` leaves a bound open. Compared with '<' or '>', they can also be ages counted
+// back from now, in hours, days, weeks or years: `modified:<30d` is newer than 30 days.
+// Parentheses and NOTs nest at most 64 deep.
+
+use std::cell::Cell;
+use std::fmt;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::{char, multispace0, multispace1};
+use nom::error::{ErrorKind, ParseError};
+use nom::sequence::{preceded, terminated};
+use nom::{IResult, Parser};
+
+// --- AST ---
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),     // Analyzed with the tokenizer at search time
+    Prefix(String),   // `word*`
+    Wildcard(String), // Lowercased pattern with a leading or inner `*`, matched against words as written
+    Exact(String),    // `=NaN`: neither lowercased nor stemmed
+    Fuzzy {
+        term: String,
+        distance: Option<usize>, // Edits allowed; None for the configured default
+    },
+    Phrase {
+        text: String,
+        slop: usize, // Other words allowed among the phrase's, in all; 0 for an exact phrase
+    },
+    Tag(String),
+    Field {
+        field: TextField,
+        value: String,
+        phrase: bool, // Quoted value, matched as a whole rather than word by word
+    },
+    Range {
+        field: RangeField,
+        lower: Option<u64>,
+        upper: Option<u64>,
+    },
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    Title,
+    Filename, // File name including the extension, whatever the title says
+    Author,
+    Language, // Declared language code; `en` also matches `en-us`
+    Path,
+    Type,   // File extension
+    Source, // Name of the corpus root
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeField {
+    Modified, // Unix seconds
+    Created,  // Unix seconds; documents without a creation time never match
+    Size,     // File size in bytes
+    Tokens,   // Document length in tokens
+}
+
+// Written back in the query language, so a built `Query` can be shown,
+// saved or cached as text. Parsing the text gives the same query again.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Groups inside other groups or under NOT keep their parentheses
+        let operand = |f: &mut fmt::Formatter<'_>, query: &Query| match query {
+            Query::And(_) | Query::Or(_) => write!(f, "({})", query),
+            _ => write!(f, "{}", query),
+        };
+        match self {
+            Query::Term(word) | Query::Wildcard(word) => write!(f, "{}", word),
+            Query::Prefix(prefix) => write!(f, "{}*", prefix),
+            Query::Exact(word) => write!(f, "={}", word),
+            Query::Fuzzy { term, distance } => match distance {
+                Some(distance) => write!(f, "{}~{}", term, distance),
+                None => write!(f, "{}~", term),
+            },
+            Query::Phrase { text, slop: 0 } => write!(f, "\"{}\"", text),
+            Query::Phrase { text, slop } => write!(f, "\"{}\"~{}", text, slop),
+            Query::Tag(tag) if tag.chars().all(is_word_char) => write!(f, "#{}", tag),
+            Query::Tag(tag) => write!(f, "tag:\"{}\"", tag),
+            Query::Field {
+                field,
+                value,
+                phrase,
+            } => {
+                let name = match field {
+                    TextField::Title => "title",
+                    TextField::Filename => "filename",
+                    TextField::Author => "author",
+                    TextField::Language => "lang",
+                    TextField::Path => "path",
+                    TextField::Type => "type",
+                    TextField::Source => "source",
+                };
+                if *phrase {
+                    write!(f, "{}:\"{}\"", name, value)
+                } else {
+                    write!(f, "{}:{}", name, value)
+                }
+            }
+            Query::Range {
+                field,
+                lower,
+                upper,
+            } => {
+                let name = match field {
+                    RangeField::Modified => "modified",
+                    RangeField::Created => "created",
+                    RangeField::Size => "size",
+                    RangeField::Tokens => "tokens",
+                };
+                let bound = |value: &Option<u64>| value.map_or("*".to_string(), |v| v.to_string());
+                write!(f, "{}:[{} TO {}]", name, bound(lower), bound(upper))
+            }
+            Query::And(queries) | Query::Or(queries) => {
+                let separator = if matches!(self, Query::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                for (i, query) in queries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(separator)?;
+                    }
+                    operand(f, query)?;
+                }
+                Ok(())
+            }
+            Query::Not(query) => {
+                f.write_str("NOT ")?;
+                operand(f, query)
+            }
+        }
+    }
+}
+
+// --- ERRORS ---
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub query: String,
+    pub position: usize, // Character offset into `query`
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Syntax error at column {}: {}",
+            self.position + 1,
+            self.message
+        )?;
+        writeln!(f, "  {}", self.query)?;
+        write!(f, "  {}^", " ".repeat(self.position))
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+// nom error carrying the remaining input (for the position) and a message
+#[derive(Debug)]
+struct SyntaxError<'a> {
+    input: &'a str,
+    message: String,
+}
+
+impl<'a> SyntaxError<'a> {
+    fn new(input: &'a str, message: impl Into<String>) -> Self {
+        SyntaxError {
+            input,
+            message: message.into(),
+        }
+    }
+}
+
+impl<'a> ParseError<&'a str> for SyntaxError<'a> {
+    fn from_error_kind(input: &'a str, _kind: ErrorKind) -> Self {
+        SyntaxError::new(input, "unexpected input")
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+type PResult<'a, O> = IResult<&'a str, O, SyntaxError<'a>>;
+
+fn failure<O>(input: &str, message: impl Into<String>) -> PResult<'_, O> {
+    Err(nom::Err::Failure(SyntaxError::new(input, message)))
+}
+
+// Turns a recoverable error into a failure reported at the current position
+fn expect<'a, O>(
+    mut parser: impl FnMut(&'a str) -> PResult<'a, O>,
+    message: impl Into<String> + Clone,
+) -> impl FnMut(&'a str) -> PResult<'a, O> {
+    move |input: &'a str| match parser(input) {
+        Err(nom::Err::Error(_)) => failure(input.trim_start(), message.clone()),
+        other => other,
+    }
+}
+
+// --- PARSER ---
+// Groups and negations the parser recurses into, well short of overflowing the stack
+const MAX_NESTING: usize = 64;
+
+thread_local! {
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+// One level of nesting, given back when dropped
+struct DepthGuard;
+
+impl DepthGuard {
+    fn enter() -> Option<DepthGuard> {
+        NESTING.with(|nesting| {
+            if nesting.get() > MAX_NESTING {
+                return None;
+            }
+            nesting.set(nesting.get() + 1);
+            Some(DepthGuard)
+        })
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        NESTING.with(|nesting| nesting.set(nesting.get() - 1));
+    }
+}
+
+const KEYWORDS: [&str; 6] = ["AND", "OR", "NOT", "&&", "||", "!"];
+// Every name `field` accepts before the ':', e.g. for completion
+pub const FIELD_NAMES: [&str; 12] = [
+    "title", "filename", "author", "lang", "path", "tag", "type", "source", "modified", "created",
+    "size", "tokens",
+];
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '"' | '(' | ')')
+}
+
+fn keyword<'a>(kw: &'static str) -> impl FnMut(&'a str) -> PResult<'a, &'a str> {
+    move |input: &'a str| {
+        let (rest, matched) = preceded(multispace0, tag(kw)).parse(input)?;
+        if rest.is_empty()
+            || multispace1::<_, SyntaxError>(rest).is_ok()
+            || rest.starts_with(['"', '(', ')'])
+        {
+            Ok((rest, matched))
+        } else {
+            Err(nom::Err::Error(SyntaxError::new(input, "expected keyword")))
+        }
+    }
+}
+
+// An operator's word, or its symbol; the symbol needs no space after it
+fn operator<'a>(
+    kw: &'static str,
+    symbol: &'static str,
+) -> impl FnMut(&'a str) -> PResult<'a, &'a str> {
+    move |input: &'a str| {
+        keyword(kw)(input).or_else(|_| preceded(multispace0, tag(symbol)).parse(input))
+    }
+}
+
+fn or_expr(input: &str) -> PResult<'_, Query> {
+    let (mut input, first) = and_expr(input)?;
+    let mut operands = vec![first];
+    while let Ok((rest, op)) = operator("OR", "||")(input) {
+        let message = format!("expected a search term after '{}'", op);
+        let (rest, operand) = expect(and_expr, message)(rest)?;
+        operands.push(operand);
+        input = rest;
+    }
+    Ok((input, flatten(operands, Query::Or)))
+}
+
+fn and_expr(input: &str) -> PResult<'_, Query> {
+    let (mut input, first) = unary(input)?;
+    let mut operands = vec![first];
+    loop {
+        if let Ok((rest, op)) = operator("AND", "&&")(input) {
+            let message = format!("expected a search term after '{}'", op);
+            let (rest, operand) = expect(unary, message)(rest)?;
+            operands.push(operand);
+            input = rest;
+            continue;
+        }
+        match unary(input) {
+            Ok((rest, operand)) => {
+                operands.push(operand);
+                input = rest;
+            }
+            Err(nom::Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok((input, flatten(operands, Query::And)))
+}
+
+fn flatten(mut operands: Vec<Query>, combine: fn(Vec<Query>) -> Query) -> Query {
+    if operands.len() == 1 {
+        operands.remove(0)
+    } else {
+        combine(operands)
+    }
+}
+
+fn unary(input: &str) -> PResult<'_, Query> {
+    // Every nested group and negation goes through here
+    let Some(_depth) = DepthGuard::enter() else {
+        return failure(
+            input.trim_start(),
+            format!(
+                "query nested too deeply (at most {} levels of parentheses and NOTs)",
+                MAX_NESTING
+            ),
+        );
+    };
+    if let Ok((rest, op)) = operator("NOT", "!")(input) {
+        let message = format!("expected a search term after '{}'", op);
+        let (rest, operand) = expect(unary, message)(rest)?;
+        return Ok((rest, Query::Not(Box::new(operand))));
+    }
+    // `-term` is shorthand for `NOT term`; a lone '-' is left to the word parser
+    let trimmed = input.trim_start();
+    if let Some(rest) = trimmed.strip_prefix('-')
+        && rest.starts_with(|c: char| !c.is_whitespace() && c != ')')
+    {
+        let (rest, operand) = unary(rest)?;
+        return Ok((rest, Query::Not(Box::new(operand))));
+    }
+    atom(input)
+}
+
+fn atom(input: &str) -> PResult<'_, Query> {
+    let (input, _) = multispace0(input)?;
+    if let Some(inner) = input.strip_prefix('(') {
+        let (rest, grouped) = expect(or_expr, "expected a search term after '('")(inner)?;
+        let (rest, _) = multispace0(rest)?;
+        return match rest.strip_prefix(')') {
+            Some(rest) => Ok((rest, grouped)),
+            None => failure(input, "unclosed '('; add a matching ')'"),
+        };
+    }
+    if input.starts_with('"') {
+        let (rest, text) = phrase(input)?;
+        let (rest, slop) = slop(rest)?;
+        let text = text.to_string();
+        return Ok((rest, Query::Phrase { text, slop }));
+    }
+    if let Some(after_hash) = input.strip_prefix('#') {
+        return match take_while1::<_, _, SyntaxError>(is_word_char)(after_hash) {
+            Ok((rest, name)) => Ok((rest, Query::Tag(name.to_lowercase()))),
+            Err(_) => failure(input, "expected a tag name after '#'"),
+        };
+    }
+    if let Some(after_equals) = input.strip_prefix('=') {
+        return match take_while1::<_, _, SyntaxError>(is_word_char)(after_equals) {
+            Ok((rest, text)) => Ok((rest, Query::Exact(text.to_string()))),
+            Err(_) => failure(input, "expected a word after '=', e.g. '=NaN'"),
+        };
+    }
+    if let Ok((rest, name)) = terminated(
+        take_while1(|c: char| c.is_ascii_alphabetic()),
+        char::<_, SyntaxError>(':'),
+    )
+    .parse(input)
+    {
+        return field(input, name, rest);
+    }
+    word(input)
+}
+
+fn phrase(input: &str) -> PResult<'_, &str> {
+    let (rest, _) = char('"')(input)?;
+    match rest.find('"') {
+        Some(end) => Ok((&rest[end + 1..], &rest[..end])),
+        None => failure(input, "unterminated phrase; add a closing '\"'"),
+    }
+}
+
+// `~N` right after a phrase
+fn slop(input: &str) -> PResult<'_, usize> {
+    let Some(digits) = input.strip_prefix('~') else {
+        return Ok((input, 0));
+    };
+    match take_while1::<_, _, SyntaxError>(|c: char| c.is_ascii_digit())(digits) {
+        Ok((rest, n)) => match n.parse() {
+            Ok(slop) => Ok((rest, slop)),
+            Err(_) => failure(digits, "proximity is too large"),
+        },
+        Err(_) => failure(
+            digits,
+            "expected a number of positions after '~', e.g. '\"rust async\"~3'",
+        ),
+    }
+}
+
+fn word(input: &str) -> PResult<'_, Query> {
+    let (rest, text) = take_while1(is_word_char)(input)?;
+    if KEYWORDS.contains(&text) || text.starts_with("&&") || text.starts_with("||") {
+        return Err(nom::Err::Error(SyntaxError::new(
+            input,
+            "unexpected keyword",
+        )));
+    }
+    if let Some(tilde) = text.find('~') {
+        let (term, distance) = (&text[..tilde], &text[tilde + 1..]);
+        if term.is_empty() {
+            return failure(input, "expected a word before '~', e.g. 'recieve~'");
+        }
+        if term.contains('*') {
+            return failure(input, "a term cannot be both a wildcard and fuzzy");
+        }
+        let distance = match distance {
+            "" => None,
+            digits => match digits.parse() {
+                Ok(distance) => Some(distance),
+                Err(_) => {
+                    return failure(
+                        &input[tilde + 1..],
+                        "expected a number of edits after '~', e.g. 'recieve~1'",
+                    );
+                }
+            },
+        };
+        let term = term.to_string();
+        return Ok((rest, Query::Fuzzy { term, distance }));
+    }
+    match text.find('*') {
+        None => Ok((rest, Query::Term(text.to_string()))),
+        Some(idx) if idx == text.len() - 1 && idx > 0 => {
+            Ok((rest, Query::Prefix(text[..idx].to_string())))
+        }
+        Some(_) if text.chars().any(char::is_alphanumeric) => {
+            Ok((rest, Query::Wildcard(text.to_lowercase())))
+        }
+        Some(_) => failure(input, "expected letters around '*', e.g. '*ization'"),
+    }
+}
+
+fn field<'a>(start: &'a str, name: &str, rest: &'a str) -> PResult<'a, Query> {
+    let text_field = match name.to_lowercase().as_str() {
+        "title" => Some(TextField::Title),
+        "filename" => Some(TextField::Filename),
+        "author" => Some(TextField::Author),
+        "lang" => Some(TextField::Language),
+        "path" => Some(TextField::Path),
+        "type" => Some(TextField::Type),
+        "source" => Some(TextField::Source),
+        "tag" => {
+            if rest.starts_with('"') {
+                let (rest, value) = phrase(rest)?;
+                return Ok((rest, Query::Tag(value.trim().to_lowercase())));
+            }
+            return match take_while1::<_, _, SyntaxError>(is_word_char)(rest) {
+                Ok((rest, value)) => Ok((rest, Query::Tag(value.to_lowercase()))),
+                Err(_) => failure(rest, "expected a tag name after 'tag:'"),
+            };
+        }
+        "modified" => return range(rest, RangeField::Modified),
+        "created" => return range(rest, RangeField::Created),
+        "size" => return range(rest, RangeField::Size),
+        "tokens" => return range(rest, RangeField::Tokens),
+        _ => None,
+    };
+
+    let Some(text_field) = text_field else {
+        return failure(
+            start,
+            format!(
+                "unknown field '{}' (expected title, filename, author, lang, path, tag, type, \
+                 source, modified, created, size or tokens; quote the text to search for it \
+                 literally)",
+                name
+            ),
+        );
+    };
+    if rest.starts_with('"') {
+        let (after, value) = phrase(rest)?;
+        if value.trim().is_empty() {
+            return failure(rest, format!("expected a value inside '{}:\"\"'", name));
+        }
+        return Ok((
+            after,
+            Query::Field {
+                field: text_field,
+                value: value.to_string(),
+                phrase: true,
+            },
+        ));
+    }
+    match take_while1::<_, _, SyntaxError>(is_word_char)(rest) {
+        Ok((after, value)) => Ok((
+            after,
+            Query::Field {
+                field: text_field,
+                value: value.to_string(),
+                phrase: false,
+            },
+        )),
+        Err(_) => failure(rest, format!("expected a value after '{}:'", name)),
+    }
+}
+
+fn range(input: &str, field: RangeField) -> PResult<'_, Query> {
+    let example = match field {
+        RangeField::Modified => "modified:[2024-01-01 TO 2024-06-30]",
+        RangeField::Created => "created:[2024-01-01 TO *]",
+        RangeField::Size => "size:[10KB TO 2MB]",
+        RangeField::Tokens => "tokens:[100 TO *]",
+    };
+    if let Some((op, value)) = ["<=", ">=", "<", ">"]
+        .into_iter()
+        .find_map(|op| input.strip_prefix(op).map(|value| (op, value)))
+    {
+        return comparison(value, field, op);
+    }
+    let Ok((rest, _)) = char::<_, SyntaxError>('[')(input) else {
+        return failure(input, format!("expected a range like {}", example));
+    };
+
+    let bound = |input| {
+        let (input, _) = multispace0(input)?;
+        let (rest, text) = take_while1(|c: char| !c.is_whitespace() && c != ']')(input)?;
+        Ok((rest, (input, text)))
+    };
+    let (rest, (lower_at, lower_text)) =
+        expect(bound, "expected a lower bound (use '*' for none)")(rest)?;
+    let (rest, _) = expect(keyword("TO"), "expected 'TO' between range bounds")(rest)?;
+    let (rest, (upper_at, upper_text)) =
+        expect(bound, "expected an upper bound (use '*' for none)")(rest)?;
+    let (rest, _) = multispace0(rest)?;
+    let Ok((rest, _)) = char::<_, SyntaxError>(']')(rest) else {
+        return failure(rest, "expected ']' to close the range");
+    };
+
+    let lower = parse_bound(field, lower_at, lower_text, false)?;
+    let upper = parse_bound(field, upper_at, upper_text, true)?;
+    if let (Some(lower), Some(upper)) = (lower, upper)
+        && lower > upper
+    {
+        return failure(
+            lower_at,
+            "range lower bound is greater than its upper bound",
+        );
+    }
+    Ok((
+        rest,
+        Query::Range {
+            field,
+            lower,
+            upper,
+        },
+    ))
+}
+
+// `modified:>2024-06-01`, `size:<=1MB` and the like
+fn comparison<'a>(input: &'a str, field: RangeField, op: &str) -> PResult<'a, Query> {
+    let Ok((rest, text)) = take_while1::<_, _, SyntaxError>(is_word_char)(input) else {
+        return failure(input, format!("expected a value after '{}'", op));
+    };
+    let below = op.starts_with('<');
+    if matches!(field, RangeField::Modified | RangeField::Created)
+        && let Some(age) = parse_age(text)
+    {
+        // Younger than the age is after the time that long ago
+        let cutoff = (Local::now().timestamp().max(0) as u64).saturating_sub(age);
+        let (lower, upper) = if below {
+            (Some(cutoff), None)
+        } else {
+            (None, Some(cutoff))
+        };
+        return Ok((
+            rest,
+            Query::Range {
+                field,
+                lower,
+                upper,
+            },
+        ));
+    }
+    if text == "*" {
+        return failure(input, format!("expected a value after '{}'", op));
+    }
+
+    // Dates compare whole days: `>2024-06-01` starts the day after
+    let inclusive = op.ends_with('=');
+    let value = parse_bound(field, input, text, below == inclusive)?.unwrap_or_default();
+    let value = match (inclusive, below) {
+        (true, _) => Some(value),
+        (false, true) => value.checked_sub(1),
+        (false, false) => value.checked_add(1),
+    };
+    let Some(value) = value else {
+        return failure(input, format!("nothing is {} {}", op, text));
+    };
+    let (lower, upper) = if below {
+        (None, Some(value))
+    } else {
+        (Some(value), None)
+    };
+    Ok((
+        rest,
+        Query::Range {
+            field,
+            lower,
+            upper,
+        },
+    ))
+}
+
+// Ages like `12h`, `30d`, `2w` or `1y`, in seconds
+fn parse_age(text: &str) -> Option<u64> {
+    let split = text.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = text.split_at(split);
+    let seconds: u64 = match unit {
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(seconds)
+}
+
+fn parse_bound<'a>(
+    field: RangeField,
+    at: &'a str,
+    text: &str,
+    is_upper: bool,
+) -> Result<Option<u64>, nom::Err<SyntaxError<'a>>> {
+    if text == "*" {
+        return Ok(None);
+    }
+    if let Ok(value) = text.parse::<u64>() {
+        return Ok(Some(value));
+    }
+    if field == RangeField::Size
+        && let Some(bytes) = parse_size(text)
+    {
+        return Ok(Some(bytes));
+    }
+    if matches!(field, RangeField::Modified | RangeField::Created)
+        && let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+    {
+        // Upper date bounds include the whole day
+        let time = if is_upper {
+            date.and_hms_opt(23, 59, 59)
+        } else {
+            date.and_hms_opt(0, 0, 0)
+        };
+        if let Some(timestamp) = time
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .map(|t| t.timestamp().max(0) as u64)
+        {
+            return Ok(Some(timestamp));
+        }
+    }
+    let expected = match field {
+        RangeField::Modified | RangeField::Created => {
+            "a date (YYYY-MM-DD), unix seconds or '*', or after '<' or '>' an age such as 30d"
+        }
+        RangeField::Size => "a size in bytes, optionally with a KB, MB or GB suffix, or '*'",
+        RangeField::Tokens => "a whole number or '*'",
+    };
+    Err(nom::Err::Failure(SyntaxError::new(
+        at,
+        format!("invalid range bound '{}'; expected {}", text, expected),
+    )))
+}
+
+// Sizes like `10KB` or `2mb`; units are powers of 1024.
+fn parse_size(text: &str) -> Option<u64> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+pub fn parse_query(query: &str) -> Result<Query, QueryError> {
+    let error_at = |remaining: &str, message: String| {
+        let offset = query.len() - remaining.len();
+        QueryError {
+            query: query.to_string(),
+            position: query[..offset].chars().count(),
+            message,
+        }
+    };
+
+    match or_expr(query) {
+        Ok((rest, parsed)) => {
+            let rest_trimmed = rest.trim_start();
+            if rest_trimmed.is_empty() {
+                Ok(parsed)
+            } else if rest_trimmed.starts_with(')') {
+                Err(error_at(
+                    rest_trimmed,
+                    "unexpected ')' without a matching '('".to_string(),
+                ))
+            } else {
+                Err(error_at(rest_trimmed, "unexpected input".to_string()))
+            }
+        }
+        Err(nom::Err::Error(e)) => {
+            let at = e.input.trim_start();
+            let message = if at.is_empty() {
+                "expected a search term".to_string()
+            } else {
+                format!(
+                    "expected a search term, found '{}'",
+                    at.split_whitespace().next().unwrap_or(at)
+                )
+            };
+            Err(error_at(at, message))
+        }
+        Err(nom::Err::Failure(e)) => Err(error_at(e.input, e.message)),
+        Err(nom::Err::Incomplete(_)) => Err(error_at("", "incomplete query".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displayed_queries_parse_back_to_themselves() {
+        let queries = [
+            "rust",
+            "program* OR *ization OR inter*tion",
+            "=NaN =C++",
+            "rst~ recieve~1",
+            "\"rust programming\" \"async rust\"~2",
+            "#rust tag:\"to read\"",
+            "title:\"release notes\" author:ada lang:en path:notes/2024 type:md source:work",
+            "modified:[2024-01-01 TO 2024-06-30] size:>10KB tokens:[* TO 500]",
+            "rust async OR tokio",
+            "(rust OR go) AND NOT (archive OR #draft)",
+            "!(a && (b || c)) -d",
+            "filename:README.md NOT NOT rust",
+        ];
+        for text in queries {
+            let query = parse_query(text).unwrap();
+            let shown = query.to_string();
+            assert_eq!(
+                parse_query(&shown).unwrap(),
+                query,
+                "{} shown as {}",
+                text,
+                shown
+            );
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}rust{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_query(&nested(MAX_NESTING)).is_ok());
+        let error = parse_query(&nested(MAX_NESTING + 1)).unwrap_err();
+        assert!(error.message.contains("nested too deeply"));
+        let negated = |depth: usize| format!("{}rust", "NOT ".repeat(depth));
+        assert!(parse_query(&negated(MAX_NESTING)).is_ok());
+        assert!(parse_query(&negated(MAX_NESTING + 1)).is_err());
+        // The depth is given back between queries
+        assert!(parse_query(&nested(MAX_NESTING)).is_ok());
+    }
+}
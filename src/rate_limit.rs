@@ -0,0 +1,95 @@
+// src/rate_limit.rs
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// --- CONSTANTS ---
+// Once this many clients are tracked, the ones with a full bucket are
+// forgotten, and at most this many are ever kept
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// --- STRUCTS ---
+// Per-IP token buckets: each client may burst up to `requests_per_minute`
+// requests, and regains one every 60 / `requests_per_minute` seconds.
+pub struct RateLimiter {
+    capacity: f64,
+    tokens_per_sec: f64,
+    buckets: Mutex<HashMap<IpAddr, (f64, Instant)>>, // Tokens left, when they were counted
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32) -> Self {
+        RateLimiter {
+            capacity: requests_per_minute as f64,
+            tokens_per_sec: requests_per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Takes a token for `ip`, or returns how long until one is available
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&ip) {
+            buckets.retain(|_, bucket| self.refilled(*bucket, now) < self.capacity);
+            // Every client is still limited: forget the one closest to a full
+            // bucket, which loses it the least
+            if buckets.len() >= MAX_TRACKED_CLIENTS
+                && let Some(fullest) = buckets
+                    .iter()
+                    .max_by(|a, b| {
+                        self.refilled(*a.1, now)
+                            .total_cmp(&self.refilled(*b.1, now))
+                    })
+                    .map(|(ip, _)| *ip)
+            {
+                buckets.remove(&fullest);
+            }
+        }
+
+        let bucket = buckets.entry(ip).or_insert((self.capacity, now));
+        let tokens = self.refilled(*bucket, now);
+        if tokens >= 1.0 {
+            *bucket = (tokens - 1.0, now);
+            Ok(())
+        } else {
+            *bucket = (tokens, now);
+            Err(Duration::from_secs_f64(
+                (1.0 - tokens) / self.tokens_per_sec,
+            ))
+        }
+    }
+
+    fn refilled(&self, (tokens, counted_at): (f64, Instant), now: Instant) -> f64 {
+        let elapsed = now.duration_since(counted_at).as_secs_f64();
+        (tokens + elapsed * self.tokens_per_sec).min(self.capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn tracked_clients_stay_bounded() {
+        let limiter = RateLimiter::new(10);
+        for n in 0..MAX_TRACKED_CLIENTS as u128 + 100 {
+            let ip = IpAddr::V6(Ipv6Addr::from(n));
+            assert!(limiter.check(ip).is_ok());
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_TRACKED_CLIENTS);
+    }
+
+    #[test]
+    fn a_burst_is_limited_to_the_capacity() {
+        let limiter = RateLimiter::new(3);
+        let ip = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        for _ in 0..3 {
+            assert!(limiter.check(ip).is_ok());
+        }
+        assert!(limiter.check(ip).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+// src/remote.rs
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::inverted_index::ResultSummary;
+use crate::serve::SearchResponse;
+
+// Runs a query against a serve-mode instance, e.g. `http://host:8080`.
+// Returns the results ranked `offset` onwards, at most `limit` of them, and
+// how many matched in all. The server caps the results of one response, so
+// longer pages are fetched over several.
+pub fn search(
+    base_url: &str,
+    query: &str,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<(Vec<ResultSummary>, usize)> {
+    let mut results = Vec::new();
+    loop {
+        let wanted = limit.map(|limit| limit - results.len());
+        let response = search_page(
+            base_url,
+            query,
+            offset.saturating_add(results.len()),
+            wanted,
+        )?;
+        let received = response.results.len();
+        results.extend(response.results);
+        if received == 0
+            || wanted.is_some_and(|wanted| received >= wanted)
+            || offset.saturating_add(results.len()) >= response.total
+        {
+            return Ok((results, response.total));
+        }
+    }
+}
+
+fn search_page(
+    base_url: &str,
+    query: &str,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<SearchResponse> {
+    let url = format!("{}/api/search", base_url.trim_end_matches('/'));
+    let mut request = ureq::get(&url)
+        .query("q", query)
+        .query("offset", offset.to_string());
+    if let Some(limit) = limit {
+        request = request.query("limit", limit.to_string());
+    }
+    let mut response = request
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .call()
+        .with_context(|| format!("Failed to reach infospark server at {}", base_url))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let message = response.body_mut().read_to_string().unwrap_or_default();
+        return Err(anyhow!(
+            "Server returned {}: {}",
+            status,
+            message.trim_end()
+        ));
+    }
+    response
+        .body_mut()
+        .read_json()
+        .context("Failed to decode search response from server")
+}
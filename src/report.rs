@@ -0,0 +1,259 @@
+// src/report.rs
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use chrono::{Local, TimeZone};
+
+use crate::inverted_index::{Document, InvertedIndex};
+
+// --- CONSTANTS ---
+const TOP_TAGS: usize = 30;
+const TOP_DOCUMENTS: usize = 10;
+const CHART_WIDTH: f64 = 640.0;
+const CHART_HEIGHT: f64 = 180.0;
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_time(timestamp: u64) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn month_of(timestamp: u64) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|t| t.format("%Y-%m").to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Turns per-month counts into a running total, month by month.
+fn cumulative(counts: BTreeMap<String, usize>) -> Vec<(String, usize)> {
+    let mut total = 0;
+    counts
+        .into_iter()
+        .map(|(month, count)| {
+            total += count;
+            (month, total)
+        })
+        .collect()
+}
+
+fn line_chart(points: &[(String, usize)]) -> String {
+    if points.is_empty() {
+        return "<p class=\"empty\">No data.</p>".to_string();
+    }
+    let max_value = points.iter().map(|(_, v)| *v).max().unwrap_or(0).max(1) as f64;
+    let step = if points.len() > 1 {
+        CHART_WIDTH / (points.len() - 1) as f64
+    } else {
+        0.0
+    };
+    let coordinates: Vec<String> = points
+        .iter()
+        .enumerate()
+        .map(|(i, (_, value))| {
+            let x = i as f64 * step;
+            let y = CHART_HEIGHT - (*value as f64 / max_value) * CHART_HEIGHT;
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect();
+    let (first_month, _) = &points[0];
+    let (last_month, last_value) = &points[points.len() - 1];
+    format!(
+        r##"<svg viewBox="-10 -10 {w} {h}" class="chart">
+<polyline fill="none" stroke="#4f46e5" stroke-width="2" points="{points}" />
+<text x="0" y="{label_y}">{first}</text>
+<text x="{w_inner}" y="{label_y}" text-anchor="end">{last}</text>
+<text x="0" y="4">{max}</text>
+</svg>
+<p>{last_value} at {last}</p>"##,
+        w = CHART_WIDTH + 20.0,
+        h = CHART_HEIGHT + 40.0,
+        w_inner = CHART_WIDTH,
+        points = coordinates.join(" "),
+        label_y = CHART_HEIGHT + 20.0,
+        first = escape_html(first_month),
+        last = escape_html(last_month),
+        max = max_value,
+        last_value = last_value,
+    )
+}
+
+fn bar_rows(rows: &[(String, usize)]) -> String {
+    if rows.is_empty() {
+        return "<p class=\"empty\">No data.</p>".to_string();
+    }
+    let max_value = rows.iter().map(|(_, v)| *v).max().unwrap_or(0).max(1) as f64;
+    let mut html = String::from("<table class=\"bars\">");
+    for (label, value) in rows {
+        let _ = write!(
+            html,
+            r#"<tr><td>{}</td><td class="bar-cell"><div class="bar" style="width: {:.1}%"></div></td><td>{}</td></tr>"#,
+            escape_html(label),
+            *value as f64 / max_value * 100.0,
+            value
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn document_table(docs: &[&Document]) -> String {
+    if docs.is_empty() {
+        return "<p class=\"empty\">None.</p>".to_string();
+    }
+    let mut html = String::from(
+        "<table class=\"docs\"><tr><th>Title</th><th>Path</th><th>Tokens</th><th>Modified</th></tr>",
+    );
+    for doc in docs {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&doc.title),
+            escape_html(&doc.path.display().to_string()),
+            doc.num_tokens,
+            format_time(doc.modified_time)
+        );
+    }
+    html.push_str("</table>");
+    html
+}
+
+// Renders a standalone HTML health report of the indexed corpus. Document
+// modified times stand in for creation times in the growth charts.
+pub fn render_report(index: &InvertedIndex) -> String {
+    let docs: Vec<&Document> = index.documents().collect();
+    let modified_by_id: HashMap<u32, u64> =
+        docs.iter().map(|doc| (doc.id, doc.modified_time)).collect();
+
+    let mut docs_per_month: BTreeMap<String, usize> = BTreeMap::new();
+    for doc in &docs {
+        *docs_per_month
+            .entry(month_of(doc.modified_time))
+            .or_default() += 1;
+    }
+
+    // A term counts towards the vocabulary from the month of the oldest
+    // document containing it.
+    let mut new_terms_per_month: BTreeMap<String, usize> = BTreeMap::new();
+    let mut vocabulary_size = 0;
+    for (_, doc_ids) in index.term_documents() {
+        if let Some(first_seen) = doc_ids.filter_map(|id| modified_by_id.get(&id)).min() {
+            *new_terms_per_month
+                .entry(month_of(*first_seen))
+                .or_default() += 1;
+            vocabulary_size += 1;
+        }
+    }
+
+    let mut docs_per_type: HashMap<String, usize> = HashMap::new();
+    let mut docs_per_tag: HashMap<&str, usize> = HashMap::new();
+    for doc in &docs {
+        let extension = doc
+            .path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_else(|| "(none)".to_string());
+        *docs_per_type.entry(extension).or_default() += 1;
+        for tag in &doc.tags {
+            *docs_per_tag.entry(tag).or_default() += 1;
+        }
+    }
+    let mut type_rows: Vec<(String, usize)> = docs_per_type.into_iter().collect();
+    type_rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut tag_rows: Vec<(String, usize)> = docs_per_tag
+        .iter()
+        .map(|(tag, count)| (format!("#{}", tag), *count))
+        .collect();
+    tag_rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    tag_rows.truncate(TOP_TAGS);
+
+    let mut largest = docs.clone();
+    largest.sort_by(|a, b| b.num_tokens.cmp(&a.num_tokens).then(a.id.cmp(&b.id)));
+    largest.truncate(TOP_DOCUMENTS);
+    let mut stalest = docs.clone();
+    stalest.sort_by(|a, b| a.modified_time.cmp(&b.modified_time).then(a.id.cmp(&b.id)));
+    stalest.truncate(TOP_DOCUMENTS);
+
+    // Orphans share no tag with any other document, so they have no edges in
+    // the graph view.
+    let mut orphans: Vec<&Document> = docs
+        .iter()
+        .filter(|doc| doc.tags.iter().all(|tag| docs_per_tag[tag.as_str()] <= 1))
+        .copied()
+        .collect();
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let total_tokens: usize = docs.iter().map(|doc| doc.num_tokens).sum();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Infospark Corpus Report</title>
+    <style type="text/css">
+        body {{ font-family: sans-serif; margin: 0 auto; max-width: 960px; padding: 24px; background-color: #f0f2f5; color: #1f2937; }}
+        section {{ background-color: #fff; border-radius: 8px; box-shadow: 0 1px 3px rgba(0,0,0,0.1); margin-bottom: 20px; padding: 16px 20px; }}
+        h1 {{ margin-bottom: 4px; }}
+        .generated {{ color: #6b7280; margin-top: 0; }}
+        .cards {{ display: flex; gap: 16px; flex-wrap: wrap; }}
+        .card {{ flex: 1; min-width: 140px; text-align: center; }}
+        .card .value {{ font-size: 1.8em; font-weight: bold; color: #4f46e5; }}
+        .chart {{ width: 100%; height: auto; }}
+        .chart text {{ font-size: 12px; fill: #6b7280; }}
+        table {{ border-collapse: collapse; width: 100%; }}
+        td, th {{ padding: 4px 8px; text-align: left; vertical-align: middle; }}
+        .docs tr:nth-child(even) {{ background-color: #f9fafb; }}
+        .bar-cell {{ width: 60%; }}
+        .bar {{ background-color: #4f46e5; height: 12px; border-radius: 3px; }}
+        .empty {{ color: #6b7280; font-style: italic; }}
+    </style>
+</head>
+<body>
+    <h1>Infospark Corpus Report</h1>
+    <p class="generated">Generated {generated}</p>
+    <section class="cards">
+        <div class="card"><div class="value">{doc_count}</div>documents</div>
+        <div class="card"><div class="value">{total_tokens}</div>tokens</div>
+        <div class="card"><div class="value">{vocabulary_size}</div>distinct terms</div>
+        <div class="card"><div class="value">{tag_count}</div>tags</div>
+        <div class="card"><div class="value">{orphan_count}</div>orphaned notes</div>
+    </section>
+    <section><h2>Corpus Size Over Time</h2>{size_chart}</section>
+    <section><h2>Vocabulary Growth</h2>{vocabulary_chart}</section>
+    <section><h2>Documents per Type</h2>{type_bars}</section>
+    <section><h2>Tag Distribution (top {top_tags})</h2>{tag_bars}</section>
+    <section><h2>Largest Documents</h2>{largest}</section>
+    <section><h2>Stalest Documents</h2>{stalest}</section>
+    <section><h2>Orphaned Notes</h2><p>Documents that share no tag with any other document.</p>{orphans}</section>
+</body>
+</html>
+"#,
+        generated = Local::now().format("%Y-%m-%d %H:%M"),
+        doc_count = docs.len(),
+        total_tokens = total_tokens,
+        vocabulary_size = vocabulary_size,
+        tag_count = docs_per_tag.len(),
+        orphan_count = orphans.len(),
+        size_chart = line_chart(&cumulative(docs_per_month)),
+        vocabulary_chart = line_chart(&cumulative(new_terms_per_month)),
+        type_bars = bar_rows(&type_rows),
+        top_tags = TOP_TAGS,
+        tag_bars = bar_rows(&tag_rows),
+        largest = document_table(&largest),
+        stalest = document_table(&stalest),
+        orphans = document_table(&orphans),
+    )
+}
@@ -0,0 +1,158 @@
+// src/saved_queries.rs
+// Queries saved under a name at the prompt (`save rusty "#rust async"`) and
+// run again with `run rusty`. They are kept next to the index they were saved
+// against, as a TOML table of names to query text. A saved query may end in
+// `limit:<n>` to show that many results rather than a page.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+
+use infospark::query;
+
+// --- CONSTANTS ---
+const SAVED_QUERIES_HEADER: &str =
+    "# Saved at the infospark prompt with `save <name> <query>`; run with `run <name>`";
+const LIMIT_PREFIX: &str = "limit:";
+
+// --- STRUCTS ---
+#[derive(Debug, Default)]
+pub struct SavedQueries {
+    queries: BTreeMap<String, String>, // Name -> query
+}
+
+// `search_index.bin` keeps its saved queries in `search_index.bin.queries`
+pub fn saved_queries_path(index_path: &Path) -> PathBuf {
+    let mut file_name = index_path.as_os_str().to_os_string();
+    file_name.push(".queries");
+    PathBuf::from(file_name)
+}
+
+impl SavedQueries {
+    // None saved yet when the file does not exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(SavedQueries::default());
+        }
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read saved queries {:?}", path))?;
+        let queries = toml::from_str(&raw)
+            .with_context(|| format!("Failed to parse saved queries {:?}", path))?;
+        Ok(SavedQueries { queries })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let table = toml::to_string(&self.queries).context("Failed to encode saved queries")?;
+        fs::write(path, format!("{}\n{}", SAVED_QUERIES_HEADER, table))
+            .with_context(|| format!("Failed to write saved queries {:?}", path))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.queries.get(name).map(String::as_str)
+    }
+
+    // Names and queries, in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.queries
+            .iter()
+            .map(|(name, query)| (name.as_str(), query.as_str()))
+    }
+
+    // Saves `query` as `name`, replacing any query saved under it before.
+    // Names are single words, so `run <name>` can tell them from queries,
+    // and the query must parse, so mistakes show up now rather than at `run`.
+    pub fn insert(&mut self, name: &str, query: &str) -> Result<()> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "Invalid query name '{}': use letters, digits, '-' and '_'",
+                name
+            ));
+        }
+        let (terms, _) = split_limit(query)?;
+        if terms.is_empty() {
+            return Err(anyhow!("Nothing to save as '{}'", name));
+        }
+        query::parse_query(terms)?;
+        self.queries
+            .insert(name.to_string(), query.trim().to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.queries.remove(name).is_some()
+    }
+}
+
+// Splits a saved query into the query proper and the number of results
+// its trailing `limit:<n>`, if any, asks for
+pub fn split_limit(query: &str) -> Result<(&str, Option<usize>)> {
+    let query = query.trim();
+    let (terms, last) = query
+        .rsplit_once(char::is_whitespace)
+        .unwrap_or(("", query));
+    let Some(limit) = last.strip_prefix(LIMIT_PREFIX) else {
+        return Ok((query, None));
+    };
+    match limit.parse::<usize>() {
+        Ok(limit) if limit > 0 => Ok((terms.trim_end(), Some(limit))),
+        _ => Err(anyhow!(
+            "Invalid '{}': {} takes a number of results above 0",
+            last,
+            LIMIT_PREFIX
+        )),
+    }
+}
+
+// Splits `save <name> <query>` arguments. The query may be wrapped in
+// single quotes, or in double quotes when it has none of its own; a query
+// that is one phrase is saved as `'"a phrase"'`.
+pub fn parse_save_args(args: &str) -> Option<(&str, &str)> {
+    let (name, query) = args.trim().split_once(char::is_whitespace)?;
+    let query = query.trim();
+    let unquoted = query
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+        .or_else(|| {
+            query
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .filter(|inner| !inner.contains('"'))
+        });
+    Some((name, unquoted.unwrap_or(query)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_that_do_not_parse_are_refused() {
+        let mut saved = SavedQueries::default();
+        assert!(saved.insert("broken", "title:").is_err());
+        assert!(saved.insert("typo", "tga:rust").is_err());
+        assert!(saved.insert("rusty", "#rust async").is_ok());
+        assert_eq!(saved.get("rusty"), Some("#rust async"));
+        assert!(saved.get("broken").is_none());
+    }
+
+    #[test]
+    fn a_trailing_limit_is_split_off() {
+        assert_eq!(
+            split_limit("tag:rust limit:20").unwrap(),
+            ("tag:rust", Some(20))
+        );
+        assert_eq!(split_limit("tag:rust").unwrap(), ("tag:rust", None));
+        assert!(split_limit("tag:rust limit:0").is_err());
+        assert!(split_limit("tag:rust limit:many").is_err());
+
+        let mut saved = SavedQueries::default();
+        assert!(saved.insert("recent", "tag:rust limit:20").is_ok());
+        assert!(saved.insert("empty", "limit:20").is_err());
+    }
+}
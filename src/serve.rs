@@ -0,0 +1,1294 @@
+// src/serve.rs
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use anyhow::{Context, Result, anyhow};
+use axum::Router;
+use axum::extract::{ConnectInfo, DefaultBodyLimit, Multipart, Path, Query, Request, State};
+use axum::http::{HeaderName, HeaderValue, Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::async_index::{self, SharedIndex};
+use crate::config::{self, CorpusRoot, CorsConfig, ServeConfig};
+use crate::inverted_index::{Document, IndexingReport, InvertedIndex, ResultSummary, WebAppData};
+use crate::rate_limit::RateLimiter;
+use crate::sync::{self, ManifestEntry};
+use crate::tag_sidecar::{self, TagOverrides};
+use crate::watcher::{CorpusWatcher, DEFAULT_DEBOUNCE};
+use crate::web_app::{Layout, PageTemplate};
+use crate::web_pages;
+
+// --- CONSTANTS ---
+// Graphs with more nodes than this open around a single focus node
+const LAZY_GRAPH_THRESHOLD: usize = 500;
+// Cap on the nodes sent per expansion, strongest links first
+const MAX_GRAPH_NEIGHBORS: usize = 200;
+
+// Swagger UI for `/openapi.json`, loaded from a CDN like the graph page's vis.js
+const API_DOCS_PAGE: &str = include_str!("../templates/api_docs.html");
+
+// --- STRUCTS ---
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "infospark",
+        description = "Search, graph and corpus management API of `infospark serve`",
+        license(name = "MIT")
+    ),
+    components(schemas(Layout)),
+    paths(
+        index_page,
+        app_data,
+        graph_neighbors,
+        document_page,
+        search,
+        list_documents,
+        upload_documents,
+        get_document,
+        update_document,
+        delete_document,
+        list_tags,
+        tag_documents,
+        rename_tag,
+        delete_tag,
+        edit_tags,
+        sync_manifest,
+        sync_documents,
+        sync_push,
+    )
+)]
+struct ApiDoc;
+
+struct AppState {
+    index: SharedIndex,
+    web_data: RwLock<WebAppData>,
+    // Serialized `web_data`, built lazily and dropped whenever it changes
+    json_cache: Mutex<Option<Arc<String>>>,
+    index_path: PathBuf,
+    corpus_roots: Vec<CorpusRoot>,
+    template: PageTemplate,
+    rate_limiter: Option<RateLimiter>,
+    max_query_chars: usize,
+    max_results: usize,
+}
+
+impl AppState {
+    // Brings the web data and the saved index up to date after `report`'s
+    // documents changed in the index.
+    fn apply_changes(&self, report: &IndexingReport) {
+        let index = self.index.read().unwrap();
+        index.refresh_web_app_data(&mut self.web_data.write().unwrap(), &report.changed_doc_ids);
+        *self.json_cache.lock().unwrap() = None;
+
+        if let Err(e) = index.save_changes(&self.index_path, &report.changed_doc_ids) {
+            eprintln!("Failed to save index: {:#}", e);
+        }
+    }
+
+    fn web_json(&self) -> Result<Arc<String>> {
+        let mut cache = self.json_cache.lock().unwrap();
+        if let Some(json) = cache.as_ref() {
+            return Ok(json.clone());
+        }
+        let json = Arc::new(self.web_data.read().unwrap().to_json()?);
+        *cache = Some(json.clone());
+        Ok(json)
+    }
+
+    // `doc_id`, its strongest neighbors and the edges linking them to it, or
+    // `None` if there is no such document.
+    fn neighborhood_json(&self, doc_id: u32) -> Result<Option<String>> {
+        let index = self.index.read().unwrap();
+        if index.document(doc_id).is_none() {
+            return Ok(None);
+        }
+        let mut neighbors = index.graph_neighbors(doc_id);
+        neighbors.truncate(MAX_GRAPH_NEIGHBORS);
+        let edge_keys: Vec<(u32, u32)> = neighbors
+            .iter()
+            .map(|other| (doc_id.min(*other), doc_id.max(*other)))
+            .collect();
+        let mut node_ids = vec![doc_id];
+        node_ids.extend(neighbors);
+        self.web_data
+            .read()
+            .unwrap()
+            .subgraph_json(&node_ids, &edge_keys)
+            .map(Some)
+    }
+
+    // Writes each edit to its document's sidecar first, so the corpus stays
+    // the source of truth, then applies it to the index. Documents that do not
+    // exist are skipped; the edited ones are returned. Files inside an archive
+    // share its sidecar, as web pages share their URL list's, so their tag
+    // edits apply to all of them and their titles cannot be edited. Fails
+    // without editing anything if a sidecar would lie outside the corpus.
+    fn edit_documents(&self, edits: &[(u32, DocumentEdit)]) -> Result<Vec<DocumentInfo>> {
+        let mut index = self.index.write().unwrap();
+        if let Some(file) = edits
+            .iter()
+            .filter_map(|(doc_id, _)| index.document(*doc_id))
+            .map(|doc| InvertedIndex::source_file(doc, &self.corpus_roots))
+            .find(|file| !config::within_roots(file, &self.corpus_roots))
+        {
+            return Err(anyhow!("{:?} is outside the corpus roots", file));
+        }
+        let mut report = IndexingReport::default();
+        let mut edited = Vec::new();
+        for (doc_id, edit) in edits {
+            let Some(doc) = index.document(*doc_id) else {
+                continue;
+            };
+            let path = doc.path.clone();
+            let file = InvertedIndex::source_file(doc, &self.corpus_roots);
+            let title = edit.title.as_ref().filter(|_| file == path);
+            let mut overrides = TagOverrides::load(&file)?;
+            for tag in &edit.add {
+                overrides.add(tag, &doc.tags);
+            }
+            for tag in &edit.remove {
+                overrides.remove(tag, &doc.tags);
+            }
+            if title.is_some() {
+                overrides.title = title.cloned();
+            }
+            overrides.save(&file)?;
+
+            let edited_ids: Vec<u32> = if file == path {
+                vec![*doc_id]
+            } else {
+                index
+                    .documents()
+                    .filter(|doc| InvertedIndex::source_file(doc, &self.corpus_roots) == file)
+                    .map(|doc| doc.id)
+                    .collect()
+            };
+            for edited_id in edited_ids {
+                let doc_report = index.edit_document(edited_id, |doc| {
+                    doc.tags.retain(|tag| !edit.remove.contains(tag));
+                    for tag in &edit.add {
+                        if !doc.tags.contains(tag) {
+                            doc.tags.push(tag.clone());
+                        }
+                    }
+                    if let Some(title) = title {
+                        doc.title = title.clone();
+                    }
+                });
+                if let Some(doc_report) = doc_report {
+                    report.updated.extend(doc_report.updated);
+                    report.changed_doc_ids.extend(doc_report.changed_doc_ids);
+                }
+            }
+            if let Some(doc) = index.document(*doc_id) {
+                println!("Edited {:?}: {} [{}]", path, doc.title, doc.tags.join(", "));
+                edited.push(DocumentInfo::new(doc));
+            }
+        }
+        drop(index);
+        if report.has_changes() {
+            self.apply_changes(&report);
+        }
+        Ok(edited)
+    }
+
+    // The documents carrying `tag`, without edges; expanding them fills the
+    // graph in from there.
+    fn tag_json(&self, tag: &str) -> Result<String> {
+        let mut node_ids = self.index.read().unwrap().documents_with_tag(tag);
+        node_ids.truncate(MAX_GRAPH_NEIGHBORS);
+        self.web_data.read().unwrap().subgraph_json(&node_ids, &[])
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GraphParams {
+    focus: Option<u32>,
+    tag: Option<String>,
+    #[serde(default)]
+    layout: Layout,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DocumentParams {
+    #[serde(default = "first_page")]
+    page: usize,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+// Tags to add to and remove from a document, with or without the leading '#'
+#[derive(Deserialize, ToSchema)]
+struct TagEdit {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+// Body of `PATCH /documents/{id}`; `tags` replaces the whole tag list
+#[derive(Deserialize, ToSchema)]
+struct DocumentUpdate {
+    title: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+// Body of `PATCH /tags/{tag}`
+#[derive(Deserialize, ToSchema)]
+struct TagRename {
+    name: String,
+}
+
+// A normalized change to one document's tags and title
+#[derive(Default)]
+struct DocumentEdit {
+    title: Option<String>,
+    add: Vec<String>,
+    remove: Vec<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UploadParams {
+    #[param(value_type = Option<String>)]
+    root: Option<PathBuf>, // Corpus root to save into; the first one by default
+}
+
+#[derive(Serialize, ToSchema)]
+struct DocumentInfo {
+    id: u32,
+    title: String,
+    #[schema(value_type = String)]
+    path: PathBuf,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct TagInfo {
+    name: String,
+    documents: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ErrorBody {
+    error: String,
+}
+
+impl DocumentInfo {
+    fn new(doc: &Document) -> Self {
+        DocumentInfo {
+            id: doc.id,
+            title: doc.title.clone(),
+            path: doc.path.clone(),
+            tags: doc.tags.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchParams {
+    q: String,
+    #[serde(default)]
+    offset: usize, // Results to skip
+    limit: Option<usize>, // At most the server's `max_results`, which is also the default
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SearchResponse {
+    pub query: String,
+    pub results: Vec<ResultSummary>,
+    pub total: usize, // Matches in all, past this page too
+}
+
+pub struct ServeOptions {
+    pub addr: SocketAddr,
+    pub watch: bool,
+    pub corpus_roots: Vec<CorpusRoot>,
+    pub index_path: PathBuf,
+    pub template: PageTemplate,
+    pub limits: ServeConfig,
+}
+
+pub fn run_server(index: InvertedIndex, options: ServeOptions) -> Result<()> {
+    let web_data = index.build_web_app_data();
+    let state = Arc::new(AppState {
+        index: Arc::new(RwLock::new(index)),
+        web_data: RwLock::new(web_data),
+        json_cache: Mutex::new(None),
+        index_path: options.index_path.clone(),
+        corpus_roots: options.corpus_roots.clone(),
+        template: options.template,
+        rate_limiter: options.limits.requests_per_minute.map(RateLimiter::new),
+        max_query_chars: options.limits.max_query_chars,
+        max_results: options.limits.max_results,
+    });
+
+    if options.watch {
+        let watch_state = state.clone();
+        let watcher = CorpusWatcher::new(&options.corpus_roots)?;
+        let roots = options.corpus_roots.clone();
+        thread::spawn(move || refresh_on_changes(&watch_state, &watcher, &roots));
+        println!("Watching corpus roots for changes...");
+    }
+
+    let cors = cors_layer(&options.limits.cors)?;
+    let mut app = Router::new()
+        .route("/", get(index_page))
+        .route("/openapi.json", get(openapi_spec))
+        .route("/docs", get(api_docs))
+        .route("/api/data", get(app_data))
+        .route("/graph/neighbors/{id}", get(graph_neighbors))
+        .route("/doc/{id}", get(document_page))
+        .route("/api/doc/{id}/tags", post(edit_tags))
+        .route(
+            "/documents",
+            get(list_documents)
+                .post(upload_documents)
+                .layer(DefaultBodyLimit::max(options.limits.max_upload_bytes)),
+        )
+        .route(
+            "/documents/{id}",
+            get(get_document)
+                .patch(update_document)
+                .delete(delete_document),
+        )
+        .route("/tags", get(list_tags))
+        .route(
+            "/tags/{tag}",
+            get(tag_documents).patch(rename_tag).delete(delete_tag),
+        )
+        .route("/api/search", get(search))
+        .route("/api/sync/manifest", get(sync_manifest))
+        .route("/api/sync/documents", post(sync_documents))
+        // Pushed document batches carry full document contents
+        .route(
+            "/api/sync/push",
+            post(sync_push).layer(DefaultBodyLimit::max(options.limits.max_sync_bytes)),
+        )
+        .layer(DefaultBodyLimit::max(options.limits.max_body_bytes))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .with_state(state);
+    // Outermost, so preflights are answered directly and rejections carry the headers too
+    if let Some(cors) = cors {
+        app = app.layer(cors);
+    }
+
+    // Snippets are sent to clients, so keep terminal escape codes out of them
+    colored::control::set_override(false);
+
+    let runtime = tokio::runtime::Runtime::new().context("Failed to start async runtime")?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(options.addr)
+            .await
+            .with_context(|| format!("Failed to bind {}", options.addr))?;
+        println!(
+            "Serving infospark on {}",
+            format!("http://{}", options.addr).blue()
+        );
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .context("HTTP server terminated unexpectedly")
+    })
+}
+
+// Applies debounced batches of file events to the index and rebuilds only the
+// web data entries of the documents that changed.
+fn refresh_on_changes(state: &AppState, watcher: &CorpusWatcher, corpus_roots: &[CorpusRoot]) {
+    while let Some(paths) = watcher.next_batch(DEFAULT_DEBOUNCE) {
+        let report = match InvertedIndex::refresh_shared(&state.index, corpus_roots) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Failed to refresh index after file changes: {:#}", e);
+                continue;
+            }
+        };
+        if !report.has_changes() {
+            continue;
+        }
+        println!(
+            "Refreshed {} file event(s): {}",
+            paths.len(),
+            report.summary()
+        );
+        state.apply_changes(&report);
+    }
+}
+
+// Runs `work` on the blocking thread pool, for handlers whose work (editing
+// sidecars, serializing the graph, saving the index) would stall the runtime,
+// and for any that take the index lock, which a refresh can hold for a while
+async fn blocking<T: Send + 'static>(
+    state: &Arc<AppState>,
+    work: impl FnOnce(&AppState) -> T + Send + 'static,
+) -> T {
+    let state = state.clone();
+    async_index::run_blocking(move || work(&state)).await
+}
+
+fn internal_error(e: anyhow::Error) -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e)).into_response()
+}
+
+// Error responses of the REST endpoints: `{"error": "..."}`
+fn json_error(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        axum::Json(ErrorBody {
+            error: message.into(),
+        }),
+    )
+        .into_response()
+}
+
+fn no_document(doc_id: u32) -> Response {
+    json_error(
+        StatusCode::NOT_FOUND,
+        format!("No document with id {}", doc_id),
+    )
+}
+
+// `None` when no origin is allowed, which leaves the browser defaults in place
+fn cors_layer(config: &CorsConfig) -> Result<Option<CorsLayer>> {
+    if config.allowed_origins.is_empty() {
+        return Ok(None);
+    }
+    let origins = if config.allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = config
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .with_context(|| format!("Invalid CORS origin '{}'", origin))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+    let methods = config
+        .allowed_methods
+        .iter()
+        .map(|method| {
+            Method::from_bytes(method.to_uppercase().as_bytes())
+                .with_context(|| format!("Invalid CORS method '{}'", method))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let headers = if config.allowed_headers.iter().any(|name| name == "*") {
+        AllowHeaders::any()
+    } else {
+        let headers = config
+            .allowed_headers
+            .iter()
+            .map(|name| {
+                HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid CORS header '{}'", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowHeaders::list(headers)
+    };
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers),
+    ))
+}
+
+// Rejects clients that exceeded `requests_per_minute` with 429 Too Many Requests
+async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(limiter) = &state.rate_limiter
+        && let Err(retry_after) = limiter.check(client.ip())
+    {
+        let retry_after_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after_secs.to_string())],
+            axum::Json(ErrorBody {
+                error: format!("Too many requests; retry in {} s", retry_after_secs),
+            }),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+async fn openapi_spec() -> axum::Json<utoipa::openapi::OpenApi> {
+    axum::Json(ApiDoc::openapi())
+}
+
+async fn api_docs() -> Html<&'static str> {
+    Html(API_DOCS_PAGE)
+}
+
+fn render_page(state: &AppState, json: &str, lazy_expansion: bool, layout: Layout) -> Response {
+    match state.template.render(json, lazy_expansion, layout) {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/", tag = "graph", params(GraphParams),
+    summary = "The interactive graph & search page",
+    responses((status = 200, description = "HTML page", content_type = "text/html"), (status = 404, description = "No such focus document"))
+)]
+async fn index_page(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<GraphParams>,
+) -> Response {
+    blocking(&state, move |state| render_index_page(state, params)).await
+}
+
+fn render_index_page(state: &AppState, params: GraphParams) -> Response {
+    let focus = match (params.focus, params.tag) {
+        (Some(doc_id), _) => Some(doc_id),
+        (None, Some(tag)) => {
+            let tag = tag.trim_start_matches('#').to_lowercase();
+            return match state.tag_json(&tag) {
+                Ok(json) => render_page(state, &json, true, params.layout),
+                Err(e) => internal_error(e),
+            };
+        }
+        (None, None) => {
+            let web_data = state.web_data.read().unwrap();
+            if web_data.node_count() > LAZY_GRAPH_THRESHOLD {
+                web_data.most_connected_node()
+            } else {
+                None
+            }
+        }
+    };
+
+    let Some(doc_id) = focus else {
+        return match state.web_json() {
+            Ok(json) => render_page(state, &json, false, params.layout),
+            Err(e) => internal_error(e),
+        };
+    };
+    match state.neighborhood_json(doc_id) {
+        Ok(Some(json)) => render_page(state, &json, true, params.layout),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            format!("No document with id {}", doc_id),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/graph/neighbors/{id}", tag = "graph",
+    summary = "A document, its strongest neighbors and the edges linking them",
+    params(("id" = u32, Path, description = "Document id")),
+    responses((status = 200, description = "Partial graph data, shaped like /api/data", body = Object), (status = 404, description = "No such document"))
+)]
+async fn graph_neighbors(State(state): State<Arc<AppState>>, Path(doc_id): Path<u32>) -> Response {
+    match blocking(&state, move |state| state.neighborhood_json(doc_id)).await {
+        Ok(Some(json)) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            format!("No document with id {}", doc_id),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/doc/{id}", tag = "documents", params(("id" = u32, Path, description = "Document id"), DocumentParams),
+    summary = "One page of a document's full text",
+    responses((status = 200, description = "HTML page", content_type = "text/html"), (status = 404, description = "No such document"))
+)]
+async fn document_page(
+    State(state): State<Arc<AppState>>,
+    Path(doc_id): Path<u32>,
+    Query(params): Query<DocumentParams>,
+) -> Response {
+    blocking(&state, move |state| {
+        let index = state.index.read().unwrap();
+        let Some(doc) = index.document(doc_id) else {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("No document with id {}", doc_id),
+            )
+                .into_response();
+        };
+        match state.template.render_document(doc, params.page) {
+            Ok(html) => Html(html).into_response(),
+            Err(e) => internal_error(e),
+        }
+    })
+    .await
+}
+
+#[utoipa::path(
+    get, path = "/api/data", tag = "graph",
+    summary = "Graph nodes, edges and searchable documents of the whole corpus",
+    responses((status = 200, description = "Graph and search data", body = Object))
+)]
+async fn app_data(State(state): State<Arc<AppState>>) -> Response {
+    match blocking(&state, |state| state.web_json()).await {
+        Ok(json) => (
+            [(header::CONTENT_TYPE, "application/json")],
+            json.to_string(),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/search", tag = "search", params(SearchParams),
+    summary = "Run a query",
+    responses((status = 200, description = "Success", body = SearchResponse), (status = 400, description = "Malformed or overlong query", body = String))
+)]
+async fn search(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SearchParams>,
+) -> Response {
+    if params.q.chars().count() > state.max_query_chars {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Query is longer than {} characters", state.max_query_chars),
+        )
+            .into_response();
+    }
+    let limit = params
+        .limit
+        .unwrap_or(state.max_results)
+        .min(state.max_results);
+    match async_index::search_page(&state.index, &params.q, params.offset, Some(limit)).await {
+        Ok(page) => axum::Json(SearchResponse {
+            query: params.q,
+            results: page.results.iter().map(|result| result.summary()).collect(),
+            total: page.total,
+        })
+        .into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/documents", tag = "documents",
+    summary = "List documents",
+    responses((status = 200, description = "Success", body = Vec<DocumentInfo>))
+)]
+async fn list_documents(State(state): State<Arc<AppState>>) -> axum::Json<Vec<DocumentInfo>> {
+    blocking(&state, |state| {
+        let index = state.index.read().unwrap();
+        let mut documents: Vec<DocumentInfo> = index.documents().map(DocumentInfo::new).collect();
+        documents.sort_by_key(|doc| doc.id);
+        axum::Json(documents)
+    })
+    .await
+}
+
+// The whole document, extracted content included
+#[utoipa::path(
+    get, path = "/documents/{id}", tag = "documents",
+    summary = "A whole document, including its extracted text",
+    params(("id" = u32, Path, description = "Document id")),
+    responses((status = 200, description = "Success", body = Document), (status = 404, description = "Not found", body = ErrorBody))
+)]
+async fn get_document(State(state): State<Arc<AppState>>, Path(doc_id): Path<u32>) -> Response {
+    blocking(&state, move |state| {
+        match state.index.read().unwrap().document(doc_id) {
+            Some(doc) => axum::Json(doc).into_response(),
+            None => no_document(doc_id),
+        }
+    })
+    .await
+}
+
+// Saves the files of a multipart upload into a corpus root and indexes them
+// right away. Nothing is written unless every file is acceptable.
+#[utoipa::path(
+    post, path = "/documents", tag = "documents", params(UploadParams),
+    summary = "Upload files into a corpus root and index them",
+    request_body(content_type = "multipart/form-data", description = "One or more file fields"),
+    responses(
+        (status = 201, description = "The new documents", body = Vec<DocumentInfo>),
+        (status = 400, description = "Invalid request, or a file that cannot be uploaded (a URL list, tag sidecar or hidden file)", body = ErrorBody),
+        (status = 409, description = "A file already exists", body = ErrorBody),
+        (status = 413, description = "Upload too large", body = ErrorBody),
+        (status = 415, description = "A file is not indexed in the root", body = ErrorBody),
+    )
+)]
+async fn upload_documents(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<UploadParams>,
+    mut multipart: Multipart,
+) -> Response {
+    let root = match &params.root {
+        Some(path) => state.corpus_roots.iter().find(|root| &root.path == path),
+        None => state.corpus_roots.first(),
+    };
+    let Some(root) = root else {
+        return json_error(StatusCode::BAD_REQUEST, "No such corpus root");
+    };
+
+    let mut files: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return json_error(e.status(), e.body_text()),
+        };
+        let Some(file_name) = field.file_name() else {
+            continue;
+        };
+        let path = match upload_path(root, file_name) {
+            Ok(path) => path,
+            Err((status, message)) => return json_error(status, message),
+        };
+        if files.iter().any(|(other, _)| *other == path) {
+            return json_error(StatusCode::CONFLICT, format!("{:?} already exists", path));
+        }
+        match field.bytes().await {
+            Ok(bytes) => files.push((path, bytes.to_vec())),
+            Err(e) => return json_error(e.status(), e.body_text()),
+        }
+    }
+    if files.is_empty() {
+        return json_error(StatusCode::BAD_REQUEST, "The upload contains no files");
+    }
+
+    let paths: Vec<PathBuf> = files.iter().map(|(path, _)| path.clone()).collect();
+    // The error response, if the files could not be written
+    let refused = blocking(&state, move |_| {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Some(json_error(
+                StatusCode::CONFLICT,
+                format!("{:?} already exists", path),
+            ));
+        }
+        async_index::write_new_files(&files)
+            .err()
+            .map(internal_error)
+    })
+    .await;
+    if let Some(response) = refused {
+        return response;
+    }
+    match async_index::refresh(&state.index, &state.corpus_roots).await {
+        Ok(report) => {
+            println!("Uploaded {} file(s): {}", paths.len(), report.summary());
+            blocking(&state, move |state| state.apply_changes(&report)).await;
+        }
+        Err(e) => return internal_error(e),
+    }
+
+    blocking(&state, move |state| {
+        let index = state.index.read().unwrap();
+        let uploaded: Vec<DocumentInfo> = paths
+            .iter()
+            .flat_map(|path| index.documents_at_path(path))
+            .map(DocumentInfo::new)
+            .collect();
+        (StatusCode::CREATED, axum::Json(uploaded)).into_response()
+    })
+    .await
+}
+
+// Where an uploaded file goes in `root`. Only the file name is kept, so
+// uploads cannot escape the root, and files that steer indexing rather than
+// hold documents are refused: the root's URL list, tag sidecars, and hidden
+// files such as `.infosparkignore`.
+fn upload_path(root: &CorpusRoot, file_name: &str) -> Result<PathBuf, (StatusCode, String)> {
+    let Some(file_name) = std::path::Path::new(file_name).file_name() else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{:?} is not a file name", file_name),
+        ));
+    };
+    let path = root.path.join(file_name);
+    if file_name.to_string_lossy().starts_with('.')
+        || tag_sidecar::is_sidecar(&path)
+        || web_pages::is_manifest(&path, root)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{:?} cannot be uploaded", file_name),
+        ));
+    }
+    if !InvertedIndex::is_supported_file(&path) || !root.includes(&path) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!(
+                "{:?} is not indexed in corpus root {:?}",
+                file_name, root.path
+            ),
+        ));
+    }
+    Ok(path)
+}
+
+// Retitles and/or retags a document
+#[utoipa::path(
+    patch, path = "/documents/{id}", tag = "documents",
+    summary = "Retitle and/or retag a document",
+    params(("id" = u32, Path, description = "Document id")),
+    request_body = DocumentUpdate,
+    responses((status = 200, description = "Success", body = DocumentInfo), (status = 400, description = "Invalid request", body = ErrorBody), (status = 404, description = "Not found", body = ErrorBody))
+)]
+async fn update_document(
+    State(state): State<Arc<AppState>>,
+    Path(doc_id): Path<u32>,
+    axum::Json(update): axum::Json<DocumentUpdate>,
+) -> Response {
+    let title = update.title.map(|title| title.trim().to_string());
+    if title.as_ref().is_some_and(|title| title.is_empty()) {
+        return json_error(StatusCode::BAD_REQUEST, "The title cannot be empty");
+    }
+    if title
+        .as_ref()
+        .is_some_and(|title| !tag_sidecar::is_valid_title(title))
+    {
+        return json_error(
+            StatusCode::BAD_REQUEST,
+            "The title cannot contain line breaks or other control characters",
+        );
+    }
+    let current_tags = blocking(&state, move |state| {
+        let index = state.index.read().unwrap();
+        index.document(doc_id).map(|doc| doc.tags.clone())
+    })
+    .await;
+    let Some(current_tags) = current_tags else {
+        return no_document(doc_id);
+    };
+
+    let mut edit = DocumentEdit {
+        title,
+        ..DocumentEdit::default()
+    };
+    if let Some(tags) = update.tags {
+        let Some(tags) = normalize_tags(&tags) else {
+            return json_error(StatusCode::BAD_REQUEST, "Tags must be single words");
+        };
+        edit.remove = current_tags
+            .iter()
+            .filter(|tag| !tags.contains(tag))
+            .cloned()
+            .collect();
+        edit.add = tags;
+    }
+
+    match blocking(&state, move |state| state.edit_documents(&[(doc_id, edit)])).await {
+        Ok(mut edited) if !edited.is_empty() => axum::Json(edited.remove(0)).into_response(),
+        Ok(_) => no_document(doc_id),
+        Err(e) => internal_error(e),
+    }
+}
+
+// Deletes the document's file (and tag sidecar) from the corpus
+#[utoipa::path(
+    delete, path = "/documents/{id}", tag = "documents",
+    summary = "Delete a document's file and tag sidecar from the corpus",
+    params(("id" = u32, Path, description = "Document id")),
+    responses((status = 204, description = "Deleted"), (status = 403, description = "Outside the corpus roots", body = ErrorBody), (status = 404, description = "Not found", body = ErrorBody))
+)]
+async fn delete_document(State(state): State<Arc<AppState>>, Path(doc_id): Path<u32>) -> Response {
+    let source = blocking(&state, move |state| {
+        let index = state.index.read().unwrap();
+        index.document(doc_id).map(|doc| {
+            (
+                doc.path.clone(),
+                InvertedIndex::source_file(doc, &state.corpus_roots),
+            )
+        })
+    })
+    .await;
+    let Some((path, file)) = source else {
+        return no_document(doc_id);
+    };
+    // Archive members and web pages go with the file they come from
+    if file != path {
+        return json_error(
+            StatusCode::CONFLICT,
+            format!(
+                "{:?} comes from {:?}; edit or delete that instead",
+                path, file
+            ),
+        );
+    }
+    if !config::within_roots(&path, &state.corpus_roots) {
+        return json_error(
+            StatusCode::FORBIDDEN,
+            format!("{:?} is outside the corpus roots", path),
+        );
+    }
+    let files = [path.clone(), tag_sidecar::sidecar_path(&path)];
+    match async_index::remove_files(&state.index, &state.corpus_roots, &files).await {
+        Ok(report) => {
+            println!("Deleted {:?}: {}", path, report.summary());
+            blocking(&state, move |state| state.apply_changes(&report)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/tags", tag = "tags",
+    summary = "List tags with their document counts",
+    responses((status = 200, description = "Success", body = Vec<TagInfo>))
+)]
+async fn list_tags(State(state): State<Arc<AppState>>) -> axum::Json<Vec<TagInfo>> {
+    blocking(&state, |state| {
+        let index = state.index.read().unwrap();
+        let mut tags: Vec<TagInfo> = index
+            .tag_counts()
+            .map(|(name, documents)| TagInfo {
+                name: name.to_string(),
+                documents,
+            })
+            .collect();
+        tags.sort_by(|a, b| b.documents.cmp(&a.documents).then(a.name.cmp(&b.name)));
+        axum::Json(tags)
+    })
+    .await
+}
+
+#[utoipa::path(
+    get, path = "/tags/{tag}", tag = "tags",
+    summary = "List the documents carrying a tag",
+    params(("tag" = String, Path, description = "Tag, with or without the leading '#'")),
+    responses((status = 200, description = "Success", body = Vec<DocumentInfo>), (status = 404, description = "Not found", body = ErrorBody))
+)]
+async fn tag_documents(State(state): State<Arc<AppState>>, Path(tag): Path<String>) -> Response {
+    blocking(&state, move |state| {
+        let index = state.index.read().unwrap();
+        let documents: Vec<DocumentInfo> = normalize_tag(&tag)
+            .map(|tag| index.documents_with_tag(&tag))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|doc_id| index.document(doc_id))
+            .map(DocumentInfo::new)
+            .collect();
+        if documents.is_empty() {
+            return json_error(
+                StatusCode::NOT_FOUND,
+                format!("No document is tagged '{}'", tag),
+            );
+        }
+        axum::Json(documents).into_response()
+    })
+    .await
+}
+
+// Replaces `tag` with a new name on every document carrying it, merging it
+// into the new tag when that already exists
+#[utoipa::path(
+    patch, path = "/tags/{tag}", tag = "tags",
+    summary = "Rename a tag on every document carrying it",
+    params(("tag" = String, Path, description = "Tag, with or without the leading '#'")),
+    request_body = TagRename,
+    responses((status = 200, description = "Success", body = TagInfo), (status = 400, description = "Invalid request", body = ErrorBody), (status = 404, description = "Not found", body = ErrorBody))
+)]
+async fn rename_tag(
+    State(state): State<Arc<AppState>>,
+    Path(tag): Path<String>,
+    axum::Json(rename): axum::Json<TagRename>,
+) -> Response {
+    let (Some(tag), Some(name)) = (normalize_tag(&tag), normalize_tag(&rename.name)) else {
+        return json_error(StatusCode::BAD_REQUEST, "Tags must be single words");
+    };
+    let edits: Vec<(u32, DocumentEdit)> = tagged_documents(&state, &tag)
+        .await
+        .into_iter()
+        .map(|doc_id| {
+            let edit = DocumentEdit {
+                add: vec![name.clone()],
+                remove: if name == tag {
+                    Vec::new()
+                } else {
+                    vec![tag.clone()]
+                },
+                ..DocumentEdit::default()
+            };
+            (doc_id, edit)
+        })
+        .collect();
+    if edits.is_empty() {
+        return json_error(
+            StatusCode::NOT_FOUND,
+            format!("No document is tagged '{}'", tag),
+        );
+    }
+
+    let renamed = blocking(&state, move |state| -> Result<TagInfo> {
+        state.edit_documents(&edits)?;
+        let documents = state.index.read().unwrap().documents_with_tag(&name).len();
+        Ok(TagInfo { name, documents })
+    })
+    .await;
+    match renamed {
+        Ok(tag) => axum::Json(tag).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+// Removes `tag` from every document carrying it
+#[utoipa::path(
+    delete, path = "/tags/{tag}", tag = "tags",
+    summary = "Remove a tag from every document carrying it",
+    params(("tag" = String, Path, description = "Tag, with or without the leading '#'")),
+    responses((status = 204, description = "Removed"), (status = 400, description = "Invalid request", body = ErrorBody), (status = 404, description = "Not found", body = ErrorBody))
+)]
+async fn delete_tag(State(state): State<Arc<AppState>>, Path(tag): Path<String>) -> Response {
+    let Some(tag) = normalize_tag(&tag) else {
+        return json_error(StatusCode::BAD_REQUEST, "Tags must be single words");
+    };
+    let edits: Vec<(u32, DocumentEdit)> = tagged_documents(&state, &tag)
+        .await
+        .into_iter()
+        .map(|doc_id| {
+            let edit = DocumentEdit {
+                remove: vec![tag.clone()],
+                ..DocumentEdit::default()
+            };
+            (doc_id, edit)
+        })
+        .collect();
+    if edits.is_empty() {
+        return json_error(
+            StatusCode::NOT_FOUND,
+            format!("No document is tagged '{}'", tag),
+        );
+    }
+
+    match blocking(&state, move |state| state.edit_documents(&edits)).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+async fn tagged_documents(state: &Arc<AppState>, tag: &str) -> Vec<u32> {
+    let tag = tag.to_string();
+    blocking(state, move |state| {
+        state.index.read().unwrap().documents_with_tag(&tag)
+    })
+    .await
+}
+
+fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim().trim_start_matches('#').to_lowercase();
+    (!tag.is_empty() && !tag.chars().any(char::is_whitespace)).then_some(tag)
+}
+
+// Normalizes every tag, or `None` if any of them is not a single word
+fn normalize_tags(tags: &[String]) -> Option<Vec<String>> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag)?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Some(normalized)
+}
+
+// Adds and removes tags on one document. Responds with its new tags.
+#[utoipa::path(
+    post, path = "/api/doc/{id}/tags", tag = "tags",
+    summary = "Add and remove tags on one document",
+    params(("id" = u32, Path, description = "Document id")),
+    request_body = TagEdit,
+    responses((status = 200, description = "The document's new tags", body = Vec<String>), (status = 400, description = "Invalid request", body = String), (status = 404, description = "Not found", body = String))
+)]
+async fn edit_tags(
+    State(state): State<Arc<AppState>>,
+    Path(doc_id): Path<u32>,
+    axum::Json(edit): axum::Json<TagEdit>,
+) -> Response {
+    let (Some(add), Some(remove)) = (normalize_tags(&edit.add), normalize_tags(&edit.remove))
+    else {
+        return (StatusCode::BAD_REQUEST, "Tags must be single words").into_response();
+    };
+    let edit = DocumentEdit {
+        add,
+        remove,
+        ..DocumentEdit::default()
+    };
+    match blocking(&state, move |state| state.edit_documents(&[(doc_id, edit)])).await {
+        Ok(mut edited) if !edited.is_empty() => axum::Json(edited.remove(0).tags).into_response(),
+        Ok(_) => (
+            StatusCode::NOT_FOUND,
+            format!("No document with id {}", doc_id),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/api/sync/manifest", tag = "sync",
+    summary = "Path, content hash and modified time of every document",
+    responses((status = 200, description = "Success", body = Vec<ManifestEntry>))
+)]
+async fn sync_manifest(State(state): State<Arc<AppState>>) -> axum::Json<Vec<ManifestEntry>> {
+    blocking(&state, |state| {
+        axum::Json(sync::manifest(
+            &state.index.read().unwrap(),
+            &state.corpus_roots,
+        ))
+    })
+    .await
+}
+
+#[utoipa::path(
+    post, path = "/api/sync/documents", tag = "sync",
+    summary = "The documents with the given paths",
+    request_body = Vec<String>,
+    responses(
+        (status = 200, description = "Success", body = Vec<Document>),
+        (status = 400, description = "A path climbs out of its corpus root", body = ErrorBody)
+    )
+)]
+async fn sync_documents(
+    State(state): State<Arc<AppState>>,
+    axum::Json(paths): axum::Json<Vec<PathBuf>>,
+) -> Response {
+    blocking(&state, move |state| {
+        let index = state.index.read().unwrap();
+        match sync::documents_for_paths(&index, &state.corpus_roots, &paths) {
+            Ok(docs) => axum::Json(docs).into_response(),
+            Err(e) => json_error(StatusCode::BAD_REQUEST, e.to_string()),
+        }
+    })
+    .await
+}
+
+#[utoipa::path(
+    post, path = "/api/sync/push", tag = "sync",
+    summary = "Import already-extracted documents",
+    request_body = Vec<Document>,
+    responses(
+        (status = 200, description = "Number of changed documents", body = usize),
+        (status = 400, description = "A document comes from outside the corpus roots", body = ErrorBody)
+    )
+)]
+async fn sync_push(
+    State(state): State<Arc<AppState>>,
+    axum::Json(docs): axum::Json<Vec<Document>>,
+) -> Response {
+    let docs = blocking(&state, |state| {
+        sync::local_documents(docs, &state.corpus_roots)
+    })
+    .await;
+    let docs = match docs {
+        Ok(docs) => docs,
+        Err(e) => return json_error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let report = match async_index::import_documents(&state.index, &state.corpus_roots, docs).await
+    {
+        Ok(report) => report,
+        Err(e) => return json_error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    println!("Received synced documents: {}", report.summary());
+    let changed = report.changed_doc_ids.len();
+    blocking(&state, move |state| state.apply_changes(&report)).await;
+    axum::Json(changed).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refused(file_name: &str) -> StatusCode {
+        let root = CorpusRoot::new(std::path::Path::new("/notes"));
+        upload_path(&root, file_name).unwrap_err().0
+    }
+
+    #[test]
+    fn uploads_keep_only_their_file_name() {
+        let root = CorpusRoot::new(std::path::Path::new("/notes"));
+        assert_eq!(
+            upload_path(&root, "../../etc/plan.md").unwrap(),
+            PathBuf::from("/notes/plan.md")
+        );
+        assert_eq!(refused(".."), StatusCode::BAD_REQUEST);
+        assert_eq!(refused("photo.bmp"), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn url_lists_cannot_be_uploaded() {
+        assert_eq!(refused("urls.txt"), StatusCode::BAD_REQUEST);
+        assert_eq!(refused("sub/urls.txt"), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn tag_sidecars_cannot_be_uploaded() {
+        assert_eq!(refused("plan.md.tags"), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn hidden_files_cannot_be_uploaded() {
+        assert_eq!(refused(".infosparkignore"), StatusCode::BAD_REQUEST);
+        assert_eq!(refused(".plan.md"), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn searches_return_at_most_max_results() {
+        let dir = tempfile::tempdir().unwrap();
+        for n in 0..5 {
+            std::fs::write(dir.path().join(format!("{}.md", n)), "quasar").unwrap();
+        }
+        let root = CorpusRoot::new(dir.path());
+        let mut index = InvertedIndex::new();
+        index
+            .load_documents_from_roots(std::slice::from_ref(&root))
+            .unwrap();
+        let state = Arc::new(AppState {
+            web_data: RwLock::new(index.build_web_app_data()),
+            index: Arc::new(RwLock::new(index)),
+            json_cache: Mutex::new(None),
+            index_path: dir.path().join("index.bin"),
+            corpus_roots: vec![root],
+            template: PageTemplate::load(None).unwrap(),
+            rate_limiter: None,
+            max_query_chars: 100,
+            max_results: 2,
+        });
+
+        let mut pages = Vec::new();
+        for (offset, limit) in [(0, None), (1, Some(10)), (4, Some(1))] {
+            let params = SearchParams {
+                q: "quasar".to_string(),
+                offset,
+                limit,
+            };
+            let response = search(State(state.clone()), Query(params)).await;
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let page: SearchResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(page.total, 5);
+            pages.push(page.results.len());
+        }
+        assert_eq!(pages, [2, 2, 1]);
+    }
+}
@@ -0,0 +1,216 @@
+// src/snapshot.rs
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use chrono::Local;
+use colored::*;
+
+use crate::journal;
+
+// --- CONSTANTS ---
+pub const SNAPSHOT_DIR: &str = ".infospark_snapshots";
+const SNAPSHOT_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+// --- STRUCTS ---
+// Files captured by a snapshot. The index is mandatory, and its journal goes
+// with it; metadata stores (history, and anything else living next to the
+// index) are optional.
+pub struct SnapshotTargets {
+    pub index_file: PathBuf,
+    pub journal_file: PathBuf,
+    pub metadata_files: Vec<PathBuf>,
+}
+
+pub struct SnapshotInfo {
+    pub name: String,
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub files: Vec<String>,
+}
+
+fn snapshot_root(targets: &SnapshotTargets) -> PathBuf {
+    targets
+        .index_file
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(SNAPSHOT_DIR)
+}
+
+pub fn create_snapshot(
+    targets: &SnapshotTargets,
+    label: Option<&str>,
+    include_metadata: bool,
+) -> Result<SnapshotInfo> {
+    if !targets.index_file.exists() {
+        return Err(anyhow!(
+            "No index found at {:?}; nothing to snapshot",
+            targets.index_file
+        ));
+    }
+
+    let mut name = Local::now().format(SNAPSHOT_TIME_FORMAT).to_string();
+    if let Some(label) = label {
+        let label: String = label
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        name = format!("{}-{}", name, label);
+    }
+
+    let snapshot_path = snapshot_root(targets).join(&name);
+    if snapshot_path.exists() {
+        return Err(anyhow!("Snapshot '{}' already exists", name));
+    }
+    fs::create_dir_all(&snapshot_path).context("Failed to create snapshot directory")?;
+
+    let mut to_copy = vec![targets.index_file.clone()];
+    if targets.journal_file.exists() {
+        to_copy.push(targets.journal_file.clone());
+    }
+    if include_metadata {
+        to_copy.extend(
+            targets
+                .metadata_files
+                .iter()
+                .filter(|p| p.exists())
+                .cloned(),
+        );
+    }
+    for source in &to_copy {
+        let file_name = source
+            .file_name()
+            .context("Snapshot target has no file name")?;
+        fs::copy(source, snapshot_path.join(file_name))
+            .with_context(|| format!("Failed to copy {:?} into snapshot", source))?;
+    }
+
+    read_snapshot_info(&snapshot_path)
+}
+
+fn read_snapshot_info(snapshot_path: &Path) -> Result<SnapshotInfo> {
+    let mut size_bytes = 0;
+    let mut files = Vec::new();
+    for entry in fs::read_dir(snapshot_path)? {
+        let entry = entry?;
+        size_bytes += entry.metadata()?.len();
+        files.push(entry.file_name().to_string_lossy().to_string());
+    }
+    files.sort();
+    Ok(SnapshotInfo {
+        name: snapshot_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        path: snapshot_path.to_path_buf(),
+        size_bytes,
+        files,
+    })
+}
+
+// Snapshots sorted oldest first; names start with a sortable timestamp.
+pub fn list_snapshots(targets: &SnapshotTargets) -> Result<Vec<SnapshotInfo>> {
+    let root = snapshot_root(targets);
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&root)? {
+        let entry = entry?;
+        if entry.path().is_dir() {
+            snapshots.push(read_snapshot_info(&entry.path())?);
+        }
+    }
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+// Restores every file stored in the snapshot, taking a safety snapshot of the
+// current state first so a restore can itself be rolled back.
+pub fn restore_snapshot(targets: &SnapshotTargets, name: &str) -> Result<Option<SnapshotInfo>> {
+    let snapshots = list_snapshots(targets)?;
+    let matching: Vec<&SnapshotInfo> = snapshots
+        .iter()
+        .filter(|s| s.name == name || s.name.starts_with(name))
+        .collect();
+    let snapshot = match matching.as_slice() {
+        [] => return Err(anyhow!("No snapshot named '{}'", name)),
+        [single] => *single,
+        _ => {
+            if let Some(exact) = matching.iter().find(|s| s.name == name) {
+                *exact
+            } else {
+                return Err(anyhow!(
+                    "Snapshot name '{}' is ambiguous ({} matches)",
+                    name,
+                    matching.len()
+                ));
+            }
+        }
+    };
+
+    let safety_snapshot = if targets.index_file.exists() {
+        Some(create_snapshot(targets, Some("pre-restore"), true)?)
+    } else {
+        None
+    };
+
+    let all_targets: Vec<&PathBuf> = [&targets.index_file, &targets.journal_file]
+        .into_iter()
+        .chain(targets.metadata_files.iter())
+        .collect();
+    for file_name in &snapshot.files {
+        let destination = all_targets
+            .iter()
+            .find(|t| {
+                t.file_name()
+                    .is_some_and(|f| f.to_string_lossy() == *file_name)
+            })
+            .with_context(|| format!("Snapshot file '{}' has no restore target", file_name))?;
+        // Copied next to the destination and renamed over it, since a running
+        // instance may have the index file memory-mapped
+        let mut temp_path = destination.as_os_str().to_os_string();
+        temp_path.push(".tmp");
+        fs::copy(snapshot.path.join(file_name), &temp_path)
+            .and_then(|_| fs::rename(&temp_path, destination))
+            .with_context(|| format!("Failed to restore {:?}", destination))?;
+    }
+    // A journal left from the current index would otherwise be replayed onto
+    // the restored one
+    let journal_name = targets
+        .journal_file
+        .file_name()
+        .map(|f| f.to_string_lossy());
+    if !snapshot
+        .files
+        .iter()
+        .any(|file_name| Some(file_name.as_str()) == journal_name.as_deref())
+    {
+        journal::remove(&targets.journal_file)?;
+    }
+
+    Ok(safety_snapshot)
+}
+
+pub fn print_snapshots(snapshots: &[SnapshotInfo]) {
+    if snapshots.is_empty() {
+        println!("No snapshots found.");
+        return;
+    }
+    for snapshot in snapshots {
+        println!(
+            "  - {} ({:.1} KiB): {}",
+            snapshot.name.green(),
+            snapshot.size_bytes as f64 / 1024.0,
+            snapshot.files.join(", ")
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// src/stored.rs
+// Bulky parts of the index (document text, posting lists, token offsets)
+// that a memory-mapped index leaves in the file until they are first used.
+//
+// Encoding an index moves each stored value into a blob area ahead of the
+// rest of the payload, leaving only its byte range in place. An index read
+// into memory decodes the values straight away; a mapped one keeps the
+// ranges and decodes each value from the map on first access, so startup
+// only pays for the term dictionary and document metadata. Compressed values
+// (document text) are zstd-compressed in the blob area, and decompressed the
+// first time they are used however the index was read; an index read into
+// memory checks that they decompress while it is decoded.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::sync::{Arc, OnceLock};
+
+use serde::de::{DeserializeOwned, Deserializer, Error as _};
+use serde::ser::{Error as _, Serializer};
+use serde::{Deserialize, Serialize};
+
+// --- CONSTANTS ---
+const COMPRESSION_LEVEL: i32 = 3;
+
+// --- TYPE ALIASES ---
+// The index file contents, read into memory or mapped
+pub type FileBytes = Arc<dyn AsRef<[u8]> + Send + Sync>;
+// A stored value kept compressed in the index file
+pub type Compressed<T> = Stored<T, true>;
+
+// --- STRUCTS ---
+// Where the blob area of an index being decoded lies, and whether its values
+// are decoded on first use
+#[derive(Clone)]
+struct BlobSource {
+    bytes: FileBytes,
+    blobs: Range<usize>,
+    lazy: bool,
+}
+
+thread_local! {
+    // Blob area of the index being encoded on this thread
+    static ENCODING: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+    static DECODING: RefCell<Option<BlobSource>> = const { RefCell::new(None) };
+}
+
+// A value that is serialized inline, except inside an index payload encoded
+// with `encode_with_blobs`, where it goes to the blob area, compressed if
+// `COMPRESSED`.
+pub struct Stored<T, const COMPRESSED: bool = false> {
+    value: OnceLock<T>,
+    // Encoded form of a value not decoded yet
+    blob: Option<(FileBytes, Range<usize>)>,
+}
+
+impl<T, const COMPRESSED: bool> Stored<T, COMPRESSED> {
+    pub fn new(value: T) -> Self {
+        Stored {
+            value: OnceLock::from(value),
+            blob: None,
+        }
+    }
+
+    fn encoded(&self) -> Option<&[u8]> {
+        self.blob
+            .as_ref()
+            .map(|(bytes, range)| &(**bytes).as_ref()[range.clone()])
+    }
+}
+
+impl<T: DeserializeOwned, const COMPRESSED: bool> Stored<T, COMPRESSED> {
+    // Panics if the blob no longer decodes. The blob area is checksummed when
+    // an index is opened, so that only happens when the index file was
+    // modified in place while mapped.
+    pub fn load(&self) -> &T {
+        self.value.get_or_init(|| {
+            let encoded = self
+                .encoded()
+                .expect("a stored value has a blob or a value");
+            decode_blob(encoded, COMPRESSED)
+                .expect("stored value no longer decodes; was the mapped index file modified?")
+        })
+    }
+}
+
+fn decode_blob<T: DeserializeOwned>(encoded: &[u8], compressed: bool) -> anyhow::Result<T> {
+    let decompressed;
+    let encoded = if compressed {
+        decompressed = zstd::decode_all(encoded)?;
+        &decompressed
+    } else {
+        encoded
+    };
+    let (value, _) = bincode::serde::decode_from_slice(encoded, bincode::config::standard())?;
+    Ok(value)
+}
+
+impl<T: DeserializeOwned, const COMPRESSED: bool> Deref for Stored<T, COMPRESSED> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.load()
+    }
+}
+
+// Values still in the map share it rather than being decoded to be cloned
+impl<T: Clone, const COMPRESSED: bool> Clone for Stored<T, COMPRESSED> {
+    fn clone(&self) -> Self {
+        match self.value.get() {
+            Some(value) => Stored::new(value.clone()),
+            None => Stored {
+                value: OnceLock::new(),
+                blob: self.blob.clone(),
+            },
+        }
+    }
+}
+
+impl<T: DeserializeOwned + PartialEq, const COMPRESSED: bool> PartialEq for Stored<T, COMPRESSED> {
+    fn eq(&self, other: &Self) -> bool {
+        self.load() == other.load()
+    }
+}
+
+impl<T: DeserializeOwned + fmt::Debug, const COMPRESSED: bool> fmt::Debug
+    for Stored<T, COMPRESSED>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.load().fmt(f)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, const COMPRESSED: bool> Serialize for Stored<T, COMPRESSED> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let range = ENCODING.with_borrow_mut(|blobs| {
+            let blobs = blobs.as_mut()?;
+            let start = blobs.len();
+            // Undecoded values are copied over without decoding them
+            let result = match (self.value.get(), self.encoded()) {
+                (None, Some(encoded)) => {
+                    blobs.extend_from_slice(encoded);
+                    Ok(())
+                }
+                _ if COMPRESSED => {
+                    bincode::serde::encode_to_vec(self.load(), bincode::config::standard())
+                        .map_err(anyhow::Error::from)
+                        .and_then(|encoded| {
+                            let compressed = zstd::encode_all(&encoded[..], COMPRESSION_LEVEL)?;
+                            blobs.extend(compressed);
+                            Ok(())
+                        })
+                }
+                _ => bincode::serde::encode_into_std_write(
+                    self.load(),
+                    blobs,
+                    bincode::config::standard(),
+                )
+                .map(|_| ())
+                .map_err(anyhow::Error::from),
+            };
+            Some(result.map(|()| (start as u64, blobs.len() as u64)))
+        });
+        match range {
+            Some(Ok(range)) => range.serialize(serializer),
+            Some(Err(e)) => Err(S::Error::custom(e)),
+            None => self.load().serialize(serializer),
+        }
+    }
+}
+
+impl<'de, T: DeserializeOwned, const COMPRESSED: bool> Deserialize<'de> for Stored<T, COMPRESSED> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let Some(source) = DECODING.with_borrow(Clone::clone) else {
+            return T::deserialize(deserializer).map(Stored::new);
+        };
+        let (start, end) = <(u64, u64)>::deserialize(deserializer)?;
+        let offset = |at: u64| {
+            usize::try_from(at)
+                .ok()
+                .and_then(|at| source.blobs.start.checked_add(at))
+        };
+        let range = match (offset(start), offset(end)) {
+            (Some(start), Some(end)) if start <= end && end <= source.blobs.end => start..end,
+            _ => return Err(D::Error::custom("stored value lies outside the blob area")),
+        };
+        if source.lazy {
+            return Ok(Stored {
+                value: OnceLock::new(),
+                blob: Some((source.bytes, range)),
+            });
+        }
+        // Kept compressed, without holding on to the rest of the file, once
+        // it is known to decompress
+        if COMPRESSED {
+            let compressed = (*source.bytes).as_ref()[range].to_vec();
+            decode_blob::<T>(&compressed, true).map_err(D::Error::custom)?;
+            let len = compressed.len();
+            return Ok(Stored {
+                value: OnceLock::new(),
+                blob: Some((Arc::new(compressed), 0..len)),
+            });
+        }
+        decode_blob(&(*source.bytes).as_ref()[range], false)
+            .map(Stored::new)
+            .map_err(D::Error::custom)
+    }
+}
+
+// Encodes `value`, returning the blob area and the rest of the payload
+pub fn encode_with_blobs<T: Serialize>(value: &T) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    ENCODING.set(Some(Vec::new()));
+    let payload = bincode::serde::encode_to_vec(value, bincode::config::standard());
+    let blobs = ENCODING.take().unwrap_or_default();
+    Ok((blobs, payload?))
+}
+
+// Decodes a payload from `encode_with_blobs` whose blob area lies at `blobs`
+// in `bytes`, which must be within it. Lazily decoded values keep `bytes` alive.
+pub fn decode_with_blobs<T: DeserializeOwned>(
+    bytes: FileBytes,
+    blobs: Range<usize>,
+    payload: Range<usize>,
+    lazy: bool,
+) -> anyhow::Result<T> {
+    DECODING.set(Some(BlobSource {
+        bytes: bytes.clone(),
+        blobs,
+        lazy,
+    }));
+    let decoded =
+        bincode::serde::decode_from_slice(&(*bytes).as_ref()[payload], bincode::config::standard());
+    DECODING.set(None);
+    Ok(decoded?.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Values {
+        postings: Stored<Vec<u32>>,
+        text: Compressed<String>,
+    }
+
+    // The blob area followed by the payload, and where each lies
+    fn encoded() -> (Vec<u8>, Range<usize>, Range<usize>) {
+        let values = Values {
+            postings: Stored::new(vec![1, 2, 3]),
+            text: Compressed::new("some stored text ".repeat(20)),
+        };
+        let (mut bytes, payload) = encode_with_blobs(&values).unwrap();
+        let blobs = 0..bytes.len();
+        bytes.extend(payload);
+        let payload = blobs.end..bytes.len();
+        (bytes, blobs, payload)
+    }
+
+    fn decode(
+        bytes: Vec<u8>,
+        blobs: Range<usize>,
+        payload: Range<usize>,
+        lazy: bool,
+    ) -> anyhow::Result<Values> {
+        decode_with_blobs(Arc::new(bytes), blobs, payload, lazy)
+    }
+
+    #[test]
+    fn values_round_trip_through_the_blob_area() {
+        for lazy in [false, true] {
+            let (bytes, blobs, payload) = encoded();
+            let values = decode(bytes, blobs, payload, lazy).unwrap();
+            assert_eq!(*values.postings, vec![1, 2, 3]);
+            assert_eq!(*values.text, "some stored text ".repeat(20));
+        }
+    }
+
+    #[test]
+    fn damaged_blobs_are_rejected_when_read_into_memory() {
+        let (mut bytes, blobs, payload) = encoded();
+        // The compressed text follows the postings
+        for byte in &mut bytes[blobs.end - 4..blobs.end] {
+            *byte ^= 0xff;
+        }
+        assert!(decode(bytes, blobs, payload, false).is_err());
+
+        let (bytes, blobs, payload) = encoded();
+        let short = blobs.start..blobs.end - 1;
+        let error = decode(bytes, short, payload, true).err().unwrap();
+        assert!(
+            error.to_string().contains("outside the blob area"),
+            "{}",
+            error
+        );
+    }
+}
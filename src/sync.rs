@@ -0,0 +1,348 @@
+// src/sync.rs
+//
+// Document-level synchronization between two indexes. Each side is described
+// by a manifest of (path, content hash, modified time); a file's documents
+// are copied when the other side lacks them, or holds different content with
+// an older modified time. Paths are exchanged relative to the corpus roots,
+// prefixed with the root's name, so the two sides may keep their corpus in
+// different places; documents of a root the receiving side does not have are
+// left out. Deletions are not propagated: the next reindex drops documents
+// whose files do not exist locally, and a document deleted on one side is
+// copied back from the other until it is deleted there too.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::CorpusRoot;
+use crate::inverted_index::{Document, IndexingReport, InvertedIndex};
+use crate::web_pages;
+
+// --- CONSTANTS ---
+// Written inside a shared directory (e.g. one kept in sync by Syncthing)
+pub const SYNC_BUNDLE_FILE: &str = "infospark_sync.bin";
+// Largest manifest or document batch accepted from a server
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024 * 1024;
+
+// --- STRUCTS ---
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ManifestEntry {
+    #[schema(value_type = String)]
+    pub path: PathBuf, // Shared path (see `shared_path`)
+    pub content_hash: u64,
+    pub modified_time: u64,
+}
+
+pub struct SyncReport {
+    pub pulled: IndexingReport,
+    pub pushed: Vec<PathBuf>,
+}
+
+// 64-bit FNV-1a; stable across machines and Rust versions, unlike the std hasher
+pub fn content_hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// A document's path as both sides know it: the name of its corpus root
+// joined with its path inside the root. Web pages keep their URL. None for a
+// document outside every root.
+fn shared_path(doc: &Document, roots: &[CorpusRoot]) -> Option<PathBuf> {
+    if web_pages::is_url(&doc.path) {
+        return Some(doc.path.clone());
+    }
+    let root = roots.iter().find(|root| root.name() == doc.source)?;
+    let relative = doc.path.strip_prefix(&root.path).ok()?;
+    Some(Path::new(&doc.source).join(relative))
+}
+
+// The local path for a shared path, or None when no root here has its root's
+// name. Fails for a path that would climb out of its root.
+fn local_path(path: &Path, roots: &[CorpusRoot]) -> Result<Option<PathBuf>> {
+    if web_pages::is_url(path) {
+        return Ok(Some(path.to_path_buf()));
+    }
+    let mut components = path.components();
+    let Some(name) = components.next() else {
+        return Ok(None);
+    };
+    let Some(root) = roots
+        .iter()
+        .find(|root| name.as_os_str() == root.name().as_str())
+    else {
+        return Ok(None);
+    };
+    let local = root.path.join(components.as_path());
+    if !root.contains(&local) {
+        return Err(anyhow!("{:?} lies outside its corpus root", path));
+    }
+    Ok(Some(local))
+}
+
+// Copies of documents with their shared paths, leaving out ones outside the
+// roots
+fn shared_documents<'a>(
+    docs: impl Iterator<Item = &'a Document>,
+    roots: &[CorpusRoot],
+) -> Vec<Document> {
+    docs.filter_map(|doc| {
+        let path = shared_path(doc, roots)?;
+        Some(Document {
+            path,
+            ..doc.clone()
+        })
+    })
+    .collect()
+}
+
+// Received documents moved to their local paths, leaving out ones of roots
+// this side does not have. Fails if one would lie outside its root.
+pub fn local_documents(docs: Vec<Document>, roots: &[CorpusRoot]) -> Result<Vec<Document>> {
+    let mut local = Vec::new();
+    for mut doc in docs {
+        if let Some(path) = local_path(&doc.path, roots)? {
+            doc.path = path;
+            local.push(doc);
+        }
+    }
+    Ok(local)
+}
+
+// An entry per file; the hash of a file indexed a document per row covers
+// every row and the line it came from
+fn manifest_of<'a>(docs: impl Iterator<Item = (PathBuf, &'a Document)>) -> Vec<ManifestEntry> {
+    let mut docs_by_path: HashMap<PathBuf, Vec<&Document>> = HashMap::new();
+    for (path, doc) in docs {
+        docs_by_path.entry(path).or_default().push(doc);
+    }
+    docs_by_path
+        .into_iter()
+        .map(|(path, mut docs)| {
+            docs.sort_by_key(|doc| doc.row);
+            let content_hash = match docs.as_slice() {
+                [doc] => content_hash(&doc.content),
+                _ => docs.iter().fold(0, |hash, doc| {
+                    hash ^ content_hash(&format!("{:?}\n{}", doc.row, *doc.content))
+                }),
+            };
+            ManifestEntry {
+                path,
+                content_hash,
+                modified_time: docs.iter().map(|doc| doc.modified_time).max().unwrap_or(0),
+            }
+        })
+        .collect()
+}
+
+pub fn manifest(index: &InvertedIndex, roots: &[CorpusRoot]) -> Vec<ManifestEntry> {
+    manifest_of(
+        index
+            .documents()
+            .filter_map(|doc| Some((shared_path(doc, roots)?, doc))),
+    )
+}
+
+// Paths from `theirs` that should replace (or be added to) `ours`.
+pub fn wanted_paths(ours: &[ManifestEntry], theirs: &[ManifestEntry]) -> Vec<PathBuf> {
+    let ours_by_path: HashMap<&Path, &ManifestEntry> =
+        ours.iter().map(|e| (e.path.as_path(), e)).collect();
+    let mut wanted: Vec<PathBuf> = theirs
+        .iter()
+        .filter(|theirs| match ours_by_path.get(theirs.path.as_path()) {
+            None => true,
+            Some(ours) => {
+                ours.content_hash != theirs.content_hash
+                    && theirs.modified_time > ours.modified_time
+            }
+        })
+        .map(|e| e.path.clone())
+        .collect();
+    wanted.sort();
+    wanted
+}
+
+// The documents with the given shared paths, with their shared paths
+pub fn documents_for_paths(
+    index: &InvertedIndex,
+    roots: &[CorpusRoot],
+    paths: &[PathBuf],
+) -> Result<Vec<Document>> {
+    let mut docs = Vec::new();
+    for path in paths {
+        if let Some(path) = local_path(path, roots)? {
+            docs.extend(shared_documents(
+                index.documents_at_path(&path).into_iter(),
+                roots,
+            ));
+        }
+    }
+    Ok(docs)
+}
+
+// `remote` is either the URL of a serve-mode instance or a shared directory.
+pub fn sync(index: &mut InvertedIndex, roots: &[CorpusRoot], remote: &str) -> Result<SyncReport> {
+    if remote.starts_with("http://") || remote.starts_with("https://") {
+        sync_with_server(index, roots, remote.trim_end_matches('/'))
+    } else {
+        sync_with_directory(index, roots, Path::new(remote))
+    }
+}
+
+fn sync_with_directory(
+    index: &mut InvertedIndex,
+    roots: &[CorpusRoot],
+    dir: &Path,
+) -> Result<SyncReport> {
+    if !dir.is_dir() {
+        return Err(anyhow!("Sync target {:?} is not a directory", dir));
+    }
+    let bundle_path = dir.join(SYNC_BUNDLE_FILE);
+    let bundle: Vec<Document> = if bundle_path.exists() {
+        let data = fs::read(&bundle_path)
+            .with_context(|| format!("Failed to read sync bundle {:?}", bundle_path))?;
+        bincode::serde::decode_from_slice(&data, bincode::config::standard())
+            .with_context(|| format!("Failed to decode sync bundle {:?}", bundle_path))?
+            .0
+    } else {
+        Vec::new()
+    };
+
+    let bundle_manifest: Vec<ManifestEntry> =
+        manifest_of(bundle.iter().map(|doc| (doc.path.clone(), doc)));
+    let local_manifest = manifest(index, roots);
+    let pushed = wanted_paths(&bundle_manifest, &local_manifest);
+    let pulled_paths = wanted_paths(&local_manifest, &bundle_manifest);
+
+    // Documents of roots this side does not have stay in the bundle as they are
+    let mut merged = Vec::new();
+    let mut pulled_docs = Vec::new();
+    for doc in bundle {
+        if local_path(&doc.path, roots)?.is_none() {
+            merged.push(doc);
+        } else if pulled_paths.contains(&doc.path) {
+            pulled_docs.push(doc);
+        }
+    }
+    let pulled = index.import_documents(local_documents(pulled_docs, roots)?);
+
+    // After the pull every other bundle document is present locally in its
+    // newest version, so the local documents complete the merged bundle.
+    if !pushed.is_empty() {
+        merged.extend(shared_documents(index.documents(), roots));
+        let data = bincode::serde::encode_to_vec(&merged, bincode::config::standard())
+            .context("Failed to encode sync bundle")?;
+        let tmp_path = bundle_path.with_extension("tmp");
+        fs::write(&tmp_path, data)
+            .with_context(|| format!("Failed to write sync bundle {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &bundle_path)
+            .with_context(|| format!("Failed to replace sync bundle {:?}", bundle_path))?;
+    }
+
+    Ok(SyncReport { pulled, pushed })
+}
+
+fn sync_with_server(
+    index: &mut InvertedIndex,
+    roots: &[CorpusRoot],
+    base_url: &str,
+) -> Result<SyncReport> {
+    let remote_manifest: Vec<ManifestEntry> =
+        read_response(ureq::get(format!("{}/api/sync/manifest", base_url)).call())?;
+    let local_manifest = manifest(index, roots);
+    let pushed = wanted_paths(&remote_manifest, &local_manifest);
+    let pulled_paths = wanted_paths(&local_manifest, &remote_manifest);
+
+    let pulled = if pulled_paths.is_empty() {
+        IndexingReport::default()
+    } else {
+        let docs: Vec<Document> = read_response(
+            ureq::post(format!("{}/api/sync/documents", base_url)).send_json(&pulled_paths),
+        )?;
+        index.import_documents(local_documents(docs, roots)?)
+    };
+
+    if !pushed.is_empty() {
+        let docs = documents_for_paths(index, roots, &pushed)?;
+        let _: usize =
+            read_response(ureq::post(format!("{}/api/sync/push", base_url)).send_json(&docs))?;
+    }
+
+    Ok(SyncReport { pulled, pushed })
+}
+
+fn read_response<T: serde::de::DeserializeOwned>(
+    response: Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+) -> Result<T> {
+    response
+        .context("Sync request failed")?
+        .body_mut()
+        .with_config()
+        .limit(MAX_RESPONSE_BYTES) // Document batches can exceed the default 10 MB cap
+        .read_json()
+        .context("Failed to decode sync response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use infospark::stored::Stored;
+
+    fn document(path: &Path, source: &str) -> Document {
+        Document {
+            id: 1,
+            path: path.to_path_buf(),
+            content: Stored::new("content".to_string()),
+            title: String::new(),
+            tags: Vec::new(),
+            num_tokens: 1,
+            modified_time: 0,
+            created_time: None,
+            size: 7,
+            author: None,
+            language: None,
+            source: source.to_string(),
+            row: None,
+            parent_doc: None,
+            description: None,
+            content_hash: 0,
+        }
+    }
+
+    #[test]
+    fn paths_are_shared_relative_to_the_root_of_the_same_name() {
+        let here = tempfile::tempdir().unwrap();
+        let there = tempfile::tempdir().unwrap();
+        let ours = [CorpusRoot::new(here.path().join("notes"))];
+        let theirs = [CorpusRoot::new(there.path().join("deep/notes"))];
+        fs::create_dir(&ours[0].path).unwrap();
+        fs::create_dir_all(&theirs[0].path).unwrap();
+
+        let doc = document(&ours[0].path.join("sub/a.md"), "notes");
+        let shared = shared_documents([&doc].into_iter(), &ours);
+        assert_eq!(shared[0].path, Path::new("notes/sub/a.md"));
+
+        let received = local_documents(shared, &theirs).unwrap();
+        assert_eq!(received[0].path, theirs[0].path.join("sub/a.md"));
+    }
+
+    #[test]
+    fn received_paths_stay_inside_their_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = [CorpusRoot::new(dir.path().join("notes"))];
+        fs::create_dir(&roots[0].path).unwrap();
+
+        let escaping = document(Path::new("notes/../../outside.md"), "notes");
+        assert!(local_documents(vec![escaping], &roots).is_err());
+        let unknown_root = document(Path::new("other/a.md"), "other");
+        assert!(
+            local_documents(vec![unknown_root], &roots)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,162 @@
+// src/tag_sidecar.rs
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+// --- CONSTANTS ---
+// `notes.md` keeps its edited tags in `notes.md.tags`
+pub const SIDECAR_EXTENSION: &str = "tags";
+const SIDECAR_HEADER: &str = "# Edited in infospark: an optional 'title:' line, then one tag per line; '-tag' hides a tag found in the document";
+const TITLE_PREFIX: &str = "title:";
+
+// --- STRUCTS ---
+// Tag (and title) edits that live next to a document rather than in it, so
+// they work for every format (PDFs included) and survive reindexing. Tags are
+// stored as shown in the index, i.e. after tag prefixes and synonyms.
+#[derive(Debug, Default, PartialEq)]
+pub struct TagOverrides {
+    pub title: Option<String>, // Replaces the extracted title
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+pub fn sidecar_path(doc_path: &Path) -> PathBuf {
+    let mut file_name = doc_path.as_os_str().to_os_string();
+    file_name.push(".");
+    file_name.push(SIDECAR_EXTENSION);
+    PathBuf::from(file_name)
+}
+
+pub fn is_sidecar(path: &Path) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some(SIDECAR_EXTENSION)
+}
+
+// Whether `title` fits on the sidecar's title line
+pub fn is_valid_title(title: &str) -> bool {
+    !title.chars().any(char::is_control)
+}
+
+impl TagOverrides {
+    // The overrides of `doc_path`; none if it has no sidecar file.
+    pub fn load(doc_path: &Path) -> Result<Self> {
+        let path = sidecar_path(doc_path);
+        if !path.exists() {
+            return Ok(TagOverrides::default());
+        }
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read tag sidecar {:?}", path))?;
+
+        let mut overrides = TagOverrides::default();
+        for line in raw.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(title) = line.strip_prefix(TITLE_PREFIX) {
+                overrides.title = Some(title.trim().to_string());
+                continue;
+            }
+            match line.strip_prefix('-') {
+                Some(tag) => overrides.removed.push(tag.trim().to_lowercase()),
+                None => overrides.added.push(line.to_lowercase()),
+            }
+        }
+        Ok(overrides)
+    }
+
+    // Writes the overrides next to `doc_path`, deleting the sidecar once
+    // there is nothing left in it. Titles with control characters are
+    // refused, as a line break in one would turn the rest into tags.
+    pub fn save(&self, doc_path: &Path) -> Result<()> {
+        let path = sidecar_path(doc_path);
+        if let Some(title) = self.title.as_ref().filter(|title| !is_valid_title(title)) {
+            bail!("The title {:?} contains control characters", title);
+        }
+        if self.title.is_none() && self.added.is_empty() && self.removed.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove tag sidecar {:?}", path))?;
+            }
+            return Ok(());
+        }
+
+        let mut raw = format!("{}\n", SIDECAR_HEADER);
+        if let Some(title) = &self.title {
+            raw.push_str(&format!("{} {}\n", TITLE_PREFIX, title));
+        }
+        for tag in &self.added {
+            raw.push_str(&format!("{}\n", tag));
+        }
+        for tag in &self.removed {
+            raw.push_str(&format!("-{}\n", tag));
+        }
+        fs::write(&path, raw).with_context(|| format!("Failed to write tag sidecar {:?}", path))
+    }
+
+    pub fn apply(&self, tags: &mut Vec<String>) {
+        tags.retain(|tag| !self.removed.contains(tag));
+        for tag in &self.added {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+    }
+
+    // Records adding `tag` to a document currently tagged with `tags`
+    pub fn add(&mut self, tag: &str, tags: &[String]) {
+        let was_removed = self.removed.len();
+        self.removed.retain(|removed| removed != tag);
+        if self.removed.len() == was_removed
+            && !tags.iter().any(|existing| existing == tag)
+            && !self.added.iter().any(|added| added == tag)
+        {
+            self.added.push(tag.to_string());
+        }
+    }
+
+    // Records removing `tag` from a document currently tagged with `tags`
+    pub fn remove(&mut self, tag: &str, tags: &[String]) {
+        let was_added = self.added.len();
+        self.added.retain(|added| added != tag);
+        if self.added.len() == was_added
+            && tags.iter().any(|existing| existing == tag)
+            && !self.removed.iter().any(|removed| removed == tag)
+        {
+            self.removed.push(tag.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = dir.path().join("notes.md");
+        let overrides = TagOverrides {
+            title: Some("Release notes: 2.0".to_string()),
+            added: vec!["rust".to_string()],
+            removed: vec!["draft".to_string()],
+        };
+        overrides.save(&doc).unwrap();
+        assert_eq!(TagOverrides::load(&doc).unwrap(), overrides);
+    }
+
+    #[test]
+    fn titles_with_control_characters_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let doc = dir.path().join("notes.md");
+        for title in ["Notes\nsecret\n-public", "Notes\r-public", "Notes\u{7}"] {
+            let overrides = TagOverrides {
+                title: Some(title.to_string()),
+                ..TagOverrides::default()
+            };
+            assert!(overrides.save(&doc).is_err(), "{:?} was saved", title);
+            assert!(!sidecar_path(&doc).exists());
+            assert_eq!(TagOverrides::load(&doc).unwrap(), TagOverrides::default());
+        }
+    }
+}
@@ -1,27 +1,239 @@
 // src/tokenizer.rs
 use rust_stemmers::{Algorithm, Stemmer};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use stop_words::{LANGUAGE, get};
 
+// --- CONSTANTS ---
+// Languages prose is stemmed and stop-word filtered in, by ISO 639-1 code
+// and the ISO 639-3 code language detection gives. Documents in any other
+// language are analyzed as English.
+pub const DEFAULT_ANALYZER: &str = "en";
+const ANALYZED_LANGUAGES: &[(&str, &str, Algorithm, LANGUAGE)] = &[
+    ("ar", "ara", Algorithm::Arabic, LANGUAGE::Arabic),
+    ("da", "dan", Algorithm::Danish, LANGUAGE::Danish),
+    ("de", "deu", Algorithm::German, LANGUAGE::German),
+    ("el", "ell", Algorithm::Greek, LANGUAGE::Greek),
+    ("en", "eng", Algorithm::English, LANGUAGE::English),
+    ("es", "spa", Algorithm::Spanish, LANGUAGE::Spanish),
+    ("fi", "fin", Algorithm::Finnish, LANGUAGE::Finnish),
+    ("fr", "fra", Algorithm::French, LANGUAGE::French),
+    ("hu", "hun", Algorithm::Hungarian, LANGUAGE::Hungarian),
+    ("it", "ita", Algorithm::Italian, LANGUAGE::Italian),
+    ("nl", "nld", Algorithm::Dutch, LANGUAGE::Dutch),
+    ("no", "nob", Algorithm::Norwegian, LANGUAGE::Norwegian),
+    ("pt", "por", Algorithm::Portuguese, LANGUAGE::Portuguese),
+    ("ro", "ron", Algorithm::Romanian, LANGUAGE::Romanian),
+    ("ru", "rus", Algorithm::Russian, LANGUAGE::Russian),
+    ("sv", "swe", Algorithm::Swedish, LANGUAGE::Swedish),
+    ("tr", "tur", Algorithm::Turkish, LANGUAGE::Turkish),
+];
+// Text sampled for language detection, in bytes
+const DETECTION_SAMPLE_BYTES: usize = 4096;
+
 lazy_static::lazy_static! {
-    static ref STOP_WORDS: HashSet<String> = get(LANGUAGE::English).into_iter().collect();
+    static ref STOP_WORDS: HashMap<&'static str, HashSet<String>> = ANALYZED_LANGUAGES
+        .iter()
+        .map(|(code, _, _, language)| (*code, get(language.clone()).into_iter().collect()))
+        .collect();
+}
+
+// The analyzer for a language code such as "de" or "pt-br": its language if
+// it is analyzed, or else English
+pub fn analyzer_for(language: Option<&str>) -> &'static str {
+    let primary = language.and_then(|language| language.split(['-', '_']).next());
+    ANALYZED_LANGUAGES
+        .iter()
+        .find(|(code, ..)| primary.is_some_and(|primary| primary.eq_ignore_ascii_case(code)))
+        .map_or(DEFAULT_ANALYZER, |(code, ..)| code)
+}
+
+// The language of `text`, as an ISO 639-1 code where it has an analyzer and
+// an ISO 639-3 code otherwise, or None if detection is unsure
+pub fn detect_language(text: &str) -> Option<String> {
+    let sample = &text[..text.floor_char_boundary(DETECTION_SAMPLE_BYTES)];
+    let info = whatlang::detect(sample).filter(|info| info.is_reliable())?;
+    let detected = info.lang().code();
+    let code = ANALYZED_LANGUAGES
+        .iter()
+        .find(|(_, iso3, ..)| *iso3 == detected)
+        .map_or(detected, |(code, ..)| code);
+    Some(code.to_string())
 }
 
 pub fn tokenize(text: &str) -> Vec<(String, usize)> {
-    let en_stemmer = Stemmer::create(Algorithm::English);
+    tokenize_in(text, DEFAULT_ANALYZER)
+}
+
+pub fn tokenize_in(text: &str, analyzer: &str) -> Vec<(String, usize)> {
+    tokenize_with_offsets_in(text, analyzer)
+        .into_iter()
+        .map(|(token, pos, _)| (token, pos))
+        .collect()
+}
+
+pub fn tokenize_with_offsets(text: &str) -> Vec<(String, usize, (usize, usize))> {
+    tokenize_with_offsets_in(text, DEFAULT_ANALYZER)
+}
+
+// Like `tokenize_in`, but also returns the byte range of the surface word
+// each token came from, so matches can be located in the original text.
+pub fn tokenize_with_offsets_in(
+    text: &str,
+    analyzer: &str,
+) -> Vec<(String, usize, (usize, usize))> {
     let mut tokens_with_positions = Vec::new();
-    let mut current_word_index = 0;
-
-    text.to_lowercase()
-        .split(|c: char| !c.is_alphanumeric())
-        .filter(|s| !s.is_empty())
-        .for_each(|s| {
-            let token_string = s.to_string();
-            if !STOP_WORDS.contains(&token_string) {
-                let stemmed_token = en_stemmer.stem(&token_string).to_string();
-                tokens_with_positions.push((stemmed_token, current_word_index));
-                current_word_index += 1; // Increment position for the next valid word
-            }
-        });
+    for_each_word(text, analyzer, |_, token, span| {
+        if let Some(token) = token {
+            let pos = tokens_with_positions.len(); // Stop words take no position
+            tokens_with_positions.push((token, pos, span));
+        }
+    });
     tokens_with_positions
 }
+
+// Every word of `text` in order, lowercased, with its token, or None for a
+// stop word; for matching phrases stop words and all
+pub fn words_in(text: &str, analyzer: &str) -> Vec<(String, Option<String>)> {
+    let mut words = Vec::new();
+    for_each_word(text, analyzer, |word, token, _| words.push((word, token)));
+    words
+}
+
+// Like `words_in`, with the byte range of the surface word each came from
+pub fn words_with_offsets_in(
+    text: &str,
+    analyzer: &str,
+) -> Vec<(String, Option<String>, (usize, usize))> {
+    let mut words = Vec::new();
+    for_each_word(text, analyzer, |word, token, span| {
+        words.push((word, token, span))
+    });
+    words
+}
+
+// Calls `visit` with each lowercased word of `text`, its stemmed token (None
+// for a stop word) and the byte range of the surface word it came from
+fn for_each_word(
+    text: &str,
+    analyzer: &str,
+    mut visit: impl FnMut(String, Option<String>, (usize, usize)),
+) {
+    let analyzer = analyzer_for(Some(analyzer));
+    let (_, _, algorithm, _) = ANALYZED_LANGUAGES
+        .iter()
+        .find(|(code, ..)| *code == analyzer)
+        .unwrap();
+    let stop_words = &STOP_WORDS[analyzer];
+    let stemmer = Stemmer::create(*algorithm);
+
+    let mut push_word = |start: usize, end: usize| {
+        // Lowercasing can produce non-alphanumeric chars; those split the word
+        // further, with every piece pointing at the same surface range.
+        text[start..end]
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|s| !s.is_empty())
+            .for_each(|s| {
+                let word = s.to_string();
+                let token = (!stop_words.contains(&word)).then(|| stemmer.stem(&word).to_string());
+                visit(word, token, (start, end));
+            });
+    };
+
+    let mut word_start = None;
+    for (idx, c) in text.char_indices() {
+        match (c.is_alphanumeric(), word_start) {
+            (true, None) => word_start = Some(idx),
+            (false, Some(start)) => {
+                push_word(start, idx);
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        push_word(start, text.len());
+    }
+}
+
+pub fn tokenize_code(text: &str) -> Vec<(String, usize)> {
+    tokenize_code_with_offsets(text)
+        .into_iter()
+        .map(|(token, pos, _)| (token, pos))
+        .collect()
+}
+
+// Tokens for source code: each identifier made of several words, lowercased
+// and without underscores, followed by the words themselves, so
+// `parseQuery` and `parse_query` both give "parsequery", "parse", "query".
+// Nothing is stemmed or dropped as a stop word; `is` and `for` matter in code.
+pub fn tokenize_code_with_offsets(text: &str) -> Vec<(String, usize, (usize, usize))> {
+    let mut tokens = Vec::new();
+    let mut push_token = |token: String, span: (usize, usize)| {
+        let pos = tokens.len();
+        tokens.push((token, pos, span));
+    };
+    let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut identifier_start = None;
+    let chars = text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')));
+    for (idx, c) in chars {
+        match (is_identifier_char(c), identifier_start) {
+            (true, None) => identifier_start = Some(idx),
+            (false, Some(start)) => {
+                identifier_start = None;
+                let identifier = &text[start..idx];
+                let parts = identifier_parts(identifier);
+                if parts.len() > 1 {
+                    push_token(identifier.to_lowercase().replace('_', ""), (start, idx));
+                }
+                for (part_start, part_end) in parts {
+                    push_token(
+                        identifier[part_start..part_end].to_lowercase(),
+                        (start + part_start, start + part_end),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+// Byte ranges of the words of an identifier, split at underscores and where
+// the case changes: `HTTPServer2Config` gives "HTTP", "Server2", "Config".
+fn identifier_parts(identifier: &str) -> Vec<(usize, usize)> {
+    let chars: Vec<(usize, char)> = identifier.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut part_start: Option<usize> = None;
+    for (i, &(idx, c)) in chars.iter().enumerate() {
+        if c == '_' {
+            if let Some(start) = part_start.take() {
+                parts.push((start, idx));
+            }
+            continue;
+        }
+        let Some(start) = part_start else {
+            part_start = Some(idx);
+            continue;
+        };
+        let previous = chars[i - 1].1;
+        let next_is_lowercase = chars
+            .get(i + 1)
+            .is_some_and(|(_, next)| next.is_lowercase());
+        let boundary = c.is_uppercase()
+            && (previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next_is_lowercase));
+        if boundary {
+            parts.push((start, idx));
+            part_start = Some(idx);
+        }
+    }
+    if let Some(start) = part_start {
+        parts.push((start, identifier.len()));
+    }
+    parts
+}
@@ -0,0 +1,79 @@
+// src/watcher.rs
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, channel};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::config::CorpusRoot;
+
+// --- CONSTANTS ---
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+// Upper bound on how long a continuous stream of events (e.g. a bulk sync) can
+// postpone a refresh.
+const MAX_BATCH_WAIT: Duration = Duration::from_secs(10);
+
+pub struct CorpusWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl CorpusWatcher {
+    pub fn new(roots: &[CorpusRoot]) -> Result<Self> {
+        let (tx, events) = channel();
+        let mut watcher =
+            notify::recommended_watcher(tx).context("Failed to create file watcher")?;
+        for root in roots {
+            let mode = if root.max_depth == Some(1) {
+                RecursiveMode::NonRecursive
+            } else {
+                RecursiveMode::Recursive
+            };
+            watcher
+                .watch(&root.path, mode)
+                .with_context(|| format!("Failed to watch corpus root {:?}", root.path))?;
+        }
+        Ok(CorpusWatcher {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    // Blocks until a file event arrives, then keeps collecting events until none
+    // arrived for `debounce`. Returns the touched paths, or None once the
+    // watcher has shut down.
+    pub fn next_batch(&self, debounce: Duration) -> Option<Vec<PathBuf>> {
+        let mut paths: HashSet<PathBuf> = HashSet::new();
+        let mut batch_started: Option<Instant> = None;
+
+        loop {
+            let event = match batch_started {
+                None => self.events.recv().ok()?,
+                Some(started) => {
+                    if started.elapsed() >= MAX_BATCH_WAIT {
+                        break;
+                    }
+                    match self.events.recv_timeout(debounce) {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            };
+
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    paths.extend(event.paths);
+                    batch_started.get_or_insert_with(Instant::now);
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("File watch error: {:?}", e),
+            }
+        }
+
+        Some(paths.into_iter().collect())
+    }
+}
@@ -0,0 +1,228 @@
+// src/web_app.rs
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+use clap::ValueEnum;
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use serde::Deserialize;
+use tera::Tera;
+use utoipa::ToSchema;
+
+use crate::inverted_index::Document;
+
+// --- CONSTANTS ---
+const BASE_TEMPLATE_NAME: &str = "graph.html";
+const DEFAULT_TEMPLATE: &str = include_str!("../templates/graph.html");
+// Layouts other than `Full`; the graph ones extend the base template
+const LAYOUT_TEMPLATES: [(&str, &str); 3] = [
+    (
+        "graph_only.html",
+        include_str!("../templates/graph_only.html"),
+    ),
+    (
+        "search_first.html",
+        include_str!("../templates/search_first.html"),
+    ),
+    ("print.html", include_str!("../templates/print.html")),
+];
+const DOCUMENT_TEMPLATE_NAME: &str = "document.html";
+const DOCUMENT_TEMPLATE: &str = include_str!("../templates/document.html");
+// Pages end at the first paragraph break past this many bytes, or at a line
+// break past twice as many
+const DOCUMENT_PAGE_BYTES: usize = 20_000;
+// Link and image URLs kept in rendered Markdown, besides relative ones
+const SAFE_URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+// --- STRUCTS ---
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// Search sidebar next to the graph
+    #[default]
+    Full,
+    /// Fullscreen graph without the search sidebar
+    GraphOnly,
+    /// Search results first, with a graph that opens on demand
+    SearchFirst,
+    /// Printable list of documents with their tags and previews
+    Print,
+}
+
+impl Layout {
+    fn template_name(self) -> &'static str {
+        match self {
+            Layout::Full => BASE_TEMPLATE_NAME,
+            Layout::GraphOnly => "graph_only.html",
+            Layout::SearchFirst => "search_first.html",
+            Layout::Print => "print.html",
+        }
+    }
+}
+
+// The standalone graph & search page. The built-in templates are compiled
+// into the binary; `--template` swaps in a user file for the base template
+// (which the graph-only and search-first layouts extend), receiving the same
+// variables: `json_data` (serialized `FullWebAppData`, escaped for a JS
+// template literal) and `lazy_expansion` (whether clicking a node should
+// fetch its neighbors from a serve-mode server). The serve-mode document page
+// always uses the built-in template.
+pub struct PageTemplate {
+    tera: Tera,
+}
+
+impl PageTemplate {
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let source = match path {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("Failed to read web template {:?}", path))?,
+            None => DEFAULT_TEMPLATE.to_string(),
+        };
+        let mut tera = Tera::default();
+        // The JSON is escaped for JavaScript below, not for HTML
+        tera.autoescape_on(Vec::new());
+        let mut templates = vec![(BASE_TEMPLATE_NAME, source.as_str())];
+        templates.extend(LAYOUT_TEMPLATES);
+        templates.push((DOCUMENT_TEMPLATE_NAME, DOCUMENT_TEMPLATE));
+        tera.add_raw_templates(templates)
+            .context("Failed to parse web template")?;
+        Ok(PageTemplate { tera })
+    }
+
+    // Renders the page with `json_data` embedded into it. With `lazy_expansion`
+    // the embedded data is only part of the graph.
+    pub fn render(&self, json_data: &str, lazy_expansion: bool, layout: Layout) -> Result<String> {
+        let escaped_json_data = json_data
+            .replace("\\", "\\\\")
+            .replace("\"", "\\\"")
+            .replace("\n", "\\n")
+            .replace("\r", "\\r")
+            .replace("\t", "\\t")
+            .replace("`", "\\`");
+
+        let mut context = tera::Context::new();
+        context.insert("json_data", &escaped_json_data);
+        context.insert("lazy_expansion", &lazy_expansion);
+        self.tera
+            .render(layout.template_name(), &context)
+            .context("Failed to render web template")
+    }
+
+    // Renders one page of the whole extracted text of `doc`, with Markdown
+    // files rendered to HTML. `page` is 1-based and clamped to the last page.
+    pub fn render_document(&self, doc: &Document, page: usize) -> Result<String> {
+        let markdown = doc.path.extension().and_then(|s| s.to_str()) == Some("md");
+        let pages = document_pages(&doc.content);
+        let page = page.clamp(1, pages.len());
+        let text = pages[page - 1];
+        let body = if markdown {
+            render_markdown(text)
+        } else {
+            format!("<pre>{}</pre>", tera::escape_html(text))
+        };
+        let modified = Local
+            .timestamp_opt(doc.modified_time as i64, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+
+        let mut context = tera::Context::new();
+        context.insert("id", &doc.id);
+        context.insert("title", &doc.title);
+        context.insert("path", &doc.path.display().to_string());
+        context.insert("tags", &doc.tags);
+        context.insert("modified", &modified);
+        context.insert("size", &doc.size);
+        context.insert("markdown", &markdown);
+        context.insert("body", &body);
+        context.insert("page", &page);
+        context.insert("page_count", &pages.len());
+        self.tera
+            .render(DOCUMENT_TEMPLATE_NAME, &context)
+            .context("Failed to render document template")
+    }
+}
+
+// Splits `content` into pages at line boundaries, preferring blank lines
+// outside fenced code blocks so Markdown constructs stay whole. Always returns
+// at least one (possibly empty) page.
+fn document_pages(content: &str) -> Vec<&str> {
+    let mut pages = Vec::new();
+    let mut page_start = 0;
+    let mut offset = 0;
+    let mut in_fence = false;
+    for line in content.split_inclusive('\n') {
+        offset += line.len();
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+        let page_len = offset - page_start;
+        let paragraph_break = trimmed.is_empty() && !in_fence;
+        if (page_len >= DOCUMENT_PAGE_BYTES && paragraph_break)
+            || page_len >= 2 * DOCUMENT_PAGE_BYTES
+        {
+            pages.push(&content[page_start..offset]);
+            page_start = offset;
+        }
+    }
+    if page_start < content.len() || pages.is_empty() {
+        pages.push(&content[page_start..]);
+    }
+    pages
+}
+
+// Raw HTML in the Markdown is shown as text rather than injected into the
+// page, and links and images to anything but web, mail or relative URLs
+// (`javascript:`, `data:`) lead nowhere
+fn render_markdown(text: &str) -> String {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let events = Parser::new_ext(text, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if !is_safe_url(&dest_url) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::Borrowed(""),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if !is_safe_url(&dest_url) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: CowStr::Borrowed(""),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+// Relative URLs, or ones with a scheme in `SAFE_URL_SCHEMES`. Browsers ignore
+// whitespace and control characters in a scheme (`java\tscript:`), so they
+// are left out before looking for one.
+fn is_safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect();
+    match url.find([':', '/', '?', '#']) {
+        Some(end) if url[end..].starts_with(':') => SAFE_URL_SCHEMES
+            .iter()
+            .any(|scheme| url[..end].eq_ignore_ascii_case(scheme)),
+        _ => true,
+    }
+}
@@ -0,0 +1,101 @@
+// src/web_pages.rs
+// Web pages in a corpus: a `urls.txt` at the top of a corpus root lists one
+// URL per line, and each page is fetched and indexed with its URL as path.
+// Pages are fetched again whenever the list changes, and on a verifying pass.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::config::CorpusRoot;
+
+// --- CONSTANTS ---
+pub const MANIFEST_FILE: &str = "urls.txt";
+// A host that stalls fails its page rather than holding up the refresh
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_PAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref AGENT: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_connect(Some(CONNECT_TIMEOUT))
+        .timeout_global(Some(FETCH_TIMEOUT))
+        .build()
+        .into();
+}
+
+pub fn manifest_path(root: &Path) -> PathBuf {
+    root.join(MANIFEST_FILE)
+}
+
+pub fn is_manifest(path: &Path, root: &CorpusRoot) -> bool {
+    path == manifest_path(&root.path)
+}
+
+pub fn is_url(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+// The URLs listed in a manifest, without duplicates; blank lines and lines
+// starting with '#' are skipped
+pub(crate) fn urls(manifest: &Path) -> Result<Vec<String>> {
+    let raw = fs::read_to_string(manifest).context("Failed to read URL list")?;
+    let mut urls: Vec<String> = Vec::new();
+    for line in raw.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || urls.iter().any(|url| url == line) {
+            continue;
+        }
+        if !is_url(Path::new(line)) {
+            return Err(anyhow!("Not an http(s) URL in {:?}: {}", manifest, line));
+        }
+        urls.push(line.to_string());
+    }
+    Ok(urls)
+}
+
+// Adds `url` to the manifest, creating it if needed. Returns false if it was
+// already listed.
+pub fn add_url(manifest: &Path, url: &str) -> Result<bool> {
+    if !is_url(Path::new(url)) {
+        return Err(anyhow!("Not an http(s) URL: {}", url));
+    }
+    let existing = if manifest.exists() {
+        fs::read_to_string(manifest)
+            .with_context(|| format!("Failed to read URL list {:?}", manifest))?
+    } else {
+        String::new()
+    };
+    if existing.lines().any(|line| line.trim() == url) {
+        return Ok(false);
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(manifest)
+        .with_context(|| format!("Failed to open URL list {:?}", manifest))?;
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    writeln!(file, "{}{}", separator, url)
+        .with_context(|| format!("Failed to write URL list {:?}", manifest))?;
+    Ok(true)
+}
+
+// The page's HTML
+pub(crate) fn fetch(url: &str) -> Result<String> {
+    AGENT
+        .get(url)
+        .call()
+        .with_context(|| format!("Failed to fetch {}", url))?
+        .body_mut()
+        .with_config()
+        .limit(MAX_PAGE_BYTES)
+        .read_to_string()
+        .with_context(|| format!("Failed to read page {}", url))
+}